@@ -0,0 +1,70 @@
+//! Compares the ways of counting the elements with a given tag:
+//! `cargo bench --bench tag_equals`
+
+extern crate osmpbf;
+
+use osmpbf::testing::TestPbfBuilder;
+use osmpbf::*;
+use std::time::{Duration, Instant};
+
+const NODES: i64 = 100_000;
+const ROUNDS: u32 = 20;
+
+fn bench<F>(name: &str, blocks: &[PrimitiveBlock], count: F)
+where
+    F: Fn(&PrimitiveBlock) -> usize,
+{
+    let mut best = Duration::from_secs(u64::MAX);
+    let mut matches = 0;
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        matches = blocks.iter().map(&count).sum::<usize>();
+        best = best.min(start.elapsed());
+    }
+    println!("{:<12} {:>10.3?} ({} matches)", name, best, matches);
+}
+
+fn main() {
+    let mut builder = TestPbfBuilder::new();
+    for id in 0..NODES {
+        let value = if id % 10 == 0 {
+            "traffic_signals"
+        } else {
+            "crossing"
+        };
+        let tags = [("highway", value), ("ref", "42"), ("source", "survey")];
+        builder = builder.node(id, 52.0, 13.0, &tags);
+    }
+    let data = builder.build().expect("failed to build benchmark data");
+
+    let blocks: Vec<PrimitiveBlock> = BlobReader::from_bytes(&data)
+        .map(|blob| blob.expect("failed to read benchmark data"))
+        .filter(|blob| blob.get_type() == BlobType::OsmData)
+        .map(|blob| {
+            blob.to_primitiveblock()
+                .expect("failed to decode benchmark data")
+        })
+        .collect();
+
+    bench("tags", &blocks, |block| {
+        block
+            .elements()
+            .filter(|e| e.tags().any(|t| t == ("highway", "traffic_signals")))
+            .count()
+    });
+    bench("tag_equals", &blocks, |block| {
+        block
+            .elements()
+            .filter(|e| match *e {
+                Element::Node(ref node) => node.tag_equals("highway", "traffic_signals"),
+                Element::DenseNode(ref node) => node.tag_equals("highway", "traffic_signals"),
+                Element::Way(ref way) => way.tag_equals("highway", "traffic_signals"),
+                Element::Relation(ref rel) => rel.tag_equals("highway", "traffic_signals"),
+            })
+            .count()
+    });
+    bench("tag_matcher", &blocks, |block| {
+        let matcher = block.tag_matcher("highway", Some("traffic_signals"));
+        block.elements().filter(|e| matcher.matches(e)).count()
+    });
+}
@@ -0,0 +1,47 @@
+// Measure the decode throughput for a PBF file given as the first command line argument. The
+// optional second argument is the number of passes over the file (default: 1). Compare builds
+// with and without `--no-default-features` to compare the zlib decoders.
+
+extern crate osmpbf;
+
+use osmpbf::*;
+use std::time::Instant;
+
+fn main() {
+    let mut args = std::env::args_os().skip(1);
+    let path = args.next().expect("need a *.osm.pbf file as argument");
+    let passes: u32 = args
+        .next()
+        .map(|s| {
+            s.to_string_lossy()
+                .parse()
+                .expect("invalid number of passes")
+        })
+        .unwrap_or(1);
+
+    let blobs = BlobReader::from_path(path)
+        .unwrap()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+
+    let start = Instant::now();
+    let mut groups = 0;
+    for _ in 0..passes {
+        for blob in &blobs {
+            if let BlobDecode::OsmData(block) = blob.decode().unwrap() {
+                groups += block.groups().count();
+            }
+        }
+    }
+    let elapsed = start.elapsed();
+
+    let seconds = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9;
+    println!(
+        "Decoded {} blobs ({} groups) {} times in {:.3} s ({:.1} blobs/s)",
+        blobs.len(),
+        groups / passes as usize,
+        passes,
+        seconds,
+        f64::from(passes) * blobs.len() as f64 / seconds
+    );
+}
@@ -0,0 +1,43 @@
+// Count the number of nodes, ways and relations in a PBF file that is passed from JavaScript as a
+// `Uint8Array`. Build with
+//
+//     cargo build --example wasm --target wasm32-unknown-unknown --no-default-features
+//
+// and generate the JavaScript bindings with `wasm-bindgen`. Memory maps and the parallel
+// `par_*` methods are not available in the browser, so the file is decoded sequentially.
+
+extern crate osmpbf;
+#[cfg(target_arch = "wasm32")]
+extern crate wasm_bindgen;
+
+use osmpbf::*;
+
+pub fn count_elements(data: &[u8]) -> Result<(u64, u64, u64)> {
+    let reader = ElementReader::new(std::io::Cursor::new(data));
+    let mut counts = (0, 0, 0);
+
+    reader.for_each(|element| match element {
+        Element::Node(_) | Element::DenseNode(_) => counts.0 += 1,
+        Element::Way(_) => counts.1 += 1,
+        Element::Relation(_) => counts.2 += 1,
+    })?;
+
+    Ok(counts)
+}
+
+#[cfg(target_arch = "wasm32")]
+mod bindings {
+    use wasm_bindgen::prelude::*;
+
+    /// Returns a summary of the element counts or throws an error message.
+    #[wasm_bindgen]
+    pub fn count(data: &[u8]) -> Result<String, JsValue> {
+        match super::count_elements(data) {
+            Ok((nodes, ways, relations)) => Ok(format!(
+                "Nodes: {}, Ways: {}, Relations: {}",
+                nodes, ways, relations
+            )),
+            Err(e) => Err(JsValue::from_str(&e.to_string())),
+        }
+    }
+}
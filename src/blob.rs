@@ -1,22 +1,38 @@
-//! Read and decode blobs
+//! Read, decode and write blobs
 
 extern crate byteorder;
 extern crate protobuf;
 
 use block::{HeaderBlock, PrimitiveBlock};
-use byteorder::ReadBytesExt;
-use error::{new_blob_error, new_protobuf_error, BlobError, Result};
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use error::{new_blob_error, new_protobuf_error, BlobError, Error, Result};
+use metrics::ReaderMetrics;
 use proto::fileformat;
+use protobuf::Message;
+use rayon::iter::plumbing::UnindexedConsumer;
+use rayon::iter::{ParallelBridge, ParallelIterator};
+use std::convert::TryFrom;
+use std::fmt;
 use std::fs::File;
-use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom, Write};
 use std::path::Path;
-use util::{parse_message_from_bytes, parse_message_from_reader};
+use std::sync::Arc;
+use util::{parse_message_from_bytes, parse_message_from_reader, LimitedReader};
 
 #[cfg(feature = "system-libz")]
 use flate2::read::ZlibDecoder;
+#[cfg(feature = "system-libz")]
+use flate2::write::ZlibEncoder;
 
 #[cfg(not(feature = "system-libz"))]
-use inflate::DeflateDecoder;
+use miniz_oxide::deflate::compress_to_vec_zlib;
+#[cfg(not(feature = "system-libz"))]
+use util::ZlibReader;
+
+#[cfg(feature = "lzma")]
+use xz2::read::XzDecoder;
+#[cfg(feature = "lzma")]
+use xz2::stream::Stream;
 
 /// Maximum allowed `BlobHeader` size in bytes.
 pub static MAX_BLOB_HEADER_SIZE: u64 = 64 * 1024;
@@ -24,6 +40,9 @@ pub static MAX_BLOB_HEADER_SIZE: u64 = 64 * 1024;
 /// Maximum allowed uncompressed `Blob` content size in bytes.
 pub static MAX_BLOB_MESSAGE_SIZE: u64 = 32 * 1024 * 1024;
 
+/// Average size of an encoded element, used by `Blob::estimate_element_count`.
+const ESTIMATED_BYTES_PER_ELEMENT: u64 = 64;
+
 /// The content type of a blob.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum BlobType<'a> {
@@ -36,6 +55,33 @@ pub enum BlobType<'a> {
     Unknown(&'a str),
 }
 
+impl<'a> fmt::Display for BlobType<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BlobType::OsmHeader => write!(f, "OSMHeader"),
+            BlobType::OsmData => write!(f, "OSMData"),
+            BlobType::Unknown(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+/// The format that the content of a blob is stored in.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Compression {
+    /// Uncompressed data.
+    Raw,
+    /// zlib compressed data.
+    Zlib,
+    /// LZMA compressed data (can be decoded with the `lzma` feature).
+    Lzma,
+    /// bzip2 compressed data. This format is deprecated and cannot be decoded.
+    Bzip2,
+    /// LZ4 compressed data. This format cannot be decoded.
+    Lz4,
+    /// Zstandard compressed data. This format cannot be decoded.
+    Zstd,
+}
+
 //TODO rename variants to fit proto files
 /// The decoded content of a blob (analogous to `BlobType`).
 #[derive(Clone, Debug)]
@@ -50,7 +96,7 @@ pub enum BlobDecode<'a> {
 }
 
 /// The offset of a blob in bytes from stream start.
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct ByteOffset(pub u64);
 
 /// A blob.
@@ -65,7 +111,7 @@ pub struct Blob {
 }
 
 impl Blob {
-    fn new(
+    pub(crate) fn new(
         header: fileformat::BlobHeader,
         blob: fileformat::Blob,
         offset: Option<ByteOffset>,
@@ -77,6 +123,46 @@ impl Blob {
         }
     }
 
+    /// Creates a blob from its encoded parts: the `BlobHeader` message and the `Blob` message
+    /// that follows it in a file (without the leading header size). The `datasize` field of the
+    /// header is not checked, `blob` has to contain exactly one message. This is useful to decode
+    /// blobs that are stored or transmitted separately, or to feed arbitrary bytes to the
+    /// decoder in a fuzz target. The blob has no offset.
+    ///
+    /// # Errors
+    /// Returns an error if the header is too big or if one of the parts cannot be parsed.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let data = std::fs::read("tests/test.osm.pbf")?;
+    /// let header_size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    /// let second_blob = BlobReader::from_bytes(&data).nth(1).unwrap()?;
+    /// let end = second_blob.offset().unwrap().0 as usize;
+    ///
+    /// let blob = Blob::from_parts(&data[4..4 + header_size], &data[4 + header_size..end])?;
+    ///
+    /// assert_eq!(blob.get_type(), BlobType::OsmHeader);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn from_parts(header: &[u8], blob: &[u8]) -> Result<Blob> {
+        let header_size = header.len() as u64;
+        if header_size >= MAX_BLOB_HEADER_SIZE {
+            return Err(new_blob_error(BlobError::HeaderTooBig {
+                size: header_size,
+            }));
+        }
+        let header: fileformat::BlobHeader =
+            parse_message_from_bytes(header).map_err(|e| new_protobuf_error(e, "blob header"))?;
+        let blob =
+            parse_message_from_bytes(blob).map_err(|e| new_protobuf_error(e, "blob content"))?;
+        Ok(Blob::new(header, blob, None))
+    }
+
     /// Decodes the Blob and tries to obtain the inner content (usually a `HeaderBlock` or a
     /// `PrimitiveBlock`). This operation might involve an expensive decompression step.
     pub fn decode(&self) -> Result<BlobDecode> {
@@ -93,6 +179,42 @@ impl Blob {
         }
     }
 
+    /// Like `decode`, but fails with `BlobError::MessageTooBig` if the decompressed content is
+    /// bigger than `max_size` bytes. Decompression stops as soon as the limit is exceeded, so
+    /// this is cheap even for blobs that would expand to a huge size. Use a limit below
+    /// `MAX_BLOB_MESSAGE_SIZE` to protect services that parse untrusted files.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// for blob in reader {
+    ///     match blob?.decode_with_limit(16) {
+    ///         Ok(_) => println!("blob is small enough"),
+    ///         Err(e) => println!("rejected blob: {}", e),
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn decode_with_limit(&self, max_size: u64) -> Result<BlobDecode<'_>> {
+        match self.get_type() {
+            BlobType::OsmHeader => {
+                let block = HeaderBlock::new(decode_blob_with_limit(&self.blob, max_size)?);
+                Ok(BlobDecode::OsmHeader(Box::new(block)))
+            }
+            BlobType::OsmData => {
+                let block = PrimitiveBlock::new(decode_blob_with_limit(&self.blob, max_size)?)?;
+                Ok(BlobDecode::OsmData(block))
+            }
+            BlobType::Unknown(x) => Ok(BlobDecode::Unknown(x)),
+        }
+    }
+
     /// Returns the type of a blob without decoding its content.
     pub fn get_type(&self) -> BlobType {
         match self.header.get_field_type() {
@@ -102,12 +224,172 @@ impl Blob {
         }
     }
 
+    /// Returns `true` if the blob contains a `HeaderBlock`. Like `get_type` this does not decode
+    /// the content.
+    pub fn is_header(&self) -> bool {
+        self.get_type() == BlobType::OsmHeader
+    }
+
+    /// Returns `true` if the blob contains a `PrimitiveBlock`. Like `get_type` this does not
+    /// decode the content, so it is cheap enough to route blobs to workers.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// let mut data_blobs = vec![];
+    /// for blob in reader {
+    ///     let blob = blob?;
+    ///     if blob.is_data() {
+    ///         data_blobs.push(blob);
+    ///     }
+    /// }
+    /// assert_eq!(data_blobs.len(), 1);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn is_data(&self) -> bool {
+        self.get_type() == BlobType::OsmData
+    }
+
+    /// Returns how the content of this blob is stored, derived from the data field that is set.
+    /// Returns `None` if the blob is empty. This does not decode the content, so it is cheap to
+    /// use for statistics or to decide if a blob can be copied verbatim with
+    /// `BlobWriter::write_raw_blob`.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test_nozlib.osm.pbf")?;
+    ///
+    /// for blob in reader {
+    ///     assert_eq!(blob?.compression(), Some(Compression::Raw));
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn compression(&self) -> Option<Compression> {
+        let blob = &self.blob;
+        let unknown_fields = blob.get_unknown_fields();
+        if blob.has_raw() {
+            Some(Compression::Raw)
+        } else if blob.has_zlib_data() {
+            Some(Compression::Zlib)
+        } else if blob.has_lzma_data() {
+            Some(Compression::Lzma)
+        } else if blob.has_OBSOLETE_bzip2_data() {
+            Some(Compression::Bzip2)
+        } else if unknown_fields.get(LZ4_DATA_FIELD).is_some() {
+            Some(Compression::Lz4)
+        } else if unknown_fields.get(ZSTD_DATA_FIELD).is_some() {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the encoded content of the blob.
+    pub(crate) fn content(&self) -> &fileformat::Blob {
+        &self.blob
+    }
+
+    /// Returns the declared size of the content after decompression, or zero if the blob is
+    /// stored uncompressed or declares a negative size.
+    pub(crate) fn decompressed_size(&self) -> u64 {
+        if self.blob.has_raw() {
+            0
+        } else {
+            u64::try_from(self.blob.get_raw_size()).unwrap_or(0)
+        }
+    }
+
+    /// Estimates the number of elements in an `OSMData` blob from the declared size of its
+    /// decompressed content, without decompressing it. Returns `None` for other blob types and for
+    /// compressed blobs that do not declare a valid `raw_size`.
+    ///
+    /// The estimate assumes an average of 64 bytes per element, which is typical for files with
+    /// metadata. It can be off by a factor of two or more: dense nodes without tags are much
+    /// smaller, ways with many nodes and relations much larger. This is good enough to balance
+    /// blobs of very different sizes between threads, but not to allocate exact buffers.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut blobs = BlobReader::from_path("tests/test.osm.pbf")?.collect::<Result<Vec<_>>>()?;
+    ///
+    /// // Decode the biggest blobs first.
+    /// blobs.sort_by_key(|blob| std::cmp::Reverse(blob.estimate_element_count()));
+    /// assert_eq!(blobs.last().unwrap().estimate_element_count(), None);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn estimate_element_count(&self) -> Option<usize> {
+        if self.get_type() != BlobType::OsmData {
+            return None;
+        }
+        let size = if self.blob.has_raw() {
+            self.blob.get_raw().len() as u64
+        } else if self.blob.has_raw_size() {
+            u64::try_from(self.blob.get_raw_size()).ok()?
+        } else {
+            return None;
+        };
+        Some(((size + ESTIMATED_BYTES_PER_ELEMENT - 1) / ESTIMATED_BYTES_PER_ELEMENT) as usize)
+    }
+
+    /// Returns the raw `indexdata` field of the blob header or `None` if it is not set. The format
+    /// of this field is not specified; some tools use it to store custom metadata about the
+    /// blob content, e.g. a bounding box, that can be read without decoding the blob.
+    pub fn index_data(&self) -> Option<&[u8]> {
+        if self.header.has_indexdata() {
+            Some(self.header.get_indexdata())
+        } else {
+            None
+        }
+    }
+
     /// Returns the byte offset of the blob from the start of its source stream.
     /// This might be `None` if the source stream does not implement `Seek`.
     pub fn offset(&self) -> Option<ByteOffset> {
         self.offset
     }
 
+    /// Returns the content of the blob after decompression, without parsing it as a
+    /// `HeaderBlock` or `PrimitiveBlock`. This is useful to decode the content with a different
+    /// protobuf library or to inspect a malformed block.
+    ///
+    /// # Errors
+    /// Returns an error if decompression fails, if the compression format is not supported or if
+    /// the content is bigger than `MAX_BLOB_MESSAGE_SIZE`.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// for blob in BlobReader::from_path("tests/test.osm.pbf")? {
+    ///     let blob = blob?;
+    ///     let bytes = blob.decompressed_bytes()?;
+    ///     println!("{:?} blob: {} bytes", blob.get_type(), bytes.len());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn decompressed_bytes(&self) -> Result<Vec<u8>> {
+        decompress_blob(&self.blob, MAX_BLOB_MESSAGE_SIZE)
+    }
+
     /// Tries to decode the blob to a `HeaderBlock`. This operation might involve an expensive
     /// decompression step.
     pub fn to_headerblock(&self) -> Result<HeaderBlock> {
@@ -117,7 +399,7 @@ impl Blob {
     /// Tries to decode the blob to a `PrimitiveBlock`. This operation might involve an expensive
     /// decompression step.
     pub fn to_primitiveblock(&self) -> Result<PrimitiveBlock> {
-        decode_blob(&self.blob).map(PrimitiveBlock::new)
+        decode_blob(&self.blob).and_then(PrimitiveBlock::new)
     }
 }
 
@@ -127,7 +409,30 @@ pub struct BlobReader<R: Read> {
     reader: R,
     /// Current reader offset in bytes from the start of the stream.
     offset: Option<ByteOffset>,
+    /// Offset of the first blob.
+    start_offset: ByteOffset,
+    /// Index of the blob that is read next (if known).
+    blob_index: Option<usize>,
     last_blob_ok: bool,
+    metrics: Arc<ReaderMetrics>,
+    /// Skips bytes of the stream if the reader is seekable.
+    seek: Option<SeekFn<R>>,
+}
+
+/// Skips the given number of bytes of a reader. It is set by the constructors that know that the
+/// reader is seekable, as `Iterator::nth` cannot require `R: Seek`.
+struct SeekFn<R>(fn(&mut R, i64) -> ::std::io::Result<()>);
+
+impl<R> Clone for SeekFn<R> {
+    fn clone(&self) -> Self {
+        SeekFn(self.0)
+    }
+}
+
+impl<R> fmt::Debug for SeekFn<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SeekFn")
+    }
 }
 
 impl<R: Read> BlobReader<R> {
@@ -151,7 +456,138 @@ impl<R: Read> BlobReader<R> {
         BlobReader {
             reader,
             offset: None,
+            start_offset: ByteOffset(0),
+            blob_index: Some(0),
             last_blob_ok: true,
+            metrics: Arc::new(ReaderMetrics::default()),
+            seek: None,
+        }
+    }
+
+    /// Returns the counters of the blobs and bytes that were read so far.
+    pub fn metrics(&self) -> &ReaderMetrics {
+        &self.metrics
+    }
+
+    /// Returns a handle to the counters that stays valid after the reader is consumed, e.g. by
+    /// an `ElementReader`. Clones of this reader share the same counters.
+    pub fn shared_metrics(&self) -> Arc<ReaderMetrics> {
+        self.metrics.clone()
+    }
+
+    /// Returns the current offset of the reader in bytes from the start of the stream, which is
+    /// also the offset of the blob that is read next. Returns `None` if the offset is unknown,
+    /// e.g. for a reader constructed with `new`, after the end of the stream has been reached, or
+    /// after an error.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    /// assert_eq!(reader.offset(), Some(ByteOffset(0)));
+    ///
+    /// let _header = reader.next().unwrap()?;
+    /// let offset = reader.offset();
+    /// let data = reader.next().unwrap()?;
+    /// assert_eq!(data.offset(), offset);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn offset(&self) -> Option<ByteOffset> {
+        self.offset
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Returns a mutable reference to the underlying reader.
+    ///
+    /// Reading from or seeking the underlying reader directly invalidates the offset and blob
+    /// index that this `BlobReader` keeps track of, so subsequent offsets may be wrong.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Consumes this `BlobReader` and returns the underlying reader.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    /// use std::io::Seek;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = BlobReader::seekable_from_path("tests/test.osm.pbf")?;
+    /// let _header = reader.next().unwrap()?;
+    ///
+    /// let mut buf_reader = reader.into_inner();
+    /// assert!(buf_reader.stream_position()? > 0);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Returns an iterator over the `HeaderBlock`s of the stream. All other blobs are skipped
+    /// without being parsed or decompressed, so this is a cheap way to collect the bounding boxes,
+    /// features and replication information of many files. A file that is a concatenation of
+    /// several PBF files yields one header per part.
+    ///
+    /// Readers created with `from_path`, `from_bytes` or `new_seekable` seek past the contents of
+    /// skipped blobs, other readers read and discard them (see `nth`).
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// for header in reader.header_blocks() {
+    ///     let header = header?;
+    ///     println!("required features: {:?}", header.required_features());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn header_blocks(self) -> HeaderBlocks<R> {
+        HeaderBlocks { reader: self }
+    }
+
+    /// Returns an adaptor that yields at most the first `n` blobs, e.g. to preview the beginning
+    /// of a huge file. Unlike `Iterator::take`, the adaptor gives the `BlobReader` back with
+    /// `into_inner`, positioned at the blob after the last one that was returned, so reading can
+    /// continue from there. (`Iterator::by_ref` followed by `take` works as well if the reader
+    /// does not need to be moved.)
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut preview = BlobReader::from_path("tests/test.osm.pbf")?.take_blobs(1);
+    /// for blob in &mut preview {
+    ///     let blob = blob?;
+    ///     println!("{} blob at {:?}", blob.get_type(), blob.offset());
+    /// }
+    ///
+    /// let rest: Vec<_> = preview.into_inner().collect::<Result<_>>()?;
+    /// assert_eq!(rest.len(), 1);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn take_blobs(self, n: usize) -> TakeBlobs<R> {
+        TakeBlobs {
+            reader: self,
+            remaining: n,
         }
     }
 }
@@ -179,22 +615,25 @@ impl BlobReader<BufReader<File>> {
         Ok(BlobReader {
             reader,
             offset: Some(ByteOffset(0)),
+            start_offset: ByteOffset(0),
+            blob_index: Some(0),
             last_blob_ok: true,
+            metrics: Arc::new(ReaderMetrics::default()),
+            // Unlike `seek`, `seek_relative` keeps the buffer if the target is inside it.
+            seek: Some(SeekFn(BufReader::seek_relative)),
         })
     }
 }
 
-impl<R: Read> Iterator for BlobReader<R> {
-    type Item = Result<Blob>;
-
-    fn next(&mut self) -> Option<Self::Item> {
+impl<R: Read> BlobReader<R> {
+    /// Reads the header of the next blob and updates the offset accordingly. Returns the header
+    /// and its encoded size, or `None` at the end of the stream.
+    fn next_header(&mut self) -> Option<Result<(fileformat::BlobHeader, u64)>> {
         // Stop iteration if there was an error.
         if !self.last_blob_ok {
             return None;
         }
 
-        let prev_offset = self.offset;
-
         let header_size: u64 = match self.reader.read_u32::<byteorder::BigEndian>() {
             Ok(n) => {
                 self.offset = self.offset.map(|x| ByteOffset(x.0 + 4));
@@ -222,16 +661,76 @@ impl<R: Read> Iterator for BlobReader<R> {
             })));
         }
 
-        let header: fileformat::BlobHeader =
-            match parse_message_from_reader(&mut self.reader.by_ref().take(header_size)) {
-                Ok(header) => header,
-                Err(e) => {
-                    self.offset = None;
-                    self.last_blob_ok = false;
-                    return Some(Err(new_protobuf_error(e, "blob header")));
-                }
-            };
+        match parse_message_from_reader(&mut self.reader.by_ref().take(header_size)) {
+            Ok(header) => Some(Ok((header, header_size))),
+            Err(e) => {
+                self.offset = None;
+                self.last_blob_ok = false;
+                Some(Err(new_protobuf_error(e, "blob header")))
+            }
+        }
+    }
+
+    /// Advances the offset and blob index past a blob whose header and content are consumed.
+    fn finish_blob(&mut self, header: &fileformat::BlobHeader, header_size: u64) {
+        self.offset = self
+            .offset
+            .map(|x| ByteOffset(x.0 + header_size + header.get_datasize() as u64));
+        self.blob_index = self.blob_index.map(|i| i + 1);
+    }
 
+    /// Skips the content of a blob without parsing it. Seekable readers seek past it, other
+    /// readers read and discard it.
+    fn skip_content(&mut self, header: &fileformat::BlobHeader, header_size: u64) -> Result<()> {
+        let datasize = match checked_datasize(header) {
+            Ok(datasize) => datasize,
+            Err(e) => {
+                self.offset = None;
+                self.last_blob_ok = false;
+                return Err(e);
+            }
+        };
+        let skipped = match self.seek {
+            Some(SeekFn(seek)) => seek(&mut self.reader, datasize as i64).map(|_| datasize),
+            None => ::std::io::copy(
+                &mut self.reader.by_ref().take(datasize),
+                &mut ::std::io::sink(),
+            ),
+        };
+        match skipped {
+            Ok(skipped) if skipped == datasize => {}
+            Ok(_) => {
+                self.offset = None;
+                self.last_blob_ok = false;
+                return Err(::std::io::Error::new(
+                    ::std::io::ErrorKind::UnexpectedEof,
+                    "blob content is truncated",
+                )
+                .into());
+            }
+            Err(e) => {
+                self.offset = None;
+                self.last_blob_ok = false;
+                return Err(e.into());
+            }
+        }
+
+        log_trace!(
+            "skipped {} blob: {} bytes",
+            header.get_field_type(),
+            datasize
+        );
+        self.finish_blob(header, header_size);
+        self.metrics.add_bytes(4 + header_size + datasize);
+        Ok(())
+    }
+
+    /// Reads and parses the content of a blob whose header was just read.
+    fn read_content(
+        &mut self,
+        header: &fileformat::BlobHeader,
+        header_size: u64,
+    ) -> Result<fileformat::Blob> {
         let blob: fileformat::Blob = match parse_message_from_reader(
             &mut self.reader.by_ref().take(header.get_datasize() as u64),
         ) {
@@ -239,28 +738,86 @@ impl<R: Read> Iterator for BlobReader<R> {
             Err(e) => {
                 self.offset = None;
                 self.last_blob_ok = false;
-                return Some(Err(new_protobuf_error(e, "blob content")));
+                return Err(new_protobuf_error(e, "blob content"));
             }
         };
 
-        self.offset = self
-            .offset
-            .map(|x| ByteOffset(x.0 + header_size + header.get_datasize() as u64));
+        self.finish_blob(header, header_size);
+        Ok(blob)
+    }
 
-        Some(Ok(Blob::new(header, blob, prev_offset)))
+    /// Counts a blob whose header and content were read in the metrics.
+    fn count_blob(&self, header: &fileformat::BlobHeader, header_size: u64) {
+        self.metrics
+            .add_blob(4 + header_size + header.get_datasize() as u64);
     }
 }
 
-impl<R: Read + Seek> BlobReader<R> {
-    /// Creates a new `BlobReader` from the given reader that is seekable and will be initialized
-    /// with a valid offset.
-    ///
-    /// # Example
-    /// ```
-    /// use osmpbf::*;
-    ///
-    /// # fn foo() -> Result<()> {
-    /// let f = std::fs::File::open("tests/test.osm.pbf")?;
+impl<R: Read> Iterator for BlobReader<R> {
+    type Item = Result<Blob>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let prev_offset = self.offset;
+        let (header, header_size) = match self.next_header()? {
+            Ok(h) => h,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let blob = match self.read_content(&header, header_size) {
+            Ok(blob) => blob,
+            Err(e) => return Some(Err(e)),
+        };
+        self.count_blob(&header, header_size);
+
+        log_debug!(
+            "read {} blob at offset {:?}: {} bytes",
+            header.get_field_type(),
+            prev_offset,
+            header.get_datasize()
+        );
+        log_trace!(
+            "{} blob at offset {:?} decompresses to {} bytes",
+            header.get_field_type(),
+            prev_offset,
+            if blob.has_raw() {
+                blob.get_raw().len() as i64
+            } else {
+                i64::from(blob.get_raw_size())
+            }
+        );
+
+        Some(Ok(Blob::new(header, blob, prev_offset)))
+    }
+
+    /// Skips `n` blobs by reading only their headers. The contents of skipped blobs are not
+    /// parsed or decompressed: readers created with `from_path`, `from_bytes` or `new_seekable`
+    /// seek past them, other readers read and discard them. A truncated content that is skipped
+    /// by seeking ends the iteration instead of returning an error.
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        for _ in 0..n {
+            let (header, header_size) = match self.next_header()? {
+                Ok(h) => h,
+                Err(e) => return Some(Err(e)),
+            };
+
+            if let Err(e) = self.skip_content(&header, header_size) {
+                return Some(Err(e));
+            }
+        }
+        self.next()
+    }
+}
+
+impl<R: Read + Seek> BlobReader<R> {
+    /// Creates a new `BlobReader` from the given reader that is seekable and will be initialized
+    /// with a valid offset.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let f = std::fs::File::open("tests/test.osm.pbf")?;
     /// let buf_reader = std::io::BufReader::new(f);
     ///
     /// let mut reader = BlobReader::new_seekable(buf_reader)?;
@@ -277,7 +834,11 @@ impl<R: Read + Seek> BlobReader<R> {
         Ok(BlobReader {
             reader,
             offset: Some(ByteOffset(pos)),
+            start_offset: ByteOffset(pos),
+            blob_index: Some(0),
             last_blob_ok: true,
+            metrics: Arc::new(ReaderMetrics::default()),
+            seek: Some(SeekFn(seek_forward)),
         })
     }
 
@@ -304,6 +865,11 @@ impl<R: Read + Seek> BlobReader<R> {
         match self.reader.seek(SeekFrom::Start(pos.0)) {
             Ok(offset) => {
                 self.offset = Some(ByteOffset(offset));
+                self.blob_index = if offset == self.start_offset.0 {
+                    Some(0)
+                } else {
+                    None
+                };
                 Ok(())
             }
             Err(e) => {
@@ -318,6 +884,7 @@ impl<R: Read + Seek> BlobReader<R> {
         match self.reader.seek(pos) {
             Ok(offset) => {
                 self.offset = Some(ByteOffset(offset));
+                self.blob_index = None;
                 Ok(offset)
             }
             Err(e) => {
@@ -326,6 +893,589 @@ impl<R: Read + Seek> BlobReader<R> {
             }
         }
     }
+
+    /// Seek to the start of the `n`th blob (counting from zero) of the stream. This is a more
+    /// stable address than a byte offset, e.g. for sharing positions between processes. Only the
+    /// blob headers are read to find the position; the blob contents are skipped.
+    ///
+    /// If the index of the current blob is known, scanning continues from the current position;
+    /// otherwise it starts at the first blob. Use [`index`](#method.index) and
+    /// [`seek`](#method.seek) to jump to previously found blobs without scanning.
+    ///
+    /// # Errors
+    /// Returns an error if the stream contains fewer than `n + 1` blobs or if a blob header is
+    /// invalid.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// reader.seek_to_blob(1)?;
+    /// let second_blob = reader.next().unwrap()?;
+    /// assert_eq!(second_blob.get_type(), BlobType::OsmData);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn seek_to_blob(&mut self, n: usize) -> Result<()> {
+        let mut index = match (self.blob_index, self.offset) {
+            (Some(i), Some(_)) if i <= n => i,
+            _ => {
+                let start = self.start_offset;
+                self.seek(start)?;
+                0
+            }
+        };
+
+        let out_of_range = || {
+            ::std::io::Error::new(
+                ::std::io::ErrorKind::UnexpectedEof,
+                "blob index is out of range",
+            )
+        };
+
+        while index < n {
+            if !self.skip_blob()? {
+                return Err(out_of_range().into());
+            }
+            index += 1;
+        }
+
+        // Make sure that the stream does not end right before the requested blob.
+        let pos = self.offset.unwrap_or(self.start_offset);
+        let mut size_buf = [0u8; 4];
+        if let Err(e) = self.reader.read_exact(&mut size_buf) {
+            self.offset = None;
+            return Err(match e.kind() {
+                ::std::io::ErrorKind::UnexpectedEof => out_of_range().into(),
+                _ => e.into(),
+            });
+        }
+        self.seek(pos)?;
+
+        self.blob_index = Some(n);
+        self.last_blob_ok = true;
+        Ok(())
+    }
+
+    /// Returns the byte offsets of all blobs in the stream, so that the position of a blob can be
+    /// looked up by its index. Only the blob headers are read; the blob contents are skipped.
+    /// Afterwards, the reader is back at its previous position.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// let index = reader.index()?;
+    /// assert_eq!(index.len(), 2);
+    ///
+    /// reader.seek(index[1])?;
+    /// let second_blob = reader.next().unwrap()?;
+    /// assert_eq!(second_blob.offset(), Some(index[1]));
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn index(&mut self) -> Result<Vec<ByteOffset>> {
+        let (prev_offset, prev_blob_index) = (self.offset, self.blob_index);
+        let result = self.collect_offsets();
+
+        // Restore the position even if a blob could not be skipped.
+        if let Some(offset) = prev_offset {
+            self.seek(offset)?;
+            self.blob_index = prev_blob_index;
+        }
+        result
+    }
+
+    fn collect_offsets(&mut self) -> Result<Vec<ByteOffset>> {
+        let start = self.start_offset;
+        self.seek(start)?;
+        let mut offsets = vec![];
+        let mut pos = start;
+        while self.skip_blob()? {
+            offsets.push(pos);
+            pos = self.offset.unwrap_or(pos);
+        }
+        Ok(offsets)
+    }
+
+    /// Returns the sum of the uncompressed sizes of all blobs in the stream in bytes. The sizes
+    /// are taken from the `raw_size` field of each blob, so nothing is decompressed. Returns
+    /// `None` if the size of a compressed blob is unknown because the writer did not populate
+    /// this field. Afterwards, the reader is back at its previous position. The blobs that are
+    /// read for the estimate are not counted in the `metrics`.
+    ///
+    /// The result is the size of the encoded blocks, not of the decoded elements in memory, but
+    /// it scales with the latter and can be used to estimate memory requirements.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// if let Some(size) = reader.estimated_uncompressed_size()? {
+    ///     println!("uncompressed size: {} bytes", size);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn estimated_uncompressed_size(&mut self) -> Result<Option<u64>> {
+        let (prev_offset, prev_blob_index) = (self.offset, self.blob_index);
+        let prev_blob_ok = self.last_blob_ok;
+        let pos = self.reader.stream_position()?;
+
+        // A reader created with `new` does not know its offset, but before the first blob is
+        // read, the current position is the start of the stream.
+        let start = match (prev_offset, prev_blob_index) {
+            (None, Some(0)) => ByteOffset(pos),
+            _ => self.start_offset,
+        };
+        let result = self.sum_raw_sizes(start);
+
+        let restored = self.reader.seek(SeekFrom::Start(pos));
+        self.offset = prev_offset;
+        self.blob_index = prev_blob_index;
+        self.last_blob_ok = prev_blob_ok;
+        if let Err(e) = restored {
+            self.offset = None;
+            return Err(e.into());
+        }
+        result
+    }
+
+    fn sum_raw_sizes(&mut self, start: ByteOffset) -> Result<Option<u64>> {
+        self.reader.seek(SeekFrom::Start(start.0))?;
+        self.offset = Some(start);
+        self.last_blob_ok = true;
+
+        let mut total = 0;
+        while let Some(header) = self.next_header() {
+            let (header, header_size) = header?;
+            let blob = self.read_content(&header, header_size)?;
+            if blob.has_raw() {
+                total += blob.get_raw().len() as u64;
+            } else if blob.has_raw_size() && blob.get_raw_size() >= 0 {
+                total += blob.get_raw_size() as u64;
+            } else {
+                return Ok(None);
+            }
+        }
+        Ok(Some(total))
+    }
+
+    /// Reads the next blob header and seeks past the blob content. Returns `false` if the end of
+    /// the stream is reached.
+    fn skip_blob(&mut self) -> Result<bool> {
+        let header_size = match self.reader.read_u32::<byteorder::BigEndian>() {
+            Ok(n) => u64::from(n),
+            Err(e) => {
+                self.offset = None;
+                return match e.kind() {
+                    ::std::io::ErrorKind::UnexpectedEof => Ok(false),
+                    _ => Err(new_blob_error(BlobError::InvalidHeaderSize)),
+                };
+            }
+        };
+
+        if header_size >= MAX_BLOB_HEADER_SIZE {
+            self.offset = None;
+            return Err(new_blob_error(BlobError::HeaderTooBig {
+                size: header_size,
+            }));
+        }
+
+        let header: fileformat::BlobHeader = parse_message_from_reader(
+            &mut self.reader.by_ref().take(header_size),
+        )
+        .map_err(|e| {
+            self.offset = None;
+            new_protobuf_error(e, "blob header")
+        })?;
+
+        let datasize = checked_datasize(&header).map_err(|e| {
+            self.offset = None;
+            e
+        })?;
+        self.seek_raw(SeekFrom::Current(datasize as i64))?;
+        Ok(true)
+    }
+
+    /// Returns an adaptor that does not stop iterating after an error. Instead, it tries to find
+    /// the start of the next blob and resumes from there. Errors are still returned as `Err`
+    /// items. This is useful to recover the intact parts of a partially corrupt file.
+    ///
+    /// Resynchronization is heuristic: the stream after the failed blob is scanned for bytes
+    /// that look like the header of an `OSMHeader` or `OSMData` blob. Blobs of unknown types
+    /// cannot be recognized and are skipped along with the corrupt data, and in rare cases
+    /// corrupt data might be mistaken for a blob header.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::seekable_from_path("tests/test.osm.pbf")?;
+    ///
+    /// for blob in reader.skip_errors() {
+    ///     match blob {
+    ///         Ok(blob) => println!("blob at {:?}", blob.offset()),
+    ///         Err(e) => println!("skipping corrupt data: {}", e),
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn skip_errors(self) -> SkipErrors<R> {
+        SkipErrors {
+            reader: self,
+            done: false,
+        }
+    }
+
+    /// Returns a parallel iterator that decodes the blobs on the rayon thread pool and yields
+    /// each decoded blob together with its offset. The blobs are read sequentially, and the
+    /// offset of each blob is taken before it is dispatched to a worker thread. For a reader that
+    /// was created with `new`, the offsets start at the current position of the stream.
+    ///
+    /// The items arrive in no particular order; sort them by offset to restore the order of the
+    /// file. Blobs of unknown types are skipped, so `BlobDecode::Unknown` is never yielded.
+    /// Errors of the blob framing and of decoding are returned as `Err` items.
+    ///
+    /// # Example
+    /// ```
+    /// extern crate osmpbf;
+    /// extern crate rayon;
+    ///
+    /// use osmpbf::*;
+    /// use rayon::prelude::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// let mut data_blobs = reader
+    ///     .par_decode()
+    ///     .filter_map(|decoded| match decoded {
+    ///         Ok((offset, BlobDecode::OsmData(block))) => Some(Ok((offset, block.elements().count()))),
+    ///         Ok(_) => None,
+    ///         Err(e) => Some(Err(e)),
+    ///     })
+    ///     .collect::<Result<Vec<_>>>()?;
+    /// data_blobs.sort();
+    ///
+    /// assert_eq!(data_blobs.len(), 1);
+    /// assert_eq!(data_blobs[0].1, 5);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn par_decode(self) -> ParDecode<R>
+    where
+        R: Send,
+    {
+        ParDecode { reader: self }
+    }
+}
+
+/// Size of the chunks that are scanned when resynchronizing after an error.
+const RESYNC_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Number of bytes that consecutive chunks overlap, so that blob header candidates that cross a
+/// chunk boundary are not missed (4-byte header size + tag + length + "OSMHeader").
+const RESYNC_CHUNK_OVERLAP: usize = 16;
+
+/// Returns `BlobError::MessageTooBig` if a message of the given size cannot be written to a blob.
+fn check_message_size(size: usize) -> Result<()> {
+    if size as u64 > MAX_BLOB_MESSAGE_SIZE {
+        return Err(new_blob_error(BlobError::MessageTooBig {
+            size: size as u64,
+        }));
+    }
+    Ok(())
+}
+
+/// Returns the size of the blob content, or an error if the header declares a negative size.
+/// Skipping such a blob by seeking would move backwards in the stream.
+fn checked_datasize(header: &fileformat::BlobHeader) -> Result<u64> {
+    u64::try_from(header.get_datasize()).map_err(|_| {
+        ::std::io::Error::new(
+            ::std::io::ErrorKind::InvalidData,
+            "blob datasize is negative",
+        )
+        .into()
+    })
+}
+
+/// Seeks forward from the current position of a reader (see `SeekFn`).
+fn seek_forward<R: Seek>(reader: &mut R, n: i64) -> ::std::io::Result<()> {
+    reader.seek(SeekFrom::Current(n)).map(|_| ())
+}
+
+/// An iterator over the `HeaderBlock`s of a `BlobReader` that skips all other blobs (see
+/// [`BlobReader::header_blocks`](struct.BlobReader.html#method.header_blocks)).
+#[derive(Clone, Debug)]
+pub struct HeaderBlocks<R: Read> {
+    reader: BlobReader<R>,
+}
+
+impl<R: Read> HeaderBlocks<R> {
+    /// Returns a reference to the underlying `BlobReader`, e.g. to read its metrics.
+    pub fn get_ref(&self) -> &BlobReader<R> {
+        &self.reader
+    }
+
+    /// Consumes this adaptor and returns the underlying `BlobReader`.
+    pub fn into_inner(self) -> BlobReader<R> {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for HeaderBlocks<R> {
+    type Item = Result<HeaderBlock>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (header, header_size) = match self.reader.next_header()? {
+                Ok(h) => h,
+                Err(e) => return Some(Err(e)),
+            };
+            if header.get_field_type() != "OSMHeader" {
+                if let Err(e) = self.reader.skip_content(&header, header_size) {
+                    return Some(Err(e));
+                }
+                continue;
+            }
+            let blob = self.reader.read_content(&header, header_size);
+            if blob.is_ok() {
+                self.reader.count_blob(&header, header_size);
+            }
+            return Some(
+                blob.and_then(|blob| decode_blob(&blob))
+                    .map(HeaderBlock::new),
+            );
+        }
+    }
+}
+
+/// An iterator over the first blobs of a `BlobReader` (see
+/// [`BlobReader::take_blobs`](struct.BlobReader.html#method.take_blobs)).
+#[derive(Clone, Debug)]
+pub struct TakeBlobs<R: Read> {
+    reader: BlobReader<R>,
+    remaining: usize,
+}
+
+impl<R: Read> TakeBlobs<R> {
+    /// Returns a reference to the underlying `BlobReader`.
+    pub fn get_ref(&self) -> &BlobReader<R> {
+        &self.reader
+    }
+
+    /// Consumes this adaptor and returns the underlying `BlobReader`, which continues with the
+    /// blob after the last one that was returned.
+    pub fn into_inner(self) -> BlobReader<R> {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for TakeBlobs<R> {
+    type Item = Result<Blob>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.reader.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining))
+    }
+}
+
+/// A parallel iterator over the decoded blobs of a `BlobReader` and their offsets (see
+/// [`BlobReader::par_decode`](struct.BlobReader.html#method.par_decode)).
+#[derive(Debug)]
+pub struct ParDecode<R: Read + Seek> {
+    reader: BlobReader<R>,
+}
+
+impl<R: Read + Seek + Send> ParallelIterator for ParDecode<R> {
+    type Item = Result<(ByteOffset, BlobDecode<'static>)>;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        let mut reader = self.reader;
+        // A reader that was created with `BlobReader::new` does not know its offset yet, so it is
+        // taken from the stream position. Afterwards, every blob that is read successfully has
+        // an offset.
+        if reader.offset.is_none() {
+            match reader.reader.stream_position() {
+                Ok(pos) => reader.offset = Some(ByteOffset(pos)),
+                Err(e) => return ::rayon::iter::once(Err(e.into())).drive_unindexed(consumer),
+            }
+        }
+
+        reader
+            .map(|blob| {
+                blob.and_then(|blob| match blob.offset() {
+                    Some(offset) => Ok((offset, blob)),
+                    None => Err(::std::io::Error::new(
+                        ::std::io::ErrorKind::Other,
+                        "blob offset is unknown",
+                    )
+                    .into()),
+                })
+            })
+            .par_bridge()
+            .filter_map(|blob| {
+                let (offset, blob) = match blob {
+                    Ok(blob) => blob,
+                    Err(e) => return Some(Err(e)),
+                };
+                match blob.decode() {
+                    Ok(BlobDecode::OsmHeader(header)) => {
+                        Some(Ok((offset, BlobDecode::OsmHeader(header))))
+                    }
+                    Ok(BlobDecode::OsmData(block)) => {
+                        Some(Ok((offset, BlobDecode::OsmData(block))))
+                    }
+                    Ok(BlobDecode::Unknown(_)) => None,
+                    Err(e) => Some(Err(e)),
+                }
+            })
+            .drive_unindexed(consumer)
+    }
+}
+
+/// An adaptor for a `BlobReader` that resumes iteration after errors (see
+/// [`BlobReader::skip_errors`](struct.BlobReader.html#method.skip_errors)).
+#[derive(Clone, Debug)]
+pub struct SkipErrors<R: Read + Seek> {
+    reader: BlobReader<R>,
+    done: bool,
+}
+
+impl<R: Read + Seek> SkipErrors<R> {
+    /// Scans the stream after `failed_at` for the start of a blob and seeks to it. Returns
+    /// `false` if the end of the stream is reached without finding a blob.
+    fn resync(&mut self, failed_at: u64) -> Result<bool> {
+        let mut scan_pos = failed_at + 1;
+        let mut buf = vec![0u8; RESYNC_CHUNK_SIZE];
+
+        loop {
+            self.reader.seek(ByteOffset(scan_pos))?;
+            let len = read_up_to(&mut self.reader.reader, &mut buf)?;
+            let window = &buf[..len];
+
+            for i in 4..len {
+                let rest = &window[i..];
+                if rest.starts_with(b"\x0a\x07OSMData") || rest.starts_with(b"\x0a\x09OSMHeader") {
+                    let candidate = scan_pos + i as u64 - 4;
+                    if self.is_blob_start(candidate) {
+                        self.reader.seek(ByteOffset(candidate))?;
+                        self.reader.last_blob_ok = true;
+                        return Ok(true);
+                    }
+                }
+            }
+
+            if len < buf.len() {
+                // Reached the end of the stream.
+                return Ok(false);
+            }
+            scan_pos += (len - RESYNC_CHUNK_OVERLAP) as u64;
+        }
+    }
+
+    /// Checks if a valid blob header of a known type starts at the given position.
+    fn is_blob_start(&mut self, pos: u64) -> bool {
+        if self.reader.seek(ByteOffset(pos)).is_err() {
+            return false;
+        }
+        let header_size = match self.reader.reader.read_u32::<byteorder::BigEndian>() {
+            Ok(n) => u64::from(n),
+            Err(_) => return false,
+        };
+        if header_size == 0 || header_size >= MAX_BLOB_HEADER_SIZE {
+            return false;
+        }
+        let header: fileformat::BlobHeader =
+            match parse_message_from_reader(&mut self.reader.reader.by_ref().take(header_size)) {
+                Ok(header) => header,
+                Err(_) => return false,
+            };
+        let datasize = header.get_datasize();
+        match header.get_field_type() {
+            "OSMHeader" | "OSMData" => datasize > 0 && datasize as u64 <= MAX_BLOB_MESSAGE_SIZE,
+            _ => false,
+        }
+    }
+}
+
+impl<R: Read + Seek> Iterator for SkipErrors<R> {
+    type Item = Result<Blob>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let start = match self.reader.offset {
+            Some(offset) => offset.0,
+            None => match self.reader.seek_raw(SeekFrom::Current(0)) {
+                Ok(pos) => pos,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            },
+        };
+
+        match self.reader.next() {
+            Some(Err(e)) => {
+                log_warn!("skipping corrupt data at offset {}: {}", start, e);
+                match self.resync(start) {
+                    Ok(true) => {}
+                    Ok(false) | Err(_) => self.done = true,
+                }
+                Some(Err(e))
+            }
+            Some(Ok(blob)) => Some(Ok(blob)),
+            None => {
+                self.done = true;
+                None
+            }
+        }
+    }
+}
+
+/// Reads from `reader` until `buf` is full or the end of the stream is reached. Returns the
+/// number of bytes read.
+pub(crate) fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> ::std::io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(ref e) if e.kind() == ::std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
 }
 
 impl BlobReader<BufReader<File>> {
@@ -352,45 +1502,322 @@ impl BlobReader<BufReader<File>> {
     }
 }
 
-#[cfg(feature = "system-libz")]
+impl<'a> BlobReader<Cursor<&'a [u8]>> {
+    /// Creates a new seekable `BlobReader` that reads from a byte slice, e.g. a PBF file that is
+    /// already in memory. No `BufReader` is necessary, as reading from a slice is cheap.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let data = std::fs::read("tests/test.osm.pbf")?;
+    /// let mut reader = BlobReader::from_bytes(&data);
+    /// let first_blob = reader.next().unwrap()?;
+    ///
+    /// assert_eq!(first_blob.offset(), Some(ByteOffset(0)));
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn from_bytes(bytes: &'a [u8]) -> BlobReader<Cursor<&'a [u8]>> {
+        BlobReader {
+            reader: Cursor::new(bytes),
+            offset: Some(ByteOffset(0)),
+            start_offset: ByteOffset(0),
+            blob_index: Some(0),
+            last_blob_ok: true,
+            metrics: Arc::new(ReaderMetrics::default()),
+            seek: Some(SeekFn(seek_forward)),
+        }
+    }
+}
+
+/// A writer for the blob structure of PBF files.
+#[derive(Debug)]
+pub struct BlobWriter<W: Write> {
+    writer: W,
+    offset: u64,
+}
+
+impl<W: Write> BlobWriter<W> {
+    /// Creates a new `BlobWriter` that writes to the given writer.
+    pub fn new(writer: W) -> BlobWriter<W> {
+        BlobWriter { writer, offset: 0 }
+    }
+
+    /// Writes a blob that was read from a PBF file without decoding or re-compressing its
+    /// content. This makes it cheap to copy unchanged blobs from one file to another, e.g. in a
+    /// filter that only modifies a few blocks.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    /// let mut writer = BlobWriter::new(vec![]);
+    ///
+    /// for blob in reader {
+    ///     writer.write_raw_blob(&blob?)?;
+    /// }
+    ///
+    /// let copy = writer.into_inner();
+    /// assert_eq!(copy, std::fs::read("tests/test.osm.pbf")?);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn write_raw_blob(&mut self, blob: &Blob) -> Result<()> {
+        self.write_parts(blob.header.clone(), &blob.blob)
+    }
+
+    /// Compresses the given serialized message with zlib and writes it as a blob of the given
+    /// type. Use this to write blocks that were modified or built from scratch.
+    ///
+    /// # Errors
+    /// Returns `BlobError::MessageTooBig` if the message or the compressed blob is bigger than
+    /// `MAX_BLOB_MESSAGE_SIZE`, because readers would reject the blob. Also returns the errors of
+    /// the compression and of the underlying writer.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut writer = BlobWriter::new(vec![]);
+    /// writer.write_blob(BlobType::Unknown("Example"), b"some data")?;
+    ///
+    /// let bytes = writer.into_inner();
+    /// let blob = BlobReader::from_bytes(&bytes).next().unwrap()?;
+    /// assert_eq!(blob.get_type(), BlobType::Unknown("Example"));
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn write_blob(&mut self, blob_type: BlobType, data: &[u8]) -> Result<()> {
+        check_message_size(data.len())?;
+        let mut blob = fileformat::Blob::new();
+        blob.set_raw_size(data.len() as i32);
+        blob.set_zlib_data(zlib_compress(data)?);
+
+        let mut header = fileformat::BlobHeader::new();
+        header.set_field_type(blob_type.to_string());
+        self.write_parts(header, &blob)
+    }
+
+    fn write_parts(
+        &mut self,
+        mut header: fileformat::BlobHeader,
+        blob: &fileformat::Blob,
+    ) -> Result<()> {
+        let blob_bytes = blob
+            .write_to_bytes()
+            .map_err(|e| new_protobuf_error(e, "blob content"))?;
+        check_message_size(blob_bytes.len())?;
+        header.set_datasize(blob_bytes.len() as i32);
+        let header_bytes = header
+            .write_to_bytes()
+            .map_err(|e| new_protobuf_error(e, "blob header"))?;
+
+        self.writer
+            .write_u32::<byteorder::BigEndian>(header_bytes.len() as u32)?;
+        self.writer.write_all(&header_bytes)?;
+        self.writer.write_all(&blob_bytes)?;
+        self.offset += 4 + header_bytes.len() as u64 + blob_bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Returns the number of bytes that this `BlobWriter` has written so far. This is the offset
+    /// of the next blob if the underlying writer was empty at the start, e.g. to build an index
+    /// of the blobs while writing. Bytes that are written with `get_mut` are not counted.
+    pub fn offset(&self) -> ByteOffset {
+        ByteOffset(self.offset)
+    }
+
+    /// Returns a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Returns a mutable reference to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Consumes this `BlobWriter` and returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
 pub(crate) fn decode_blob<T>(blob: &fileformat::Blob) -> Result<T>
+where
+    T: protobuf::Message,
+{
+    decode_blob_with_limit(blob, MAX_BLOB_MESSAGE_SIZE)
+}
+
+pub(crate) fn decode_blob_with_limit<T>(blob: &fileformat::Blob, max_size: u64) -> Result<T>
 where
     T: protobuf::Message,
 {
     if blob.has_raw() {
         let size = blob.get_raw().len() as u64;
-        if size < MAX_BLOB_MESSAGE_SIZE {
+        if size <= max_size {
             parse_message_from_bytes(blob.get_raw())
                 .map_err(|e| new_protobuf_error(e, "raw blob data"))
         } else {
             Err(new_blob_error(BlobError::MessageTooBig { size }))
         }
     } else if blob.has_zlib_data() {
-        let mut decoder = ZlibDecoder::new(blob.get_zlib_data()).take(MAX_BLOB_MESSAGE_SIZE);
-        parse_message_from_reader(&mut decoder).map_err(|e| new_protobuf_error(e, "blob zlib data"))
+        let decoder = zlib_decoder(blob.get_zlib_data());
+        parse_limited_message(decoder, max_size, "blob zlib data")
     } else {
-        Err(new_blob_error(BlobError::Empty))
+        decode_optional_formats(blob, max_size)
     }
 }
 
-#[cfg(not(feature = "system-libz"))]
-pub(crate) fn decode_blob<T>(blob: &fileformat::Blob) -> Result<T>
-where
-    T: protobuf::Message,
-{
+/// Returns the decompressed content of a blob without parsing it.
+fn decompress_blob(blob: &fileformat::Blob, max_size: u64) -> Result<Vec<u8>> {
     if blob.has_raw() {
         let size = blob.get_raw().len() as u64;
-        if size < MAX_BLOB_MESSAGE_SIZE {
-            parse_message_from_bytes(blob.get_raw())
-                .map_err(|e| new_protobuf_error(e, "raw blob data"))
+        if size <= max_size {
+            Ok(blob.get_raw().to_vec())
         } else {
             Err(new_blob_error(BlobError::MessageTooBig { size }))
         }
     } else if blob.has_zlib_data() {
-        let mut decoder =
-            DeflateDecoder::from_zlib(blob.get_zlib_data()).take(MAX_BLOB_MESSAGE_SIZE);
-        parse_message_from_reader(&mut decoder).map_err(|e| new_protobuf_error(e, "blob zlib data"))
+        read_limited(zlib_decoder(blob.get_zlib_data()), blob, max_size)
+    } else {
+        decompress_optional_formats(blob, max_size)
+    }
+}
+
+#[cfg(feature = "system-libz")]
+fn zlib_decoder(data: &[u8]) -> ZlibDecoder<&[u8]> {
+    ZlibDecoder::new(data)
+}
+
+#[cfg(not(feature = "system-libz"))]
+fn zlib_decoder(data: &[u8]) -> ZlibReader<'_> {
+    ZlibReader::new(data)
+}
+
+#[cfg(feature = "system-libz")]
+fn zlib_compress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), ::flate2::Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+#[cfg(not(feature = "system-libz"))]
+fn zlib_compress(data: &[u8]) -> Result<Vec<u8>> {
+    Ok(compress_to_vec_zlib(data, 6))
+}
+
+/// Parses a message from a decompressing reader and aborts with `BlobError::MessageTooBig` as
+/// soon as more than `max_size` bytes are decompressed.
+fn parse_limited_message<R, T>(reader: R, max_size: u64, location: &'static str) -> Result<T>
+where
+    R: Read,
+    T: protobuf::Message,
+{
+    let mut reader = LimitedReader::new(reader, max_size);
+    parse_message_from_reader(&mut reader).map_err(|e| match reader.exceeded_size() {
+        Some(size) => new_blob_error(BlobError::MessageTooBig { size }),
+        None => new_protobuf_error(e, location),
+    })
+}
+
+/// Reads all bytes from a decompressing reader and aborts with `BlobError::MessageTooBig` as soon
+/// as more than `max_size` bytes are decompressed.
+fn read_limited<R: Read>(reader: R, blob: &fileformat::Blob, max_size: u64) -> Result<Vec<u8>> {
+    let capacity = u64::from(blob.get_raw_size().max(0) as u32).min(max_size);
+    let mut data = Vec::with_capacity(capacity as usize);
+    let mut reader = LimitedReader::new(reader, max_size);
+    match reader.read_to_end(&mut data) {
+        Ok(_) => Ok(data),
+        Err(e) => match reader.exceeded_size() {
+            Some(size) => Err(new_blob_error(BlobError::MessageTooBig { size })),
+            None => Err(e.into()),
+        },
+    }
+}
+
+/// Field number of `lz4_data` in newer versions of `fileformat.proto`.
+const LZ4_DATA_FIELD: u32 = 6;
+/// Field number of `zstd_data` in newer versions of `fileformat.proto`.
+const ZSTD_DATA_FIELD: u32 = 7;
+
+/// Decodes blob data with a compression format that is not always supported. Returns
+/// `BlobError::UnsupportedCompression` or `BlobError::Empty` if no enabled decoder applies.
+#[cfg(feature = "lzma")]
+fn decode_optional_formats<T>(blob: &fileformat::Blob, max_size: u64) -> Result<T>
+where
+    T: protobuf::Message,
+{
+    if blob.has_lzma_data() {
+        // The auto decoder accepts both the .xz and the legacy .lzma container format.
+        let stream = Stream::new_auto_decoder(u64::MAX, 0).map_err(::std::io::Error::from)?;
+        let decoder = XzDecoder::new_stream(blob.get_lzma_data(), stream);
+        parse_limited_message(decoder, max_size, "blob lzma data")
+    } else {
+        Err(unsupported_compression_error(blob))
+    }
+}
+
+/// Decompresses blob data with a compression format that is not always supported, like
+/// `decode_optional_formats`.
+#[cfg(feature = "lzma")]
+fn decompress_optional_formats(blob: &fileformat::Blob, max_size: u64) -> Result<Vec<u8>> {
+    if blob.has_lzma_data() {
+        let stream = Stream::new_auto_decoder(u64::MAX, 0).map_err(::std::io::Error::from)?;
+        let decoder = XzDecoder::new_stream(blob.get_lzma_data(), stream);
+        read_limited(decoder, blob, max_size)
+    } else {
+        Err(unsupported_compression_error(blob))
+    }
+}
+
+/// Decompresses blob data with a compression format that is not always supported, like
+/// `decode_optional_formats`.
+#[cfg(not(feature = "lzma"))]
+fn decompress_optional_formats(blob: &fileformat::Blob, _max_size: u64) -> Result<Vec<u8>> {
+    Err(unsupported_compression_error(blob))
+}
+
+/// Decodes blob data with a compression format that is not always supported. Returns
+/// `BlobError::UnsupportedCompression` or `BlobError::Empty` if no enabled decoder applies.
+#[cfg(not(feature = "lzma"))]
+fn decode_optional_formats<T>(blob: &fileformat::Blob, _max_size: u64) -> Result<T>
+where
+    T: protobuf::Message,
+{
+    Err(unsupported_compression_error(blob))
+}
+
+fn unsupported_compression_error(blob: &fileformat::Blob) -> Error {
+    let mut available = vec![];
+
+    if blob.has_lzma_data() && !cfg!(feature = "lzma") {
+        available.push("lzma_data");
+    }
+    if blob.has_OBSOLETE_bzip2_data() {
+        available.push("bzip2_data");
+    }
+    // These fields are not part of the generated code, so they end up as unknown fields.
+    let unknown_fields = blob.get_unknown_fields();
+    if unknown_fields.get(LZ4_DATA_FIELD).is_some() {
+        available.push("lz4_data");
+    }
+    if unknown_fields.get(ZSTD_DATA_FIELD).is_some() {
+        available.push("zstd_data");
+    }
+
+    if available.is_empty() {
+        new_blob_error(BlobError::Empty)
     } else {
-        Err(new_blob_error(BlobError::Empty))
+        new_blob_error(BlobError::UnsupportedCompression { available })
     }
 }
@@ -1,10 +1,21 @@
 //! `HeaderBlock`, `PrimitiveBlock` and `PrimitiveGroup`s
 
-use dense::DenseNodeIter;
-use elements::{Element, Node, Relation, Way};
+use coordinate::METERS_PER_DEGREE;
+use dense::{DenseNodeIter, DenseRaw};
+use elements::{raw_tag_equals, Element, Node, Relation, Way};
 use error::{new_error, ErrorKind, Result};
+use owned::{owned_elements, OwnedElement};
 use proto::osmformat;
+use rayon::iter::plumbing::{Consumer, ProducerCallback, UnindexedConsumer};
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
+};
 use std;
+use std::collections::{btree_set, BTreeSet, HashSet};
+use std::fmt;
+use std::ptr;
+use std::str::Utf8Error;
+use std::sync::OnceLock;
 
 /// A `HeaderBlock`. It contains metadata about following `PrimitiveBlock`s.
 #[derive(Clone, Debug)]
@@ -17,6 +28,12 @@ impl HeaderBlock {
         HeaderBlock { header }
     }
 
+    /// Returns the underlying protobuf message (see
+    /// [`PrimitiveBlock::as_proto`](struct.PrimitiveBlock.html#method.as_proto)).
+    pub fn as_proto(&self) -> &osmformat::HeaderBlock {
+        &self.header
+    }
+
     /// Returns a list of required features that a parser needs to implement to parse the following
     /// `PrimitiveBlock`s.
     pub fn required_features(&self) -> &[String] {
@@ -27,17 +44,74 @@ impl HeaderBlock {
     pub fn optional_features(&self) -> &[String] {
         self.header.get_optional_features()
     }
+
+    /// Returns `true` if the file contains historical information, i.e. the required features
+    /// include `"HistoricalInformation"`. Such history files can contain multiple versions of the
+    /// same element as well as deleted elements (see `Info::visible`), so tools that assume a
+    /// single current version per id should refuse or specially handle them.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    /// let header = reader.next().unwrap()?.to_headerblock()?;
+    ///
+    /// assert!(!header.has_historical_information());
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn has_historical_information(&self) -> bool {
+        self.required_features()
+            .iter()
+            .any(|feature| feature == "HistoricalInformation")
+    }
+
+    /// Consumes the block and returns the lists of required and optional features (in that
+    /// order). This avoids cloning the lists if the rest of the header is not needed anymore.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    /// let header = reader.next().unwrap()?.to_headerblock()?;
+    ///
+    /// let (required, optional) = header.into_features();
+    /// assert!(required.contains(&"OsmSchema-V0.6".to_string()));
+    /// assert!(optional.is_empty());
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn into_features(mut self) -> (Vec<String>, Vec<String>) {
+        (
+            self.header.take_required_features().into_vec(),
+            self.header.take_optional_features().into_vec(),
+        )
+    }
 }
 
 /// A `PrimitiveBlock`. It contains a sequence of groups.
 #[derive(Clone, Debug)]
 pub struct PrimitiveBlock {
     block: osmformat::PrimitiveBlock,
+    decoded_strings: OnceLock<std::result::Result<Vec<String>, (usize, Utf8Error)>>,
 }
 
 impl PrimitiveBlock {
-    pub(crate) fn new(block: osmformat::PrimitiveBlock) -> PrimitiveBlock {
-        PrimitiveBlock { block }
+    /// Wraps a decoded block after checking that its stringtable indices and the lengths of its
+    /// parallel arrays are consistent, so that a malformed block is rejected up front.
+    pub(crate) fn new(block: osmformat::PrimitiveBlock) -> Result<PrimitiveBlock> {
+        validate_block(&block)
+            .map_err(|reason| new_error(ErrorKind::InvalidPrimitiveBlock { reason }))?;
+        Ok(PrimitiveBlock {
+            block,
+            decoded_strings: OnceLock::new(),
+        })
     }
 
     /// Returns an iterator over the elements in this `PrimitiveBlock`.
@@ -45,11 +119,175 @@ impl PrimitiveBlock {
         BlockElementsIter::new(&self.block)
     }
 
+    /// Returns the elements in this `PrimitiveBlock` ordered by type (nodes, ways, relations) and
+    /// then by id. The file format does not guarantee any order of the elements within a block,
+    /// so use this for merge-join style processing instead of relying on the group layout.
+    /// Elements with the same type and id keep their relative order.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// for blob in reader {
+    ///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+    ///         let ids: Vec<_> = block
+    ///             .elements_sorted_by_id()
+    ///             .iter()
+    ///             .map(|e| e.osm_id())
+    ///             .collect();
+    ///         assert!(ids.windows(2).all(|w| w[0] <= w[1]));
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn elements_sorted_by_id(&self) -> Vec<Element<'_>> {
+        let mut elements: Vec<_> = self.elements().collect();
+        elements.sort_by_key(|e| e.osm_id());
+        elements
+    }
+
+    /// Returns an iterator over the elements in this `PrimitiveBlock` that have a tag with the
+    /// given key and, if `value` is not `None`, the given value. The key and value are looked up
+    /// in the stringtable once, so filtering only compares indices and does not decode any
+    /// strings.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// for blob in reader {
+    ///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+    ///         let buildings: Vec<_> = block
+    ///             .elements_with_tag("building", Some("yes"))
+    ///             .map(|e| e.osm_id())
+    ///             .collect();
+    ///         assert_eq!(buildings, [OsmId::Way(107)]);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn elements_with_tag(&self, key: &str, value: Option<&str>) -> TagFilterIter<'_> {
+        TagFilterIter {
+            elements: BlockElementsIter::new(&self.block),
+            matcher: TagMatcher::new(&self.block, key, value),
+        }
+    }
+
+    /// Returns a matcher that checks elements for the tag `key=value` (or any value if `value` is
+    /// `None`). The key and value are looked up in the stringtable once, which makes it faster
+    /// than calling `tag_equals` on each element of the block.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// for blob in reader {
+    ///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+    ///         let matcher = block.tag_matcher("building", Some("yes"));
+    ///         let buildings = block.elements().filter(|e| matcher.matches(e)).count();
+    ///         assert_eq!(buildings, 1);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn tag_matcher(&self, key: &str, value: Option<&str>) -> TagMatcher<'_> {
+        TagMatcher::new(&self.block, key, value)
+    }
+
     /// Returns an iterator over the groups in this `PrimitiveBlock`.
     pub fn groups(&self) -> GroupIter {
         GroupIter::new(&self.block)
     }
 
+    /// Returns the number of groups in this `PrimitiveBlock`.
+    pub fn num_groups(&self) -> usize {
+        self.block.get_primitivegroup().len()
+    }
+
+    /// Returns the group at the given index or `None` if the index is out of bounds. Together
+    /// with `num_groups` this allows to distribute the groups of a block to several threads,
+    /// which can be useful for files with few but large blobs.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// for blob in reader {
+    ///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+    ///         let block = &block;
+    ///         let way_counts: Vec<usize> = std::thread::scope(|s| {
+    ///             let handles: Vec<_> = (0..block.num_groups())
+    ///                 .map(|i| s.spawn(move || block.group(i).unwrap().ways().count()))
+    ///                 .collect();
+    ///             handles.into_iter().map(|h| h.join().unwrap()).collect()
+    ///         });
+    ///         assert_eq!(way_counts.iter().sum::<usize>(), 1);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn group(&self, index: usize) -> Option<PrimitiveGroup<'_>> {
+        self.block
+            .get_primitivegroup()
+            .get(index)
+            .map(|group| PrimitiveGroup::new(&self.block, group))
+    }
+
+    /// Returns the underlying protobuf message. This gives access to all fields of the block,
+    /// including those that are not wrapped by the high-level API.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// for blob in reader {
+    ///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+    ///         let proto: &proto::osmformat::PrimitiveBlock = block.as_proto();
+    ///         assert_eq!(proto.get_primitivegroup().len(), block.num_groups());
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn as_proto(&self) -> &osmformat::PrimitiveBlock {
+        &self.block
+    }
+
+    /// Returns `true` if at least one group of this block contains dense nodes. Writers usually
+    /// store all nodes of a file either as dense nodes (if the `DenseNodes` feature is listed in
+    /// the `HeaderBlock`) or as sparse nodes, so code that only handles nodes can use this to
+    /// skip one of the two representations.
+    pub fn uses_dense_nodes(&self) -> bool {
+        self.block
+            .get_primitivegroup()
+            .iter()
+            .any(|group| group.has_dense() && !group.get_dense().get_id().is_empty())
+    }
+
     /// Calls the given closure on each element.
     pub fn for_each_element<F>(&self, mut f: F)
     where
@@ -71,6 +309,306 @@ impl PrimitiveBlock {
         }
     }
 
+    /// Calls the given closure on each tag of each element in this block with the element id, the
+    /// key and the value. Every entry of the stringtable is decoded only once, so this is faster
+    /// than iterating over the tags of each element separately. Tags that reference invalid
+    /// strings are skipped.
+    ///
+    /// Note that ids are only unique per element type, so a node and a way might report the same
+    /// id.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    /// use std::collections::HashMap;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    /// let mut key_counts = HashMap::new();
+    ///
+    /// for blob in reader {
+    ///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+    ///         block.for_each_tag(|_id, key, _value| {
+    ///             *key_counts.entry(key.to_string()).or_insert(0) += 1;
+    ///         });
+    ///     }
+    /// }
+    ///
+    /// assert_eq!(key_counts["building"], 1);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn for_each_tag<F>(&self, mut f: F)
+    where
+        F: FnMut(i64, &str, &str),
+    {
+        let strings = DecodedStrings::new(self.raw_stringtable());
+        let mut visit = |id: i64, key: usize, val: usize| {
+            if let (Ok(k), Ok(v)) = (strings.get(key), strings.get(val)) {
+                f(id, k, v);
+            }
+        };
+
+        for group in self.block.get_primitivegroup() {
+            for node in group.get_nodes() {
+                for (&k, &v) in node.get_keys().iter().zip(node.get_vals()) {
+                    visit(node.get_id(), k as usize, v as usize);
+                }
+            }
+
+            let dense = group.get_dense();
+            let mut keys_vals = dense.get_keys_vals().iter();
+            let mut id = 0_i64;
+            for &did in dense.get_id() {
+                id = id.wrapping_add(did);
+                // The tags of each node are terminated by a single zero.
+                while let Some(&k) = keys_vals.next() {
+                    if k == 0 {
+                        break;
+                    }
+                    match keys_vals.next() {
+                        Some(&v) => visit(id, k as usize, v as usize),
+                        None => break,
+                    }
+                }
+            }
+
+            for way in group.get_ways() {
+                for (&k, &v) in way.get_keys().iter().zip(way.get_vals()) {
+                    visit(way.get_id(), k as usize, v as usize);
+                }
+            }
+            for rel in group.get_relations() {
+                for (&k, &v) in rel.get_keys().iter().zip(rel.get_vals()) {
+                    visit(rel.get_id(), k as usize, v as usize);
+                }
+            }
+        }
+    }
+
+    /// Calls the given closure once for each distinct pair of user id and user name that is
+    /// referenced by the metadata of the elements in this block. User names are interned in the
+    /// stringtable, so each name is decoded only once. Elements without a user id or user name are
+    /// ignored, as are names that reference invalid strings.
+    ///
+    /// The same pair is usually reported again by other blocks, so deduplicate across blocks if
+    /// needed.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    /// use std::collections::BTreeMap;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    /// let mut users = BTreeMap::new();
+    ///
+    /// for blob in reader {
+    ///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+    ///         block.for_each_uid_user(|uid, user| {
+    ///             users.insert(uid, user.to_string());
+    ///         });
+    ///     }
+    /// }
+    ///
+    /// assert_eq!(users[&17], "testuser");
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn for_each_uid_user<F>(&self, mut f: F)
+    where
+        F: FnMut(i32, &str),
+    {
+        let strings = DecodedStrings::new(self.raw_stringtable());
+        let mut seen = HashSet::new();
+        let mut visit = |uid: i32, user_sid: i32| {
+            if seen.insert((uid, user_sid)) {
+                if let Ok(user) = strings.get(user_sid as usize) {
+                    f(uid, user);
+                }
+            }
+        };
+
+        for group in self.block.get_primitivegroup() {
+            let infos = group
+                .get_nodes()
+                .iter()
+                .map(|node| node.get_info())
+                .chain(group.get_ways().iter().map(|way| way.get_info()))
+                .chain(group.get_relations().iter().map(|rel| rel.get_info()));
+            for info in infos {
+                if info.has_uid() && info.has_user_sid() {
+                    visit(info.get_uid(), info.get_user_sid() as i32);
+                }
+            }
+
+            let info = group.get_dense().get_denseinfo();
+            let (mut uid, mut user_sid) = (0_i32, 0_i32);
+            for (&duid, &duser_sid) in info.get_uid().iter().zip(info.get_user_sid()) {
+                uid = uid.wrapping_add(duid);
+                user_sid = user_sid.wrapping_add(duser_sid);
+                visit(uid, user_sid);
+            }
+        }
+    }
+
+    /// Returns the granularity of coordinates in nano-degrees. Coordinates of nodes in this block
+    /// are stored as multiples of this value (with an additional offset).
+    pub fn granularity(&self) -> i32 {
+        self.block.get_granularity()
+    }
+
+    /// Returns the ground distance in meters that one unit of the granularity of coordinates
+    /// spans at the equator, where it is largest. Coordinates in this block cannot be more precise
+    /// than this, so tools can warn if it is coarser than the accuracy that they need. The
+    /// default granularity of 100 nano-degrees corresponds to about 1.1 centimeters.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// for blob in reader {
+    ///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+    ///         let precision = block.effective_precision_meters();
+    ///         if precision > 0.1 {
+    ///             println!("warning: coordinates are rounded to {:.1} m", precision);
+    ///         }
+    ///         assert!((precision - 0.0111).abs() < 1e-4);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn effective_precision_meters(&self) -> f64 {
+        f64::from(self.granularity()) * 1e-9 * METERS_PER_DEGREE
+    }
+
+    /// Returns the latitude offset in nano-degrees that is added to all latitude coordinates.
+    pub fn lat_offset(&self) -> i64 {
+        self.block.get_lat_offset()
+    }
+
+    /// Returns the longitude offset in nano-degrees that is added to all longitude coordinates.
+    pub fn lon_offset(&self) -> i64 {
+        self.block.get_lon_offset()
+    }
+
+    /// Returns the granularity of time stamps in milliseconds. Time stamps of elements in this
+    /// block are stored as multiples of this value.
+    pub fn date_granularity(&self) -> i32 {
+        self.block.get_date_granularity()
+    }
+
+    /// Returns the minimum and maximum id of the nodes (and dense nodes) in this block or `None`
+    /// if it contains no nodes. The ids of dense nodes are computed as a running sum of the delta
+    /// coded values without decoding any other node data. That makes this cheap enough to build an
+    /// index that allows to skip blocks when looking up a node by id.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// for blob in reader {
+    ///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+    ///         assert_eq!(block.id_range(), Some((105, 108)));
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn id_range(&self) -> Option<(i64, i64)> {
+        let mut range: Option<(i64, i64)> = None;
+        let mut update = |id: i64| {
+            range = Some(match range {
+                Some((min, max)) => (min.min(id), max.max(id)),
+                None => (id, id),
+            });
+        };
+
+        for group in self.block.get_primitivegroup() {
+            for node in group.get_nodes() {
+                update(node.get_id());
+            }
+
+            let mut id = 0_i64;
+            for &delta in group.get_dense().get_id() {
+                id = id.wrapping_add(delta);
+                update(id);
+            }
+        }
+
+        range
+    }
+
+    /// Returns the distinct changeset ids of the elements in this block in ascending order.
+    /// Elements without a changeset id are skipped. Dense nodes are not decoded, only the delta
+    /// coded changeset ids are summed up.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// for blob in reader {
+    ///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+    ///         for changeset in block.changesets() {
+    ///             println!("changeset {}", changeset);
+    ///         }
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn changesets(&self) -> ChangesetIter {
+        let mut changesets = BTreeSet::new();
+
+        for group in self.block.get_primitivegroup() {
+            let infos = group
+                .get_nodes()
+                .iter()
+                .map(|node| node.get_info())
+                .chain(group.get_ways().iter().map(|way| way.get_info()))
+                .chain(group.get_relations().iter().map(|rel| rel.get_info()));
+            for info in infos {
+                if info.has_changeset() {
+                    changesets.insert(info.get_changeset());
+                }
+            }
+
+            let mut changeset = 0_i64;
+            for &delta in group.get_dense().get_denseinfo().get_changeset() {
+                changeset = changeset.wrapping_add(delta);
+                changesets.insert(changeset);
+            }
+        }
+
+        ChangesetIter {
+            changesets: changesets.into_iter(),
+        }
+    }
+
+    /// Returns `true` if at least one node (or dense node) in this block has coordinates outside
+    /// of the valid ranges of latitudes and longitudes (see `Node::coordinates_valid`).
+    pub fn has_invalid_coordinates(&self) -> bool {
+        self.groups().any(|group| {
+            group.nodes().any(|node| !node.coordinates_valid())
+                || group.dense_nodes().any(|dnode| !dnode.coordinates_valid())
+        })
+    }
+
     /// Returns the raw stringtable. Elements in a `PrimitiveBlock` do not store strings
     /// themselves; instead, they just store indices to the stringtable. By convention, the
     /// contained strings are UTF-8 encoded but it is not safe to assume that (use
@@ -78,6 +616,82 @@ impl PrimitiveBlock {
     pub fn raw_stringtable(&self) -> &[Vec<u8>] {
         self.block.get_stringtable().get_s()
     }
+
+    /// Returns the stringtable with all entries validated as UTF-8. The whole stringtable is
+    /// validated and copied on the first call and the result is cached in the block, so later
+    /// calls are cheap. For tag-heavy scans, indexing into this
+    /// slice with the indices of the raw tags (e.g. `Node::raw_tags`) avoids validating the same
+    /// strings again for every element.
+    ///
+    /// # Errors
+    /// Returns an error for the first entry that is not valid UTF-8.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// for blob in reader {
+    ///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+    ///         let strings = block.decoded_stringtable()?;
+    ///         for element in block.elements() {
+    ///             for (key, value) in element.raw_tags() {
+    ///                 println!("{}={}", strings[key as usize], strings[value as usize]);
+    ///             }
+    ///         }
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn decoded_stringtable(&self) -> Result<&[String]> {
+        let decoded = self.decoded_strings.get_or_init(|| {
+            self.raw_stringtable()
+                .iter()
+                .enumerate()
+                .map(|(index, bytes)| match std::str::from_utf8(bytes) {
+                    Ok(s) => Ok(s.to_string()),
+                    Err(err) => Err((index, err)),
+                })
+                .collect()
+        });
+        match *decoded {
+            Ok(ref strings) => Ok(strings),
+            Err((index, err)) => Err(new_error(ErrorKind::StringtableUtf8 { err, index })),
+        }
+    }
+
+    /// Consumes the block and returns owned copies of all contained elements. Each entry of the
+    /// stringtable is decoded only once, so this is cheaper than converting the elements one by
+    /// one. The result does not borrow from the block and can be sent to another thread.
+    ///
+    /// # Errors
+    /// Returns an error if an element references a stringtable entry that is out of bounds or not
+    /// valid UTF-8.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// for blob in reader {
+    ///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+    ///         let elements = block.into_owned_elements()?;
+    ///         println!("Decoded {} elements", elements.len());
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn into_owned_elements(self) -> Result<Vec<OwnedElement>> {
+        owned_elements(&self.block)
+    }
 }
 
 /// A `PrimitiveGroup` contains a sequence of elements of one type.
@@ -95,6 +709,11 @@ impl<'a> PrimitiveGroup<'a> {
         PrimitiveGroup { block, group }
     }
 
+    /// Returns an iterator over all elements in this group.
+    pub fn elements(&self) -> BlockElementsIter<'a> {
+        BlockElementsIter::from_groups(self.block, std::slice::from_ref(self.group))
+    }
+
     /// Returns an iterator over the nodes in this group.
     pub fn nodes(&self) -> GroupNodeIter<'a> {
         GroupNodeIter::new(self.block, self.group)
@@ -105,6 +724,46 @@ impl<'a> PrimitiveGroup<'a> {
         DenseNodeIter::new(self.block, self.group.get_dense())
     }
 
+    /// Returns the raw, still delta coded arrays of the dense nodes in this group or `None` if the
+    /// group does not contain dense nodes.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// for blob in reader {
+    ///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+    ///         for group in block.groups() {
+    ///             if let Some(dense) = group.dense_raw() {
+    ///                 // Decode the ids in bulk
+    ///                 let ids: Vec<i64> = dense
+    ///                     .ids()
+    ///                     .iter()
+    ///                     .scan(0, |id, delta| {
+    ///                         *id += delta;
+    ///                         Some(*id)
+    ///                     })
+    ///                     .collect();
+    ///                 assert_eq!(ids, [105, 106, 108]);
+    ///             }
+    ///         }
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn dense_raw(&self) -> Option<DenseRaw<'a>> {
+        if self.group.has_dense() {
+            Some(DenseRaw::new(self.group.get_dense()))
+        } else {
+            None
+        }
+    }
+
     /// Returns an iterator over the ways in this group.
     pub fn ways(&self) -> GroupWayIter<'a> {
         GroupWayIter::new(self.block, self.group)
@@ -116,7 +775,7 @@ impl<'a> PrimitiveGroup<'a> {
     }
 }
 
-/// An iterator over the elements in a `PrimitiveGroup`.
+/// An iterator over the elements in a `PrimitiveBlock` or a `PrimitiveGroup`.
 #[derive(Clone, Debug)]
 pub struct BlockElementsIter<'a> {
     block: &'a osmformat::PrimitiveBlock,
@@ -139,10 +798,17 @@ enum ElementsIterState {
 
 impl<'a> BlockElementsIter<'a> {
     fn new(block: &'a osmformat::PrimitiveBlock) -> BlockElementsIter<'a> {
+        BlockElementsIter::from_groups(block, block.get_primitivegroup())
+    }
+
+    fn from_groups(
+        block: &'a osmformat::PrimitiveBlock,
+        groups: &'a [osmformat::PrimitiveGroup],
+    ) -> BlockElementsIter<'a> {
         BlockElementsIter {
             block,
             state: ElementsIterState::Group,
-            groups: block.get_primitivegroup().iter(),
+            groups: groups.iter(),
             dense_nodes: DenseNodeIter::empty(block),
             nodes: [].iter(),
             ways: [].iter(),
@@ -210,6 +876,194 @@ impl<'a> Iterator for BlockElementsIter<'a> {
     }
 }
 
+/// Iterates over the elements of the block, just like
+/// [`PrimitiveBlock::elements`](struct.PrimitiveBlock.html#method.elements).
+impl<'a> IntoIterator for &'a PrimitiveBlock {
+    type Item = Element<'a>;
+    type IntoIter = BlockElementsIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.elements()
+    }
+}
+
+/// Iterates over the elements of the group, just like
+/// [`PrimitiveGroup::elements`](struct.PrimitiveGroup.html#method.elements).
+impl<'a> IntoIterator for &'a PrimitiveGroup<'a> {
+    type Item = Element<'a>;
+    type IntoIter = BlockElementsIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.elements()
+    }
+}
+
+/// Iterates over the groups of the block in parallel on the rayon thread pool. Within a single
+/// large block this spreads the work across threads, which per-blob parallelism cannot do for
+/// files that are written with few but large blocks.
+///
+/// # Example
+/// ```
+/// extern crate osmpbf;
+/// extern crate rayon;
+///
+/// use osmpbf::*;
+/// use rayon::prelude::*;
+///
+/// # fn foo() -> Result<()> {
+/// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+///
+/// for blob in reader {
+///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+///         let elements: usize = (&block)
+///             .into_par_iter()
+///             .map(|group| group.elements().count())
+///             .sum();
+///         assert_eq!(elements, 5);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+impl<'a> IntoParallelIterator for &'a PrimitiveBlock {
+    type Item = PrimitiveGroup<'a>;
+    type Iter = ParGroupIter<'a>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        ParGroupIter {
+            block: &self.block,
+            groups: self.block.get_primitivegroup(),
+        }
+    }
+}
+
+/// An iterator over the elements of a `PrimitiveBlock` with a given tag (see
+/// [`PrimitiveBlock::elements_with_tag`](struct.PrimitiveBlock.html#method.elements_with_tag)).
+///
+/// Like the other iterators of this crate it is a named type, so it can be stored in a struct.
+#[derive(Clone, Debug)]
+pub struct TagFilterIter<'a> {
+    elements: BlockElementsIter<'a>,
+    matcher: TagMatcher<'a>,
+}
+
+impl<'a> Iterator for TagFilterIter<'a> {
+    type Item = Element<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.matcher.can_match() {
+            return None;
+        }
+
+        let matcher = &self.matcher;
+        self.elements.find(|element| matcher.matches(element))
+    }
+}
+
+/// Checks elements of a `PrimitiveBlock` for a tag (see
+/// [`PrimitiveBlock::tag_matcher`](struct.PrimitiveBlock.html#method.tag_matcher)).
+///
+/// The key and value are resolved to stringtable indices once, so checking an element of the
+/// same block only compares integers. Elements of other blocks are compared byte by byte.
+#[derive(Clone, Debug)]
+pub struct TagMatcher<'a> {
+    stringtable: &'a [Vec<u8>],
+    key: String,
+    value: Option<String>,
+    key_indices: Vec<usize>,
+    value_indices: Option<Vec<usize>>,
+}
+
+impl<'a> TagMatcher<'a> {
+    fn new(block: &'a osmformat::PrimitiveBlock, key: &str, value: Option<&str>) -> Self {
+        let stringtable = block.get_stringtable().get_s();
+        // Stringtables usually contain each string only once, but this is not guaranteed.
+        let indices = |s: &str| -> Vec<usize> {
+            stringtable
+                .iter()
+                .enumerate()
+                .filter(|&(_, entry)| entry.as_slice() == s.as_bytes())
+                .map(|(i, _)| i)
+                .collect()
+        };
+
+        TagMatcher {
+            stringtable,
+            key: key.to_string(),
+            value: value.map(str::to_string),
+            key_indices: indices(key),
+            value_indices: value.map(indices),
+        }
+    }
+
+    /// Returns `false` if no element of the block can have the tag because the key or value is
+    /// missing from its stringtable.
+    pub fn can_match(&self) -> bool {
+        !self.key_indices.is_empty() && self.value_indices.as_ref().map_or(true, |v| !v.is_empty())
+    }
+
+    /// Returns `true` if the element has the tag. If `value` was `None`, any value matches.
+    pub fn matches(&self, element: &Element) -> bool {
+        match *element {
+            Element::Node(ref node) => self.matches_raw(
+                node.raw_stringtable(),
+                node.raw_tags().map(|(k, v)| (k as usize, v as usize)),
+            ),
+            Element::DenseNode(ref dnode) => self.matches_raw(
+                dnode.raw_stringtable(),
+                dnode.raw_tags().map(|(k, v)| (k as usize, v as usize)),
+            ),
+            Element::Way(ref way) => self.matches_raw(
+                way.raw_stringtable(),
+                way.raw_tags().map(|(k, v)| (k as usize, v as usize)),
+            ),
+            Element::Relation(ref rel) => self.matches_raw(
+                rel.raw_stringtable(),
+                rel.raw_tags().map(|(k, v)| (k as usize, v as usize)),
+            ),
+        }
+    }
+
+    fn matches_raw<I>(&self, stringtable: &[Vec<u8>], mut raw_tags: I) -> bool
+    where
+        I: Iterator<Item = (usize, usize)>,
+    {
+        if !ptr::eq(stringtable, self.stringtable) {
+            return raw_tag_equals(stringtable, raw_tags, &self.key, self.value.as_deref());
+        }
+
+        raw_tags.any(|(k, v)| {
+            self.key_indices.contains(&k)
+                && self
+                    .value_indices
+                    .as_ref()
+                    .map_or(true, |values| values.contains(&v))
+        })
+    }
+}
+
+/// An iterator over the distinct changeset ids of a `PrimitiveBlock` (see
+/// `PrimitiveBlock::changesets`).
+#[derive(Debug)]
+pub struct ChangesetIter {
+    changesets: btree_set::IntoIter<i64>,
+}
+
+impl Iterator for ChangesetIter {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.changesets.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.changesets.size_hint()
+    }
+}
+
+impl ExactSizeIterator for ChangesetIter {}
+
 /// An iterator over the groups in a `PrimitiveBlock`.
 #[derive(Clone, Debug)]
 pub struct GroupIter<'a> {
@@ -239,10 +1093,63 @@ impl<'a> Iterator for GroupIter<'a> {
     fn size_hint(&self) -> (usize, Option<usize>) {
         self.groups.size_hint()
     }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.groups
+            .nth(n)
+            .map(|g| PrimitiveGroup::new(self.block, g))
+    }
 }
 
 impl<'a> ExactSizeIterator for GroupIter<'a> {}
 
+/// A parallel iterator over the groups in a `PrimitiveBlock` (see the `IntoParallelIterator`
+/// implementation of `&PrimitiveBlock`).
+#[derive(Clone, Debug)]
+pub struct ParGroupIter<'a> {
+    block: &'a osmformat::PrimitiveBlock,
+    groups: &'a [osmformat::PrimitiveGroup],
+}
+
+impl<'a> ParGroupIter<'a> {
+    /// Maps the raw groups to `PrimitiveGroup`s, which the trait implementations delegate to.
+    fn groups(self) -> impl IndexedParallelIterator<Item = PrimitiveGroup<'a>> {
+        let block = self.block;
+        self.groups
+            .par_iter()
+            .map(move |group| PrimitiveGroup::new(block, group))
+    }
+}
+
+impl<'a> ParallelIterator for ParGroupIter<'a> {
+    type Item = PrimitiveGroup<'a>;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        self.groups().drive_unindexed(consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        Some(self.groups.len())
+    }
+}
+
+impl<'a> IndexedParallelIterator for ParGroupIter<'a> {
+    fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    fn drive<C: Consumer<Self::Item>>(self, consumer: C) -> C::Result {
+        self.groups().drive(consumer)
+    }
+
+    fn with_producer<CB: ProducerCallback<Self::Item>>(self, callback: CB) -> CB::Output {
+        self.groups().with_producer(callback)
+    }
+}
+
 /// An iterator over the nodes in a `PrimitiveGroup`.
 #[derive(Clone, Debug)]
 pub struct GroupNodeIter<'a> {
@@ -275,6 +1182,10 @@ impl<'a> Iterator for GroupNodeIter<'a> {
     fn size_hint(&self) -> (usize, Option<usize>) {
         self.nodes.size_hint()
     }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.nodes.nth(n).map(|node| Node::new(self.block, node))
+    }
 }
 
 impl<'a> ExactSizeIterator for GroupNodeIter<'a> {}
@@ -311,6 +1222,10 @@ impl<'a> Iterator for GroupWayIter<'a> {
     fn size_hint(&self) -> (usize, Option<usize>) {
         self.ways.size_hint()
     }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.ways.nth(n).map(|way| Way::new(self.block, way))
+    }
 }
 
 impl<'a> ExactSizeIterator for GroupWayIter<'a> {}
@@ -347,10 +1262,266 @@ impl<'a> Iterator for GroupRelationIter<'a> {
     fn size_hint(&self) -> (usize, Option<usize>) {
         self.rels.size_hint()
     }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.rels.nth(n).map(|rel| Relation::new(self.block, rel))
+    }
 }
 
 impl<'a> ExactSizeIterator for GroupRelationIter<'a> {}
 
+/// The strings of a stringtable, each one checked for valid UTF-8 exactly once. For lookups of
+/// only a few strings, `on_demand` skips decoding the whole stringtable up front.
+pub(crate) struct DecodedStrings<'a> {
+    stringtable: &'a [Vec<u8>],
+    strings: Option<Vec<std::result::Result<&'a str, std::str::Utf8Error>>>,
+}
+
+impl<'a> DecodedStrings<'a> {
+    pub(crate) fn new(stringtable: &'a [Vec<u8>]) -> DecodedStrings<'a> {
+        DecodedStrings {
+            stringtable,
+            strings: Some(stringtable.iter().map(|s| std::str::from_utf8(s)).collect()),
+        }
+    }
+
+    /// Decodes each string when it is requested instead.
+    pub(crate) fn on_demand(stringtable: &'a [Vec<u8>]) -> DecodedStrings<'a> {
+        DecodedStrings {
+            stringtable,
+            strings: None,
+        }
+    }
+
+    pub(crate) fn get(&self, index: usize) -> Result<&'a str> {
+        let decoded = match self.strings {
+            Some(ref strings) => strings.get(index).cloned(),
+            None => self.stringtable.get(index).map(|s| std::str::from_utf8(s)),
+        };
+        match decoded {
+            Some(Ok(s)) => Ok(s),
+            Some(Err(err)) => Err(new_error(ErrorKind::StringtableUtf8 { err, index })),
+            None => Err(new_error(ErrorKind::StringtableIndexOutOfBounds { index })),
+        }
+    }
+}
+
+/// Checks the references of a block that the protobuf format itself does not constrain:
+///
+/// * All stringtable indices (tag keys and values, user names and member roles) are in bounds.
+///   Delta coded indices of dense nodes are checked after decoding. User name index 0 is the
+///   empty string by convention and is accepted even if the stringtable is empty.
+/// * Arrays that describe the same elements have the same length, e.g. the keys and values of a
+///   way or the ids and coordinates of dense nodes.
+/// * The packed tags of dense nodes do not describe more nodes than there are ids.
+fn validate_block(block: &osmformat::PrimitiveBlock) -> std::result::Result<(), String> {
+    let strings = block.get_stringtable().get_s().len() as i64;
+
+    for group in block.get_primitivegroup() {
+        for node in group.get_nodes() {
+            let what = Subject::Element("node", node.get_id());
+            check_tags(node.get_keys(), node.get_vals(), strings, what)?;
+            check_info(node.get_info(), strings, what)?;
+        }
+
+        let dense = group.get_dense();
+        let ids = dense.get_id().len();
+        check_len(
+            dense.get_lat().len(),
+            ids,
+            Subject::Text("latitudes of dense nodes"),
+        )?;
+        check_len(
+            dense.get_lon().len(),
+            ids,
+            Subject::Text("longitudes of dense nodes"),
+        )?;
+        if dense.has_denseinfo() {
+            let info = dense.get_denseinfo();
+            check_len(
+                info.get_version().len(),
+                ids,
+                Subject::Text("versions of dense nodes"),
+            )?;
+            check_len(
+                info.get_timestamp().len(),
+                ids,
+                Subject::Text("time stamps of dense nodes"),
+            )?;
+            check_len(
+                info.get_changeset().len(),
+                ids,
+                Subject::Text("changesets of dense nodes"),
+            )?;
+            check_len(
+                info.get_uid().len(),
+                ids,
+                Subject::Text("user ids of dense nodes"),
+            )?;
+            check_len(
+                info.get_user_sid().len(),
+                ids,
+                Subject::Text("user names of dense nodes"),
+            )?;
+            if !info.get_visible().is_empty() {
+                check_len(
+                    info.get_visible().len(),
+                    ids,
+                    Subject::Text("visible flags of dense nodes"),
+                )?;
+            }
+            let mut user_sid = 0;
+            for delta in info.get_user_sid() {
+                user_sid += i64::from(*delta);
+                check_user_index(
+                    user_sid,
+                    strings,
+                    Subject::Text("user name of a dense node"),
+                )?;
+            }
+        }
+        let mut tagged_nodes = 0;
+        let mut keys_vals = dense.get_keys_vals().iter();
+        while let Some(&key) = keys_vals.next() {
+            if key == 0 {
+                tagged_nodes += 1;
+                continue;
+            }
+            check_index(
+                i64::from(key),
+                strings,
+                Subject::Text("tag key of a dense node"),
+            )?;
+            match keys_vals.next() {
+                Some(&value) => check_index(
+                    i64::from(value),
+                    strings,
+                    Subject::Text("tag value of a dense node"),
+                )?,
+                None => return Err("tags of dense nodes end with a key".to_string()),
+            }
+        }
+        if tagged_nodes > ids {
+            return Err(format!(
+                "tags of dense nodes describe {} nodes, but there are {} ids",
+                tagged_nodes, ids
+            ));
+        }
+
+        for way in group.get_ways() {
+            let what = Subject::Element("way", way.get_id());
+            check_tags(way.get_keys(), way.get_vals(), strings, what)?;
+            check_info(way.get_info(), strings, what)?;
+        }
+
+        for rel in group.get_relations() {
+            let id = rel.get_id();
+            let what = Subject::Element("relation", id);
+            check_tags(rel.get_keys(), rel.get_vals(), strings, what)?;
+            check_info(rel.get_info(), strings, what)?;
+            let members = rel.get_memids().len();
+            check_len(
+                rel.get_roles_sid().len(),
+                members,
+                Subject::ElementPart("roles", "relation", id),
+            )?;
+            check_len(
+                rel.get_types().len(),
+                members,
+                Subject::ElementPart("member types", "relation", id),
+            )?;
+            for role in rel.get_roles_sid() {
+                check_index(i64::from(*role), strings, what)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Describes what a check of `validate_block` refers to. It is only formatted if the check
+/// fails, so that valid blocks are validated without allocating.
+#[derive(Clone, Copy, Debug)]
+enum Subject {
+    /// A fixed description, e.g. "latitudes of dense nodes".
+    Text(&'static str),
+    /// An element type and id, e.g. "way 42".
+    Element(&'static str, i64),
+    /// A part of an element, e.g. "roles of relation 42".
+    ElementPart(&'static str, &'static str, i64),
+}
+
+impl fmt::Display for Subject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Subject::Text(text) => write!(f, "{}", text),
+            Subject::Element(kind, id) => write!(f, "{} {}", kind, id),
+            Subject::ElementPart(part, kind, id) => write!(f, "{} of {} {}", part, kind, id),
+        }
+    }
+}
+
+fn check_index(index: i64, strings: i64, what: Subject) -> std::result::Result<(), String> {
+    if index >= 0 && index < strings {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} references string {}, but the stringtable has {} entries",
+            what, index, strings
+        ))
+    }
+}
+
+/// Like `check_index`, but also accepts index 0, which denotes an empty user name.
+fn check_user_index(index: i64, strings: i64, what: Subject) -> std::result::Result<(), String> {
+    if index == 0 {
+        Ok(())
+    } else {
+        check_index(index, strings, what)
+    }
+}
+
+fn check_len(len: usize, expected: usize, what: Subject) -> std::result::Result<(), String> {
+    if len == expected {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} has {} entries instead of {}",
+            what, len, expected
+        ))
+    }
+}
+
+fn check_tags(
+    keys: &[u32],
+    vals: &[u32],
+    strings: i64,
+    what: Subject,
+) -> std::result::Result<(), String> {
+    if vals.len() != keys.len() {
+        return Err(format!(
+            "tag values of {} has {} entries instead of {}",
+            what,
+            vals.len(),
+            keys.len()
+        ));
+    }
+    for index in keys.iter().chain(vals) {
+        check_index(i64::from(*index), strings, what)?;
+    }
+    Ok(())
+}
+
+fn check_info(
+    info: &osmformat::Info,
+    strings: i64,
+    what: Subject,
+) -> std::result::Result<(), String> {
+    if info.has_user_sid() {
+        check_user_index(i64::from(info.get_user_sid()), strings, what)?;
+    }
+    Ok(())
+}
+
 pub(crate) fn str_from_stringtable(
     block: &osmformat::PrimitiveBlock,
     index: usize,
@@ -0,0 +1,72 @@
+//! Convert elements to columnar Apache Arrow record batches (requires the `arrow` feature)
+
+use arrow::array::{ArrayRef, Float64Builder, Int64Builder, MapBuilder, StringBuilder};
+use arrow::record_batch::RecordBatch;
+use block::{DecodedStrings, PrimitiveBlock};
+use error::Result;
+use std::sync::Arc;
+
+/// Converts all nodes (including dense nodes) of a `PrimitiveBlock` to an Arrow `RecordBatch`
+/// with the columns `id` (`Int64`), `lat` and `lon` (`Float64`, in degrees) and `tags` (a `Map`
+/// from `Utf8` keys to `Utf8` values). Ways and relations are ignored.
+///
+/// # Errors
+/// Returns an error if a tag references a stringtable entry that is out of bounds or not valid
+/// UTF-8.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let reader = BlobReader::from_path("tests/test.osm.pbf")?;
+///
+/// for blob in reader {
+///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+///         let batch = nodes_to_record_batch(&block)?;
+///         assert_eq!(batch.num_rows(), 3);
+///         assert_eq!(batch.num_columns(), 4);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+pub fn nodes_to_record_batch(block: &PrimitiveBlock) -> Result<RecordBatch> {
+    let strings = DecodedStrings::new(block.raw_stringtable());
+    let mut ids = Int64Builder::new();
+    let mut lats = Float64Builder::new();
+    let mut lons = Float64Builder::new();
+    let mut tags = MapBuilder::new(None, StringBuilder::new(), StringBuilder::new());
+
+    for group in block.groups() {
+        for node in group.nodes() {
+            ids.append_value(node.id());
+            lats.append_value(node.lat());
+            lons.append_value(node.lon());
+            for (k, v) in node.raw_tags() {
+                tags.keys().append_value(strings.get(k as usize)?);
+                tags.values().append_value(strings.get(v as usize)?);
+            }
+            tags.append(true).expect("same number of keys and values");
+        }
+        for node in group.dense_nodes() {
+            ids.append_value(node.id);
+            lats.append_value(node.lat());
+            lons.append_value(node.lon());
+            for (k, v) in node.raw_tags() {
+                tags.keys().append_value(strings.get(k as usize)?);
+                tags.values().append_value(strings.get(v as usize)?);
+            }
+            tags.append(true).expect("same number of keys and values");
+        }
+    }
+
+    let columns: Vec<(&str, ArrayRef)> = vec![
+        ("id", Arc::new(ids.finish())),
+        ("lat", Arc::new(lats.finish())),
+        ("lon", Arc::new(lons.finish())),
+        ("tags", Arc::new(tags.finish())),
+    ];
+    Ok(RecordBatch::try_from_iter(columns).expect("columns have the same length"))
+}
@@ -0,0 +1,104 @@
+//! Geographic coordinates and distances
+
+/// Mean radius of the earth in meters (IUGG).
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Length of one degree of latitude (or of longitude at the equator) in meters.
+pub(crate) const METERS_PER_DEGREE: f64 = EARTH_RADIUS * std::f64::consts::PI / 180.0;
+
+/// A geographic coordinate in degrees (WGS 84).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Coordinate {
+    /// The latitude in degrees.
+    pub lat: f64,
+    /// The longitude in degrees.
+    pub lon: f64,
+}
+
+impl Coordinate {
+    /// Creates a new `Coordinate` from latitude and longitude in degrees.
+    pub fn new(lat: f64, lon: f64) -> Coordinate {
+        Coordinate { lat, lon }
+    }
+
+    /// Returns the great-circle distance to `other` in meters, computed with the haversine
+    /// formula on a sphere with the mean radius of the earth. The error compared to the
+    /// ellipsoidal distance is below 0.5 %, which is good enough for way lengths or
+    /// nearest-neighbor searches, but not for surveying.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// let berlin = Coordinate::new(52.5186, 13.4081);
+    /// let paris = Coordinate::new(48.8567, 2.3508);
+    ///
+    /// let distance = berlin.haversine_distance(&paris);
+    /// assert!((distance - 877_500.0).abs() < 1_000.0);
+    /// ```
+    pub fn haversine_distance(&self, other: &Coordinate) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (other.lon - self.lon).to_radians();
+
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS * a.sqrt().min(1.0).asin()
+    }
+}
+
+/// A bounding box in degrees (WGS 84). Boxes that cross the antimeridian are not supported.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BBox {
+    /// The minimum (southern) latitude in degrees.
+    pub min_lat: f64,
+    /// The minimum (western) longitude in degrees.
+    pub min_lon: f64,
+    /// The maximum (northern) latitude in degrees.
+    pub max_lat: f64,
+    /// The maximum (eastern) longitude in degrees.
+    pub max_lon: f64,
+}
+
+impl BBox {
+    /// Creates a new `BBox` from the minimum and maximum latitude and longitude in degrees.
+    pub fn new(min_lat: f64, min_lon: f64, max_lat: f64, max_lon: f64) -> BBox {
+        BBox {
+            min_lat,
+            min_lon,
+            max_lat,
+            max_lon,
+        }
+    }
+
+    /// Returns `true` if the coordinate is inside the box or on its border.
+    pub fn contains(&self, coord: Coordinate) -> bool {
+        (self.min_lat..=self.max_lat).contains(&coord.lat)
+            && (self.min_lon..=self.max_lon).contains(&coord.lon)
+    }
+}
+
+/// Returns the length of a line through the given coordinates in meters, i.e. the sum of the
+/// haversine distances of all consecutive pairs (see `Coordinate::haversine_distance`). Returns
+/// zero for fewer than two coordinates.
+///
+/// A closed way already repeats its first node at the end, so no closing segment is added.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// let coords = [
+///     Coordinate::new(0.0, 0.0),
+///     Coordinate::new(0.0, 1.0),
+///     Coordinate::new(1.0, 1.0),
+/// ];
+///
+/// let length = way_length_meters(&coords);
+/// assert!((length - 2.0 * 111_195.0).abs() < 10.0);
+/// ```
+pub fn way_length_meters(coords: &[Coordinate]) -> f64 {
+    coords
+        .windows(2)
+        .map(|pair| pair[0].haversine_distance(&pair[1]))
+        .sum()
+}
@@ -0,0 +1,208 @@
+//! Remove repeated elements from a stream of elements
+
+use elements::{Element, ElementType, OsmId};
+use owned::OwnedElement;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// A set of `OsmId`s that `DedupByOsmId` uses to remember which elements it has already seen.
+///
+/// Implement this trait to use a more compact set for big inputs, e.g. a compressed bitmap.
+pub trait OsmIdSet {
+    /// Adds an id to the set. Returns `true` if the id was not present before.
+    fn insert(&mut self, id: OsmId) -> bool;
+
+    /// Returns `true` if the set contains the id.
+    fn contains(&self, id: OsmId) -> bool;
+}
+
+impl OsmIdSet for HashSet<OsmId> {
+    fn insert(&mut self, id: OsmId) -> bool {
+        HashSet::insert(self, id)
+    }
+
+    fn contains(&self, id: OsmId) -> bool {
+        HashSet::contains(self, &id)
+    }
+}
+
+impl OsmIdSet for BTreeSet<OsmId> {
+    fn insert(&mut self, id: OsmId) -> bool {
+        BTreeSet::insert(self, id)
+    }
+
+    fn contains(&self, id: OsmId) -> bool {
+        BTreeSet::contains(self, &id)
+    }
+}
+
+/// Number of 64-bit words in each page of an `OsmIdBitmap`, so a page covers 65536 ids.
+const BITMAP_PAGE_WORDS: usize = 1024;
+
+/// Number of ids that a page of an `OsmIdBitmap` covers.
+const BITMAP_PAGE_IDS: u64 = 64 * BITMAP_PAGE_WORDS as u64;
+
+/// An `OsmIdSet` that stores one bit per id for each element type. The bits are kept in pages of
+/// 65536 ids (8 KiB) that are only allocated once an id in their range is inserted, so the memory
+/// usage follows the occupied id ranges instead of the highest id. For the densely allocated ids
+/// of OpenStreetMap data this needs far less memory than a `HashSet` once a large fraction of the
+/// ids is present, e.g. about 1.5 GB for all node ids of a planet file. For a few scattered ids,
+/// each page costs 8 KiB, so a `HashSet` is smaller.
+#[derive(Clone, Debug, Default)]
+pub struct OsmIdBitmap {
+    nodes: BitmapPages,
+    ways: BitmapPages,
+    relations: BitmapPages,
+}
+
+/// The pages of an `OsmIdBitmap` for one element type, keyed by the id divided by
+/// `BITMAP_PAGE_IDS`. Negative ids are reinterpreted as `u64`, so they get pages of their own.
+type BitmapPages = HashMap<u64, Box<[u64; BITMAP_PAGE_WORDS]>>;
+
+impl OsmIdBitmap {
+    /// Creates an empty bitmap.
+    pub fn new() -> OsmIdBitmap {
+        OsmIdBitmap::default()
+    }
+
+    fn pages(&self, element_type: ElementType) -> &BitmapPages {
+        match element_type {
+            ElementType::Node => &self.nodes,
+            ElementType::Way => &self.ways,
+            ElementType::Relation => &self.relations,
+        }
+    }
+
+    fn pages_mut(&mut self, element_type: ElementType) -> &mut BitmapPages {
+        match element_type {
+            ElementType::Node => &mut self.nodes,
+            ElementType::Way => &mut self.ways,
+            ElementType::Relation => &mut self.relations,
+        }
+    }
+}
+
+/// Returns the page, word and bit mask of an id in an `OsmIdBitmap`.
+fn bitmap_position(id: i64) -> (u64, usize, u64) {
+    let id = id as u64;
+    let offset = id % BITMAP_PAGE_IDS;
+    (
+        id / BITMAP_PAGE_IDS,
+        (offset / 64) as usize,
+        1 << (offset % 64),
+    )
+}
+
+impl OsmIdSet for OsmIdBitmap {
+    fn insert(&mut self, id: OsmId) -> bool {
+        let (page, word, mask) = bitmap_position(id.id());
+        let words = self
+            .pages_mut(id.element_type())
+            .entry(page)
+            .or_insert_with(|| Box::new([0; BITMAP_PAGE_WORDS]));
+        let new = words[word] & mask == 0;
+        words[word] |= mask;
+        new
+    }
+
+    fn contains(&self, id: OsmId) -> bool {
+        let (page, word, mask) = bitmap_position(id.id());
+        self.pages(id.element_type())
+            .get(&page)
+            .is_some_and(|words| words[word] & mask != 0)
+    }
+}
+
+/// An element that is identified by an `OsmId`, so that it can be deduplicated by
+/// `DedupByOsmId`.
+pub trait HasOsmId {
+    /// Returns the type and id of the element.
+    fn osm_id(&self) -> OsmId;
+}
+
+impl<'a> HasOsmId for Element<'a> {
+    fn osm_id(&self) -> OsmId {
+        Element::osm_id(self)
+    }
+}
+
+impl HasOsmId for OwnedElement {
+    fn osm_id(&self) -> OsmId {
+        OwnedElement::osm_id(self)
+    }
+}
+
+/// An iterator adaptor that drops elements whose `OsmId` occurred before and keeps the first
+/// one. This is useful to merge several extracts that overlap. By default, the seen ids are
+/// stored in a `HashSet`; use `with_set` to choose a different `OsmIdSet` such as
+/// `OsmIdBitmap`.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let mut elements = vec![];
+/// for _ in 0..2 {
+///     for blob in BlobReader::from_path("tests/test.osm.pbf")? {
+///         if let BlobDecode::OsmData(block) = blob?.decode()? {
+///             elements.extend(block.into_owned_elements()?);
+///         }
+///     }
+/// }
+/// assert_eq!(elements.len(), 10);
+///
+/// let merged: Vec<_> = DedupByOsmId::new(elements.into_iter()).collect();
+/// assert_eq!(merged.len(), 5);
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct DedupByOsmId<I, S = HashSet<OsmId>> {
+    iter: I,
+    seen: S,
+}
+
+impl<I> DedupByOsmId<I> {
+    /// Creates an adaptor that stores the seen ids in a `HashSet`.
+    pub fn new(iter: I) -> DedupByOsmId<I> {
+        DedupByOsmId::with_set(iter, HashSet::new())
+    }
+}
+
+impl<I, S> DedupByOsmId<I, S> {
+    /// Creates an adaptor that stores the seen ids in the given set. Ids that are already in the
+    /// set are dropped as well, so a set can be reused to continue deduplicating a later stream.
+    pub fn with_set(iter: I, seen: S) -> DedupByOsmId<I, S> {
+        DedupByOsmId { iter, seen }
+    }
+
+    /// Returns the set of the ids that were seen so far.
+    pub fn seen(&self) -> &S {
+        &self.seen
+    }
+
+    /// Returns the set of the ids that were seen so far, e.g. to pass it to the adaptor of the
+    /// next input with `with_set`.
+    pub fn into_set(self) -> S {
+        self.seen
+    }
+}
+
+impl<I, S> Iterator for DedupByOsmId<I, S>
+where
+    I: Iterator,
+    I::Item: HasOsmId,
+    S: OsmIdSet,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let seen = &mut self.seen;
+        self.iter.find(|element| seen.insert(element.osm_id()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
@@ -1,12 +1,20 @@
 //! Iterate over the dense nodes in a `PrimitiveGroup`
 
 use block::str_from_stringtable;
+use coordinate::Coordinate;
+use elements::{raw_tag_equals, ElementRawTagIter, OsmId, TryTagIter};
 use error::Result;
 use proto::osmformat;
 use std;
+use std::hash::{Hash, Hasher};
+use util::{
+    checked_milli_timestamp, checked_nano_coordinate, nano_coordinates_valid, nano_to_micro_degrees,
+};
 
 //TODO Add getter functions for id, version, uid, ...
 /// An OpenStreetMap node element from a compressed array of dense nodes (See [OSM wiki](http://wiki.openstreetmap.org/wiki/Node)).
+///
+/// Dense nodes are compared and hashed by id only, just like `Node`s.
 #[derive(Clone, Debug)]
 pub struct DenseNode<'a> {
     block: &'a osmformat::PrimitiveBlock,
@@ -22,17 +30,33 @@ pub struct DenseNode<'a> {
     /// The user id.
     pub uid: i32,
     user_sid: i32,
+    visible: bool,
+    has_info: bool,
     lat: i64,
     lon: i64,
     keys_vals_indices: &'a [i32],
 }
 
 impl<'a> DenseNode<'a> {
+    /// Returns `true` if the group of this node contains metadata (`DenseInfo`). Otherwise the
+    /// version is -1 and the other metadata fields are 0.
+    pub fn has_info(&self) -> bool {
+        self.has_info
+    }
+
     /// Returns the user name.
     pub fn user(&self) -> Result<&'a str> {
         str_from_stringtable(self.block, self.user_sid as usize)
     }
 
+    pub(crate) fn user_sid(&self) -> i32 {
+        self.user_sid
+    }
+
+    pub(crate) fn raw_stringtable(&self) -> &'a [Vec<u8>] {
+        self.block.get_stringtable().get_s()
+    }
+
     /// Returns the latitude coordinate in degrees.
     pub fn lat(&self) -> f64 {
         0.000_000_001_f64 * self.lat_in_nano_degrees() as f64
@@ -43,19 +67,99 @@ impl<'a> DenseNode<'a> {
         0.000_000_001_f64 * self.lon_in_nano_degrees() as f64
     }
 
-    /// Returns the latitude coordinate in nano-degrees.
+    /// Returns the latitude and longitude in degrees as a `Coordinate` (see
+    /// [`Node::coordinate`](../elements/struct.Node.html#method.coordinate)).
+    pub fn coordinate(&self) -> Coordinate {
+        Coordinate::new(self.lat(), self.lon())
+    }
+
+    /// Returns the latitude coordinate in nano-degrees. The result wraps around on overflow (see
+    /// [`Node::lat_in_nano_degrees`](../elements/struct.Node.html#method.lat_in_nano_degrees)).
     pub fn lat_in_nano_degrees(&self) -> i64 {
-        self.block.get_lat_offset() + (i64::from(self.block.get_granularity()) * self.lat)
+        self.block
+            .get_lat_offset()
+            .wrapping_add(i64::from(self.block.get_granularity()).wrapping_mul(self.lat))
     }
 
-    /// Returns the longitude coordinate in nano-degrees.
+    /// Returns the longitude coordinate in nano-degrees. The result wraps around on overflow (see
+    /// [`Node::lon_in_nano_degrees`](../elements/struct.Node.html#method.lon_in_nano_degrees)).
     pub fn lon_in_nano_degrees(&self) -> i64 {
-        self.block.get_lon_offset() + (i64::from(self.block.get_granularity()) * self.lon)
+        self.block
+            .get_lon_offset()
+            .wrapping_add(i64::from(self.block.get_granularity()).wrapping_mul(self.lon))
     }
 
-    /// Returns the time stamp in milliseconds since the epoch.
+    /// Returns the latitude coordinate in nano-degrees, or an `ErrorKind::ArithmeticOverflow`
+    /// error if it overflows.
+    pub fn lat_in_nano_degrees_checked(&self) -> Result<i64> {
+        checked_nano_coordinate(
+            self.block.get_lat_offset(),
+            self.block.get_granularity(),
+            self.lat,
+            "latitude",
+        )
+    }
+
+    /// Returns the longitude coordinate in nano-degrees, or an `ErrorKind::ArithmeticOverflow`
+    /// error if it overflows.
+    pub fn lon_in_nano_degrees_checked(&self) -> Result<i64> {
+        checked_nano_coordinate(
+            self.block.get_lon_offset(),
+            self.block.get_granularity(),
+            self.lon,
+            "longitude",
+        )
+    }
+
+    /// Returns the latitude and longitude coordinates in micro-degrees, rounded to the nearest
+    /// value (see [`Node::lat_lon_micro`](../elements/struct.Node.html#method.lat_lon_micro)).
+    pub fn lat_lon_micro(&self) -> (i32, i32) {
+        (
+            nano_to_micro_degrees(self.lat_in_nano_degrees()),
+            nano_to_micro_degrees(self.lon_in_nano_degrees()),
+        )
+    }
+
+    /// Returns `true` if the coordinates are within the valid ranges of latitudes and longitudes
+    /// (see [`Node::coordinates_valid`](../elements/struct.Node.html#method.coordinates_valid)).
+    pub fn coordinates_valid(&self) -> bool {
+        nano_coordinates_valid(self.lat_in_nano_degrees(), self.lon_in_nano_degrees())
+    }
+
+    /// Returns the time stamp in milliseconds since the epoch. The result wraps around on
+    /// overflow (see `milli_timestamp_checked`).
     pub fn milli_timestamp(&self) -> i64 {
-        self.timestamp * i64::from(self.block.get_date_granularity())
+        self.timestamp
+            .wrapping_mul(i64::from(self.block.get_date_granularity()))
+    }
+
+    /// Returns the time stamp in milliseconds since the epoch, or an
+    /// `ErrorKind::ArithmeticOverflow` error if it overflows.
+    pub fn milli_timestamp_checked(&self) -> Result<i64> {
+        checked_milli_timestamp(self.timestamp, self.block.get_date_granularity())
+    }
+
+    /// Returns the visibility status. A node is only invisible (deleted) in files with
+    /// historical information; otherwise this is always `true`.
+    pub fn visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Returns `true` if this node has at least one tag (see
+    /// [`Node::is_tagged`](../elements/struct.Node.html#method.is_tagged)).
+    pub fn is_tagged(&self) -> bool {
+        !self.keys_vals_indices.is_empty()
+    }
+
+    /// Returns `true` if this node has a tag with the given key and value (see
+    /// [`Node::tag_equals`](../elements/struct.Node.html#method.tag_equals)).
+    pub fn tag_equals(&self, key: &str, value: &str) -> bool {
+        raw_tag_equals(
+            self.raw_stringtable(),
+            self.raw_tags().map(|(k, v)| (k as usize, v as usize)),
+            key,
+            Some(value),
+        )
     }
 
     /// Returns an iterator over the tags of this node (See [OSM wiki](http://wiki.openstreetmap.org/wiki/Tags)).
@@ -66,6 +170,16 @@ impl<'a> DenseNode<'a> {
         }
     }
 
+    /// Returns an iterator over the tags of this node that returns an error for tags that
+    /// reference invalid strings (see [`Element::try_tags`](../elements/enum.Element.html#method.try_tags)).
+    pub fn try_tags(&self) -> TryTagIter<'a> {
+        TryTagIter::new(
+            self.block,
+            OsmId::Node(self.id),
+            ElementRawTagIter::dense(self.raw_tags()),
+        )
+    }
+
     /// Returns an iterator over the tags of this node
     /// (See [OSM wiki](http://wiki.openstreetmap.org/wiki/Tags)).
     /// A tag is represented as a pair of indices (key and value) to the stringtable of the current
@@ -77,6 +191,89 @@ impl<'a> DenseNode<'a> {
     }
 }
 
+impl<'a> PartialEq for DenseNode<'a> {
+    fn eq(&self, other: &DenseNode<'a>) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<'a> Eq for DenseNode<'a> {}
+
+impl<'a> Hash for DenseNode<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+/// The raw arrays of the dense nodes in a `PrimitiveGroup`. This is useful for columnar
+/// processing where the arrays are decoded in bulk instead of node by node.
+///
+/// Most arrays are delta coded; the first value is stored as is and each following value as the
+/// difference to its predecessor. Only `versions` and `visible` are not delta coded.
+#[derive(Clone, Copy, Debug)]
+pub struct DenseRaw<'a> {
+    dense: &'a osmformat::DenseNodes,
+}
+
+impl<'a> DenseRaw<'a> {
+    pub(crate) fn new(dense: &'a osmformat::DenseNodes) -> DenseRaw<'a> {
+        DenseRaw { dense }
+    }
+
+    /// Returns the delta coded node ids.
+    pub fn ids(&self) -> &'a [i64] {
+        self.dense.get_id()
+    }
+
+    /// Returns the delta coded latitudes in units of the block granularity (without the offset).
+    pub fn lats(&self) -> &'a [i64] {
+        self.dense.get_lat()
+    }
+
+    /// Returns the delta coded longitudes in units of the block granularity (without the offset).
+    pub fn lons(&self) -> &'a [i64] {
+        self.dense.get_lon()
+    }
+
+    /// Returns the packed tags of all nodes. Stringtable indices of keys and values alternate and
+    /// the tags of each node are terminated by a single zero. This array is empty if none of the
+    /// nodes have tags.
+    pub fn keys_vals(&self) -> &'a [i32] {
+        self.dense.get_keys_vals()
+    }
+
+    /// Returns the versions (not delta coded).
+    pub fn versions(&self) -> &'a [i32] {
+        self.dense.get_denseinfo().get_version()
+    }
+
+    /// Returns the delta coded time stamps in units of the block date granularity.
+    pub fn timestamps(&self) -> &'a [i64] {
+        self.dense.get_denseinfo().get_timestamp()
+    }
+
+    /// Returns the delta coded changeset ids.
+    pub fn changesets(&self) -> &'a [i64] {
+        self.dense.get_denseinfo().get_changeset()
+    }
+
+    /// Returns the delta coded user ids.
+    pub fn uids(&self) -> &'a [i32] {
+        self.dense.get_denseinfo().get_uid()
+    }
+
+    /// Returns the delta coded stringtable indices of the user names.
+    pub fn user_sids(&self) -> &'a [i32] {
+        self.dense.get_denseinfo().get_user_sid()
+    }
+
+    /// Returns the visibility flags (not delta coded). This array is usually only present in
+    /// files with historical information.
+    pub fn visible(&self) -> &'a [bool] {
+        self.dense.get_denseinfo().get_visible()
+    }
+}
+
 /// An iterator over dense nodes. It decodes the delta encoded values.
 #[derive(Clone, Debug)]
 pub struct DenseNodeIter<'a> {
@@ -92,6 +289,8 @@ pub struct DenseNodeIter<'a> {
     cuid: i32,
     duser_sids: std::slice::Iter<'a, i32>, // deltas
     cuser_sid: i32,
+    visible: std::slice::Iter<'a, bool>,
+    has_info: bool,
     dlats: std::slice::Iter<'a, i64>, // deltas
     clat: i64,
     dlons: std::slice::Iter<'a, i64>, // deltas
@@ -119,6 +318,8 @@ impl<'a> DenseNodeIter<'a> {
             cuid: 0,
             duser_sids: info.get_user_sid().iter(),
             cuser_sid: 0,
+            visible: info.get_visible().iter(),
+            has_info: osmdense.has_denseinfo(),
             dlats: osmdense.get_lat().iter(),
             clat: 0,
             dlons: osmdense.get_lon().iter(),
@@ -142,6 +343,8 @@ impl<'a> DenseNodeIter<'a> {
             cuid: 0,
             duser_sids: [].iter(),
             cuser_sid: 0,
+            visible: [].iter(),
+            has_info: false,
             dlats: [].iter(),
             clat: 0,
             dlons: [].iter(),
@@ -156,61 +359,64 @@ impl<'a> Iterator for DenseNodeIter<'a> {
     type Item = DenseNode<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match (
-            self.dids.next(),
-            self.versions.next(),
-            self.dtimestamps.next(),
-            self.dchangesets.next(),
-            self.duids.next(),
-            self.duser_sids.next(),
-            self.dlats.next(),
-            self.dlons.next(),
-        ) {
-            (
-                Some(did),
-                Some(version),
-                Some(dtimestamp),
-                Some(dchangeset),
-                Some(duid),
-                Some(duser_sid),
-                Some(dlat),
-                Some(dlon),
-            ) => {
-                self.cid += *did;
-                self.ctimestamp += *dtimestamp;
-                self.cchangeset += *dchangeset;
-                self.cuid += *duid;
-                self.cuser_sid += *duser_sid;
-                self.clat += *dlat;
-                self.clon += *dlon;
-
-                let start_index = self.keys_vals_index;
-                let mut end_index = start_index;
-                for chunk in self.keys_vals_slice[self.keys_vals_index..].chunks(2) {
-                    if chunk[0] != 0 && chunk.len() == 2 {
-                        end_index += 2;
-                        self.keys_vals_index += 2;
-                    } else {
-                        self.keys_vals_index += 1;
-                        break;
-                    }
+        let (did, dlat, dlon) = match (self.dids.next(), self.dlats.next(), self.dlons.next()) {
+            (Some(did), Some(dlat), Some(dlon)) => (did, dlat, dlon),
+            _ => return None,
+        };
+
+        // Without `DenseInfo`, the metadata keeps its default values.
+        let mut version = -1;
+        if self.has_info {
+            match (
+                self.versions.next(),
+                self.dtimestamps.next(),
+                self.dchangesets.next(),
+                self.duids.next(),
+                self.duser_sids.next(),
+            ) {
+                (Some(v), Some(dtimestamp), Some(dchangeset), Some(duid), Some(duser_sid)) => {
+                    version = *v;
+                    self.ctimestamp = self.ctimestamp.wrapping_add(*dtimestamp);
+                    self.cchangeset = self.cchangeset.wrapping_add(*dchangeset);
+                    self.cuid = self.cuid.wrapping_add(*duid);
+                    self.cuser_sid = self.cuser_sid.wrapping_add(*duser_sid);
                 }
+                _ => return None,
+            }
+        }
 
-                Some(DenseNode {
-                    block: self.block,
-                    id: self.cid,
-                    version: *version,
-                    timestamp: self.ctimestamp,
-                    changeset: self.cchangeset,
-                    uid: self.cuid,
-                    user_sid: self.cuser_sid,
-                    lat: self.clat,
-                    lon: self.clon,
-                    keys_vals_indices: &self.keys_vals_slice[start_index..end_index],
-                })
+        // The sums wrap around like in release builds, so corrupt deltas cannot panic.
+        self.cid = self.cid.wrapping_add(*did);
+        self.clat = self.clat.wrapping_add(*dlat);
+        self.clon = self.clon.wrapping_add(*dlon);
+
+        let start_index = self.keys_vals_index;
+        let mut end_index = start_index;
+        for chunk in self.keys_vals_slice[self.keys_vals_index..].chunks(2) {
+            if chunk[0] != 0 && chunk.len() == 2 {
+                end_index += 2;
+                self.keys_vals_index += 2;
+            } else {
+                self.keys_vals_index += 1;
+                break;
             }
-            _ => None,
         }
+
+        Some(DenseNode {
+            block: self.block,
+            id: self.cid,
+            version,
+            timestamp: self.ctimestamp,
+            changeset: self.cchangeset,
+            uid: self.cuid,
+            user_sid: self.cuser_sid,
+            // The visible flags are usually only present in history files.
+            visible: self.visible.next().copied().unwrap_or(true),
+            has_info: self.has_info,
+            lat: self.clat,
+            lon: self.clon,
+            keys_vals_indices: &self.keys_vals_slice[start_index..end_index],
+        })
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
@@ -1,13 +1,25 @@
 //! Nodes, ways and relations
 
 use block::str_from_stringtable;
-use dense::DenseNode;
-use error::Result;
+use coordinate::Coordinate;
+use dense::{DenseNode, DenseRawTagIter, DenseTagIter};
+use error::{new_error, ErrorKind, Result, TagPart};
 use proto::osmformat;
 use proto::osmformat::PrimitiveBlock;
 use std;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use util::{
+    checked_milli_timestamp, checked_nano_coordinate, nano_coordinates_valid,
+    nano_to_micro_degrees, StableHasher,
+};
 
 /// An enum with the OSM core elements: nodes, ways and relations.
+///
+/// Elements are compared and hashed by their OSM identity, the element type and id, not by their
+/// contents. So two versions of the same way are equal, and a `Node` is equal to a `DenseNode`
+/// with the same id. This makes it possible to deduplicate elements with a `HashSet` when merging
+/// files.
 #[derive(Clone, Debug)]
 pub enum Element<'a> {
     /// A node. Also, see `DenseNode`.
@@ -25,7 +37,380 @@ pub enum Element<'a> {
     Relation(Relation<'a>),
 }
 
+impl<'a> Element<'a> {
+    /// Returns the type of this element. `Node` and `DenseNode` both have the type
+    /// `ElementType::Node`.
+    pub fn element_type(&self) -> ElementType {
+        match *self {
+            Element::Node(_) | Element::DenseNode(_) => ElementType::Node,
+            Element::Way(_) => ElementType::Way,
+            Element::Relation(_) => ElementType::Relation,
+        }
+    }
+
+    /// Returns the id of this element together with its type. Unlike a plain `i64` id, an
+    /// `OsmId` can be used as a key for elements of different types, since the id spaces of
+    /// nodes, ways and relations overlap.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    /// use std::collections::HashMap;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    /// let mut tag_counts = HashMap::new();
+    ///
+    /// reader.for_each(|element| {
+    ///     let count = match element {
+    ///         Element::Node(ref node) => node.tags().len(),
+    ///         Element::DenseNode(ref dnode) => dnode.tags().len(),
+    ///         Element::Way(ref way) => way.tags().len(),
+    ///         Element::Relation(ref rel) => rel.tags().len(),
+    ///     };
+    ///     tag_counts.insert(element.osm_id(), count);
+    /// })?;
+    ///
+    /// assert_eq!(tag_counts[&OsmId::Way(107)], 2);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn osm_id(&self) -> OsmId {
+        match *self {
+            Element::Node(ref node) => OsmId::Node(node.id()),
+            Element::DenseNode(ref dnode) => OsmId::Node(dnode.id),
+            Element::Way(ref way) => OsmId::Way(way.id()),
+            Element::Relation(ref rel) => OsmId::Relation(rel.id()),
+        }
+    }
+
+    /// Returns an iterator over the tags of this element, regardless of its type. A tag is
+    /// represented as a pair of strings (key and value).
+    pub fn tags(&self) -> ElementTagIter<'a> {
+        let inner = match *self {
+            Element::Node(ref node) => TagIterInner::Sparse(node.tags()),
+            Element::DenseNode(ref dnode) => TagIterInner::Dense(dnode.tags()),
+            Element::Way(ref way) => TagIterInner::Sparse(way.tags()),
+            Element::Relation(ref rel) => TagIterInner::Sparse(rel.tags()),
+        };
+        ElementTagIter { inner }
+    }
+
+    /// Returns an iterator over the tags of this element like `tags`, but instead of skipping
+    /// tags that reference invalid strings, it returns an `ErrorKind::InvalidTag` error that
+    /// names the element and the part of the tag (key or value) that is invalid.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// reader.for_each(|element| {
+    ///     for tag in element.try_tags() {
+    ///         match tag {
+    ///             Ok((key, value)) => println!("{}: {}={}", element.osm_id(), key, value),
+    ///             Err(e) => println!("{}", e),
+    ///         }
+    ///     }
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn try_tags(&self) -> TryTagIter<'a> {
+        match *self {
+            Element::Node(ref node) => node.try_tags(),
+            Element::DenseNode(ref dnode) => dnode.try_tags(),
+            Element::Way(ref way) => way.try_tags(),
+            Element::Relation(ref rel) => rel.try_tags(),
+        }
+    }
+
+    /// Returns an iterator over the tags of this element, regardless of its type. A tag is
+    /// represented as a pair of indices (key and value) to the stringtable of the current
+    /// `PrimitiveBlock` (see `PrimitiveBlock::raw_stringtable`).
+    pub fn raw_tags(&self) -> ElementRawTagIter<'a> {
+        let inner = match *self {
+            Element::Node(ref node) => RawTagIterInner::Sparse(node.raw_tags()),
+            Element::DenseNode(ref dnode) => RawTagIterInner::Dense(dnode.raw_tags()),
+            Element::Way(ref way) => RawTagIterInner::Sparse(way.raw_tags()),
+            Element::Relation(ref rel) => RawTagIterInner::Sparse(rel.raw_tags()),
+        };
+        ElementRawTagIter { inner }
+    }
+
+    /// Returns a fingerprint of the contents of this element: the element type, the id, the
+    /// tags, and the coordinates (nodes), node references (ways) or members (relations).
+    /// Metadata such as the version, timestamp or changeset is ignored, so two versions of an
+    /// element have the same hash unless the element was actually changed. Tags are hashed in
+    /// sorted order, so their order in the file does not matter, while the order of refs and
+    /// members is significant.
+    ///
+    /// The hash function is stable across platforms and releases of this crate, so hashes of
+    /// different snapshots can be stored and compared. Nodes and dense nodes with the same
+    /// contents have the same hash.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    /// use std::collections::HashMap;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut hashes = HashMap::new();
+    /// ElementReader::from_path("tests/test.osm.pbf")?.for_each(|element| {
+    ///     hashes.insert(element.osm_id(), element.content_hash());
+    /// })?;
+    ///
+    /// // Other encodings of the same data have the same hashes.
+    /// ElementReader::from_path("tests/test_nozlib_nodense.osm.pbf")?.for_each(|element| {
+    ///     assert_eq!(hashes[&element.osm_id()], element.content_hash());
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = StableHasher::new();
+        hasher.write(&[self.element_type() as u8]);
+        hasher.write_i64(self.osm_id().id());
+
+        let mut tags: Vec<_> = self.tags().collect();
+        tags.sort_unstable();
+        hasher.write_i64(tags.len() as i64);
+        for (key, value) in tags {
+            hasher.write_prefixed(key.as_bytes());
+            hasher.write_prefixed(value.as_bytes());
+        }
+
+        match *self {
+            Element::Node(ref node) => {
+                hasher.write_i64(node.lat_in_nano_degrees());
+                hasher.write_i64(node.lon_in_nano_degrees());
+            }
+            Element::DenseNode(ref dnode) => {
+                hasher.write_i64(dnode.lat_in_nano_degrees());
+                hasher.write_i64(dnode.lon_in_nano_degrees());
+            }
+            Element::Way(ref way) => {
+                hasher.write_i64(way.refs().len() as i64);
+                for node_id in way.refs() {
+                    hasher.write_i64(node_id);
+                }
+            }
+            Element::Relation(ref rel) => {
+                let stringtable = rel.raw_stringtable();
+                hasher.write_i64(rel.members().len() as i64);
+                for member in rel.members() {
+                    hasher.write(&[member.member_type as u8]);
+                    hasher.write_i64(member.member_id);
+                    hasher.write_prefixed(raw_role(stringtable, member.role_sid));
+                }
+            }
+        }
+        hasher.finish()
+    }
+
+    /// Returns `true` if both elements have the same contents, i.e. if `content_hash` would
+    /// return the same value for them, but without the chance of a hash collision: the element
+    /// type, the id, the tags, and the coordinates (nodes), node references (ways) or members
+    /// (relations) are compared, while the version, timestamp, changeset and user are ignored.
+    ///
+    /// Tags are compared regardless of their order. If an element contains the same key several
+    /// times, the other element must contain each tag equally often. The order of refs and
+    /// members is significant. A `Node` and a `DenseNode` can be semantically equal. Unlike
+    /// `==`, which only compares the type and id, this tells real data changes apart from
+    /// metadata churn when diffing two snapshots.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let block = BlobReader::from_path("tests/test.osm.pbf")?
+    ///     .filter_map(|blob| blob.ok()?.to_primitiveblock().ok())
+    ///     .next()
+    ///     .unwrap();
+    /// let elements: Vec<Element> = block.elements().collect();
+    ///
+    /// // The same data, but with sparse nodes
+    /// let mut index = 0;
+    /// ElementReader::from_path("tests/test_nozlib_nodense.osm.pbf")?.for_each(|element| {
+    ///     assert!(element.semantically_equals(&elements[index]));
+    ///     index += 1;
+    /// })?;
+    /// assert_eq!(index, elements.len());
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn semantically_equals(&self, other: &Element) -> bool {
+        if self.osm_id() != other.osm_id() {
+            return false;
+        }
+
+        let equal_contents = match (self, other) {
+            (Element::Way(a), Element::Way(b)) => a.refs().eq(b.refs()),
+            (Element::Relation(a), Element::Relation(b)) => {
+                let (table_a, table_b) = (a.raw_stringtable(), b.raw_stringtable());
+                a.members().len() == b.members().len()
+                    && a.members().zip(b.members()).all(|(x, y)| {
+                        x.member_type == y.member_type
+                            && x.member_id == y.member_id
+                            && raw_role(table_a, x.role_sid) == raw_role(table_b, y.role_sid)
+                    })
+            }
+            _ => self.nano_coordinates() == other.nano_coordinates(),
+        };
+        if !equal_contents {
+            return false;
+        }
+
+        // Sorting both lists compares them as multisets, so duplicate keys are handled.
+        let mut tags: Vec<_> = self.tags().collect();
+        let mut other_tags: Vec<_> = other.tags().collect();
+        tags.sort_unstable();
+        other_tags.sort_unstable();
+        tags == other_tags
+    }
+
+    /// Returns the latitude and longitude in nanodegrees or `None` if this is not a node.
+    fn nano_coordinates(&self) -> Option<(i64, i64)> {
+        match *self {
+            Element::Node(ref node) => {
+                Some((node.lat_in_nano_degrees(), node.lon_in_nano_degrees()))
+            }
+            Element::DenseNode(ref dnode) => {
+                Some((dnode.lat_in_nano_degrees(), dnode.lon_in_nano_degrees()))
+            }
+            Element::Way(_) | Element::Relation(_) => None,
+        }
+    }
+}
+
+/// Returns the role of a relation member as raw bytes, or an empty role if the index is out of
+/// bounds.
+fn raw_role(stringtable: &[Vec<u8>], role_sid: i32) -> &[u8] {
+    stringtable
+        .get(role_sid as usize)
+        .map_or(&[][..], |s| s.as_slice())
+}
+
+impl<'a> PartialEq for Element<'a> {
+    fn eq(&self, other: &Element<'a>) -> bool {
+        self.osm_id() == other.osm_id()
+    }
+}
+
+impl<'a> Eq for Element<'a> {}
+
+impl<'a> Hash for Element<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.osm_id().hash(state);
+    }
+}
+
+/// The type of an OSM core element. Unlike `Element` it does not distinguish between nodes and
+/// dense nodes.
+///
+/// The `Display` implementation prints the lowercase name as it is used in the OSM XML format
+/// (e.g. `"way"`).
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ElementType {
+    /// A node.
+    Node,
+    /// A way.
+    Way,
+    /// A relation.
+    Relation,
+}
+
+impl fmt::Display for ElementType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ElementType::Node => write!(f, "node"),
+            ElementType::Way => write!(f, "way"),
+            ElementType::Relation => write!(f, "relation"),
+        }
+    }
+}
+
+/// The id of an element together with its type. Nodes, ways and relations have separate id
+/// spaces, e.g. there can be a node with the id 5 and a way with the id 5, so an `OsmId` is a
+/// safe key for maps that contain elements of different types.
+///
+/// The `Display` implementation uses the common `type/id` notation, e.g. `"way/5"`.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum OsmId {
+    /// The id of a node.
+    Node(i64),
+    /// The id of a way.
+    Way(i64),
+    /// The id of a relation.
+    Relation(i64),
+}
+
+impl OsmId {
+    /// Returns the element type.
+    pub fn element_type(&self) -> ElementType {
+        match *self {
+            OsmId::Node(_) => ElementType::Node,
+            OsmId::Way(_) => ElementType::Way,
+            OsmId::Relation(_) => ElementType::Relation,
+        }
+    }
+
+    /// Returns the plain id without the element type.
+    pub fn id(&self) -> i64 {
+        match *self {
+            OsmId::Node(id) | OsmId::Way(id) | OsmId::Relation(id) => id,
+        }
+    }
+}
+
+impl fmt::Display for OsmId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.element_type(), self.id())
+    }
+}
+
+/// Parses an element type and id in the common OSM object notation. The type is given either as
+/// the full lowercase name followed by a slash (`"node/42"`, `"way/12345"`, `"relation/7"`) or as
+/// the single letter prefix `n`, `w` or `r` (`"n42"`, `"w12345"`, `"r7"`). Returns `None` if the
+/// string does not follow this notation.
+///
+/// This is the inverse of the `Display` implementation of `RelMember`.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// assert_eq!(parse_osm_id("node/42"), Some((ElementType::Node, 42)));
+/// assert_eq!(parse_osm_id("w12345"), Some((ElementType::Way, 12345)));
+/// assert_eq!(parse_osm_id("relation/7"), Some((ElementType::Relation, 7)));
+/// assert_eq!(parse_osm_id("area/7"), None);
+/// ```
+pub fn parse_osm_id(s: &str) -> Option<(ElementType, i64)> {
+    const PREFIXES: [(&str, ElementType); 6] = [
+        ("node/", ElementType::Node),
+        ("way/", ElementType::Way),
+        ("relation/", ElementType::Relation),
+        ("n", ElementType::Node),
+        ("w", ElementType::Way),
+        ("r", ElementType::Relation),
+    ];
+
+    PREFIXES.iter().find_map(|&(prefix, element_type)| {
+        let id = s.strip_prefix(prefix)?.parse().ok()?;
+        Some((element_type, id))
+    })
+}
+
 /// An OpenStreetMap node element (See [OSM wiki](http://wiki.openstreetmap.org/wiki/Node)).
+///
+/// Nodes are compared and hashed by id only (see [`Element`](enum.Element.html)).
 #[derive(Clone, Debug)]
 pub struct Node<'a> {
     block: &'a PrimitiveBlock,
@@ -74,6 +459,58 @@ impl<'a> Node<'a> {
         }
     }
 
+    /// Returns an iterator over the tags of this node that returns an error for tags that
+    /// reference invalid strings (see [`Element::try_tags`](enum.Element.html#method.try_tags)).
+    pub fn try_tags(&self) -> TryTagIter<'a> {
+        TryTagIter::new(
+            self.block,
+            OsmId::Node(self.id()),
+            ElementRawTagIter::sparse(self.raw_tags()),
+        )
+    }
+
+    /// Returns `true` if this node has at least one tag. Untagged nodes usually only serve as
+    /// geometry for ways. This is cheaper than checking `tags().next().is_some()` because no
+    /// strings have to be decoded.
+    pub fn is_tagged(&self) -> bool {
+        !self.osmnode.get_keys().is_empty()
+    }
+
+    /// Returns `true` if this node has a tag with the given key and value. The strings are
+    /// compared byte-wise with the stringtable, so no UTF-8 validation is necessary. This is
+    /// faster than searching the result of `tags()`, which validates every key and value. To
+    /// check many elements of the same block, use
+    /// [`PrimitiveBlock::tag_matcher`](../block/struct.PrimitiveBlock.html#method.tag_matcher),
+    /// which looks up the strings only once.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    /// let mut signals = 0_u64;
+    ///
+    /// reader.for_each(|element| {
+    ///     if let Element::Node(node) = element {
+    ///         if node.tag_equals("highway", "traffic_signals") {
+    ///             signals += 1;
+    ///         }
+    ///     }
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn tag_equals(&self, key: &str, value: &str) -> bool {
+        raw_tag_equals(
+            self.raw_stringtable(),
+            self.raw_tags().map(|(k, v)| (k as usize, v as usize)),
+            key,
+            Some(value),
+        )
+    }
+
     /// Returns additional metadata for this element.
     pub fn info(&self) -> Info<'a> {
         Info::new(self.block, self.osmnode.get_info())
@@ -89,16 +526,67 @@ impl<'a> Node<'a> {
         0.000_000_001_f64 * self.lon_in_nano_degrees() as f64
     }
 
-    /// Returns the latitude coordinate in nano-degrees.
+    /// Returns the latitude and longitude in degrees as a `Coordinate`.
+    pub fn coordinate(&self) -> Coordinate {
+        Coordinate::new(self.lat(), self.lon())
+    }
+
+    /// Returns the latitude coordinate in nano-degrees. For corrupt data the computation can
+    /// overflow, in which case the result wraps around (see `lat_in_nano_degrees_checked`).
     pub fn lat_in_nano_degrees(&self) -> i64 {
-        self.block.get_lat_offset()
-            + (i64::from(self.block.get_granularity()) * self.osmnode.get_lat())
+        self.block.get_lat_offset().wrapping_add(
+            i64::from(self.block.get_granularity()).wrapping_mul(self.osmnode.get_lat()),
+        )
     }
 
-    /// Returns the longitude coordinate in nano-degrees.
+    /// Returns the longitude coordinate in nano-degrees. For corrupt data the computation can
+    /// overflow, in which case the result wraps around (see `lon_in_nano_degrees_checked`).
     pub fn lon_in_nano_degrees(&self) -> i64 {
-        self.block.get_lon_offset()
-            + (i64::from(self.block.get_granularity()) * self.osmnode.get_lon())
+        self.block.get_lon_offset().wrapping_add(
+            i64::from(self.block.get_granularity()).wrapping_mul(self.osmnode.get_lon()),
+        )
+    }
+
+    /// Returns the latitude coordinate in nano-degrees, or an `ErrorKind::ArithmeticOverflow`
+    /// error if the offset and granularity of the block make it overflow.
+    pub fn lat_in_nano_degrees_checked(&self) -> Result<i64> {
+        checked_nano_coordinate(
+            self.block.get_lat_offset(),
+            self.block.get_granularity(),
+            self.osmnode.get_lat(),
+            "latitude",
+        )
+    }
+
+    /// Returns the longitude coordinate in nano-degrees, or an `ErrorKind::ArithmeticOverflow`
+    /// error if the offset and granularity of the block make it overflow.
+    pub fn lon_in_nano_degrees_checked(&self) -> Result<i64> {
+        checked_nano_coordinate(
+            self.block.get_lon_offset(),
+            self.block.get_granularity(),
+            self.osmnode.get_lon(),
+            "longitude",
+        )
+    }
+
+    /// Returns the latitude and longitude coordinates in micro-degrees (1e-6 degrees, about
+    /// 0.1 m). Two `i32`s need half the memory of two `f64`s, which makes this representation
+    /// useful for compact in-memory indexes. Values are rounded to the nearest micro-degree.
+    ///
+    /// Coordinates outside of the `i32` range cannot occur for valid latitudes and longitudes.
+    /// For corrupt data such values are saturated to `i32::MIN` or `i32::MAX`.
+    pub fn lat_lon_micro(&self) -> (i32, i32) {
+        (
+            nano_to_micro_degrees(self.lat_in_nano_degrees()),
+            nano_to_micro_degrees(self.lon_in_nano_degrees()),
+        )
+    }
+
+    /// Returns `true` if the latitude is within [-90, 90] degrees and the longitude is within
+    /// [-180, 180] degrees. Files written with a wrong offset or granularity can contain
+    /// coordinates outside of these ranges.
+    pub fn coordinates_valid(&self) -> bool {
+        nano_coordinates_valid(self.lat_in_nano_degrees(), self.lon_in_nano_degrees())
     }
 
     /// Returns an iterator over the tags of this node
@@ -122,10 +610,24 @@ impl<'a> Node<'a> {
     }
 }
 
+impl<'a> PartialEq for Node<'a> {
+    fn eq(&self, other: &Node<'a>) -> bool {
+        self.id() == other.id()
+    }
+}
+
+impl<'a> Eq for Node<'a> {}
+
+impl<'a> Hash for Node<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id().hash(state);
+    }
+}
+
 /// An OpenStreetMap way element (See [OSM wiki](http://wiki.openstreetmap.org/wiki/Way)).
 ///
 /// A way contains an ordered list of node references that can be accessed with the `refs` or the
-/// `raw_refs` method.
+/// `raw_refs` method. Ways are compared and hashed by id only.
 #[derive(Clone, Debug)]
 pub struct Way<'a> {
     block: &'a PrimitiveBlock,
@@ -173,6 +675,27 @@ impl<'a> Way<'a> {
         }
     }
 
+    /// Returns an iterator over the tags of this way that returns an error for tags that
+    /// reference invalid strings (see [`Element::try_tags`](enum.Element.html#method.try_tags)).
+    pub fn try_tags(&self) -> TryTagIter<'a> {
+        TryTagIter::new(
+            self.block,
+            OsmId::Way(self.id()),
+            ElementRawTagIter::sparse(self.raw_tags()),
+        )
+    }
+
+    /// Returns `true` if this way has a tag with the given key and value (see
+    /// [`Node::tag_equals`](struct.Node.html#method.tag_equals)).
+    pub fn tag_equals(&self, key: &str, value: &str) -> bool {
+        raw_tag_equals(
+            self.raw_stringtable(),
+            self.raw_tags().map(|(k, v)| (k as usize, v as usize)),
+            key,
+            Some(value),
+        )
+    }
+
     /// Returns additional metadata for this element.
     pub fn info(&self) -> Info<'a> {
         Info::new(self.block, self.osmway.get_info())
@@ -187,11 +710,119 @@ impl<'a> Way<'a> {
         }
     }
 
+    /// Returns an iterator over the references of this way that checks each decoded node id.
+    /// Node ids in uploaded data are positive, so if the running sum of the delta coded ids
+    /// becomes negative (or overflows), an `ErrorKind::InvalidNodeRef` is returned and the
+    /// iteration stops. Use `refs` if the data is trusted.
+    ///
+    /// Note that files with elements that have not been uploaded to a server yet may legitimately
+    /// contain negative ids.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    /// let mut refs = vec![];
+    ///
+    /// reader.for_each(|element| {
+    ///     if let Element::Way(way) = element {
+    ///         refs = way.try_refs().collect::<Result<Vec<_>>>().unwrap();
+    ///     }
+    /// })?;
+    ///
+    /// assert_eq!(refs, [105, 106, 108, 105]);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn try_refs(&self) -> TryWayRefIter<'a> {
+        TryWayRefIter {
+            deltas: self.osmway.get_refs().iter(),
+            current: 0,
+            way_id: self.osmway.get_id(),
+            index: 0,
+            failed: false,
+        }
+    }
+
+    /// Returns `true` if the same node id occurs twice in a row in the references of this way.
+    /// Such ways are valid in the file format, but zero-length segments break many geometry
+    /// consumers. Since the references are delta coded, no ids need to be decoded for this check.
+    pub fn has_duplicate_consecutive_refs(&self) -> bool {
+        self.osmway
+            .get_refs()
+            .iter()
+            .skip(1)
+            .any(|&delta| delta == 0)
+    }
+
+    /// Returns an iterator over the references of this way like `refs`, but collapses runs of the
+    /// same node id into a single reference. Closed ways stay closed, since only consecutive
+    /// duplicates are removed.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// reader.for_each(|element| {
+    ///     if let Element::Way(way) = element {
+    ///         assert!(!way.has_duplicate_consecutive_refs());
+    ///         assert_eq!(way.dedup_refs().collect::<Vec<_>>(), [105, 106, 108, 105]);
+    ///     }
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn dedup_refs(&self) -> DedupWayRefIter<'a> {
+        DedupWayRefIter {
+            deltas: self.osmway.get_refs().iter(),
+            current: 0,
+            first: true,
+        }
+    }
+
     /// Returns a slice of delta coded node ids.
     pub fn raw_refs(&self) -> &[i64] {
         self.osmway.get_refs()
     }
 
+    /// Appends the decoded node ids of this way to `out`, like `out.extend(self.refs())`. Reusing
+    /// one buffer for many ways avoids an allocation per way.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    /// let mut refs = Vec::new();
+    ///
+    /// reader.for_each(|element| {
+    ///     if let Element::Way(way) = element {
+    ///         refs.clear();
+    ///         way.decoded_refs_into(&mut refs);
+    ///         assert_eq!(refs, [105, 106, 108, 105]);
+    ///     }
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn decoded_refs_into(&self, out: &mut Vec<i64>) {
+        out.reserve(self.osmway.get_refs().len());
+        let mut current = 0_i64;
+        for &delta in self.osmway.get_refs() {
+            current = current.wrapping_add(delta);
+            out.push(current);
+        }
+    }
+
     /// Returns an iterator over the tags of this way
     /// (See [OSM wiki](http://wiki.openstreetmap.org/wiki/Tags)).
     /// A tag is represented as a pair of indices (key and value) to the stringtable of the current
@@ -213,9 +844,24 @@ impl<'a> Way<'a> {
     }
 }
 
+impl<'a> PartialEq for Way<'a> {
+    fn eq(&self, other: &Way<'a>) -> bool {
+        self.id() == other.id()
+    }
+}
+
+impl<'a> Eq for Way<'a> {}
+
+impl<'a> Hash for Way<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id().hash(state);
+    }
+}
+
 /// An OpenStreetMap relation element (See [OSM wiki](http://wiki.openstreetmap.org/wiki/Relation)).
 ///
-/// A relation contains an ordered list of members that can be of any element type.
+/// A relation contains an ordered list of members that can be of any element type. Relations are
+/// compared and hashed by id only.
 #[derive(Clone, Debug)]
 pub struct Relation<'a> {
     block: &'a PrimitiveBlock,
@@ -263,6 +909,57 @@ impl<'a> Relation<'a> {
         }
     }
 
+    /// Returns an iterator over the tags of this relation that returns an error for tags that
+    /// reference invalid strings (see [`Element::try_tags`](enum.Element.html#method.try_tags)).
+    pub fn try_tags(&self) -> TryTagIter<'a> {
+        TryTagIter::new(
+            self.block,
+            OsmId::Relation(self.id()),
+            ElementRawTagIter::sparse(self.raw_tags()),
+        )
+    }
+
+    /// Returns `true` if this relation has a tag with the given key and value (see
+    /// [`Node::tag_equals`](struct.Node.html#method.tag_equals)).
+    pub fn tag_equals(&self, key: &str, value: &str) -> bool {
+        raw_tag_equals(
+            self.raw_stringtable(),
+            self.raw_tags().map(|(k, v)| (k as usize, v as usize)),
+            key,
+            Some(value),
+        )
+    }
+
+    /// Returns the value of the `type` tag, which tells how the members of this relation are to
+    /// be interpreted (see [OSM wiki](https://wiki.openstreetmap.org/wiki/Types_of_relation)).
+    /// Convert it to a `RelationType` to match the common types.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// reader.for_each(|element| {
+    ///     if let Element::Relation(rel) = element {
+    ///         match rel.relation_type().map(RelationType::from) {
+    ///             Some(RelationType::Multipolygon) => println!("area {}", rel.id()),
+    ///             Some(other) => println!("{} relation {}", other, rel.id()),
+    ///             None => println!("relation {} has no type", rel.id()),
+    ///         }
+    ///     }
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn relation_type(&self) -> Option<&'a str> {
+        self.tags()
+            .find(|&(key, _)| key == "type")
+            .map(|(_, value)| value)
+    }
+
     /// Returns additional metadata for this element.
     pub fn info(&self) -> Info<'a> {
         Info::new(self.block, self.osmrel.get_info())
@@ -273,6 +970,70 @@ impl<'a> Relation<'a> {
         RelMemberIter::new(self.block, self.osmrel)
     }
 
+    /// Returns the type, id and role of each member, with the roles copied into owned strings.
+    /// Unlike `members()`, the result does not borrow from the `PrimitiveBlock`, so it can be
+    /// stored after the block is dropped. Invalid UTF-8 in a role is replaced with
+    /// `U+FFFD REPLACEMENT CHARACTER`.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    /// let mut memberships = Vec::new();
+    ///
+    /// reader.for_each(|element| {
+    ///     if let Element::Relation(rel) = element {
+    ///         memberships.push((rel.id(), rel.owned_members()));
+    ///     }
+    /// })?;
+    ///
+    /// assert_eq!(memberships[0].1[0].0, RelMemberType::Way);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn owned_members(&self) -> Vec<(RelMemberType, i64, String)> {
+        let stringtable = self.raw_stringtable();
+        self.members()
+            .map(|member| {
+                let role = stringtable
+                    .get(member.role_sid as usize)
+                    .map(|bytes| String::from_utf8_lossy(bytes).into_owned())
+                    .unwrap_or_default();
+                (member.member_type, member.member_id, role)
+            })
+            .collect()
+    }
+
+    /// Returns an iterator over the roles of the members of this relation, in member order. This
+    /// is a shortcut for `members().map(|m| m.role())` that does not decode the member ids.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// reader.for_each(|element| {
+    ///     if let Element::Relation(rel) = element {
+    ///         let has_outer = rel.roles().any(|role| role.ok() == Some("outer"));
+    ///         println!("relation {} has an outer member: {}", rel.id(), has_outer);
+    ///     }
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn roles(&self) -> RelRoleIter<'a> {
+        RelRoleIter {
+            block: self.block,
+            role_sids: self.osmrel.get_roles_sid().iter(),
+        }
+    }
+
     /// Returns an iterator over the tags of this relation
     /// (See [OSM wiki](http://wiki.openstreetmap.org/wiki/Tags)).
     /// A tag is represented as a pair of indices (key and value) to the stringtable of the current
@@ -294,6 +1055,20 @@ impl<'a> Relation<'a> {
     }
 }
 
+impl<'a> PartialEq for Relation<'a> {
+    fn eq(&self, other: &Relation<'a>) -> bool {
+        self.id() == other.id()
+    }
+}
+
+impl<'a> Eq for Relation<'a> {}
+
+impl<'a> Hash for Relation<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id().hash(state);
+    }
+}
+
 /// An iterator over the references of a way.
 ///
 /// Each reference corresponds to a node id.
@@ -309,7 +1084,7 @@ impl<'a> Iterator for WayRefIter<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         match self.deltas.next() {
             Some(&d) => {
-                self.current += d;
+                self.current = self.current.wrapping_add(d);
                 Some(self.current)
             }
             None => None,
@@ -323,8 +1098,84 @@ impl<'a> Iterator for WayRefIter<'a> {
 
 impl<'a> ExactSizeIterator for WayRefIter<'a> {}
 
+/// An iterator over the references of a way without consecutive duplicates (see
+/// [`Way::dedup_refs`](struct.Way.html#method.dedup_refs)).
+#[derive(Clone, Debug)]
+pub struct DedupWayRefIter<'a> {
+    deltas: std::slice::Iter<'a, i64>,
+    current: i64,
+    first: bool,
+}
+
+impl<'a> Iterator for DedupWayRefIter<'a> {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.first {
+            self.first = false;
+            self.current = *self.deltas.next()?;
+            return Some(self.current);
+        }
+
+        // A zero delta repeats the previous id.
+        let delta = *self.deltas.find(|&&d| d != 0)?;
+        self.current = self.current.wrapping_add(delta);
+        Some(self.current)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (_, upper) = self.deltas.size_hint();
+        (0, upper)
+    }
+}
+
+/// An iterator over the references of a way that checks the decoded node ids (see
+/// [`Way::try_refs`](struct.Way.html#method.try_refs)).
+#[derive(Clone, Debug)]
+pub struct TryWayRefIter<'a> {
+    deltas: std::slice::Iter<'a, i64>,
+    current: i64,
+    way_id: i64,
+    index: usize,
+    failed: bool,
+}
+
+impl<'a> Iterator for TryWayRefIter<'a> {
+    type Item = Result<i64>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let delta = *self.deltas.next()?;
+        match self.current.checked_add(delta) {
+            Some(id) if id >= 0 => {
+                self.current = id;
+                self.index += 1;
+                Some(Ok(id))
+            }
+            _ => {
+                self.failed = true;
+                Some(Err(new_error(ErrorKind::InvalidNodeRef {
+                    way_id: self.way_id,
+                    index: self.index,
+                })))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.failed {
+            (0, Some(0))
+        } else {
+            (0, Some(self.deltas.len()))
+        }
+    }
+}
+
 /// The element type of a relation member.
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum RelMemberType {
     Node,
     Way,
@@ -341,6 +1192,53 @@ impl From<osmformat::Relation_MemberType> for RelMemberType {
     }
 }
 
+/// The common values of the `type` tag of a relation (see `Relation::relation_type`). All other
+/// values are kept as `Other`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RelationType<'a> {
+    /// `type=multipolygon`: an area whose outline is given by member ways.
+    Multipolygon,
+    /// `type=boundary`: an administrative or other boundary.
+    Boundary,
+    /// `type=route`: a route such as a bus line or a hiking trail.
+    Route,
+    /// `type=restriction`: a turn restriction.
+    Restriction,
+    /// Any other type.
+    Other(&'a str),
+}
+
+impl<'a> RelationType<'a> {
+    /// Returns the value of the `type` tag.
+    pub fn as_str(&self) -> &'a str {
+        match *self {
+            RelationType::Multipolygon => "multipolygon",
+            RelationType::Boundary => "boundary",
+            RelationType::Route => "route",
+            RelationType::Restriction => "restriction",
+            RelationType::Other(value) => value,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for RelationType<'a> {
+    fn from(value: &'a str) -> RelationType<'a> {
+        match value {
+            "multipolygon" => RelationType::Multipolygon,
+            "boundary" => RelationType::Boundary,
+            "route" => RelationType::Route,
+            "restriction" => RelationType::Restriction,
+            other => RelationType::Other(other),
+        }
+    }
+}
+
+impl<'a> fmt::Display for RelationType<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 //TODO encapsulate member_id based on member_type (NodeId, WayId, RelationId)
 /// A member of a relation.
 ///
@@ -358,6 +1256,43 @@ impl<'a> RelMember<'a> {
     pub fn role(&self) -> Result<&'a str> {
         str_from_stringtable(self.block, self.role_sid as usize)
     }
+
+    /// Returns the type and the id of the referenced element.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// reader.for_each(|element| {
+    ///     if let Element::Relation(rel) = element {
+    ///         for member in rel.members() {
+    ///             assert_eq!(member.as_ref(), (RelMemberType::Way, 107));
+    ///             assert_eq!(member.to_string(), "way/107");
+    ///         }
+    ///     }
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn as_ref(&self) -> (RelMemberType, i64) {
+        (self.member_type, self.member_id)
+    }
+}
+
+/// Prints the referenced element in the common `type/id` notation, e.g. `"way/12345"`.
+impl<'a> fmt::Display for RelMember<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let member_type = match self.member_type {
+            RelMemberType::Node => ElementType::Node,
+            RelMemberType::Way => ElementType::Way,
+            RelMemberType::Relation => ElementType::Relation,
+        };
+        write!(f, "{}/{}", member_type, self.member_id)
+    }
 }
 
 /// An iterator over the members of a relation.
@@ -392,7 +1327,7 @@ impl<'a> Iterator for RelMemberIter<'a> {
             self.member_types.next(),
         ) {
             (Some(role_sid), Some(mem_id_delta), Some(member_type)) => {
-                self.current_member_id += *mem_id_delta;
+                self.current_member_id = self.current_member_id.wrapping_add(*mem_id_delta);
                 Some(RelMember {
                     block: self.block,
                     role_sid: *role_sid,
@@ -411,6 +1346,30 @@ impl<'a> Iterator for RelMemberIter<'a> {
 
 impl<'a> ExactSizeIterator for RelMemberIter<'a> {}
 
+/// An iterator over the member roles of a relation (see
+/// [`Relation::roles`](struct.Relation.html#method.roles)).
+#[derive(Clone, Debug)]
+pub struct RelRoleIter<'a> {
+    block: &'a PrimitiveBlock,
+    role_sids: std::slice::Iter<'a, i32>,
+}
+
+impl<'a> Iterator for RelRoleIter<'a> {
+    type Item = Result<&'a str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.role_sids
+            .next()
+            .map(|&sid| str_from_stringtable(self.block, sid as usize))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.role_sids.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for RelRoleIter<'a> {}
+
 /// An iterator over the tags of an element. It returns a pair of strings (key and value).
 #[derive(Clone, Debug)]
 pub struct TagIter<'a> {
@@ -445,6 +1404,90 @@ impl<'a> Iterator for TagIter<'a> {
 
 impl<'a> ExactSizeIterator for TagIter<'a> {}
 
+/// An iterator over the tags of an element of any type. It returns a pair of strings (key and
+/// value).
+#[derive(Clone, Debug)]
+pub struct ElementTagIter<'a> {
+    inner: TagIterInner<'a>,
+}
+
+#[derive(Clone, Debug)]
+enum TagIterInner<'a> {
+    Sparse(TagIter<'a>),
+    Dense(DenseTagIter<'a>),
+}
+
+impl<'a> Iterator for ElementTagIter<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.inner {
+            TagIterInner::Sparse(ref mut iter) => iter.next(),
+            TagIterInner::Dense(ref mut iter) => iter.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.inner {
+            TagIterInner::Sparse(ref iter) => iter.size_hint(),
+            TagIterInner::Dense(ref iter) => iter.size_hint(),
+        }
+    }
+}
+
+impl<'a> ExactSizeIterator for ElementTagIter<'a> {}
+
+/// An iterator over the tags of an element that returns an error for tags that reference invalid
+/// strings (see [`Element::try_tags`](enum.Element.html#method.try_tags)).
+#[derive(Clone, Debug)]
+pub struct TryTagIter<'a> {
+    block: &'a PrimitiveBlock,
+    id: OsmId,
+    raw_tags: ElementRawTagIter<'a>,
+}
+
+impl<'a> TryTagIter<'a> {
+    pub(crate) fn new(
+        block: &'a PrimitiveBlock,
+        id: OsmId,
+        raw_tags: ElementRawTagIter<'a>,
+    ) -> TryTagIter<'a> {
+        TryTagIter {
+            block,
+            id,
+            raw_tags,
+        }
+    }
+
+    fn resolve(&self, index: u32, part: TagPart) -> Result<&'a str> {
+        str_from_stringtable(self.block, index as usize).map_err(|err| {
+            new_error(ErrorKind::InvalidTag {
+                id: self.id,
+                part,
+                err,
+            })
+        })
+    }
+}
+
+impl<'a> Iterator for TryTagIter<'a> {
+    type Item = Result<(&'a str, &'a str)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.raw_tags.next()?;
+        Some(
+            self.resolve(key, TagPart::Key)
+                .and_then(|k| Ok((k, self.resolve(value, TagPart::Value)?))),
+        )
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.raw_tags.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for TryTagIter<'a> {}
+
 /// An iterator over the tags of an element. It returns a pair of indices (key and value) to the
 /// stringtable of the current `PrimitiveBlock`.
 #[derive(Clone, Debug)]
@@ -472,6 +1515,73 @@ impl<'a> Iterator for RawTagIter<'a> {
 
 impl<'a> ExactSizeIterator for RawTagIter<'a> {}
 
+/// An iterator over the tags of an element of any type. It returns a pair of indices (key and
+/// value) to the stringtable of the current `PrimitiveBlock`.
+#[derive(Clone, Debug)]
+pub struct ElementRawTagIter<'a> {
+    inner: RawTagIterInner<'a>,
+}
+
+#[derive(Clone, Debug)]
+enum RawTagIterInner<'a> {
+    Sparse(RawTagIter<'a>),
+    Dense(DenseRawTagIter<'a>),
+}
+
+impl<'a> ElementRawTagIter<'a> {
+    pub(crate) fn sparse(iter: RawTagIter<'a>) -> ElementRawTagIter<'a> {
+        ElementRawTagIter {
+            inner: RawTagIterInner::Sparse(iter),
+        }
+    }
+
+    pub(crate) fn dense(iter: DenseRawTagIter<'a>) -> ElementRawTagIter<'a> {
+        ElementRawTagIter {
+            inner: RawTagIterInner::Dense(iter),
+        }
+    }
+}
+
+impl<'a> Iterator for ElementRawTagIter<'a> {
+    type Item = (u32, u32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.inner {
+            RawTagIterInner::Sparse(ref mut iter) => iter.next(),
+            RawTagIterInner::Dense(ref mut iter) => iter.next().map(|(k, v)| (k as u32, v as u32)),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.inner {
+            RawTagIterInner::Sparse(ref iter) => iter.size_hint(),
+            RawTagIterInner::Dense(ref iter) => iter.size_hint(),
+        }
+    }
+}
+
+impl<'a> ExactSizeIterator for ElementRawTagIter<'a> {}
+
+/// Checks if one of the given pairs of stringtable indices refers to the key and value (or any
+/// value if `value` is `None`) without decoding the strings as UTF-8.
+pub(crate) fn raw_tag_equals<I>(
+    stringtable: &[Vec<u8>],
+    mut raw_tags: I,
+    key: &str,
+    value: Option<&str>,
+) -> bool
+where
+    I: Iterator<Item = (usize, usize)>,
+{
+    let matches = |index: usize, s: &str| {
+        stringtable.get(index).map(|entry| entry.as_slice()) == Some(s.as_bytes())
+    };
+
+    // Keys should be unique per element, but a malformed element with duplicate keys matches if
+    // any of its pairs does, like in `TagMatcher`.
+    raw_tags.any(|(k, v)| matches(k, key) && value.map_or(true, |value| matches(v, value)))
+}
+
 /// Additional metadata that might be included in each element.
 #[derive(Clone, Debug)]
 pub struct Info<'a> {
@@ -493,15 +1603,32 @@ impl<'a> Info<'a> {
         }
     }
 
-    /// Returns the time stamp in milliseconds since the epoch.
+    /// Returns the time stamp in milliseconds since the epoch. For corrupt data the computation
+    /// can overflow, in which case the result wraps around (see `milli_timestamp_checked`).
     pub fn milli_timestamp(&self) -> Option<i64> {
         if self.info.has_timestamp() {
-            Some(self.info.get_timestamp() * i64::from(self.block.get_date_granularity()))
+            Some(
+                self.info
+                    .get_timestamp()
+                    .wrapping_mul(i64::from(self.block.get_date_granularity())),
+            )
         } else {
             None
         }
     }
 
+    /// Returns the time stamp in milliseconds since the epoch, or an
+    /// `ErrorKind::ArithmeticOverflow` error if the date granularity of the block makes it
+    /// overflow.
+    pub fn milli_timestamp_checked(&self) -> Result<Option<i64>> {
+        if self.info.has_timestamp() {
+            checked_milli_timestamp(self.info.get_timestamp(), self.block.get_date_granularity())
+                .map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
     /// Returns the changeset id.
     pub fn changeset(&self) -> Option<i64> {
         if self.info.has_changeset() {
@@ -532,8 +1659,28 @@ impl<'a> Info<'a> {
         }
     }
 
+    pub(crate) fn user_sid(&self) -> Option<u32> {
+        if self.info.has_user_sid() {
+            Some(self.info.get_user_sid())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the visibility flag if it is set, i.e. `Some(true)` for an explicitly visible,
+    /// `Some(false)` for a deleted element and `None` if the flag is missing. Unlike `visible`,
+    /// this allows history-aware consumers to tell files that state the visibility of each
+    /// version apart from files that leave it out.
+    pub fn visible_explicit(&self) -> Option<bool> {
+        if self.info.has_visible() {
+            Some(self.info.get_visible())
+        } else {
+            None
+        }
+    }
+
     /// Returns the visibility status of an element. This is only relevant if the PBF file contains
-    /// historical information.
+    /// historical information. A missing flag counts as visible (see `visible_explicit`).
     pub fn visible(&self) -> bool {
         if self.info.has_visible() {
             self.info.get_visible()
@@ -5,6 +5,7 @@ use std::result;
 use std::str;
 use std::str::Utf8Error;
 
+use elements::OsmId;
 use protobuf::ProtobufError;
 
 // Error data structures are modeled just like in the `csv` crate by BurntSushi.
@@ -21,10 +22,39 @@ pub(crate) fn new_protobuf_error(err: ProtobufError, location: &'static str) ->
     Error(Box::new(ErrorKind::Protobuf { err, location }))
 }
 
+// Errors are sent between threads by the parallel readers, so this fails to compile if a variant
+// with a payload that is not `Send + Sync` is added.
+const _: () = {
+    fn assert_send_sync<T: Send + Sync + 'static>() {}
+    let _ = assert_send_sync::<Error>;
+};
+
 /// A type alias for `Result<T, osmpbf::Error>`.
 pub type Result<T> = result::Result<T, Error>;
 
 /// An error that can occur when reading PBF files.
+///
+/// `Error` is `Send + Sync`, so the `?` operator converts it into a
+/// `Box<dyn std::error::Error + Send + Sync>` through the blanket implementation of the standard
+/// library. A dedicated `From` implementation is neither needed nor possible.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+/// use std::error::Error as StdError;
+///
+/// type BoxError = Box<dyn StdError + Send + Sync>;
+///
+/// fn count_elements(path: &str) -> std::result::Result<u64, BoxError> {
+///     let reader = ElementReader::from_path(path)?;
+///     let mut count = 0;
+///     reader.for_each(|_| count += 1)?;
+///     Ok(count)
+/// }
+///
+/// assert_eq!(count_elements("tests/test.osm.pbf").unwrap(), 5);
+/// assert!(count_elements("tests/does_not_exist.osm.pbf").is_err());
+/// ```
 #[derive(Debug)]
 pub struct Error(Box<ErrorKind>);
 
@@ -57,6 +87,32 @@ pub enum ErrorKind {
     StringtableIndexOutOfBounds { index: usize },
     /// An error that occurs when decoding `Blob`s.
     Blob(BlobError),
+    /// The node reference at position `index` of the way `way_id` decodes to an implausible
+    /// (negative or overflowing) node id. This usually indicates corrupt data.
+    InvalidNodeRef { way_id: i64, index: usize },
+    /// The `HeaderBlock` lists a required feature that this crate does not support.
+    UnsupportedFeature { feature: String },
+    /// The elements of a history file or another sorted input are not sorted by type, id and
+    /// version as expected. `id` is the element that was found out of order.
+    UnsortedElements { id: OsmId },
+    /// A tag of the element `id` references an invalid string. `part` tells whether the key or
+    /// the value is invalid and `err` is the underlying `StringtableUtf8` or
+    /// `StringtableIndexOutOfBounds` error.
+    InvalidTag {
+        id: OsmId,
+        part: TagPart,
+        err: Error,
+    },
+    /// A `PrimitiveBlock` was parsed successfully, but its content is inconsistent, e.g. an
+    /// element references a string that is not in the stringtable or the arrays of dense nodes
+    /// have different lengths. This usually indicates corrupt or malicious data.
+    InvalidPrimitiveBlock { reason: String },
+    /// An OSM XML file could not be parsed by `OsmXmlReader` (only available with the `xml`
+    /// feature). `offset` is the byte position of the markup that caused the error.
+    InvalidXml { offset: u64, message: String },
+    /// Computing `value` (e.g. a coordinate or a time stamp) from the raw values of an element
+    /// and its block overflows an `i64`. This usually indicates corrupt or malicious data.
+    ArithmeticOverflow { value: &'static str },
 
     //TODO add UnexpectedPrimitiveBlock
     /// Hints that destructuring should not be exhaustive.
@@ -68,6 +124,24 @@ pub enum ErrorKind {
     __Nonexhaustive,
 }
 
+/// The part of a tag that an `ErrorKind::InvalidTag` error refers to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TagPart {
+    /// The key of the tag.
+    Key,
+    /// The value of the tag.
+    Value,
+}
+
+impl fmt::Display for TagPart {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TagPart::Key => write!(f, "key"),
+            TagPart::Value => write!(f, "value"),
+        }
+    }
+}
+
 /// An error that occurs when decoding a blob.
 #[derive(Debug)]
 pub enum BlobError {
@@ -78,13 +152,27 @@ pub enum BlobError {
         /// Blob header size in bytes.
         size: u64,
     },
-    /// Blob content is bigger than [`MAX_BLOB_MESSAGE_SIZE`](blob/MAX_BLOB_MESSAGE_SIZE.v.html).
+    /// Blob content is bigger than [`MAX_BLOB_MESSAGE_SIZE`](blob/MAX_BLOB_MESSAGE_SIZE.v.html)
+    /// or the limit given to `Blob::decode_with_limit`.
     MessageTooBig {
-        /// Blob content size in bytes.
+        /// Blob content size in bytes. Decompression stops as soon as the limit is exceeded, so
+        /// for compressed blobs this is only the number of bytes decompressed up to that point.
         size: u64,
     },
-    /// The blob is empty because the `raw` and `zlib-data` fields are missing.
+    /// The blob is empty because none of its data fields (`raw`, `zlib_data`, ...) is set.
     Empty,
+    /// The blob contains data, but only in fields with a compression format that has no enabled
+    /// decoder. Some formats can be enabled with a feature flag (e.g. `lzma`).
+    UnsupportedCompression {
+        /// Names of the data fields that are present in the blob.
+        available: Vec<&'static str>,
+    },
+    /// The blob has a type other than `OSMHeader` or `OSMData` and the reader is configured to
+    /// reject such blobs.
+    UnknownType {
+        /// The type string of the blob header.
+        blob_type: String,
+    },
     /// Hints that destructuring should not be exhaustive.
     #[doc(hidden)]
     __Nonexhaustive,
@@ -109,12 +197,23 @@ impl StdError for Error {
             ErrorKind::Protobuf { ref err, .. } => err.description(),
             ErrorKind::StringtableUtf8 { ref err, .. } => err.description(),
             ErrorKind::StringtableIndexOutOfBounds { .. } => "stringtable index out of bounds",
+            ErrorKind::InvalidNodeRef { .. } => "invalid node reference in way",
+            ErrorKind::UnsupportedFeature { .. } => "required feature is not supported",
+            ErrorKind::UnsortedElements { .. } => "elements are not sorted",
+            ErrorKind::InvalidTag { .. } => "tag references an invalid string",
+            ErrorKind::InvalidPrimitiveBlock { .. } => "primitive block is inconsistent",
+            ErrorKind::InvalidXml { .. } => "invalid OSM XML",
+            ErrorKind::ArithmeticOverflow { .. } => "arithmetic overflow",
             ErrorKind::Blob(BlobError::InvalidHeaderSize) => {
                 "blob header size could not be decoded"
             }
             ErrorKind::Blob(BlobError::HeaderTooBig { .. }) => "blob header is too big",
             ErrorKind::Blob(BlobError::MessageTooBig { .. }) => "blob message is too big",
-            ErrorKind::Blob(BlobError::Empty) => "blob is missing fields 'raw' and 'zlib_data",
+            ErrorKind::Blob(BlobError::Empty) => "blob does not contain any data",
+            ErrorKind::Blob(BlobError::UnsupportedCompression { .. }) => {
+                "blob compression is not supported"
+            }
+            ErrorKind::Blob(BlobError::UnknownType { .. }) => "unknown blob type",
             _ => unreachable!(),
         }
     }
@@ -125,10 +224,19 @@ impl StdError for Error {
             ErrorKind::Protobuf { ref err, .. } => Some(err),
             ErrorKind::StringtableUtf8 { ref err, .. } => Some(err),
             ErrorKind::StringtableIndexOutOfBounds { .. } => None,
+            ErrorKind::InvalidNodeRef { .. } => None,
+            ErrorKind::UnsupportedFeature { .. } => None,
+            ErrorKind::UnsortedElements { .. } => None,
+            ErrorKind::InvalidTag { ref err, .. } => Some(err),
+            ErrorKind::InvalidPrimitiveBlock { .. } => None,
+            ErrorKind::InvalidXml { .. } => None,
+            ErrorKind::ArithmeticOverflow { .. } => None,
             ErrorKind::Blob(BlobError::InvalidHeaderSize) => None,
             ErrorKind::Blob(BlobError::HeaderTooBig { .. }) => None,
             ErrorKind::Blob(BlobError::MessageTooBig { .. }) => None,
             ErrorKind::Blob(BlobError::Empty) => None,
+            ErrorKind::Blob(BlobError::UnsupportedCompression { .. }) => None,
+            ErrorKind::Blob(BlobError::UnknownType { .. }) => None,
             _ => unreachable!(),
         }
     }
@@ -147,6 +255,36 @@ impl fmt::Display for Error {
             ErrorKind::StringtableIndexOutOfBounds { index } => {
                 write!(f, "stringtable index out of bounds: {}", index)
             }
+            ErrorKind::InvalidNodeRef { way_id, index } => write!(
+                f,
+                "way {} has an invalid node reference at index {}",
+                way_id, index
+            ),
+            ErrorKind::UnsupportedFeature { ref feature } => {
+                write!(f, "required feature is not supported: '{}'", feature)
+            }
+            ErrorKind::UnsortedElements { id } => {
+                write!(
+                    f,
+                    "elements are not sorted by type, id and version at {}",
+                    id
+                )
+            }
+            ErrorKind::InvalidTag { id, part, ref err } => {
+                write!(f, "tag {} of {} is invalid: {}", part, id, err)
+            }
+            ErrorKind::InvalidPrimitiveBlock { ref reason } => {
+                write!(f, "primitive block is inconsistent: {}", reason)
+            }
+            ErrorKind::InvalidXml {
+                offset,
+                ref message,
+            } => {
+                write!(f, "invalid OSM XML at byte {}: {}", offset, message)
+            }
+            ErrorKind::ArithmeticOverflow { value } => {
+                write!(f, "arithmetic overflow while computing the {}", value)
+            }
             ErrorKind::Blob(BlobError::InvalidHeaderSize) => {
                 write!(f, "blob header size could not be decoded")
             }
@@ -156,8 +294,14 @@ impl fmt::Display for Error {
             ErrorKind::Blob(BlobError::MessageTooBig { size }) => {
                 write!(f, "blob message is too big: {} bytes", size)
             }
-            ErrorKind::Blob(BlobError::Empty) => {
-                write!(f, "blob is missing fields 'raw' and 'zlib_data'")
+            ErrorKind::Blob(BlobError::Empty) => write!(f, "blob does not contain any data"),
+            ErrorKind::Blob(BlobError::UnsupportedCompression { ref available }) => write!(
+                f,
+                "blob compression is not supported, available fields: '{}'",
+                available.join("', '")
+            ),
+            ErrorKind::Blob(BlobError::UnknownType { ref blob_type }) => {
+                write!(f, "unknown blob type: '{}'", blob_type)
             }
             _ => unreachable!(),
         }
@@ -0,0 +1,194 @@
+//! Extract parts of PBF files
+
+use blob::{BlobDecode, BlobReader};
+use coordinate::BBox;
+use elements::{Element, ElementTagIter, ElementType, RelMemberType};
+use error::Result;
+use owned::OwnedElement;
+use reader::ElementReader;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use writer::ElementWriter;
+
+/// The number of elements that were written by an extract.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ExtractStats {
+    /// The number of written nodes.
+    pub nodes: u64,
+    /// The number of written ways.
+    pub ways: u64,
+    /// The number of written relations.
+    pub relations: u64,
+}
+
+/// Writes all elements of the input file that are inside the bounding box to a new PBF file.
+///
+/// Ways are complete, not clipped: A way is written if at least one of its nodes is inside the
+/// box, and then all of its nodes are written as well, even those outside of the box. A relation
+/// is written if it has a node inside the box or a written way as a member. Other members of the
+/// relation are not added, so relations may reference elements that are missing in the output.
+///
+/// The input is read twice. It has to be sorted (nodes before ways before relations), as is
+/// usual for PBF files.
+///
+/// # Errors
+/// Returns the first error that occurs while reading the input or writing the output.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let output = std::env::temp_dir().join("osmpbf_extract_bbox_example.osm.pbf");
+/// let bbox = BBox::new(52.11, 11.62, 52.13, 11.64);
+///
+/// let stats = extract_bbox("tests/test.osm.pbf", &output, bbox)?;
+/// assert_eq!(stats.ways, 1);
+/// # std::fs::remove_file(output)?;
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+pub fn extract_bbox<P, Q>(input: P, output: Q, bbox: BBox) -> Result<ExtractStats>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let mut nodes = HashSet::new();
+    let mut ways = HashSet::new();
+    let mut relations = HashSet::new();
+    let mut way_nodes = HashSet::new();
+
+    ElementReader::from_path(&input)?.for_each(|element| match element {
+        Element::Node(node) => {
+            if bbox.contains(node.coordinate()) {
+                nodes.insert(node.id());
+            }
+        }
+        Element::DenseNode(dnode) => {
+            if bbox.contains(dnode.coordinate()) {
+                nodes.insert(dnode.id);
+            }
+        }
+        Element::Way(way) => {
+            if way.refs().any(|id| nodes.contains(&id)) {
+                ways.insert(way.id());
+                way_nodes.extend(way.refs());
+            }
+        }
+        Element::Relation(rel) => {
+            let keep = rel.members().any(|m| match m.member_type {
+                RelMemberType::Node => nodes.contains(&m.member_id),
+                RelMemberType::Way => ways.contains(&m.member_id),
+                RelMemberType::Relation => false,
+            });
+            if keep {
+                relations.insert(rel.id());
+            }
+        }
+    })?;
+
+    nodes.extend(way_nodes);
+    write_selected(input, output, &nodes, &ways, &relations)
+}
+
+/// Writes all elements of the input file that match the predicate to a new PBF file, together
+/// with the nodes of the written ways. The predicate is called with the type and the tags of each
+/// element.
+///
+/// Relations are written if they match, but their members are not added, so relations may
+/// reference elements that are missing in the output. The input is read twice.
+///
+/// # Errors
+/// Returns the first error that occurs while reading the input or writing the output.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let output = std::env::temp_dir().join("osmpbf_extract_by_tags_example.osm.pbf");
+///
+/// // All buildings and their nodes
+/// let stats = extract_by_tags("tests/test.osm.pbf", &output, |element_type, mut tags| {
+///     element_type == ElementType::Way && tags.any(|(key, _)| key == "building")
+/// })?;
+/// assert_eq!(stats.ways, 1);
+/// assert_eq!(stats.nodes, 3);
+/// # std::fs::remove_file(output)?;
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+pub fn extract_by_tags<P, Q, F>(input: P, output: Q, mut predicate: F) -> Result<ExtractStats>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    F: for<'a> FnMut(ElementType, ElementTagIter<'a>) -> bool,
+{
+    let mut nodes = HashSet::new();
+    let mut ways = HashSet::new();
+    let mut relations = HashSet::new();
+
+    ElementReader::from_path(&input)?.for_each(|element| {
+        if !predicate(element.element_type(), element.tags()) {
+            return;
+        }
+        match element {
+            Element::Node(node) => {
+                nodes.insert(node.id());
+            }
+            Element::DenseNode(dnode) => {
+                nodes.insert(dnode.id);
+            }
+            Element::Way(way) => {
+                ways.insert(way.id());
+                nodes.extend(way.refs());
+            }
+            Element::Relation(rel) => {
+                relations.insert(rel.id());
+            }
+        }
+    })?;
+
+    write_selected(input, output, &nodes, &ways, &relations)
+}
+
+/// Copies the elements whose ids are in the given sets from `input` to `output`.
+fn write_selected<P, Q>(
+    input: P,
+    output: Q,
+    nodes: &HashSet<i64>,
+    ways: &HashSet<i64>,
+    relations: &HashSet<i64>,
+) -> Result<ExtractStats>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let mut writer = ElementWriter::new(BufWriter::new(File::create(output)?));
+    let mut stats = ExtractStats::default();
+
+    for blob in BlobReader::from_path(input)? {
+        if let BlobDecode::OsmData(block) = blob?.decode()? {
+            for element in block.into_owned_elements()? {
+                let (selected, count) = match element {
+                    OwnedElement::Node(ref node) => (nodes.contains(&node.id), &mut stats.nodes),
+                    OwnedElement::Way(ref way) => (ways.contains(&way.id), &mut stats.ways),
+                    OwnedElement::Relation(ref rel) => {
+                        (relations.contains(&rel.id), &mut stats.relations)
+                    }
+                };
+                if selected {
+                    *count += 1;
+                    writer.write_element(element)?;
+                }
+            }
+        }
+    }
+
+    writer.finish()?;
+    Ok(stats)
+}
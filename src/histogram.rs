@@ -0,0 +1,123 @@
+//! Count elements by type and properties
+
+use proto::osmformat;
+use std::ops::Add;
+
+/// Counts of the elements of one type (see `Histogram`).
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ElementCounts {
+    /// The number of elements.
+    pub total: u64,
+    /// The number of elements with at least one tag.
+    pub tagged: u64,
+    /// The number of elements with metadata (version, time stamp, ...).
+    pub with_info: u64,
+}
+
+impl ElementCounts {
+    /// Returns the number of elements without tags.
+    pub fn untagged(&self) -> u64 {
+        self.total - self.tagged
+    }
+}
+
+impl Add for ElementCounts {
+    type Output = ElementCounts;
+
+    fn add(self, other: ElementCounts) -> ElementCounts {
+        ElementCounts {
+            total: self.total + other.total,
+            tagged: self.tagged + other.tagged,
+            with_info: self.with_info + other.with_info,
+        }
+    }
+}
+
+/// The number of elements in a file broken down by type, by whether they are tagged and by
+/// whether they carry metadata. Dense nodes are counted as nodes.
+///
+/// Use `ElementReader::element_histogram` to compute it.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Histogram {
+    /// Counts of nodes, including dense nodes.
+    pub nodes: ElementCounts,
+    /// Counts of ways.
+    pub ways: ElementCounts,
+    /// Counts of relations.
+    pub relations: ElementCounts,
+}
+
+impl Histogram {
+    /// Returns the number of elements of all types.
+    pub fn total(&self) -> u64 {
+        self.nodes.total + self.ways.total + self.relations.total
+    }
+}
+
+impl Add for Histogram {
+    type Output = Histogram;
+
+    fn add(self, other: Histogram) -> Histogram {
+        Histogram {
+            nodes: self.nodes + other.nodes,
+            ways: self.ways + other.ways,
+            relations: self.relations + other.relations,
+        }
+    }
+}
+
+/// Counts the elements of a block. This only looks at the lengths of the tag arrays and at the
+/// presence of metadata, so no strings are decoded.
+pub(crate) fn block_histogram(block: &osmformat::PrimitiveBlock) -> Histogram {
+    let mut histogram = Histogram::default();
+
+    for group in block.get_primitivegroup() {
+        for node in group.get_nodes() {
+            histogram.nodes.total += 1;
+            histogram.nodes.tagged += !node.get_keys().is_empty() as u64;
+            histogram.nodes.with_info += node.has_info() as u64;
+        }
+
+        let dense = group.get_dense();
+        let num_dense = dense.get_id().len() as u64;
+        histogram.nodes.total += num_dense;
+        histogram.nodes.tagged += dense_tagged(dense.get_keys_vals());
+        if !dense.get_denseinfo().get_version().is_empty() {
+            histogram.nodes.with_info += num_dense;
+        }
+
+        for way in group.get_ways() {
+            histogram.ways.total += 1;
+            histogram.ways.tagged += !way.get_keys().is_empty() as u64;
+            histogram.ways.with_info += way.has_info() as u64;
+        }
+
+        for rel in group.get_relations() {
+            histogram.relations.total += 1;
+            histogram.relations.tagged += !rel.get_keys().is_empty() as u64;
+            histogram.relations.with_info += rel.has_info() as u64;
+        }
+    }
+
+    histogram
+}
+
+/// Counts the dense nodes with tags, i.e. the sections of `keys_vals` that contain at least one
+/// key before their terminating zero.
+fn dense_tagged(keys_vals: &[i32]) -> u64 {
+    let mut tagged = 0;
+    let mut i = 0;
+    while i < keys_vals.len() {
+        if keys_vals[i] == 0 {
+            i += 1;
+        } else {
+            tagged += 1;
+            // Skip the key/value pairs of this node and the terminating zero.
+            while i < keys_vals.len() && keys_vals[i] != 0 {
+                i += 2;
+            }
+            i += 1;
+        }
+    }
+    tagged
+}
@@ -0,0 +1,172 @@
+//! Group the versions of elements in history files
+
+use elements::OsmId;
+use error::{new_error, ErrorKind, Result};
+use owned::OwnedElement;
+use reader::OwnedElements;
+use std::io::Read;
+
+/// All versions of a single element, as stored in a file with historical information (see
+/// `HeaderBlock::has_historical_information`).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VersionHistory {
+    id: OsmId,
+    versions: Vec<OwnedElement>,
+}
+
+impl VersionHistory {
+    /// Returns the id of the element together with its type.
+    pub fn osm_id(&self) -> OsmId {
+        self.id
+    }
+
+    /// Returns all versions of the element in the order of the file. This is never empty.
+    pub fn versions(&self) -> &[OwnedElement] {
+        &self.versions
+    }
+
+    /// Returns the last version of the element in the order of the file. For files that are
+    /// sorted by version this is the latest version. It may be deleted (see `OwnedInfo::visible`).
+    pub fn latest(&self) -> &OwnedElement {
+        // There is at least one version, as a history is only created for an element.
+        &self.versions[self.versions.len() - 1]
+    }
+
+    /// Consumes the history and returns all versions of the element.
+    pub fn into_versions(self) -> Vec<OwnedElement> {
+        self.versions
+    }
+}
+
+/// An iterator over the `VersionHistory` of each element of a PBF file. Consecutive elements
+/// with the same `OsmId` are grouped together, even if they are stored in different blocks.
+///
+/// Use `ElementReader::version_histories` to create this iterator. Once an error is returned, the
+/// iterator yields `None`.
+#[derive(Clone, Debug)]
+pub struct VersionHistories<R: Read> {
+    elements: OwnedElements<R>,
+    /// The first version of the next history, if it is already read.
+    pending: Option<OwnedElement>,
+    failed: bool,
+}
+
+impl<R: Read> VersionHistories<R> {
+    pub(crate) fn new(elements: OwnedElements<R>) -> VersionHistories<R> {
+        VersionHistories {
+            elements,
+            pending: None,
+            failed: false,
+        }
+    }
+}
+
+impl<R: Read> Iterator for VersionHistories<R> {
+    type Item = Result<VersionHistory>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let first = match self.pending.take() {
+            Some(element) => element,
+            None => match self.elements.next()? {
+                Ok(element) => element,
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            },
+        };
+
+        let id = first.osm_id();
+        let mut versions = vec![first];
+        loop {
+            match self.elements.next() {
+                Some(Ok(element)) => {
+                    if element.osm_id() == id {
+                        versions.push(element);
+                    } else {
+                        self.pending = Some(element);
+                        break;
+                    }
+                }
+                Some(Err(e)) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+                None => break,
+            }
+        }
+
+        Some(Ok(VersionHistory { id, versions }))
+    }
+}
+
+/// An iterator over the latest visible version of each element of a history file. Superseded
+/// versions and deleted elements are skipped, so the result is a snapshot of the current data.
+///
+/// Use `ElementReader::latest_versions` to create this iterator. Once an error is returned, the
+/// iterator yields `None`.
+#[derive(Clone, Debug)]
+pub struct LatestVersions<R: Read> {
+    histories: VersionHistories<R>,
+    last_id: Option<OsmId>,
+}
+
+impl<R: Read> LatestVersions<R> {
+    pub(crate) fn new(histories: VersionHistories<R>) -> LatestVersions<R> {
+        LatestVersions {
+            histories,
+            last_id: None,
+        }
+    }
+
+    /// Returns an error if the history can be detected to be out of order: The versions have to
+    /// increase and the positive ids of elements with the same type have to increase between
+    /// histories. Negative ids are not checked, as tools disagree on how to sort them.
+    fn check_order(&self, history: &VersionHistory) -> Result<()> {
+        let id = history.osm_id();
+        let unsorted = || Err(new_error(ErrorKind::UnsortedElements { id }));
+
+        if let Some(last_id) = self.last_id {
+            if id < last_id && (id.element_type() != last_id.element_type() || id.id() >= 0) {
+                return unsorted();
+            }
+        }
+
+        let mut last_version = None;
+        for version in history.versions().iter().map(|e| e.info().version) {
+            if version.is_some() && last_version.is_some() && version <= last_version {
+                return unsorted();
+            }
+            last_version = version.or(last_version);
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for LatestVersions<R> {
+    type Item = Result<OwnedElement>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let history = match self.histories.next()? {
+                Ok(history) => history,
+                Err(e) => return Some(Err(e)),
+            };
+
+            if let Err(e) = self.check_order(&history) {
+                self.histories.failed = true;
+                return Some(Err(e));
+            }
+            self.last_id = Some(history.osm_id());
+
+            let latest = history.into_versions().pop()?;
+            if latest.info().visible {
+                return Some(Ok(latest));
+            }
+        }
+    }
+}
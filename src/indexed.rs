@@ -0,0 +1,759 @@
+//! Resolve the node references of ways and look up elements by reading a file multiple times or
+//! with an index of its blocks
+
+use blob::{read_up_to, BlobDecode, BlobReader, ByteOffset};
+use block::PrimitiveBlock;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use coordinate::{way_length_meters, Coordinate, METERS_PER_DEGREE};
+use elements::{OsmId, RelMemberType, Relation, Way};
+use error::Result;
+use owned::{owned_element, OwnedElement, OwnedNode};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+use std::path::Path;
+
+/// A reader that resolves the node references of ways to coordinates. Ways only store the ids
+/// of their nodes, so the file is read multiple times: once to find the referenced node ids,
+/// once to look up the coordinates of these nodes and once more to visit the ways. Only the
+/// coordinates of referenced nodes are kept in memory.
+///
+/// For nearest node queries, a grid of all node coordinates is built on first use and kept for
+/// later queries. Likewise, the `BlockIndex` for looking up elements by id is built on first use,
+/// unless one is given with `with_index`.
+#[derive(Clone, Debug)]
+pub struct IndexedReader<R: Read + Seek> {
+    reader: BlobReader<R>,
+    node_grid: Option<NodeGrid>,
+    block_index: Option<BlockIndex>,
+}
+
+impl<R: Read + Seek> IndexedReader<R> {
+    /// Creates a new `IndexedReader` from a seekable reader.
+    pub fn new(reader: R) -> Result<IndexedReader<R>> {
+        Ok(IndexedReader {
+            reader: BlobReader::new_seekable(reader)?,
+            node_grid: None,
+            block_index: None,
+        })
+    }
+
+    /// Uses the given index to look up elements instead of building one by reading the whole
+    /// file, e.g. an index that `ElementWriter::with_index` wrote together with the file. The
+    /// index must belong to this file, otherwise lookups fail or miss elements.
+    pub fn with_index(mut self, index: BlockIndex) -> IndexedReader<R> {
+        self.block_index = Some(index);
+        self
+    }
+
+    /// Returns the index of the blocks of the file. If no index was given with `with_index`, it
+    /// is built on the first call by reading all blocks and kept for later calls.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    pub fn block_index(&mut self) -> Result<&BlockIndex> {
+        let index = match self.block_index.take() {
+            Some(index) => index,
+            None => self.read_block_index()?,
+        };
+        Ok(self.block_index.get_or_insert(index))
+    }
+
+    /// Returns the element with the given type and id or `None` if the file does not contain it.
+    /// Only the blocks whose id range in the `BlockIndex` contains the id are decoded, which is
+    /// usually a single block for sorted files.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure or an error if a
+    /// string of the element is not valid UTF-8.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// let way = reader.find_element(OsmId::Way(107))?.unwrap();
+    /// assert_eq!(way.tags().len(), 2);
+    /// assert!(reader.find_element(OsmId::Node(107))?.is_none());
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn find_element(&mut self, id: OsmId) -> Result<Option<OwnedElement>> {
+        let offsets: Vec<ByteOffset> = self
+            .block_index()?
+            .entries()
+            .iter()
+            .filter(|entry| entry.may_contain(id))
+            .map(|entry| entry.offset())
+            .collect();
+
+        for offset in offsets {
+            self.reader.seek(offset)?;
+            if let Some(blob) = self.reader.next() {
+                if let BlobDecode::OsmData(block) = blob?.decode()? {
+                    if let Some(element) = block.elements().find(|e| e.osm_id() == id) {
+                        return owned_element(&element).map(Some);
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Calls the given closure on each way together with the coordinates of its nodes, in the
+    /// order of the node references, and their length in meters (see `way_length_meters`). Ways
+    /// that reference nodes that are missing in the file (e.g. at the border of an extract) are
+    /// skipped.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// let mut total_length = 0.0;
+    ///
+    /// reader.for_each_way_geometry(|way, coords, length| {
+    ///     println!("way {} has {} nodes", way.id(), coords.len());
+    ///     total_length += length;
+    /// })?;
+    ///
+    /// println!("total length: {:.1} m", total_length);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn for_each_way_geometry<F>(&mut self, mut f: F) -> Result<()>
+    where
+        F: for<'a> FnMut(&Way<'a>, &[Coordinate], f64),
+    {
+        // First pass: collect the ids of all referenced nodes.
+        let mut node_ids = HashSet::new();
+        self.for_each_block(|_, block| {
+            for group in block.groups() {
+                for way in group.ways() {
+                    node_ids.extend(way.refs());
+                }
+            }
+        })?;
+
+        // Second pass: look up the coordinates of the referenced nodes.
+        let mut coords = HashMap::with_capacity(node_ids.len());
+        self.for_each_block(|_, block| {
+            for group in block.groups() {
+                for node in group.nodes() {
+                    if node_ids.contains(&node.id()) {
+                        coords.insert(node.id(), node.coordinate());
+                    }
+                }
+                for dnode in group.dense_nodes() {
+                    if node_ids.contains(&dnode.id) {
+                        coords.insert(dnode.id, dnode.coordinate());
+                    }
+                }
+            }
+        })?;
+        drop(node_ids);
+
+        // Third pass: visit the ways with resolved coordinates.
+        let mut way_coords = vec![];
+        self.for_each_block(|_, block| {
+            for group in block.groups() {
+                for way in group.ways() {
+                    way_coords.clear();
+                    let resolved = way.refs().all(|id| match coords.get(&id) {
+                        Some(coord) => {
+                            way_coords.push(*coord);
+                            true
+                        }
+                        None => false,
+                    });
+                    if resolved {
+                        f(&way, &way_coords, way_length_meters(&way_coords));
+                    }
+                }
+            }
+        })
+    }
+
+    /// Calls the given closure on each relation together with its resolved members, in member
+    /// order. Node members carry the coordinates of the node, and all members tell whether the
+    /// referenced element is present in the file, which is what multipolygon and boundary
+    /// assembly needs.
+    ///
+    /// The `BlockIndex` is used to decode only the blocks that are needed (see `block_index`):
+    /// the blocks with relations are read twice, once to collect the members and once to visit
+    /// the relations, and in between only the blocks whose id ranges contain a member node or
+    /// way are decoded to look up the members. Only the coordinates of member nodes are kept in
+    /// memory.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// reader.for_each_relation_resolved(|rel, members| {
+    ///     let missing = members.iter().filter(|m| !m.present).count();
+    ///     println!("relation {} has {} missing members", rel.id(), missing);
+    ///     assert_eq!(members[0].role, "test_role");
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn for_each_relation_resolved<F>(&mut self, mut f: F) -> Result<()>
+    where
+        F: for<'a> FnMut(&Relation<'a>, &[ResolvedMember]),
+    {
+        let entries = self.block_index()?.entries().to_vec();
+        let relation_blocks: Vec<ByteOffset> = entries
+            .iter()
+            .filter(|entry| entry.relation_ids().is_some())
+            .map(|entry| entry.offset())
+            .collect();
+
+        // First pass: collect the ids of all relations and of their member nodes and ways.
+        let mut node_ids = vec![];
+        let mut way_ids = vec![];
+        let mut relation_ids = HashSet::new();
+        self.for_each_block_at(&relation_blocks, |block| {
+            for group in block.groups() {
+                for rel in group.relations() {
+                    relation_ids.insert(rel.id());
+                    for member in rel.members() {
+                        match member.member_type {
+                            RelMemberType::Node => node_ids.push(member.member_id),
+                            RelMemberType::Way => way_ids.push(member.member_id),
+                            RelMemberType::Relation => {}
+                        }
+                    }
+                }
+            }
+        })?;
+        node_ids.sort_unstable();
+        node_ids.dedup();
+        way_ids.sort_unstable();
+        way_ids.dedup();
+
+        // Second pass: look up the member nodes and ways, grouped by the blocks that may
+        // contain them.
+        let member_blocks: Vec<ByteOffset> = entries
+            .iter()
+            .filter(|entry| {
+                contains_any(&node_ids, entry.node_ids()) || contains_any(&way_ids, entry.way_ids())
+            })
+            .map(|entry| entry.offset())
+            .collect();
+        let mut coords = HashMap::new();
+        let mut ways = HashSet::new();
+        self.for_each_block_at(&member_blocks, |block| {
+            for group in block.groups() {
+                for node in group.nodes() {
+                    if node_ids.binary_search(&node.id()).is_ok() {
+                        coords.insert(node.id(), node.coordinate());
+                    }
+                }
+                for dnode in group.dense_nodes() {
+                    if node_ids.binary_search(&dnode.id).is_ok() {
+                        coords.insert(dnode.id, dnode.coordinate());
+                    }
+                }
+                for way in group.ways() {
+                    if way_ids.binary_search(&way.id()).is_ok() {
+                        ways.insert(way.id());
+                    }
+                }
+            }
+        })?;
+        drop(node_ids);
+        drop(way_ids);
+
+        // Third pass: visit the relations with resolved members.
+        let mut members = vec![];
+        self.for_each_block_at(&relation_blocks, |block| {
+            for group in block.groups() {
+                for rel in group.relations() {
+                    members.clear();
+                    members.extend(rel.owned_members().into_iter().map(
+                        |(member_type, member_id, role)| {
+                            let coordinate = match member_type {
+                                RelMemberType::Node => coords.get(&member_id).cloned(),
+                                RelMemberType::Way | RelMemberType::Relation => None,
+                            };
+                            let present = match member_type {
+                                RelMemberType::Node => coordinate.is_some(),
+                                RelMemberType::Way => ways.contains(&member_id),
+                                RelMemberType::Relation => relation_ids.contains(&member_id),
+                            };
+                            ResolvedMember {
+                                member_type,
+                                member_id,
+                                role,
+                                coordinate,
+                                present,
+                            }
+                        },
+                    ));
+                    f(&rel, &members);
+                }
+            }
+        })
+    }
+
+    /// Returns the node that is closest to the given coordinate, or `None` if there is no node
+    /// within `max_radius_m` meters. Distances are computed with
+    /// `Coordinate::haversine_distance`.
+    ///
+    /// The first call reads all nodes of the file to build a grid of their coordinates, which
+    /// takes memory proportional to the number of nodes. Later calls only search the grid cells
+    /// within the radius and decode the single block that contains the closest node. Nodes on
+    /// the other side of the antimeridian are not found.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut reader = IndexedReader::from_path("tests/test.osm.pbf")?;
+    /// let gps_fix = Coordinate::new(52.11992, 11.62564);
+    ///
+    /// if let Some(node) = reader.nearest_node(gps_fix, 50.0)? {
+    ///     println!("closest node: {}", node.id);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn nearest_node(
+        &mut self,
+        coord: Coordinate,
+        max_radius_m: f64,
+    ) -> Result<Option<OwnedNode>> {
+        if self.node_grid.is_none() {
+            let mut grid = NodeGrid::default();
+            self.for_each_block(|blob_index, block| {
+                for group in block.groups() {
+                    for node in group.nodes() {
+                        grid.insert(node.id(), node.coordinate(), blob_index);
+                    }
+                    for dnode in group.dense_nodes() {
+                        grid.insert(dnode.id, dnode.coordinate(), blob_index);
+                    }
+                }
+            })?;
+            self.node_grid = Some(grid);
+        }
+
+        let nearest = match self.node_grid {
+            Some(ref grid) => grid.nearest(coord, max_radius_m),
+            None => None,
+        };
+        let nearest = match nearest {
+            Some(nearest) => nearest,
+            None => return Ok(None),
+        };
+
+        self.reader.seek_to_blob(nearest.blob_index)?;
+        if let Some(blob) = self.reader.next() {
+            if let BlobDecode::OsmData(block) = blob?.decode()? {
+                for element in block.into_owned_elements()? {
+                    if let OwnedElement::Node(node) = element {
+                        if node.id == nearest.id {
+                            return Ok(Some(node));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Decodes all data blocks from the start of the file and collects their offsets and id
+    /// ranges.
+    fn read_block_index(&mut self) -> Result<BlockIndex> {
+        let mut entries = vec![];
+        self.reader.seek_to_blob(0)?;
+        for blob in self.reader.by_ref() {
+            let blob = blob?;
+            // Seekable readers know the offset of every blob that is read successfully.
+            let offset = blob.offset().expect("offset of seekable reader");
+            if let BlobDecode::OsmData(block) = blob.decode()? {
+                entries.push(BlockIndexEntry::from_block(offset, &block));
+            }
+        }
+        Ok(BlockIndex { entries })
+    }
+
+    /// Decodes the data blocks at the given offsets and calls `f` on each of them.
+    fn for_each_block_at<F>(&mut self, offsets: &[ByteOffset], mut f: F) -> Result<()>
+    where
+        F: FnMut(&PrimitiveBlock),
+    {
+        for &offset in offsets {
+            self.reader.seek(offset)?;
+            if let Some(blob) = self.reader.next() {
+                if let BlobDecode::OsmData(block) = blob?.decode()? {
+                    f(&block);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes all data blocks from the start of the file and calls `f` on each of them together
+    /// with the index of its blob.
+    fn for_each_block<F>(&mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(usize, &PrimitiveBlock),
+    {
+        self.reader.seek_to_blob(0)?;
+        for (blob_index, blob) in self.reader.by_ref().enumerate() {
+            if let BlobDecode::OsmData(block) = blob?.decode()? {
+                f(blob_index, &block);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl IndexedReader<BufReader<File>> {
+    /// Tries to open the file at the given path and constructs an `IndexedReader` from this.
+    ///
+    /// # Errors
+    /// Returns the same errors that `std::fs::File::open` returns.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<IndexedReader<BufReader<File>>> {
+        Ok(IndexedReader {
+            reader: BlobReader::seekable_from_path(path)?,
+            node_grid: None,
+            block_index: None,
+        })
+    }
+}
+
+/// Magic bytes at the start of a serialized `BlockIndex`, including the version of the format.
+const BLOCK_INDEX_MAGIC: &[u8; 8] = b"OSMPBFI1";
+
+/// The offset and the id ranges of one `OSMData` blob in a `BlockIndex`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BlockIndexEntry {
+    offset: ByteOffset,
+    node_ids: Option<(i64, i64)>,
+    way_ids: Option<(i64, i64)>,
+    relation_ids: Option<(i64, i64)>,
+}
+
+impl BlockIndexEntry {
+    pub(crate) fn new(
+        offset: ByteOffset,
+        node_ids: Option<(i64, i64)>,
+        way_ids: Option<(i64, i64)>,
+        relation_ids: Option<(i64, i64)>,
+    ) -> BlockIndexEntry {
+        BlockIndexEntry {
+            offset,
+            node_ids,
+            way_ids,
+            relation_ids,
+        }
+    }
+
+    fn from_block(offset: ByteOffset, block: &PrimitiveBlock) -> BlockIndexEntry {
+        let groups = || block.groups();
+        BlockIndexEntry::new(
+            offset,
+            block.id_range(),
+            id_range(groups().flat_map(|g| g.ways()).map(|way| way.id())),
+            id_range(groups().flat_map(|g| g.relations()).map(|rel| rel.id())),
+        )
+    }
+
+    /// Returns the byte offset of the blob, which can be passed to `BlobReader::seek`.
+    pub fn offset(&self) -> ByteOffset {
+        self.offset
+    }
+
+    /// Returns the minimum and maximum id of the nodes in the blob or `None` if it contains no
+    /// nodes.
+    pub fn node_ids(&self) -> Option<(i64, i64)> {
+        self.node_ids
+    }
+
+    /// Returns the minimum and maximum id of the ways in the blob or `None` if it contains no
+    /// ways.
+    pub fn way_ids(&self) -> Option<(i64, i64)> {
+        self.way_ids
+    }
+
+    /// Returns the minimum and maximum id of the relations in the blob or `None` if it contains
+    /// no relations.
+    pub fn relation_ids(&self) -> Option<(i64, i64)> {
+        self.relation_ids
+    }
+
+    /// Returns `true` if the id lies in the id range of its type, i.e. if the blob may contain
+    /// the element. The ranges do not tell if there are gaps, so the blob has to be decoded to
+    /// be sure.
+    pub fn may_contain(&self, id: OsmId) -> bool {
+        let (range, id) = match id {
+            OsmId::Node(id) => (self.node_ids, id),
+            OsmId::Way(id) => (self.way_ids, id),
+            OsmId::Relation(id) => (self.relation_ids, id),
+        };
+        range.is_some_and(|(min, max)| min <= id && id <= max)
+    }
+
+    /// Writes the entry as a record of the offset, a byte that tells which ranges are present
+    /// and the present ranges, all integers in big endian.
+    pub(crate) fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let ranges = [self.node_ids, self.way_ids, self.relation_ids];
+        let mut mask = 0_u8;
+        for (i, range) in ranges.iter().enumerate() {
+            if range.is_some() {
+                mask |= 1 << i;
+            }
+        }
+
+        writer.write_u64::<BigEndian>(self.offset.0)?;
+        writer.write_u8(mask)?;
+        for &(min, max) in ranges.iter().flatten() {
+            writer.write_i64::<BigEndian>(min)?;
+            writer.write_i64::<BigEndian>(max)?;
+        }
+        Ok(())
+    }
+
+    /// Reads an entry that was written with `write_to` or returns `None` at the end of the
+    /// stream.
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<Option<BlockIndexEntry>> {
+        let mut offset = [0_u8; 8];
+        match read_up_to(reader, &mut offset)? {
+            0 => return Ok(None),
+            8 => {}
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "block index ends inside an entry",
+                ))
+            }
+        }
+
+        let mask = reader.read_u8()?;
+        if mask > 0b111 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid block index entry",
+            ));
+        }
+        let mut ranges = [None; 3];
+        for (i, range) in ranges.iter_mut().enumerate() {
+            if mask & (1 << i) != 0 {
+                let min = reader.read_i64::<BigEndian>()?;
+                let max = reader.read_i64::<BigEndian>()?;
+                *range = Some((min, max));
+            }
+        }
+
+        Ok(Some(BlockIndexEntry::new(
+            ByteOffset(u64::from_be_bytes(offset)),
+            ranges[0],
+            ranges[1],
+            ranges[2],
+        )))
+    }
+}
+
+/// An index of the `OSMData` blobs of a file with their offsets and the id ranges of their
+/// elements. It allows `IndexedReader` to decode only the blobs that may contain an element.
+///
+/// `ElementWriter::with_index` writes the index while writing a file, so that it is randomly
+/// accessible right away. `IndexedReader::block_index` builds it by reading an existing file,
+/// which can then be saved with `write_to`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BlockIndex {
+    entries: Vec<BlockIndexEntry>,
+}
+
+impl BlockIndex {
+    /// Reads an index that was written with `write_to` or `ElementWriter::with_index`.
+    ///
+    /// # Errors
+    /// Returns an error if reading fails or the data is not a complete index.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<BlockIndex> {
+        let mut magic = [0_u8; 8];
+        if read_up_to(&mut reader, &mut magic)? != magic.len() || &magic != BLOCK_INDEX_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a block index").into());
+        }
+
+        let mut entries = vec![];
+        while let Some(entry) = BlockIndexEntry::read_from(&mut reader)? {
+            entries.push(entry);
+        }
+        Ok(BlockIndex { entries })
+    }
+
+    /// Tries to open the file at the given path and reads an index from it (see `read_from`).
+    ///
+    /// # Errors
+    /// Returns the same errors that `std::fs::File::open` and `read_from` return.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<BlockIndex> {
+        BlockIndex::read_from(BufReader::new(File::open(path)?))
+    }
+
+    /// Writes the index in the format that `read_from` reads.
+    ///
+    /// # Errors
+    /// Returns an error if writing fails.
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<()> {
+        let mut writer = BufWriter::new(writer);
+        write_block_index_magic(&mut writer)?;
+        for entry in &self.entries {
+            entry.write_to(&mut writer)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Returns the entries in the order of the blobs in the file.
+    pub fn entries(&self) -> &[BlockIndexEntry] {
+        &self.entries
+    }
+}
+
+/// Writes the start of a serialized `BlockIndex`, which is followed by the entries.
+pub(crate) fn write_block_index_magic<W: Write>(writer: &mut W) -> io::Result<()> {
+    writer.write_all(BLOCK_INDEX_MAGIC)
+}
+
+/// Returns the minimum and maximum of the given ids or `None` if there are none.
+pub(crate) fn id_range<I: Iterator<Item = i64>>(ids: I) -> Option<(i64, i64)> {
+    ids.fold(None, |range, id| match range {
+        Some((min, max)) => Some((id.min(min), id.max(max))),
+        None => Some((id, id)),
+    })
+}
+
+/// A member of a relation, resolved by `IndexedReader::for_each_relation_resolved`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedMember {
+    /// The element type of the member.
+    pub member_type: RelMemberType,
+    /// The id of the member.
+    pub member_id: i64,
+    /// The role of the member. Invalid UTF-8 is replaced with `U+FFFD REPLACEMENT CHARACTER`.
+    pub role: String,
+    /// The coordinate of a node member or `None` if the member is not a node or the node is
+    /// missing in the file.
+    pub coordinate: Option<Coordinate>,
+    /// Whether the referenced element is present in the file. Members of relations at the
+    /// border of an extract are often missing.
+    pub present: bool,
+}
+
+/// Returns `true` if the sorted slice of ids contains an id within the given range.
+fn contains_any(sorted_ids: &[i64], range: Option<(i64, i64)>) -> bool {
+    match range {
+        Some((min, max)) => {
+            let start = sorted_ids.partition_point(|&id| id < min);
+            sorted_ids.get(start).is_some_and(|&id| id <= max)
+        }
+        None => false,
+    }
+}
+
+/// Size of the cells of a `NodeGrid` in degrees.
+const GRID_CELL_SIZE: f64 = 0.01;
+
+/// A node in a `NodeGrid`.
+#[derive(Clone, Copy, Debug)]
+struct GridNode {
+    id: i64,
+    coord: Coordinate,
+    blob_index: usize,
+}
+
+/// A spatial index of nodes that groups them in cells of `GRID_CELL_SIZE` degrees.
+#[derive(Clone, Debug, Default)]
+struct NodeGrid {
+    cells: HashMap<(i32, i32), Vec<GridNode>>,
+}
+
+impl NodeGrid {
+    fn cell(lat: f64, lon: f64) -> (i32, i32) {
+        (
+            (lat / GRID_CELL_SIZE).floor() as i32,
+            (lon / GRID_CELL_SIZE).floor() as i32,
+        )
+    }
+
+    fn insert(&mut self, id: i64, coord: Coordinate, blob_index: usize) {
+        self.cells
+            .entry(NodeGrid::cell(coord.lat, coord.lon))
+            .or_default()
+            .push(GridNode {
+                id,
+                coord,
+                blob_index,
+            });
+    }
+
+    fn nearest(&self, coord: Coordinate, max_radius_m: f64) -> Option<GridNode> {
+        // Degrees of longitude get shorter towards the poles.
+        let d_lat = max_radius_m / METERS_PER_DEGREE;
+        let cos_lat = (coord.lat.abs() + d_lat).min(90.0).to_radians().cos();
+        let d_lon = if cos_lat > 1e-6 {
+            d_lat / cos_lat
+        } else {
+            360.0
+        };
+
+        let (min_lat, min_lon) = NodeGrid::cell(
+            (coord.lat - d_lat).max(-90.0),
+            (coord.lon - d_lon).max(-180.0),
+        );
+        let (max_lat, max_lon) = NodeGrid::cell(
+            (coord.lat + d_lat).min(90.0),
+            (coord.lon + d_lon).min(180.0),
+        );
+        let num_cells = (i64::from(max_lat) - i64::from(min_lat) + 1)
+            * (i64::from(max_lon) - i64::from(min_lon) + 1);
+
+        let mut best: Option<(f64, GridNode)> = None;
+        let mut visit = |nodes: &Vec<GridNode>| {
+            for node in nodes {
+                let distance = coord.haversine_distance(&node.coord);
+                if distance <= max_radius_m && best.map_or(true, |(d, _)| distance < d) {
+                    best = Some((distance, *node));
+                }
+            }
+        };
+
+        // For large radii it is cheaper to look at all cells.
+        if num_cells > self.cells.len() as i64 {
+            self.cells.values().for_each(visit);
+        } else {
+            for lat in min_lat..=max_lat {
+                for lon in min_lon..=max_lon {
+                    if let Some(nodes) = self.cells.get(&(lat, lon)) {
+                        visit(nodes);
+                    }
+                }
+            }
+        }
+
+        best.map(|(_, node)| node)
+    }
+}
@@ -72,6 +72,7 @@ fn main() {
 #![recursion_limit = "1024"]
 
 extern crate byteorder;
+#[cfg(not(target_arch = "wasm32"))]
 extern crate memmap;
 extern crate protobuf;
 extern crate rayon;
@@ -80,22 +81,75 @@ extern crate rayon;
 extern crate flate2;
 
 #[cfg(not(feature = "system-libz"))]
-extern crate inflate;
+extern crate miniz_oxide;
+
+#[cfg(feature = "lzma")]
+extern crate xz2;
+
+#[cfg(feature = "arrow")]
+extern crate arrow;
+
+#[cfg(feature = "logging")]
+extern crate log;
+
+#[macro_use]
+mod logging;
 
 pub use blob::*;
 pub use block::*;
+#[cfg(feature = "arrow")]
+pub use columnar::*;
+pub use coordinate::*;
+pub use dedup::*;
 pub use dense::*;
 pub use elements::*;
-pub use error::{BlobError, Error, ErrorKind, Result};
+pub use error::{BlobError, Error, ErrorKind, Result, TagPart};
+pub use extract::*;
+pub use histogram::*;
+pub use history::*;
+pub use indexed::*;
+pub use merge::*;
+pub use metrics::*;
+#[cfg(not(target_arch = "wasm32"))]
 pub use mmap_blob::*;
+pub use node_store::*;
+#[cfg(feature = "o5m")]
+pub use o5m::*;
+pub use owned::*;
+pub use parents::*;
 pub use reader::*;
+pub use validate::*;
+pub use writer::*;
+#[cfg(feature = "xml")]
+pub use xml::*;
 
 pub mod blob;
 pub mod block;
+#[cfg(feature = "arrow")]
+pub mod columnar;
+pub mod coordinate;
+pub mod dedup;
 pub mod dense;
 pub mod elements;
 mod error;
+pub mod extract;
+pub mod histogram;
+pub mod history;
+pub mod indexed;
+pub mod merge;
+pub mod metrics;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod mmap_blob;
-mod proto;
+pub mod node_store;
+#[cfg(feature = "o5m")]
+pub mod o5m;
+pub mod owned;
+pub mod parents;
+pub mod proto;
 pub mod reader;
+pub mod testing;
 mod util;
+pub mod validate;
+pub mod writer;
+#[cfg(feature = "xml")]
+pub mod xml;
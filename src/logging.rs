@@ -0,0 +1,46 @@
+//! Internal macros for optional instrumentation with the `log` crate
+//!
+//! With the `logging` feature disabled, the macros expand to dead code that only type-checks the
+//! arguments, so they have no runtime cost.
+
+#[cfg(feature = "logging")]
+macro_rules! log_debug {
+    ($($arg:tt)+) => { ::log::debug!($($arg)+) };
+}
+
+#[cfg(not(feature = "logging"))]
+macro_rules! log_debug {
+    ($($arg:tt)+) => {
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    };
+}
+
+#[cfg(feature = "logging")]
+macro_rules! log_trace {
+    ($($arg:tt)+) => { ::log::trace!($($arg)+) };
+}
+
+#[cfg(not(feature = "logging"))]
+macro_rules! log_trace {
+    ($($arg:tt)+) => {
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    };
+}
+
+#[cfg(feature = "logging")]
+macro_rules! log_warn {
+    ($($arg:tt)+) => { ::log::warn!($($arg)+) };
+}
+
+#[cfg(not(feature = "logging"))]
+macro_rules! log_warn {
+    ($($arg:tt)+) => {
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    };
+}
@@ -0,0 +1,141 @@
+//! Merge two sorted streams of elements
+
+use elements::OsmId;
+use error::{new_error, ErrorKind, Result};
+use owned::OwnedElement;
+use std::cmp::Ordering;
+
+/// Merges two streams of elements that are sorted by type and id (nodes first, then ways, then
+/// relations, each by ascending id, which is the order of `OsmId`) into one sorted stream.
+///
+/// If both streams contain an element with the same type and id, `resolve` is called with the
+/// element of `a` and the element of `b`. It returns the element to keep, or `None` to drop both,
+/// e.g. to apply a deletion. Only one element of each stream is buffered, so this works for files
+/// of any size. Use `ElementReader::owned_elements` to turn a PBF file into a stream.
+///
+/// The iterator returns an error of kind `ErrorKind::UnsortedElements` if an input turns out not
+/// to be sorted. Once an error is returned, the iterator yields `None`.
+///
+/// # Example
+/// Apply a small update to a file, keeping the updated version of each element:
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let base = ElementReader::from_path("tests/test.osm.pbf")?;
+/// let update = vec![Ok(OwnedElement::Node(OwnedNode {
+///     id: 106,
+///     ..OwnedNode::default()
+/// }))];
+///
+/// let mut writer = ElementWriter::new(vec![]);
+/// for element in merge_sorted(base.owned_elements(), update, |_, new| Some(new)) {
+///     writer.write_element(element?)?;
+/// }
+/// let bytes = writer.finish()?;
+///
+/// let node = ElementReader::new(&bytes[..])
+///     .read_all()?
+///     .into_iter()
+///     .find(|element| element.osm_id() == OsmId::Node(106))
+///     .unwrap();
+/// assert!(node.tags().is_empty());
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+pub fn merge_sorted<A, B, F>(a: A, b: B, resolve: F) -> MergeSorted<A::IntoIter, B::IntoIter, F>
+where
+    A: IntoIterator<Item = Result<OwnedElement>>,
+    B: IntoIterator<Item = Result<OwnedElement>>,
+    F: FnMut(OwnedElement, OwnedElement) -> Option<OwnedElement>,
+{
+    MergeSorted {
+        a: SortedInput::new(a.into_iter()),
+        b: SortedInput::new(b.into_iter()),
+        resolve,
+        failed: false,
+    }
+}
+
+/// An iterator that merges two sorted streams of elements (see `merge_sorted`).
+pub struct MergeSorted<A, B, F> {
+    a: SortedInput<A>,
+    b: SortedInput<B>,
+    resolve: F,
+    failed: bool,
+}
+
+impl<A, B, F> Iterator for MergeSorted<A, B, F>
+where
+    A: Iterator<Item = Result<OwnedElement>>,
+    B: Iterator<Item = Result<OwnedElement>>,
+    F: FnMut(OwnedElement, OwnedElement) -> Option<OwnedElement>,
+{
+    type Item = Result<OwnedElement>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.failed {
+            if let Err(e) = self.a.fill().and_then(|_| self.b.fill()) {
+                self.failed = true;
+                return Some(Err(e));
+            }
+
+            let element = match (self.a.next.take(), self.b.next.take()) {
+                (Some(x), Some(y)) => match x.osm_id().cmp(&y.osm_id()) {
+                    Ordering::Less => {
+                        self.b.next = Some(y);
+                        x
+                    }
+                    Ordering::Greater => {
+                        self.a.next = Some(x);
+                        y
+                    }
+                    Ordering::Equal => match (self.resolve)(x, y) {
+                        Some(element) => element,
+                        None => continue,
+                    },
+                },
+                (Some(x), None) => x,
+                (None, Some(y)) => y,
+                (None, None) => return None,
+            };
+            return Some(Ok(element));
+        }
+        None
+    }
+}
+
+/// One input of `MergeSorted` with its next element and a check of the order.
+struct SortedInput<I> {
+    iter: I,
+    next: Option<OwnedElement>,
+    last_id: Option<OsmId>,
+}
+
+impl<I: Iterator<Item = Result<OwnedElement>>> SortedInput<I> {
+    fn new(iter: I) -> SortedInput<I> {
+        SortedInput {
+            iter,
+            next: None,
+            last_id: None,
+        }
+    }
+
+    /// Reads the next element if none is buffered.
+    fn fill(&mut self) -> Result<()> {
+        if self.next.is_some() {
+            return Ok(());
+        }
+        if let Some(element) = self.iter.next() {
+            let element = element?;
+            let id = element.osm_id();
+            if self.last_id.is_some_and(|last_id| id < last_id) {
+                return Err(new_error(ErrorKind::UnsortedElements { id }));
+            }
+            self.last_id = Some(id);
+            self.next = Some(element);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+//! Counters that describe the work of a reading session
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+/// Counters that a `BlobReader` and an `ElementReader` accumulate while reading, e.g. to export
+/// them to a monitoring system. The counters are updated atomically, so they can be read from
+/// another thread while the parallel methods of `ElementReader` are running (see
+/// `ElementReader::shared_metrics`).
+///
+/// A `BlobReader` only counts the blobs and bytes that it reads. The decoding counters are
+/// updated by the methods of `ElementReader`, blobs that are decoded directly with
+/// `Blob::decode` are not included.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+/// let metrics = reader.shared_metrics();
+///
+/// reader.for_each(|_| {})?;
+///
+/// assert_eq!(metrics.blobs_read(), 2);
+/// assert_eq!(metrics.decode_errors(), 0);
+/// println!("decoding took {:?}", metrics.decode_time());
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Debug, Default)]
+pub struct ReaderMetrics {
+    blobs_read: AtomicU64,
+    bytes_read: AtomicU64,
+    decode_errors: AtomicU64,
+    bytes_decompressed: AtomicU64,
+    decode_nanos: AtomicU64,
+}
+
+impl ReaderMetrics {
+    /// Returns the number of blobs that were read successfully. Blobs that are skipped without
+    /// parsing their content (e.g. by `Iterator::nth`) are not counted.
+    pub fn blobs_read(&self) -> u64 {
+        self.blobs_read.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of bytes that were read from the underlying reader, including the
+    /// headers and the content of skipped blobs (even if a seekable reader seeks past it).
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of blobs that failed to decode.
+    pub fn decode_errors(&self) -> u64 {
+        self.decode_errors.load(Ordering::Relaxed)
+    }
+
+    /// Returns the total size of the decoded content of compressed blobs in bytes, as declared by
+    /// their `raw_size` field. Blobs that are stored uncompressed are not included.
+    pub fn bytes_decompressed(&self) -> u64 {
+        self.bytes_decompressed.load(Ordering::Relaxed)
+    }
+
+    /// Returns the time that was spent decompressing and parsing blobs, summed over all threads.
+    /// This is always zero on `wasm32`, which has no clock.
+    pub fn decode_time(&self) -> Duration {
+        Duration::from_nanos(self.decode_nanos.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn add_blob(&self, bytes: u64) {
+        self.blobs_read.fetch_add(1, Ordering::Relaxed);
+        self.add_bytes(bytes);
+    }
+
+    pub(crate) fn add_bytes(&self, bytes: u64) {
+        self.bytes_read.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Measures the time of a decoding operation and counts its outcome.
+    pub(crate) fn time_decode<T, E, F>(&self, decompressed_size: u64, decode: F) -> Result<T, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+    {
+        #[cfg(not(target_arch = "wasm32"))]
+        let start = Instant::now();
+        let result = decode();
+        #[cfg(not(target_arch = "wasm32"))]
+        self.decode_nanos
+            .fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+
+        match result {
+            Ok(_) => self
+                .bytes_decompressed
+                .fetch_add(decompressed_size, Ordering::Relaxed),
+            Err(_) => self.decode_errors.fetch_add(1, Ordering::Relaxed),
+        };
+        result
+    }
+}
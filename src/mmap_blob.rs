@@ -100,12 +100,22 @@ impl<'a> MmapBlob<'a> {
             }
             "OSMData" => {
                 let block: osmformat::PrimitiveBlock = decode_blob(&blob)?;
-                Ok(BlobDecode::OsmData(PrimitiveBlock::new(block)))
+                Ok(BlobDecode::OsmData(PrimitiveBlock::new(block)?))
             }
             x => Ok(BlobDecode::Unknown(x)),
         }
     }
 
+    /// Returns the raw `indexdata` field of the blob header or `None` if it is not set (see
+    /// [`Blob::index_data`](../blob/struct.Blob.html#method.index_data)).
+    pub fn index_data(&self) -> Option<&[u8]> {
+        if self.header.has_indexdata() {
+            Some(self.header.get_indexdata())
+        } else {
+            None
+        }
+    }
+
     /// Returns the type of a blob without decoding its content.
     pub fn get_type(&self) -> BlobType {
         match self.header.get_field_type() {
@@ -0,0 +1,298 @@
+//! Compact in-memory stores of node coordinates
+
+use elements::OsmId;
+use error::{new_error, ErrorKind, Result};
+use std::iter::FromIterator;
+use std::mem;
+use util::{unzigzag, write_varint, zigzag};
+
+/// Number of micro-degrees per degree.
+const MICRO_DEGREES: f64 = 1_000_000.0;
+
+/// Number of nodes in each block of a `CompactNodeStore`. Larger blocks need less memory for the
+/// sparse index, but a lookup decodes up to this many nodes.
+const COMPACT_BLOCK_SIZE: usize = 64;
+
+/// A map from node ids to coordinates that needs 16 bytes per node, a fraction of what a
+/// `HashMap<i64, (f64, f64)>` needs. Coordinates are stored in micro-degrees (see
+/// `Node::lat_lon_micro`) in a vector that is sorted by id, so a lookup is a binary search.
+///
+/// Use `ElementReader::collect_node_coords` to load the nodes of a file, e.g. to resolve the
+/// geometries of ways, or collect an iterator of `(id, lat, lon)` tuples in micro-degrees. If an
+/// id occurs several times, the last occurrence is kept.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// let store: NodeCoordStore = vec![(7, 52_123_456, 13_000_000), (3, -1_000_000, 0)]
+///     .into_iter()
+///     .collect();
+///
+/// assert_eq!(store.len(), 2);
+/// assert_eq!(store.get(3), Some((-1.0, 0.0)));
+/// assert_eq!(store.get_micro(7), Some((52_123_456, 13_000_000)));
+/// assert_eq!(store.get(5), None);
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct NodeCoordStore {
+    nodes: Vec<(i64, i32, i32)>,
+}
+
+impl NodeCoordStore {
+    /// Creates an empty store.
+    pub fn new() -> NodeCoordStore {
+        NodeCoordStore::default()
+    }
+
+    /// Creates a store from `(id, lat, lon)` tuples in micro-degrees in any order.
+    pub(crate) fn from_unsorted(mut nodes: Vec<(i64, i32, i32)>) -> NodeCoordStore {
+        // Nodes of PBF files are usually sorted already.
+        if !nodes.windows(2).all(|w| w[0].0 < w[1].0) {
+            // A stable sort keeps duplicates in their original order, so that the last one
+            // can be kept.
+            nodes.sort_by_key(|&(id, _, _)| id);
+            nodes.dedup_by(|next, kept| {
+                if next.0 == kept.0 {
+                    *kept = *next;
+                    true
+                } else {
+                    false
+                }
+            });
+        }
+        nodes.shrink_to_fit();
+        NodeCoordStore { nodes }
+    }
+
+    /// Returns the latitude and longitude of the node in degrees or `None` if the store does not
+    /// contain the node.
+    pub fn get(&self, id: i64) -> Option<(f64, f64)> {
+        self.get_micro(id).map(|(lat, lon)| {
+            (
+                f64::from(lat) / MICRO_DEGREES,
+                f64::from(lon) / MICRO_DEGREES,
+            )
+        })
+    }
+
+    /// Returns the latitude and longitude of the node in micro-degrees, as they are stored, or
+    /// `None` if the store does not contain the node.
+    pub fn get_micro(&self, id: i64) -> Option<(i32, i32)> {
+        self.nodes
+            .binary_search_by_key(&id, |&(id, _, _)| id)
+            .ok()
+            .map(|index| {
+                let (_, lat, lon) = self.nodes[index];
+                (lat, lon)
+            })
+    }
+
+    /// Returns the number of nodes in the store.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns `true` if the store contains no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Copies the nodes into a `CompactNodeStore`, which needs less memory but has slower
+    /// lookups.
+    pub fn to_compact(&self) -> CompactNodeStore {
+        let mut store = CompactNodeStore::new();
+        for &(id, lat, lon) in &self.nodes {
+            store.push(id, lat, lon);
+        }
+        store.shrink_to_fit();
+        store
+    }
+}
+
+impl FromIterator<(i64, i32, i32)> for NodeCoordStore {
+    fn from_iter<I: IntoIterator<Item = (i64, i32, i32)>>(iter: I) -> NodeCoordStore {
+        NodeCoordStore::from_unsorted(iter.into_iter().collect())
+    }
+}
+
+/// The first node of a block of a `CompactNodeStore`, which is stored uncompressed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct BlockStart {
+    id: i64,
+    lat: i32,
+    lon: i32,
+    /// Offset of the encoded deltas of the following nodes in `CompactNodeStore::deltas`.
+    offset: usize,
+}
+
+/// A map from node ids to coordinates in micro-degrees that is optimized for memory instead of
+/// lookup speed. It exploits that the ids of consecutive nodes in a sorted file differ by small
+/// amounts and that their coordinates are close: nodes are grouped into blocks of 64, and only
+/// the first node of each block is stored in full. The other nodes are stored as the varint
+/// encoded difference of the id and the zigzag and varint encoded differences of the coordinates
+/// to the previous node. For the mostly sequential ids of a planet file this needs only a few
+/// bytes per node instead of the 16 bytes of `NodeCoordStore`.
+///
+/// A lookup finds the block with a binary search over the first nodes and then decodes the
+/// block up to the node, which is slower than a lookup in `NodeCoordStore`.
+///
+/// Nodes have to be inserted in ascending order of their ids.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let mut store = CompactNodeStore::new();
+/// ElementReader::from_path("tests/test.osm.pbf")?.for_each(|element| {
+///     if let Element::DenseNode(dnode) = element {
+///         let (lat, lon) = dnode.lat_lon_micro();
+///         store.insert(dnode.id, lat, lon).unwrap();
+///     }
+/// })?;
+///
+/// assert_eq!(store.len(), 3);
+/// assert!(store.get(106).is_some());
+/// assert!(store.get(107).is_none());
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CompactNodeStore {
+    blocks: Vec<BlockStart>,
+    deltas: Vec<u8>,
+    len: usize,
+    /// The last inserted node.
+    last: Option<(i64, i32, i32)>,
+}
+
+impl CompactNodeStore {
+    /// Creates an empty store.
+    pub fn new() -> CompactNodeStore {
+        CompactNodeStore::default()
+    }
+
+    /// Adds a node with coordinates in micro-degrees (see `Node::lat_lon_micro`).
+    ///
+    /// # Errors
+    /// Returns an error of kind `ErrorKind::UnsortedElements` if the id is not greater than the
+    /// id of the last inserted node. The store is not changed in that case.
+    pub fn insert(&mut self, id: i64, lat: i32, lon: i32) -> Result<()> {
+        if let Some((last_id, _, _)) = self.last {
+            if id <= last_id {
+                return Err(new_error(ErrorKind::UnsortedElements {
+                    id: OsmId::Node(id),
+                }));
+            }
+        }
+        self.push(id, lat, lon);
+        Ok(())
+    }
+
+    /// Adds a node whose id is known to be greater than the last one.
+    fn push(&mut self, id: i64, lat: i32, lon: i32) {
+        match self.last {
+            Some((last_id, last_lat, last_lon)) if self.len % COMPACT_BLOCK_SIZE != 0 => {
+                // The difference of two ids can exceed the range of i64, but not of u64.
+                write_varint(&mut self.deltas, (id as u64).wrapping_sub(last_id as u64));
+                write_varint(
+                    &mut self.deltas,
+                    zigzag(i64::from(lat) - i64::from(last_lat)),
+                );
+                write_varint(
+                    &mut self.deltas,
+                    zigzag(i64::from(lon) - i64::from(last_lon)),
+                );
+            }
+            _ => self.blocks.push(BlockStart {
+                id,
+                lat,
+                lon,
+                offset: self.deltas.len(),
+            }),
+        }
+        self.last = Some((id, lat, lon));
+        self.len += 1;
+    }
+
+    /// Returns the latitude and longitude of the node in degrees or `None` if the store does not
+    /// contain the node.
+    pub fn get(&self, id: i64) -> Option<(f64, f64)> {
+        self.get_micro(id).map(|(lat, lon)| {
+            (
+                f64::from(lat) / MICRO_DEGREES,
+                f64::from(lon) / MICRO_DEGREES,
+            )
+        })
+    }
+
+    /// Returns the latitude and longitude of the node in micro-degrees or `None` if the store
+    /// does not contain the node.
+    pub fn get_micro(&self, id: i64) -> Option<(i32, i32)> {
+        // The block with the greatest first id that is not greater than `id`
+        let index = match self.blocks.binary_search_by_key(&id, |block| block.id) {
+            Ok(index) => {
+                let block = self.blocks[index];
+                return Some((block.lat, block.lon));
+            }
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+
+        let block = self.blocks[index];
+        let count = (self.len - index * COMPACT_BLOCK_SIZE).min(COMPACT_BLOCK_SIZE);
+        let (mut node_id, mut lat, mut lon) = (block.id, block.lat, block.lon);
+        let mut pos = block.offset;
+        for _ in 1..count {
+            node_id = (node_id as u64).wrapping_add(read_varint(&self.deltas, &mut pos)) as i64;
+            lat = (i64::from(lat) + unzigzag(read_varint(&self.deltas, &mut pos))) as i32;
+            lon = (i64::from(lon) + unzigzag(read_varint(&self.deltas, &mut pos))) as i32;
+            if node_id >= id {
+                return if node_id == id {
+                    Some((lat, lon))
+                } else {
+                    None
+                };
+            }
+        }
+        None
+    }
+
+    /// Returns the number of nodes in the store.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the store contains no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bytes that the store allocated on the heap.
+    pub fn memory_usage(&self) -> usize {
+        self.blocks.capacity() * mem::size_of::<BlockStart>() + self.deltas.capacity()
+    }
+
+    /// Frees unused capacity, e.g. after inserting the last node.
+    pub fn shrink_to_fit(&mut self) {
+        self.blocks.shrink_to_fit();
+        self.deltas.shrink_to_fit();
+    }
+}
+
+/// Reads a varint that `write_varint` wrote at `pos` and advances `pos` past it.
+fn read_varint(buf: &[u8], pos: &mut usize) -> u64 {
+    let mut value = 0_u64;
+    let mut shift = 0;
+    loop {
+        let byte = buf[*pos];
+        *pos += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte < 0x80 {
+            return value;
+        }
+        shift += 7;
+    }
+}
@@ -0,0 +1,312 @@
+//! Write elements in the o5m format
+//!
+//! o5m is a compact format that is read sequentially, supported e.g. by osmconvert, osmfilter and
+//! osmium (see [OSM wiki](https://wiki.openstreetmap.org/wiki/O5m)). It stores the same data as
+//! PBF files but without compression blocks: each element is a single dataset whose numbers are
+//! delta coded against the previous element and whose strings refer back to recently written
+//! strings.
+
+use elements::{Element, RelMemberType};
+use error::Result;
+use owned::{owned_element, OwnedElement, OwnedInfo, OwnedNode, OwnedRelation, OwnedWay};
+use std::collections::HashMap;
+use std::io::Write;
+use std::mem;
+use util::{raw_coordinate, write_varint, zigzag};
+
+const NODE: u8 = 0x10;
+const WAY: u8 = 0x11;
+const RELATION: u8 = 0x12;
+const HEADER: u8 = 0xe0;
+const END: u8 = 0xfe;
+const RESET: u8 = 0xff;
+
+/// Number of strings that a reader keeps for back references.
+const STRING_TABLE_SIZE: u64 = 15_000;
+
+/// Strings that are longer than this (key and value together) are not stored in the string
+/// table.
+const MAX_TABLE_STRING_LEN: usize = 250;
+
+/// A writer for o5m files.
+///
+/// Elements are written as they come, so all nodes should be written before the ways and all
+/// ways before the relations, as most readers expect. Coordinates are rounded to 100 nanodegrees
+/// and time stamps to seconds, which is the precision of the o5m format.
+///
+/// Call `finish` after writing the last element to write the end marker of the file.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let mut writer = O5mWriter::new(vec![]);
+/// let mut result = Ok(());
+/// ElementReader::from_path("tests/test.osm.pbf")?.for_each(|element| {
+///     if result.is_ok() {
+///         result = writer.write_borrowed(&element);
+///     }
+/// })?;
+/// result?;
+///
+/// let bytes = writer.finish()?;
+/// assert_eq!(&bytes[..7], b"\xff\xe0\x04o5m2");
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct O5mWriter<W: Write> {
+    writer: W,
+    header_written: bool,
+    /// Type of the previous dataset; the delta coding is reset when it changes.
+    last_type: Option<u8>,
+    deltas: Deltas,
+    strings: StringTable,
+    buf: Vec<u8>,
+}
+
+/// The values that the next dataset is delta coded against.
+#[derive(Debug, Default)]
+struct Deltas {
+    id: i64,
+    timestamp: i64,
+    changeset: i64,
+    lon: i64,
+    lat: i64,
+    /// Previous node, way and relation references.
+    refs: [i64; 3],
+}
+
+/// The strings that a reader remembers, to replace repeated strings by back references.
+#[derive(Debug, Default)]
+struct StringTable {
+    /// Maps each remembered string to the number of strings that were stored before it.
+    positions: HashMap<Vec<u8>, u64>,
+    /// The last `STRING_TABLE_SIZE` strings, indexed by their position modulo the size. A string
+    /// is removed from `positions` when its slot is reused, so memory stays bounded.
+    slots: Vec<Vec<u8>>,
+    len: u64,
+}
+
+impl StringTable {
+    /// Returns the back reference to the string if a reader still remembers it.
+    fn reference(&self, s: &[u8]) -> Option<u64> {
+        self.positions.get(s).map(|&pos| self.len - pos)
+    }
+
+    fn insert(&mut self, s: Vec<u8>) {
+        let slot = (self.len % STRING_TABLE_SIZE) as usize;
+        if slot < self.slots.len() {
+            let evicted = mem::replace(&mut self.slots[slot], s.clone());
+            if self.positions.get(&evicted) == Some(&(self.len - STRING_TABLE_SIZE)) {
+                self.positions.remove(&evicted);
+            }
+        } else {
+            self.slots.push(s.clone());
+        }
+        self.positions.insert(s, self.len);
+        self.len += 1;
+    }
+}
+
+impl<W: Write> O5mWriter<W> {
+    /// Creates a new `O5mWriter` that writes to the given writer.
+    pub fn new(writer: W) -> O5mWriter<W> {
+        O5mWriter {
+            writer,
+            header_written: false,
+            last_type: None,
+            deltas: Deltas::default(),
+            strings: StringTable::default(),
+            buf: vec![],
+        }
+    }
+
+    /// Writes an element.
+    ///
+    /// # Errors
+    /// Returns an error if writing to the underlying writer fails.
+    pub fn write_element(&mut self, element: OwnedElement) -> Result<()> {
+        match element {
+            OwnedElement::Node(ref node) => self.write_node(node),
+            OwnedElement::Way(ref way) => self.write_way(way),
+            OwnedElement::Relation(ref rel) => self.write_relation(rel),
+        }
+    }
+
+    /// Writes a copy of a borrowed element, like `write_element`.
+    ///
+    /// # Errors
+    /// Returns an error if a string of the element is not valid UTF-8 or if writing to the
+    /// underlying writer fails.
+    pub fn write_borrowed(&mut self, element: &Element) -> Result<()> {
+        self.write_element(owned_element(element)?)
+    }
+
+    /// Writes the end marker of the file and returns the underlying writer.
+    ///
+    /// # Errors
+    /// Returns an error if writing to the underlying writer fails.
+    pub fn finish(mut self) -> Result<W> {
+        self.start_dataset(None)?;
+        self.writer.write_all(&[END])?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    /// Writes the file header before the first dataset and resets the delta coding whenever the
+    /// element type changes.
+    fn start_dataset(&mut self, dataset_type: Option<u8>) -> Result<()> {
+        if !self.header_written {
+            self.writer.write_all(&[RESET, HEADER, 0x04])?;
+            self.writer.write_all(b"o5m2")?;
+            self.header_written = true;
+            self.last_type = dataset_type;
+        } else if dataset_type.is_some() && dataset_type != self.last_type {
+            self.writer.write_all(&[RESET])?;
+            self.deltas = Deltas::default();
+            self.strings = StringTable::default();
+            self.last_type = dataset_type;
+        }
+        self.buf.clear();
+        Ok(())
+    }
+
+    fn finish_dataset(&mut self, dataset_type: u8) -> Result<()> {
+        let mut header = vec![dataset_type];
+        write_varint(&mut header, self.buf.len() as u64);
+        self.writer.write_all(&header)?;
+        self.writer.write_all(&self.buf)?;
+        Ok(())
+    }
+
+    fn write_node(&mut self, node: &OwnedNode) -> Result<()> {
+        self.start_dataset(Some(NODE))?;
+        self.write_id_and_info(node.id, &node.info);
+        if node.info.visible {
+            let lon = raw_coordinate(node.nano_lon);
+            let lat = raw_coordinate(node.nano_lat);
+            write_varint(&mut self.buf, zigzag(lon - self.deltas.lon));
+            write_varint(&mut self.buf, zigzag(lat - self.deltas.lat));
+            self.deltas.lon = lon;
+            self.deltas.lat = lat;
+            self.write_tags(&node.tags);
+        }
+        self.finish_dataset(NODE)
+    }
+
+    fn write_way(&mut self, way: &OwnedWay) -> Result<()> {
+        self.start_dataset(Some(WAY))?;
+        self.write_id_and_info(way.id, &way.info);
+        if way.info.visible {
+            let mut refs = vec![];
+            for &node_id in &way.refs {
+                write_varint(&mut refs, zigzag(node_id - self.deltas.refs[0]));
+                self.deltas.refs[0] = node_id;
+            }
+            write_varint(&mut self.buf, refs.len() as u64);
+            self.buf.extend_from_slice(&refs);
+            self.write_tags(&way.tags);
+        }
+        self.finish_dataset(WAY)
+    }
+
+    fn write_relation(&mut self, rel: &OwnedRelation) -> Result<()> {
+        self.start_dataset(Some(RELATION))?;
+        self.write_id_and_info(rel.id, &rel.info);
+        if rel.info.visible {
+            let mut members = vec![];
+            for member in &rel.members {
+                let (index, type_char) = match member.member_type {
+                    RelMemberType::Node => (0, b'0'),
+                    RelMemberType::Way => (1, b'1'),
+                    RelMemberType::Relation => (2, b'2'),
+                };
+                write_varint(
+                    &mut members,
+                    zigzag(member.member_id - self.deltas.refs[index]),
+                );
+                self.deltas.refs[index] = member.member_id;
+
+                let mut type_and_role = vec![type_char];
+                type_and_role.extend_from_slice(member.role.as_bytes());
+                type_and_role.push(0);
+                self.write_string(&mut members, type_and_role);
+            }
+            write_varint(&mut self.buf, members.len() as u64);
+            self.buf.extend_from_slice(&members);
+            self.write_tags(&rel.tags);
+        }
+        self.finish_dataset(RELATION)
+    }
+
+    fn write_id_and_info(&mut self, id: i64, info: &OwnedInfo) {
+        write_varint(&mut self.buf, zigzag(id - self.deltas.id));
+        self.deltas.id = id;
+
+        let version = match info.version {
+            Some(version) if version > 0 => version,
+            _ => {
+                // Elements without a version carry no other metadata either.
+                self.buf.push(0);
+                return;
+            }
+        };
+        write_varint(&mut self.buf, version as u64);
+
+        let timestamp = info.milli_timestamp.unwrap_or(0).div_euclid(1000);
+        write_varint(&mut self.buf, zigzag(timestamp - self.deltas.timestamp));
+        self.deltas.timestamp = timestamp;
+        if timestamp == 0 {
+            return;
+        }
+
+        let changeset = info.changeset.unwrap_or(0);
+        write_varint(&mut self.buf, zigzag(changeset - self.deltas.changeset));
+        self.deltas.changeset = changeset;
+
+        let mut uid_and_user = vec![];
+        match info.uid {
+            Some(uid) if uid > 0 => write_varint(&mut uid_and_user, uid as u64),
+            _ => {}
+        }
+        uid_and_user.push(0);
+        if let Some(ref user) = info.user {
+            uid_and_user.extend_from_slice(user.as_bytes());
+        }
+        uid_and_user.push(0);
+        let mut buf = std::mem::take(&mut self.buf);
+        self.write_string(&mut buf, uid_and_user);
+        self.buf = buf;
+    }
+
+    fn write_tags(&mut self, tags: &[(String, String)]) {
+        let mut buf = std::mem::take(&mut self.buf);
+        for (key, value) in tags {
+            let mut pair = Vec::with_capacity(key.len() + value.len() + 2);
+            pair.extend_from_slice(key.as_bytes());
+            pair.push(0);
+            pair.extend_from_slice(value.as_bytes());
+            pair.push(0);
+            self.write_string(&mut buf, pair);
+        }
+        self.buf = buf;
+    }
+
+    /// Writes a zero terminated string (or pair of strings), either as a back reference or
+    /// inline.
+    fn write_string(&mut self, buf: &mut Vec<u8>, s: Vec<u8>) {
+        if let Some(reference) = self.strings.reference(&s) {
+            write_varint(buf, reference);
+            return;
+        }
+        buf.push(0);
+        buf.extend_from_slice(&s);
+        // The length limit excludes the terminating zeros.
+        if s.len() - s.iter().filter(|&&b| b == 0).count() <= MAX_TABLE_STRING_LEN {
+            self.strings.insert(s);
+        }
+    }
+}
@@ -0,0 +1,417 @@
+//! Owned elements that do not borrow from a `PrimitiveBlock`
+
+use block::DecodedStrings;
+use dense::{DenseNode, DenseNodeIter};
+use elements::{Element, ElementType, Info, Node, OsmId, RelMemberType, Relation, Way};
+use error::Result;
+use proto::osmformat;
+use util::zigzag;
+
+/// An owned version of `Element`. Unlike `Element` it does not borrow from a `PrimitiveBlock`,
+/// so it can be stored or sent to another thread. Dense nodes are converted to `OwnedNode`s.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum OwnedElement {
+    /// A node.
+    Node(OwnedNode),
+
+    /// A way.
+    Way(OwnedWay),
+
+    /// A relation.
+    Relation(OwnedRelation),
+}
+
+impl OwnedElement {
+    /// Returns the element id.
+    pub fn id(&self) -> i64 {
+        match *self {
+            OwnedElement::Node(ref node) => node.id,
+            OwnedElement::Way(ref way) => way.id,
+            OwnedElement::Relation(ref rel) => rel.id,
+        }
+    }
+
+    /// Returns the id of this element together with its type (see `Element::osm_id`).
+    pub fn osm_id(&self) -> OsmId {
+        match *self {
+            OwnedElement::Node(ref node) => OsmId::Node(node.id),
+            OwnedElement::Way(ref way) => OsmId::Way(way.id),
+            OwnedElement::Relation(ref rel) => OsmId::Relation(rel.id),
+        }
+    }
+
+    /// Returns the type of this element.
+    pub fn element_type(&self) -> ElementType {
+        match *self {
+            OwnedElement::Node(_) => ElementType::Node,
+            OwnedElement::Way(_) => ElementType::Way,
+            OwnedElement::Relation(_) => ElementType::Relation,
+        }
+    }
+
+    /// Returns the tags of this element as pairs of strings (key and value).
+    pub fn tags(&self) -> &[(String, String)] {
+        match *self {
+            OwnedElement::Node(ref node) => &node.tags,
+            OwnedElement::Way(ref way) => &way.tags,
+            OwnedElement::Relation(ref rel) => &rel.tags,
+        }
+    }
+
+    /// Returns additional metadata for this element.
+    pub fn info(&self) -> &OwnedInfo {
+        match *self {
+            OwnedElement::Node(ref node) => &node.info,
+            OwnedElement::Way(ref way) => &way.info,
+            OwnedElement::Relation(ref rel) => &rel.info,
+        }
+    }
+
+    /// Estimates the number of bytes the element takes up in an encoded `PrimitiveBlock`: the id
+    /// and coordinates, the tag and role indices, the delta coded refs or members and the
+    /// metadata. The strings are counted with their full length, as if no other element of the
+    /// block shared them, so the estimate is an upper bound for most elements. `ElementWriter`
+    /// uses this to limit the size of its blocks.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// let way = OwnedWay {
+    ///     id: 1,
+    ///     refs: (1..=1000).collect(),
+    ///     ..OwnedWay::default()
+    /// };
+    /// let hint = OwnedElement::Way(way).encoded_size_hint();
+    /// assert!(hint > 1000 && hint < 1100);
+    /// ```
+    pub fn encoded_size_hint(&self) -> usize {
+        match *self {
+            OwnedElement::Node(ref node) => {
+                // Coordinates are usually stored in units of 100 nanodegrees.
+                signed_varint_len(node.id)
+                    + signed_varint_len(node.nano_lat / 100)
+                    + signed_varint_len(node.nano_lon / 100)
+                    + tags_size_hint(&node.tags)
+                    + info_size_hint(&node.info)
+            }
+            OwnedElement::Way(ref way) => {
+                let mut last_ref = 0;
+                let refs: usize = way
+                    .refs
+                    .iter()
+                    .map(|&node_ref| {
+                        let len = signed_varint_len(node_ref - last_ref);
+                        last_ref = node_ref;
+                        len
+                    })
+                    .sum();
+                MESSAGE_OVERHEAD
+                    + signed_varint_len(way.id)
+                    + refs
+                    + tags_size_hint(&way.tags)
+                    + info_size_hint(&way.info)
+            }
+            OwnedElement::Relation(ref rel) => {
+                let mut last_id = 0;
+                let members: usize = rel
+                    .members
+                    .iter()
+                    .map(|member| {
+                        let len = signed_varint_len(member.member_id - last_id)
+                            + STRING_INDEX_SIZE
+                            + string_size_hint(&member.role)
+                            + 1;
+                        last_id = member.member_id;
+                        len
+                    })
+                    .sum();
+                MESSAGE_OVERHEAD
+                    + signed_varint_len(rel.id)
+                    + members
+                    + tags_size_hint(&rel.tags)
+                    + info_size_hint(&rel.info)
+            }
+        }
+    }
+}
+
+/// Assumed size of an encoded index into the stringtable (enough for 2^21 strings).
+const STRING_INDEX_SIZE: usize = 3;
+
+/// Assumed size of the field key and length prefixes of a way or relation message.
+const MESSAGE_OVERHEAD: usize = 8;
+
+/// Returns the size of a zigzag encoded varint.
+fn signed_varint_len(value: i64) -> usize {
+    (64 - (zigzag(value) | 1).leading_zeros() as usize + 6) / 7
+}
+
+/// Returns the size of a string in the stringtable, including its field key and length prefix.
+fn string_size_hint(s: &str) -> usize {
+    1 + signed_varint_len(s.len() as i64) + s.len()
+}
+
+fn tags_size_hint(tags: &[(String, String)]) -> usize {
+    tags.iter()
+        .map(|(k, v)| 2 * STRING_INDEX_SIZE + string_size_hint(k) + string_size_hint(v))
+        .sum()
+}
+
+fn info_size_hint(info: &OwnedInfo) -> usize {
+    // Each value is preceded by a one byte field key.
+    info.version
+        .map_or(0, |v| 1 + signed_varint_len(i64::from(v)))
+        + info
+            .milli_timestamp
+            .map_or(0, |t| 1 + signed_varint_len(t / 1000))
+        + info.changeset.map_or(0, |c| 1 + signed_varint_len(c))
+        + info
+            .uid
+            .map_or(0, |uid| 1 + signed_varint_len(i64::from(uid)))
+        + info
+            .user
+            .as_ref()
+            .map_or(0, |user| 1 + STRING_INDEX_SIZE + string_size_hint(user))
+        + if info.visible { 0 } else { 2 }
+}
+
+/// An owned OpenStreetMap node element.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct OwnedNode {
+    /// The node id.
+    pub id: i64,
+    /// The latitude coordinate in nano-degrees.
+    pub nano_lat: i64,
+    /// The longitude coordinate in nano-degrees.
+    pub nano_lon: i64,
+    /// The tags as pairs of strings (key and value).
+    pub tags: Vec<(String, String)>,
+    /// Additional metadata.
+    pub info: OwnedInfo,
+}
+
+impl OwnedNode {
+    /// Returns the latitude coordinate in degrees.
+    pub fn lat(&self) -> f64 {
+        0.000_000_001_f64 * self.nano_lat as f64
+    }
+
+    /// Returns the longitude coordinate in degrees.
+    pub fn lon(&self) -> f64 {
+        0.000_000_001_f64 * self.nano_lon as f64
+    }
+}
+
+/// An owned OpenStreetMap way element.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct OwnedWay {
+    /// The way id.
+    pub id: i64,
+    /// The referenced node ids (not delta coded).
+    pub refs: Vec<i64>,
+    /// The tags as pairs of strings (key and value).
+    pub tags: Vec<(String, String)>,
+    /// Additional metadata.
+    pub info: OwnedInfo,
+}
+
+/// An owned OpenStreetMap relation element.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct OwnedRelation {
+    /// The relation id.
+    pub id: i64,
+    /// The members of this relation.
+    pub members: Vec<OwnedRelMember>,
+    /// The tags as pairs of strings (key and value).
+    pub tags: Vec<(String, String)>,
+    /// Additional metadata.
+    pub info: OwnedInfo,
+}
+
+/// An owned member of a relation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OwnedRelMember {
+    /// The element type of the member.
+    pub member_type: RelMemberType,
+    /// The id of the member (not delta coded).
+    pub member_id: i64,
+    /// The role of the member.
+    pub role: String,
+}
+
+/// Owned additional metadata of an element.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OwnedInfo {
+    /// The version of this element.
+    pub version: Option<i32>,
+    /// The time stamp in milliseconds since the epoch.
+    pub milli_timestamp: Option<i64>,
+    /// The changeset id.
+    pub changeset: Option<i64>,
+    /// The user id.
+    pub uid: Option<i32>,
+    /// The user name.
+    pub user: Option<String>,
+    /// The visibility status. This is only relevant if the PBF file contains historical
+    /// information.
+    pub visible: bool,
+}
+
+impl Default for OwnedInfo {
+    fn default() -> OwnedInfo {
+        OwnedInfo {
+            version: None,
+            milli_timestamp: None,
+            changeset: None,
+            uid: None,
+            user: None,
+            // If the visible flag is not present it must be assumed to be true.
+            visible: true,
+        }
+    }
+}
+
+pub(crate) fn owned_elements(block: &osmformat::PrimitiveBlock) -> Result<Vec<OwnedElement>> {
+    let strings = DecodedStrings::new(block.get_stringtable().get_s());
+    let mut elements = vec![];
+
+    for group in block.get_primitivegroup() {
+        for node in group.get_nodes() {
+            let node = Node::new(block, node);
+            elements.push(OwnedElement::Node(owned_node(&node, &strings)?));
+        }
+        for dnode in DenseNodeIter::new(block, group.get_dense()) {
+            elements.push(OwnedElement::Node(owned_dense_node(&dnode, &strings)?));
+        }
+        for way in group.get_ways() {
+            let way = Way::new(block, way);
+            elements.push(OwnedElement::Way(owned_way(&way, &strings)?));
+        }
+        for rel in group.get_relations() {
+            let rel = Relation::new(block, rel);
+            elements.push(OwnedElement::Relation(owned_relation(&rel, &strings)?));
+        }
+    }
+
+    Ok(elements)
+}
+
+/// Copies a borrowed element, decoding only the strings it references.
+pub(crate) fn owned_element(element: &Element) -> Result<OwnedElement> {
+    match *element {
+        Element::Node(ref node) => {
+            let strings = DecodedStrings::on_demand(node.raw_stringtable());
+            owned_node(node, &strings).map(OwnedElement::Node)
+        }
+        Element::DenseNode(ref dnode) => {
+            let strings = DecodedStrings::on_demand(dnode.raw_stringtable());
+            owned_dense_node(dnode, &strings).map(OwnedElement::Node)
+        }
+        Element::Way(ref way) => {
+            let strings = DecodedStrings::on_demand(way.raw_stringtable());
+            owned_way(way, &strings).map(OwnedElement::Way)
+        }
+        Element::Relation(ref rel) => {
+            let strings = DecodedStrings::on_demand(rel.raw_stringtable());
+            owned_relation(rel, &strings).map(OwnedElement::Relation)
+        }
+    }
+}
+
+fn owned_tags<I>(raw_tags: I, strings: &DecodedStrings) -> Result<Vec<(String, String)>>
+where
+    I: Iterator<Item = (usize, usize)>,
+{
+    raw_tags
+        .map(|(k, v)| Ok((strings.get(k)?.to_string(), strings.get(v)?.to_string())))
+        .collect()
+}
+
+fn owned_info(info: &Info, strings: &DecodedStrings) -> Result<OwnedInfo> {
+    let user = match info.user_sid() {
+        Some(sid) => Some(strings.get(sid as usize)?.to_string()),
+        None => None,
+    };
+
+    Ok(OwnedInfo {
+        version: info.version(),
+        milli_timestamp: info.milli_timestamp(),
+        changeset: info.changeset(),
+        uid: info.uid(),
+        user,
+        visible: info.visible(),
+    })
+}
+
+fn owned_node(node: &Node, strings: &DecodedStrings) -> Result<OwnedNode> {
+    Ok(OwnedNode {
+        id: node.id(),
+        nano_lat: node.lat_in_nano_degrees(),
+        nano_lon: node.lon_in_nano_degrees(),
+        tags: owned_tags(
+            node.raw_tags().map(|(k, v)| (k as usize, v as usize)),
+            strings,
+        )?,
+        info: owned_info(&node.info(), strings)?,
+    })
+}
+
+fn owned_dense_node(dnode: &DenseNode, strings: &DecodedStrings) -> Result<OwnedNode> {
+    Ok(OwnedNode {
+        id: dnode.id,
+        nano_lat: dnode.lat_in_nano_degrees(),
+        nano_lon: dnode.lon_in_nano_degrees(),
+        tags: owned_tags(
+            dnode.raw_tags().map(|(k, v)| (k as usize, v as usize)),
+            strings,
+        )?,
+        info: if dnode.has_info() {
+            OwnedInfo {
+                version: Some(dnode.version),
+                milli_timestamp: Some(dnode.milli_timestamp()),
+                changeset: Some(dnode.changeset),
+                uid: Some(dnode.uid),
+                user: Some(strings.get(dnode.user_sid() as usize)?.to_string()),
+                visible: dnode.visible(),
+            }
+        } else {
+            OwnedInfo::default()
+        },
+    })
+}
+
+fn owned_way(way: &Way, strings: &DecodedStrings) -> Result<OwnedWay> {
+    Ok(OwnedWay {
+        id: way.id(),
+        refs: way.refs().collect(),
+        tags: owned_tags(
+            way.raw_tags().map(|(k, v)| (k as usize, v as usize)),
+            strings,
+        )?,
+        info: owned_info(&way.info(), strings)?,
+    })
+}
+
+fn owned_relation(rel: &Relation, strings: &DecodedStrings) -> Result<OwnedRelation> {
+    let members = rel
+        .members()
+        .map(|m| {
+            Ok(OwnedRelMember {
+                role: strings.get(m.role_sid as usize)?.to_string(),
+                member_id: m.member_id,
+                member_type: m.member_type,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(OwnedRelation {
+        id: rel.id(),
+        members,
+        tags: owned_tags(
+            rel.raw_tags().map(|(k, v)| (k as usize, v as usize)),
+            strings,
+        )?,
+        info: owned_info(&rel.info(), strings)?,
+    })
+}
@@ -0,0 +1,155 @@
+//! Find the ways and relations that reference an element
+
+use elements::{Element, OsmId, RelMemberType};
+use error::Result;
+use owned::OwnedElement;
+use reader::ElementReader;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+
+/// A map from the id of an element to the ids of its parents, which `ParentIndex` fills.
+///
+/// Implement this trait to store the references more compactly or outside of memory, e.g. in a
+/// sorted file or a key-value store.
+pub trait ParentMap {
+    /// Records that `parent` references the element with the id `child`. A closed way adds its
+    /// first node twice in a row, so implementations should ignore a parent that is equal to the
+    /// last parent added for the same child.
+    fn add_parent(&mut self, child: i64, parent: OsmId);
+}
+
+impl ParentMap for HashMap<i64, Vec<OsmId>> {
+    fn add_parent(&mut self, child: i64, parent: OsmId) {
+        let parents = self.entry(child).or_default();
+        if parents.last() != Some(&parent) {
+            parents.push(parent);
+        }
+    }
+}
+
+impl ParentMap for BTreeMap<i64, Vec<OsmId>> {
+    fn add_parent(&mut self, child: i64, parent: OsmId) {
+        let parents = self.entry(child).or_default();
+        if parents.last() != Some(&parent) {
+            parents.push(parent);
+        }
+    }
+}
+
+/// Collects which ways reference each node and, optionally, which relations contain each
+/// relation. Routing and topology tools need this to find intersections (nodes that are shared
+/// by several ways) or the parents of a route.
+///
+/// The index stores an entry for every node that is part of a way, so it needs a lot of memory:
+/// with the default `HashMap` roughly 100 bytes per node, i.e. several gigabytes for a country
+/// extract and far more than a typical machine has for a planet file. Use `with_maps` to plug in
+/// a more compact `ParentMap` in that case.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+/// let index = ParentIndex::from_reader(reader)?;
+///
+/// assert_eq!(index.node_ways()[&105], [OsmId::Way(107)]);
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ParentIndex<M = HashMap<i64, Vec<OsmId>>> {
+    node_ways: M,
+    relation_parents: Option<M>,
+}
+
+impl ParentIndex {
+    /// Creates an empty index that only collects the ways of each node.
+    pub fn new() -> ParentIndex {
+        ParentIndex::default()
+    }
+
+    /// Reads all elements of a file and collects the ways of each node.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    pub fn from_reader<R: Read>(reader: ElementReader<R>) -> Result<ParentIndex> {
+        let mut index = ParentIndex::new();
+        reader.for_each(|element| index.add_element(&element))?;
+        Ok(index)
+    }
+}
+
+impl<M: ParentMap> ParentIndex<M> {
+    /// Creates an index that stores the ways of each node in `node_ways`. If `relation_parents`
+    /// is given, the parent relations of each relation are stored in it as well.
+    pub fn with_maps(node_ways: M, relation_parents: Option<M>) -> ParentIndex<M> {
+        ParentIndex {
+            node_ways,
+            relation_parents,
+        }
+    }
+
+    /// Adds the references of an element. Nodes are ignored, as are relation members that are
+    /// not relations.
+    pub fn add_element(&mut self, element: &Element) {
+        match *element {
+            Element::Way(ref way) => {
+                let parent = OsmId::Way(way.id());
+                for node_id in way.refs() {
+                    self.node_ways.add_parent(node_id, parent);
+                }
+            }
+            Element::Relation(ref rel) => {
+                if let Some(ref mut relation_parents) = self.relation_parents {
+                    let parent = OsmId::Relation(rel.id());
+                    for member in rel.members() {
+                        if member.member_type == RelMemberType::Relation {
+                            relation_parents.add_parent(member.member_id, parent);
+                        }
+                    }
+                }
+            }
+            Element::Node(_) | Element::DenseNode(_) => {}
+        }
+    }
+
+    /// Adds the references of an owned element, like `add_element`.
+    pub fn add_owned_element(&mut self, element: &OwnedElement) {
+        match *element {
+            OwnedElement::Way(ref way) => {
+                let parent = OsmId::Way(way.id);
+                for &node_id in &way.refs {
+                    self.node_ways.add_parent(node_id, parent);
+                }
+            }
+            OwnedElement::Relation(ref rel) => {
+                if let Some(ref mut relation_parents) = self.relation_parents {
+                    let parent = OsmId::Relation(rel.id);
+                    for member in &rel.members {
+                        if member.member_type == RelMemberType::Relation {
+                            relation_parents.add_parent(member.member_id, parent);
+                        }
+                    }
+                }
+            }
+            OwnedElement::Node(_) => {}
+        }
+    }
+
+    /// Returns the map from node ids to the ways that reference them.
+    pub fn node_ways(&self) -> &M {
+        &self.node_ways
+    }
+
+    /// Returns the map from relation ids to the relations that contain them, if it is collected.
+    pub fn relation_parents(&self) -> Option<&M> {
+        self.relation_parents.as_ref()
+    }
+
+    /// Consumes the index and returns the map of node ways and the map of relation parents.
+    pub fn into_maps(self) -> (M, Option<M>) {
+        (self.node_ways, self.relation_parents)
+    }
+}
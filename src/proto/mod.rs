@@ -1,2 +1,8 @@
+//! Raw protobuf messages of the PBF format, generated from `fileformat.proto` and
+//! `osmformat.proto` with rust-protobuf.
+//!
+//! These types are an escape hatch for fields that are not covered by the high-level API. They
+//! are generated code and may change with any update of rust-protobuf.
+
 pub mod fileformat;
 pub mod osmformat;
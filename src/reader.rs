@@ -1,17 +1,125 @@
 //! High level reader interface
 
-use blob::{BlobDecode, BlobReader};
-use elements::Element;
-use error::Result;
+use blob::{Blob, BlobDecode, BlobReader, MAX_BLOB_MESSAGE_SIZE};
+use block::{HeaderBlock, PrimitiveBlock};
+use elements::{Element, ElementRawTagIter};
+use error::{new_blob_error, new_error, BlobError, ErrorKind, Result};
+#[cfg(feature = "system-libz")]
+use flate2::read::MultiGzDecoder;
+use histogram::{block_histogram, Histogram};
+use history::{LatestVersions, VersionHistories};
+use metrics::ReaderMetrics;
+use node_store::NodeCoordStore;
+use owned::OwnedElement;
 use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io::{self, BufReader, Read, StdinLock};
 use std::path::Path;
+use std::sync::Arc;
+use std::vec;
+
+/// Required features of a `HeaderBlock` that this crate is able to read.
+pub(crate) const SUPPORTED_FEATURES: [&str; 3] =
+    ["OsmSchema-V0.6", "DenseNodes", "HistoricalInformation"];
 
 /// A reader for PBF files that gives access to the stored elements: nodes, ways and relations.
+///
+/// Use `ElementReaderBuilder` to configure how blobs are decoded.
 #[derive(Clone, Debug)]
 pub struct ElementReader<R: Read> {
     blob_iter: BlobReader<R>,
+    options: ReaderOptions,
+}
+
+/// Options of an `ElementReader` that are set with an `ElementReaderBuilder`.
+#[derive(Clone, Debug)]
+pub(crate) struct ReaderOptions {
+    max_blob_size: u64,
+    check_required_features: bool,
+    skip_unknown_blobs: bool,
+    num_threads: Option<usize>,
+    /// The counters of the `BlobReader`, which also receive the decoding metrics.
+    metrics: Option<Arc<ReaderMetrics>>,
+}
+
+impl Default for ReaderOptions {
+    fn default() -> ReaderOptions {
+        ReaderOptions {
+            max_blob_size: MAX_BLOB_MESSAGE_SIZE,
+            check_required_features: false,
+            skip_unknown_blobs: true,
+            num_threads: None,
+            metrics: None,
+        }
+    }
+}
+
+impl ReaderOptions {
+    pub(crate) fn decode<'b>(&self, blob: &'b Blob) -> Result<BlobDecode<'b>> {
+        let decoded = match self.metrics {
+            Some(ref metrics) => metrics.time_decode(blob.decompressed_size(), || {
+                blob.decode_with_limit(self.max_blob_size)
+            })?,
+            None => blob.decode_with_limit(self.max_blob_size)?,
+        };
+        match decoded {
+            BlobDecode::OsmHeader(ref header) if self.check_required_features => {
+                check_required_features(header)?;
+            }
+            BlobDecode::Unknown(blob_type) if !self.skip_unknown_blobs => {
+                return Err(new_blob_error(BlobError::UnknownType {
+                    blob_type: blob_type.to_string(),
+                }));
+            }
+            BlobDecode::Unknown(blob_type) => {
+                log_warn!(
+                    "skipping blob of unknown type {:?} at offset {:?}",
+                    blob_type,
+                    blob.offset()
+                );
+            }
+            _ => {}
+        }
+        Ok(decoded)
+    }
+
+    /// Returns a dedicated thread pool if the number of threads is configured.
+    fn thread_pool(&self) -> Result<Option<ThreadPool>> {
+        match self.num_threads {
+            Some(n) => ThreadPoolBuilder::new()
+                .num_threads(n)
+                .build()
+                .map(Some)
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e).into()),
+            None => Ok(None),
+        }
+    }
+}
+
+fn check_required_features(header: &HeaderBlock) -> Result<()> {
+    match header
+        .required_features()
+        .iter()
+        .find(|feature| !SUPPORTED_FEATURES.contains(&feature.as_str()))
+    {
+        Some(feature) => Err(new_error(ErrorKind::UnsupportedFeature {
+            feature: feature.clone(),
+        })),
+        None => Ok(()),
+    }
+}
+
+/// Runs `op` in the given thread pool or in the global rayon pool.
+fn install<T, OP>(pool: &Option<ThreadPool>, op: OP) -> T
+where
+    OP: FnOnce() -> T + Send,
+    T: Send,
+{
+    match *pool {
+        Some(ref pool) => pool.install(op),
+        None => op(),
+    }
 }
 
 impl<R: Read> ElementReader<R> {
@@ -32,16 +140,34 @@ impl<R: Read> ElementReader<R> {
     /// # foo().unwrap();
     /// ```
     pub fn new(reader: R) -> ElementReader<R> {
-        ElementReader {
-            blob_iter: BlobReader::new(reader),
-        }
+        ElementReader::with_options(BlobReader::new(reader), ReaderOptions::default())
+    }
+
+    fn with_options(blob_iter: BlobReader<R>, mut options: ReaderOptions) -> ElementReader<R> {
+        options.metrics = Some(blob_iter.shared_metrics());
+        ElementReader { blob_iter, options }
+    }
+
+    /// Returns the counters of this reading session. The decoding counters are only updated
+    /// by the methods of `ElementReader` (see `ReaderMetrics`).
+    pub fn metrics(&self) -> &ReaderMetrics {
+        self.blob_iter.metrics()
+    }
+
+    /// Returns a handle to the counters of this reading session that stays valid while and after
+    /// the reader is consumed by one of its methods.
+    pub fn shared_metrics(&self) -> Arc<ReaderMetrics> {
+        self.blob_iter.shared_metrics()
     }
 
     /// Decodes the PBF structure sequentially and calls the given closure on each element.
     /// Consider using `par_map_reduce` instead if you need better performance.
     ///
+    /// The blobs are read and decoded one at a time, so the input is not buffered in memory.
+    ///
     /// # Errors
-    /// Returns the first Error encountered while parsing the PBF structure.
+    /// Returns the first Error encountered while parsing the PBF structure. The closure may
+    /// already have been called for the elements of the blocks before the error.
     ///
     /// # Example
     /// ```
@@ -68,11 +194,9 @@ impl<R: Read> ElementReader<R> {
     where
         F: for<'a> FnMut(Element<'a>),
     {
-        let blobs = self.blob_iter.collect::<Result<Vec<_>>>()?;
-
         //TODO do something useful with header blocks
-        for blob in &blobs {
-            match blob.decode() {
+        for blob in self.blob_iter {
+            match self.options.decode(&blob?) {
                 Ok(BlobDecode::OsmHeader(_)) | Ok(BlobDecode::Unknown(_)) => {}
                 Ok(BlobDecode::OsmData(block)) => {
                     block.for_each_element(&mut f);
@@ -84,6 +208,326 @@ impl<R: Read> ElementReader<R> {
         Ok(())
     }
 
+    /// Decodes the PBF structure sequentially and calls the given closure on each element
+    /// together with the `PrimitiveBlock` that contains it and the most recent `HeaderBlock`
+    /// before it. This gives access to block-level values such as the granularity or the
+    /// stringtable, and for files that are a concatenation of several PBF files, to the header of
+    /// the part that the element belongs to. The header is `None` if no `OSMHeader` blob precedes
+    /// the block.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// reader.for_each_in_block(|header, block, element| {
+    ///     if let Element::Way(way) = element {
+    ///         println!(
+    ///             "way {} is stored with a date granularity of {} ms",
+    ///             way.id(),
+    ///             block.date_granularity()
+    ///         );
+    ///         if let Some(header) = header {
+    ///             println!("required features: {:?}", header.required_features());
+    ///         }
+    ///     }
+    /// })?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn for_each_in_block<F>(self, mut f: F) -> Result<()>
+    where
+        F: for<'a> FnMut(Option<&'a HeaderBlock>, &'a PrimitiveBlock, Element<'a>),
+    {
+        let mut header = None;
+        for blob in self.blob_iter {
+            match self.options.decode(&blob?) {
+                Ok(BlobDecode::OsmHeader(h)) => header = Some(h),
+                Ok(BlobDecode::Unknown(_)) => {}
+                Ok(BlobDecode::OsmData(block)) => {
+                    for element in block.elements() {
+                        f(header.as_deref(), &block, element);
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Decodes the whole file sequentially and returns owned copies of all elements in the order
+    /// in which they are stored. Owned elements do not borrow from a `PrimitiveBlock`, so they can
+    /// be returned from functions and stored freely.
+    ///
+    /// This keeps the entire file in memory, and owned elements are several times larger than
+    /// the compressed data, so only use it for small files or in tests. Prefer `for_each` or
+    /// `par_map_reduce` for anything bigger than a city extract.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure or decoding a string.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// fn load(path: &str) -> Result<Vec<OwnedElement>> {
+    ///     ElementReader::from_path(path)?.read_all()
+    /// }
+    ///
+    /// let elements = load("tests/test.osm.pbf").unwrap();
+    /// assert_eq!(elements.len(), 5);
+    /// ```
+    pub fn read_all(self) -> Result<Vec<OwnedElement>> {
+        let mut elements = vec![];
+        for blob in self.blob_iter {
+            match self.options.decode(&blob?)? {
+                BlobDecode::OsmHeader(_) | BlobDecode::Unknown(_) => {}
+                BlobDecode::OsmData(block) => elements.extend(block.into_owned_elements()?),
+            }
+        }
+        Ok(elements)
+    }
+
+    /// Decodes up to `window` blobs in parallel and calls the given closure on each element in
+    /// the order in which the elements are stored in the file. This is useful if decoding is the
+    /// bottleneck, but the elements have to be processed sequentially, e.g. to write them to
+    /// another file.
+    ///
+    /// The decoded blocks of a window are buffered until all elements of the window have been
+    /// passed to the closure, so memory usage grows linearly with `window` (a decoded blob is
+    /// typically a few megabytes). A `window` of zero is treated as one.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure. All elements of the
+    /// blobs that precede the erroneous blob have been passed to the closure at that point.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    /// let mut ids = vec![];
+    ///
+    /// // Decode up to 16 blobs at a time
+    /// reader.par_for_each_ordered(16, |element| {
+    ///     if let Element::Way(way) = element {
+    ///         ids.push(way.id());
+    ///     }
+    /// })?;
+    ///
+    /// assert_eq!(ids, [107]);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn par_for_each_ordered<F>(self, window: usize, mut f: F) -> Result<()>
+    where
+        F: for<'a> FnMut(Element<'a>),
+    {
+        let window = window.max(1);
+        let options = self.options;
+        let pool = options.thread_pool()?;
+        let mut blob_iter = self.blob_iter;
+
+        loop {
+            let blobs = blob_iter
+                .by_ref()
+                .take(window)
+                .collect::<Result<Vec<_>>>()?;
+            if blobs.is_empty() {
+                return Ok(());
+            }
+
+            // Collecting an indexed parallel iterator preserves the order of the blobs.
+            let decoded = install(&pool, || {
+                blobs
+                    .par_iter()
+                    .map(|blob| options.decode(blob))
+                    .collect::<Vec<_>>()
+            });
+
+            for decode in decoded {
+                match decode? {
+                    BlobDecode::OsmHeader(_) | BlobDecode::Unknown(_) => {}
+                    BlobDecode::OsmData(block) => block.for_each_element(&mut f),
+                }
+            }
+        }
+    }
+
+    /// Decodes the PBF structure sequentially and returns an iterator over owned copies of all
+    /// elements in the order in which they are stored. Unlike `read_all`, only one block is kept
+    /// in memory at a time.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// let ids = reader
+    ///     .owned_elements()
+    ///     .map(|element| element.map(|e| e.osm_id()))
+    ///     .collect::<Result<Vec<_>>>()?;
+    /// assert_eq!(ids.len(), 5);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn owned_elements(self) -> OwnedElements<R> {
+        OwnedElements {
+            blobs: self.blob_iter,
+            options: self.options,
+            elements: vec![].into_iter(),
+            failed: false,
+        }
+    }
+
+    /// Decodes the PBF structure sequentially and returns an iterator over the `VersionHistory`
+    /// of each element. This is meant for files with historical information, which list all
+    /// versions of an element one after another. Consecutive elements with the same type and id
+    /// are grouped together; the versions are not reordered.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// for history in reader.version_histories() {
+    ///     let history = history?;
+    ///     println!("{} has {} versions", history.osm_id(), history.versions().len());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn version_histories(self) -> VersionHistories<R> {
+        VersionHistories::new(self.owned_elements())
+    }
+
+    /// Decodes the PBF structure sequentially and returns an iterator over the latest visible
+    /// version of each element. This turns a file with historical information into a snapshot of
+    /// the current data in a single pass: superseded versions and deleted elements are dropped.
+    ///
+    /// The file is assumed to be sorted by type, id and version, as history files usually are.
+    /// The iterator returns an error of kind `ErrorKind::UnsortedElements` if it detects that this
+    /// is not the case.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// let mut ways = 0;
+    /// for element in reader.latest_versions() {
+    ///     if let OwnedElement::Way(_) = element? {
+    ///         ways += 1;
+    ///     }
+    /// }
+    /// assert_eq!(ways, 1);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn latest_versions(self) -> LatestVersions<R> {
+        LatestVersions::new(self.version_histories())
+    }
+
+    /// Decodes the PBF structure sequentially and returns the indices of all blobs (counting from
+    /// zero, including the header blob) with blocks that contain nodes with coordinates outside of
+    /// the valid ranges of latitudes and longitudes. This is a quick sanity check for input from
+    /// exporters that might have used a wrong offset or granularity.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// let invalid_blobs = reader.blobs_with_invalid_coordinates()?;
+    /// assert!(invalid_blobs.is_empty());
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn blobs_with_invalid_coordinates(self) -> Result<Vec<usize>> {
+        let mut indices = vec![];
+
+        for (index, blob) in self.blob_iter.enumerate() {
+            if let BlobDecode::OsmData(block) = self.options.decode(&blob?)? {
+                if block.has_invalid_coordinates() {
+                    indices.push(index);
+                }
+            }
+        }
+
+        Ok(indices)
+    }
+
+    /// Loads the coordinates of all nodes into a compact `NodeCoordStore`, the usual first step of
+    /// resolving the geometries of ways. Only the ids and coordinates of the nodes are copied, and
+    /// the blocks are decoded one at a time, so the store and a single block are all that is kept
+    /// in memory.
+    ///
+    /// Coordinates of corrupt nodes that do not fit into micro-degrees are saturated (see
+    /// `Node::lat_lon_micro`).
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let coords = ElementReader::from_path("tests/test.osm.pbf")?.collect_node_coords()?;
+    ///
+    /// let (lat, lon) = coords.get(106).unwrap();
+    /// assert!((lat - 52.11992).abs() < 1e-5 && (lon - 11.62564).abs() < 1e-5);
+    /// assert_eq!(coords.len(), 3);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn collect_node_coords(self) -> Result<NodeCoordStore> {
+        let mut nodes = vec![];
+
+        for blob in self.blob_iter {
+            if let BlobDecode::OsmData(block) = self.options.decode(&blob?)? {
+                for group in block.groups() {
+                    for node in group.nodes() {
+                        let (lat, lon) = node.lat_lon_micro();
+                        nodes.push((node.id(), lat, lon));
+                    }
+                    for dnode in group.dense_nodes() {
+                        let (lat, lon) = dnode.lat_lon_micro();
+                        nodes.push((dnode.id, lat, lon));
+                    }
+                }
+            }
+        }
+
+        Ok(NodeCoordStore::from_unsorted(nodes))
+    }
+
     /// Parallel map/reduce. Decodes the PBF structure in parallel, calls the closure `map_op` on
     /// each element and then reduces the number of results to one item with the closure
     /// `reduce_op`. Similarly to the `init` argument in the `fold` method on iterators, the
@@ -125,25 +569,276 @@ impl<R: Read> ElementReader<R> {
         ID: Fn() -> T + Sync + Send,
         T: Send,
     {
+        let pool = self.options.thread_pool()?;
+        let options = self.options;
+        let blobs = self.blob_iter.collect::<Result<Vec<_>>>()?;
+        install(&pool, || {
+            par_map_reduce_blobs(blobs, options, map_op, identity, reduce_op)
+        })
+    }
+
+    /// Like `par_map_reduce`, but runs on the given rayon thread pool instead of the global pool.
+    /// This isolates the decoding work from other parallel work of an application and allows to
+    /// bound the number of threads that are used for decoding.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    ///
+    /// # Example
+    /// ```
+    /// extern crate osmpbf;
+    /// extern crate rayon;
+    ///
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// // Count the ways
+    /// let ways = reader.par_map_reduce_in_pool(
+    ///     &pool,
+    ///     |element| match element {
+    ///         Element::Way(_) => 1,
+    ///         _ => 0,
+    ///     },
+    ///     || 0_u64,
+    ///     |a, b| a + b,
+    /// )?;
+    ///
+    /// assert_eq!(ways, 1);
+    /// # Ok(())
+    /// # }
+    /// # fn main() { foo().unwrap(); }
+    /// ```
+    pub fn par_map_reduce_in_pool<MP, RD, ID, T>(
+        self,
+        pool: &ThreadPool,
+        map_op: MP,
+        identity: ID,
+        reduce_op: RD,
+    ) -> Result<T>
+    where
+        MP: for<'a> Fn(Element<'a>) -> T + Sync + Send,
+        RD: Fn(T, T) -> T + Sync + Send,
+        ID: Fn() -> T + Sync + Send,
+        T: Send,
+    {
+        let options = self.options;
         let blobs = self.blob_iter.collect::<Result<Vec<_>>>()?;
+        pool.install(|| par_map_reduce_blobs(blobs, options, map_op, identity, reduce_op))
+    }
 
-        blobs
-            .into_par_iter()
-            .map(|blob| match blob.decode() {
-                Ok(BlobDecode::OsmHeader(_)) | Ok(BlobDecode::Unknown(_)) => Ok(identity()),
-                Ok(BlobDecode::OsmData(block)) => Ok(block
-                    .elements()
-                    .map(|e| map_op(e))
-                    .fold(identity(), |a, b| reduce_op(a, b))),
-                Err(e) => Err(e),
-            })
-            .reduce(
-                || Ok(identity()),
-                |a, b| match (a, b) {
-                    (Ok(x), Ok(y)) => Ok(reduce_op(x, y)),
-                    (x, y) => x.and(y),
+    /// Counts the elements of the file by type, by whether they are tagged and by whether they
+    /// carry metadata. The blocks are decoded in parallel, but no strings or coordinates are
+    /// decoded, so this is faster than counting with `par_map_reduce`.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    /// let histogram = reader.element_histogram()?;
+    ///
+    /// println!(
+    ///     "{} of {} nodes are untagged",
+    ///     histogram.nodes.untagged(),
+    ///     histogram.nodes.total
+    /// );
+    /// assert_eq!(histogram.total(), 5);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn element_histogram(self) -> Result<Histogram> {
+        let pool = self.options.thread_pool()?;
+        let options = self.options;
+        let blobs = self.blob_iter.collect::<Result<Vec<_>>>()?;
+        install(&pool, || {
+            par_reduce_blocks(
+                blobs,
+                options,
+                |block| block_histogram(block.as_proto()),
+                Histogram::default,
+                |a, b| a + b,
+            )
+        })
+    }
+
+    /// Parallel filter/map/reduce. Like `par_map_reduce`, but only calls `map_op` on elements that
+    /// pass a filter on their raw tags (see `Element::raw_tags`).
+    ///
+    /// The closure `filter` is called once per `PrimitiveBlock` and returns the predicate for the
+    /// elements of that block. This allows to look up the few stringtable entries that the
+    /// predicate is interested in once, so that checking an element only compares integers and
+    /// does not decode any strings.
+    ///
+    /// # Errors
+    /// Returns the first Error encountered while parsing the PBF structure.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_path("tests/test.osm.pbf")?;
+    ///
+    /// // Count the elements with the tag "building=yes"
+    /// let buildings = reader.filter_map_reduce(
+    ///     |block| {
+    ///         let index_of = |s: &str| {
+    ///             block
+    ///                 .raw_stringtable()
+    ///                 .iter()
+    ///                 .position(|entry| entry.as_slice() == s.as_bytes())
+    ///                 .map(|i| i as u32)
+    ///         };
+    ///         let tag = index_of("building").and_then(|k| Some((k, index_of("yes")?)));
+    ///         move |mut tags: ElementRawTagIter| tag.is_some_and(|tag| tags.any(|t| t == tag))
+    ///     },
+    ///     |_| 1,
+    ///     || 0_u64,
+    ///     |a, b| a + b,
+    /// )?;
+    ///
+    /// assert_eq!(buildings, 1);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn filter_map_reduce<FL, P, MP, RD, ID, T>(
+        self,
+        filter: FL,
+        map_op: MP,
+        identity: ID,
+        reduce_op: RD,
+    ) -> Result<T>
+    where
+        FL: Fn(&PrimitiveBlock) -> P + Sync + Send,
+        P: for<'a> FnMut(ElementRawTagIter<'a>) -> bool,
+        MP: for<'a> Fn(Element<'a>) -> T + Sync + Send,
+        RD: Fn(T, T) -> T + Sync + Send,
+        ID: Fn() -> T + Sync + Send,
+        T: Send,
+    {
+        let pool = self.options.thread_pool()?;
+        let options = self.options;
+        let blobs = self.blob_iter.collect::<Result<Vec<_>>>()?;
+        install(&pool, || {
+            par_reduce_blocks(
+                blobs,
+                options,
+                |block| {
+                    let mut predicate = filter(block);
+                    block
+                        .elements()
+                        .filter(|e| predicate(e.raw_tags()))
+                        .map(&map_op)
+                        .fold(identity(), &reduce_op)
                 },
+                &identity,
+                &reduce_op,
             )
+        })
+    }
+}
+
+fn par_map_reduce_blobs<MP, RD, ID, T>(
+    blobs: Vec<Blob>,
+    options: ReaderOptions,
+    map_op: MP,
+    identity: ID,
+    reduce_op: RD,
+) -> Result<T>
+where
+    MP: for<'a> Fn(Element<'a>) -> T + Sync + Send,
+    RD: Fn(T, T) -> T + Sync + Send,
+    ID: Fn() -> T + Sync + Send,
+    T: Send,
+{
+    par_reduce_blocks(
+        blobs,
+        options,
+        |block| block.elements().map(&map_op).fold(identity(), &reduce_op),
+        &identity,
+        &reduce_op,
+    )
+}
+
+/// Decodes the blobs in parallel, calls `block_op` on each data block and reduces the results.
+fn par_reduce_blocks<BL, RD, ID, T>(
+    blobs: Vec<Blob>,
+    options: ReaderOptions,
+    block_op: BL,
+    identity: ID,
+    reduce_op: RD,
+) -> Result<T>
+where
+    BL: Fn(&PrimitiveBlock) -> T + Sync + Send,
+    RD: Fn(T, T) -> T + Sync + Send,
+    ID: Fn() -> T + Sync + Send,
+    T: Send,
+{
+    blobs
+        .into_par_iter()
+        .map(|blob| match options.decode(&blob) {
+            Ok(BlobDecode::OsmHeader(_)) | Ok(BlobDecode::Unknown(_)) => Ok(identity()),
+            Ok(BlobDecode::OsmData(block)) => Ok(block_op(&block)),
+            Err(e) => Err(e),
+        })
+        .reduce(
+            || Ok(identity()),
+            |a, b| match (a, b) {
+                (Ok(x), Ok(y)) => Ok(reduce_op(x, y)),
+                (x, y) => x.and(y),
+            },
+        )
+}
+
+/// An iterator over owned copies of the elements of a PBF file. Blocks are decoded one at a time.
+///
+/// Use `ElementReader::owned_elements` to create this iterator. Once an error is returned, the
+/// iterator yields `None`.
+#[derive(Clone, Debug)]
+pub struct OwnedElements<R: Read> {
+    blobs: BlobReader<R>,
+    options: ReaderOptions,
+    elements: vec::IntoIter<OwnedElement>,
+    failed: bool,
+}
+
+impl<R: Read> Iterator for OwnedElements<R> {
+    type Item = Result<OwnedElement>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        loop {
+            if let Some(element) = self.elements.next() {
+                return Some(Ok(element));
+            }
+
+            let result = self
+                .blobs
+                .next()?
+                .and_then(|blob| match self.options.decode(&blob)? {
+                    BlobDecode::OsmData(block) => block.into_owned_elements(),
+                    BlobDecode::OsmHeader(_) | BlobDecode::Unknown(_) => Ok(vec![]),
+                });
+            match result {
+                Ok(elements) => self.elements = elements.into_iter(),
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            }
+        }
     }
 }
 
@@ -164,8 +859,184 @@ impl ElementReader<BufReader<File>> {
     /// # foo().unwrap();
     /// ```
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
-        Ok(ElementReader {
-            blob_iter: BlobReader::from_path(path)?,
-        })
+        Ok(ElementReader::with_options(
+            BlobReader::from_path(path)?,
+            ReaderOptions::default(),
+        ))
+    }
+}
+
+impl ElementReader<StdinLock<'static>> {
+    /// Constructs an `ElementReader` that reads a PBF file from the standard input, e.g. to use a
+    /// program in a pipe like `curl https://example.com/extract.osm.pbf | mytool`. Standard input
+    /// stays locked until the reader is dropped.
+    ///
+    /// The input is not seekable, so offsets of blobs are unknown and readers that seek to blobs
+    /// (like `IndexedReader`) cannot be used. `for_each` streams the input with constant memory
+    /// use; the parallel methods like `par_map_reduce` work as well, but they read the whole
+    /// input into memory before decoding it. Standard input is already buffered, so it is not
+    /// wrapped in another `BufReader`.
+    ///
+    /// # Example
+    /// A filter that writes the ids of all ways with a `highway` tag to the standard output:
+    /// ```no_run
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_stdin();
+    ///
+    /// reader.for_each(|element| {
+    ///     if let Element::Way(way) = element {
+    ///         if way.tags().any(|(key, _)| key == "highway") {
+    ///             println!("{}", way.id());
+    ///         }
+    ///     }
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_stdin() -> Self {
+        ElementReader::new(io::stdin().lock())
+    }
+}
+
+#[cfg(feature = "system-libz")]
+impl ElementReader<BufReader<MultiGzDecoder<File>>> {
+    /// Tries to open a gzip compressed PBF file (e.g. `*.osm.pbf.gz`) at the given path and
+    /// constructs an `ElementReader` that decompresses it on the fly. Files that consist of
+    /// several gzip members, as written by parallel compressors, are supported as well.
+    ///
+    /// The decompressed stream is not seekable, so offsets of blobs are unknown, but `for_each`
+    /// and the other methods of `ElementReader` work as usual.
+    ///
+    /// This requires the `system-libz` feature, which is enabled by default.
+    ///
+    /// # Errors
+    /// Returns the same errors that `std::fs::File::open` returns. Invalid gzip data is reported
+    /// as an I/O error while reading.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let reader = ElementReader::from_gzip_path("extract.osm.pbf.gz")?;
+    /// reader.for_each(|element| println!("{}", element.osm_id()))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_gzip_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let decoder = MultiGzDecoder::new(File::open(path)?);
+        Ok(ElementReader::new(BufReader::new(decoder)))
+    }
+}
+
+/// A builder to configure an `ElementReader`.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let reader = ElementReaderBuilder::new()
+///     .max_blob_size(8 * 1024 * 1024)
+///     .check_required_features(true)
+///     .skip_unknown_blobs(false)
+///     .num_threads(2)
+///     .from_path("tests/test.osm.pbf")?;
+///
+/// let ways = reader.par_map_reduce(
+///     |element| match element {
+///         Element::Way(_) => 1,
+///         _ => 0,
+///     },
+///     || 0_u64,
+///     |a, b| a + b,
+/// )?;
+///
+/// assert_eq!(ways, 1);
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct ElementReaderBuilder {
+    buffer_capacity: Option<usize>,
+    options: ReaderOptions,
+}
+
+impl Default for ElementReaderBuilder {
+    fn default() -> ElementReaderBuilder {
+        ElementReaderBuilder::new()
+    }
+}
+
+impl ElementReaderBuilder {
+    /// Creates a new builder with the default options, which are the same options that
+    /// `ElementReader::new` and `ElementReader::from_path` use.
+    pub fn new() -> ElementReaderBuilder {
+        ElementReaderBuilder {
+            buffer_capacity: None,
+            options: ReaderOptions::default(),
+        }
+    }
+
+    /// Sets the capacity of the buffer in bytes that is used when opening a file with
+    /// `from_path`. By default, the capacity of `std::io::BufReader` is used.
+    pub fn buffer_capacity(mut self, capacity: usize) -> ElementReaderBuilder {
+        self.buffer_capacity = Some(capacity);
+        self
+    }
+
+    /// Sets the maximum uncompressed size of a blob in bytes. Bigger blobs produce a
+    /// `BlobError::MessageTooBig` error (see `Blob::decode_with_limit`). The default is
+    /// [`MAX_BLOB_MESSAGE_SIZE`](../blob/static.MAX_BLOB_MESSAGE_SIZE.html).
+    pub fn max_blob_size(mut self, max_size: u64) -> ElementReaderBuilder {
+        self.options.max_blob_size = max_size;
+        self
+    }
+
+    /// If set to `true`, the required features of header blocks are checked and an
+    /// `ErrorKind::UnsupportedFeature` error is returned for features that this crate cannot
+    /// read. The default is `false`.
+    pub fn check_required_features(mut self, check: bool) -> ElementReaderBuilder {
+        self.options.check_required_features = check;
+        self
+    }
+
+    /// If set to `false`, blobs of unknown types produce a `BlobError::UnknownType` error instead
+    /// of being skipped. The default is `true`, as recommended by the PBF specification.
+    pub fn skip_unknown_blobs(mut self, skip: bool) -> ElementReaderBuilder {
+        self.options.skip_unknown_blobs = skip;
+        self
+    }
+
+    /// Sets the number of threads that the parallel methods use. These methods then run in a
+    /// dedicated thread pool instead of rayon's global pool. Use
+    /// `ElementReader::par_map_reduce_in_pool` to share a pool between readers.
+    pub fn num_threads(mut self, num_threads: usize) -> ElementReaderBuilder {
+        self.options.num_threads = Some(num_threads);
+        self
+    }
+
+    /// Builds an `ElementReader` that reads from the given reader. The buffer capacity is ignored;
+    /// wrap the reader in a `std::io::BufReader` if necessary.
+    pub fn build<R: Read>(self, reader: R) -> ElementReader<R> {
+        ElementReader::with_options(BlobReader::new(reader), self.options)
+    }
+
+    /// Tries to open the file at the given path and builds an `ElementReader` from this.
+    ///
+    /// # Errors
+    /// Returns the same errors that `std::fs::File::open` returns.
+    pub fn from_path<P: AsRef<Path>>(self, path: P) -> Result<ElementReader<BufReader<File>>> {
+        let blob_iter = match self.buffer_capacity {
+            Some(capacity) => {
+                BlobReader::new_seekable(BufReader::with_capacity(capacity, File::open(path)?))?
+            }
+            None => BlobReader::from_path(path)?,
+        };
+
+        Ok(ElementReader::with_options(blob_iter, self.options))
     }
 }
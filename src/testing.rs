@@ -0,0 +1,400 @@
+//! Helpers for testing code that reads or writes PBF files and for fuzzing the decoder
+
+use blob::{BlobDecode, BlobReader, BlobType, BlobWriter};
+use elements::{Element, RelMemberType};
+use error::{new_protobuf_error, Result};
+use owned::{OwnedElement, OwnedNode, OwnedRelMember, OwnedRelation, OwnedWay};
+use proto::osmformat;
+use protobuf::Message;
+use std::io::Read;
+use util::parse_message_from_bytes;
+use writer::ElementWriter;
+
+/// Asserts that two PBF files contain the same elements. This is useful to test PBF writers by
+/// comparing their output with the input file.
+///
+/// The files do not have to be byte-identical. Elements are compared by their ids, tags,
+/// coordinates, way refs and relation members, so the following differences are ignored:
+///
+/// * The order of the elements and their distribution over blocks and groups.
+/// * The order of the stringtable and the order of the tags of an element.
+/// * Dense vs. sparse node encoding.
+/// * Coordinate offsets up to the coarsest granularity of both files, as coordinates are
+///   quantized to multiples of the block granularity.
+/// * Metadata (`Info`), which is often dropped or quantized by writers.
+///
+/// # Panics
+/// Panics if one of the files cannot be read or if the elements differ.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+/// use osmpbf::testing::assert_pbf_equivalent;
+///
+/// # fn foo() -> Result<()> {
+/// let bytes = std::fs::read("tests/test.osm.pbf")?;
+/// let mut writer = ElementWriter::new(vec![]);
+/// for blob in BlobReader::from_bytes(&bytes) {
+///     if let BlobDecode::OsmData(block) = blob?.decode()? {
+///         for element in block.into_owned_elements()? {
+///             writer.write_element(element)?;
+///         }
+///     }
+/// }
+/// let copy = writer.finish()?;
+///
+/// assert_pbf_equivalent(&bytes[..], &copy[..]);
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+pub fn assert_pbf_equivalent<A: Read, B: Read>(a: A, b: B) {
+    let (a, granularity_a) = read_elements(a, "first");
+    let (b, granularity_b) = read_elements(b, "second");
+    let tolerance = i64::from(granularity_a.max(granularity_b));
+
+    let mut a_iter = a.iter();
+    let mut b_iter = b.iter();
+    loop {
+        match (a_iter.next(), b_iter.next()) {
+            (Some(x), Some(y)) => assert_elements_equivalent(x, y, tolerance),
+            (None, None) => break,
+            (Some(x), None) => panic!("{} is missing in the second file", x.osm_id()),
+            (None, Some(y)) => panic!("{} is missing in the first file", y.osm_id()),
+        }
+    }
+}
+
+/// Reads the blobs of an in-memory PBF file, decodes them and visits every element including its
+/// tags, references, members and metadata. Returns the number of elements, or the first error.
+///
+/// This is an entry point for fuzz targets: it exercises the blob framing, decompression,
+/// protobuf parsing and element decoding with arbitrary bytes and without touching the file
+/// system. Malformed input must lead to an error, never to a panic or to unbounded memory use.
+///
+/// # Example
+/// A fuzz target for `cargo fuzz`:
+/// ```
+/// use osmpbf::testing::decode_blob_bytes;
+///
+/// fn fuzz_target(data: &[u8]) {
+///     let _ = decode_blob_bytes(data);
+/// }
+///
+/// let data = std::fs::read("tests/test.osm.pbf").unwrap();
+/// fuzz_target(&data[..100]);
+/// assert_eq!(decode_blob_bytes(&data).unwrap(), 5);
+/// ```
+pub fn decode_blob_bytes(data: &[u8]) -> Result<usize> {
+    let mut count = 0;
+    for blob in BlobReader::from_bytes(data) {
+        if let BlobDecode::OsmData(block) = blob?.decode()? {
+            for element in block.elements() {
+                visit_element(&element)?;
+                count += 1;
+            }
+        }
+    }
+    Ok(count)
+}
+
+/// A builder for small PBF files in memory, so that tests can construct their input in code
+/// instead of checking in binary fixtures. Elements are written with `ElementWriter` in the
+/// order in which they are added, and the output can additionally contain unknown blobs or a
+/// corrupt block to test error handling.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+/// use osmpbf::testing::TestPbfBuilder;
+///
+/// # fn foo() -> Result<()> {
+/// let bytes = TestPbfBuilder::new()
+///     .node(1, 52.5, 13.4, &[("amenity", "cafe")])
+///     .node(2, 52.6, 13.5, &[])
+///     .way(10, &[1, 2], &[("highway", "path")])
+///     .relation(20, &[(RelMemberType::Way, 10, "outer")], &[("type", "multipolygon")])
+///     .unknown_blob("Example", b"ignored by readers")
+///     .build()?;
+///
+/// let elements = ElementReader::new(&bytes[..]).read_all()?;
+/// assert_eq!(elements.len(), 4);
+/// assert_eq!(elements[2].tags(), [("highway".to_string(), "path".to_string())]);
+///
+/// // A block whose tags reference strings that do not exist
+/// let corrupt = TestPbfBuilder::new()
+///     .node(1, 52.5, 13.4, &[("amenity", "cafe")])
+///     .truncate_stringtable()
+///     .build()?;
+/// assert!(ElementReader::new(&corrupt[..]).read_all().is_err());
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct TestPbfBuilder {
+    elements: Vec<OwnedElement>,
+    dense_nodes: bool,
+    block_element_limit: Option<usize>,
+    unknown_blobs: Vec<(String, Vec<u8>)>,
+    truncate_stringtable: bool,
+}
+
+impl Default for TestPbfBuilder {
+    fn default() -> TestPbfBuilder {
+        TestPbfBuilder {
+            elements: vec![],
+            dense_nodes: true,
+            block_element_limit: None,
+            unknown_blobs: vec![],
+            truncate_stringtable: false,
+        }
+    }
+}
+
+impl TestPbfBuilder {
+    /// Creates a builder for a file without elements.
+    pub fn new() -> TestPbfBuilder {
+        TestPbfBuilder::default()
+    }
+
+    /// Adds a node with the given coordinates in degrees and tags.
+    pub fn node(self, id: i64, lat: f64, lon: f64, tags: &[(&str, &str)]) -> TestPbfBuilder {
+        self.element(OwnedElement::Node(OwnedNode {
+            id,
+            nano_lat: (lat * 1e9).round() as i64,
+            nano_lon: (lon * 1e9).round() as i64,
+            tags: owned_tags(tags),
+            ..OwnedNode::default()
+        }))
+    }
+
+    /// Adds a way with the given node references and tags.
+    pub fn way(self, id: i64, refs: &[i64], tags: &[(&str, &str)]) -> TestPbfBuilder {
+        self.element(OwnedElement::Way(OwnedWay {
+            id,
+            refs: refs.to_vec(),
+            tags: owned_tags(tags),
+            ..OwnedWay::default()
+        }))
+    }
+
+    /// Adds a relation with the given members (type, id and role) and tags.
+    pub fn relation(
+        self,
+        id: i64,
+        members: &[(RelMemberType, i64, &str)],
+        tags: &[(&str, &str)],
+    ) -> TestPbfBuilder {
+        let members = members
+            .iter()
+            .map(|&(member_type, member_id, role)| OwnedRelMember {
+                member_type,
+                member_id,
+                role: role.to_string(),
+            })
+            .collect();
+        self.element(OwnedElement::Relation(OwnedRelation {
+            id,
+            members,
+            tags: owned_tags(tags),
+            ..OwnedRelation::default()
+        }))
+    }
+
+    /// Adds an element, e.g. one with metadata.
+    pub fn element(mut self, element: OwnedElement) -> TestPbfBuilder {
+        self.elements.push(element);
+        self
+    }
+
+    /// Sets whether nodes are encoded as `DenseNodes` (the default) or as individual `Node`
+    /// messages (see `ElementWriter::dense_nodes`).
+    pub fn dense_nodes(mut self, dense: bool) -> TestPbfBuilder {
+        self.dense_nodes = dense;
+        self
+    }
+
+    /// Sets the maximum number of elements in each block, e.g. to spread a few elements over
+    /// several blocks (see `ElementWriter::with_block_element_limit`).
+    pub fn block_element_limit(mut self, limit: usize) -> TestPbfBuilder {
+        self.block_element_limit = Some(limit);
+        self
+    }
+
+    /// Adds a blob of an unknown type with the given content. Unknown blobs are written right
+    /// after the header blob, so readers have to skip them before they reach the data.
+    pub fn unknown_blob(mut self, blob_type: &str, data: &[u8]) -> TestPbfBuilder {
+        self.unknown_blobs
+            .push((blob_type.to_string(), data.to_vec()));
+        self
+    }
+
+    /// Removes all strings but the empty string at index zero from the stringtable of the first
+    /// data block, so that every tag, role and user name in that block references an invalid
+    /// index. Decoding the block then fails with `ErrorKind::InvalidPrimitiveBlock`. A block
+    /// without any strings stays valid.
+    pub fn truncate_stringtable(mut self) -> TestPbfBuilder {
+        self.truncate_stringtable = true;
+        self
+    }
+
+    /// Writes the file and returns its bytes.
+    ///
+    /// # Errors
+    /// Returns an error if a block could not be encoded.
+    pub fn build(self) -> Result<Vec<u8>> {
+        let mut writer = ElementWriter::new(vec![]).dense_nodes(self.dense_nodes);
+        if let Some(limit) = self.block_element_limit {
+            writer = writer.with_block_element_limit(limit);
+        }
+        for element in self.elements {
+            writer.write_element(element)?;
+        }
+        let bytes = writer.finish()?;
+        if self.unknown_blobs.is_empty() && !self.truncate_stringtable {
+            return Ok(bytes);
+        }
+
+        // Copy the blobs and inject the unknown blobs and the corruption.
+        let mut output = BlobWriter::new(vec![]);
+        let mut first_data_blob = true;
+        for blob in BlobReader::from_bytes(&bytes) {
+            let blob = blob?;
+            match blob.get_type() {
+                BlobType::OsmHeader => {
+                    output.write_raw_blob(&blob)?;
+                    for (blob_type, data) in &self.unknown_blobs {
+                        output.write_blob(BlobType::Unknown(blob_type), data)?;
+                    }
+                }
+                BlobType::OsmData if first_data_blob && self.truncate_stringtable => {
+                    let mut block: osmformat::PrimitiveBlock =
+                        parse_message_from_bytes(&blob.decompressed_bytes()?)
+                            .map_err(|e| new_protobuf_error(e, "primitive block"))?;
+                    block.mut_stringtable().mut_s().truncate(1);
+                    let data = block
+                        .write_to_bytes()
+                        .map_err(|e| new_protobuf_error(e, "primitive block"))?;
+                    output.write_blob(BlobType::OsmData, &data)?;
+                    first_data_blob = false;
+                }
+                BlobType::OsmData => {
+                    output.write_raw_blob(&blob)?;
+                    first_data_blob = false;
+                }
+                BlobType::Unknown(_) => output.write_raw_blob(&blob)?,
+            }
+        }
+        Ok(output.into_inner())
+    }
+}
+
+fn owned_tags(tags: &[(&str, &str)]) -> Vec<(String, String)> {
+    tags.iter()
+        .map(|&(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Decodes all data of an element.
+fn visit_element(element: &Element) -> Result<()> {
+    for tag in element.try_tags() {
+        tag?;
+    }
+    match *element {
+        Element::Node(ref node) => {
+            node.info().user().transpose()?;
+            node.coordinate();
+        }
+        Element::DenseNode(ref node) => {
+            // Dense nodes without metadata have no user to decode.
+            if node.has_info() {
+                node.user()?;
+            }
+            node.coordinate();
+        }
+        Element::Way(ref way) => {
+            way.info().user().transpose()?;
+            for node_id in way.try_refs() {
+                node_id?;
+            }
+        }
+        Element::Relation(ref rel) => {
+            rel.info().user().transpose()?;
+            for member in rel.members() {
+                member.role()?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads all elements sorted by type and id and returns the coarsest granularity.
+fn read_elements<R: Read>(reader: R, name: &str) -> (Vec<OwnedElement>, i32) {
+    let mut elements = vec![];
+    let mut granularity = 0;
+
+    for blob in BlobReader::new(reader) {
+        let blob = blob.unwrap_or_else(|e| panic!("cannot read blob of {} file: {}", name, e));
+        let decoded = blob
+            .decode()
+            .unwrap_or_else(|e| panic!("cannot decode blob of {} file: {}", name, e));
+        if let BlobDecode::OsmData(block) = decoded {
+            granularity = granularity.max(block.granularity());
+            let block_elements = block
+                .into_owned_elements()
+                .unwrap_or_else(|e| panic!("cannot decode elements of {} file: {}", name, e));
+            elements.extend(block_elements);
+        }
+    }
+
+    // Stable sort, so multiple versions of the same element keep their order.
+    elements.sort_by_key(|e| e.osm_id());
+    (elements, granularity)
+}
+
+fn assert_elements_equivalent(a: &OwnedElement, b: &OwnedElement, tolerance: i64) {
+    let id = a.osm_id();
+    if id != b.osm_id() {
+        panic!("elements differ: {} vs. {}", id, b.osm_id());
+    }
+
+    let mut tags_a = a.tags().to_vec();
+    let mut tags_b = b.tags().to_vec();
+    tags_a.sort();
+    tags_b.sort();
+    if tags_a != tags_b {
+        panic!("{}: tags differ: {:?} vs. {:?}", id, tags_a, tags_b);
+    }
+
+    match (a, b) {
+        (OwnedElement::Node(x), OwnedElement::Node(y)) => {
+            if (x.nano_lat - y.nano_lat).abs() > tolerance
+                || (x.nano_lon - y.nano_lon).abs() > tolerance
+            {
+                panic!(
+                    "{}: coordinates differ: ({}, {}) vs. ({}, {})",
+                    id,
+                    x.lat(),
+                    x.lon(),
+                    y.lat(),
+                    y.lon()
+                );
+            }
+        }
+        (OwnedElement::Way(x), OwnedElement::Way(y)) => {
+            if x.refs != y.refs {
+                panic!("{}: refs differ: {:?} vs. {:?}", id, x.refs, y.refs);
+            }
+        }
+        (OwnedElement::Relation(x), OwnedElement::Relation(y)) => {
+            if x.members != y.members {
+                panic!(
+                    "{}: members differ: {:?} vs. {:?}",
+                    id, x.members, y.members
+                );
+            }
+        }
+        _ => unreachable!(),
+    }
+}
@@ -1,4 +1,10 @@
+use error::{new_error, Error, ErrorKind};
+#[cfg(not(feature = "system-libz"))]
+use miniz_oxide::inflate::stream::{inflate, InflateState};
+#[cfg(not(feature = "system-libz"))]
+use miniz_oxide::{DataFormat, MZError, MZFlush, MZStatus};
 use protobuf::ProtobufError;
+use std::io;
 use std::io::Read;
 
 pub(crate) fn parse_message_from_bytes<M>(bytes: &[u8]) -> Result<M, ProtobufError>
@@ -33,3 +39,220 @@ where
         Err(::protobuf::ProtobufError::message_not_initialized(""))
     }
 }
+
+/// Granularity of coordinates in nanodegrees, as used by the writers.
+pub(crate) const GRANULARITY: i32 = 100;
+
+/// Converts nanodegrees to units of `GRANULARITY`, rounding to the nearest unit.
+pub(crate) fn raw_coordinate(nano: i64) -> i64 {
+    let granularity = i64::from(GRANULARITY);
+    (nano + granularity / 2).div_euclid(granularity)
+}
+
+/// Maps signed integers to unsigned integers so that values close to zero stay small.
+pub(crate) fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// The inverse of `zigzag`.
+pub(crate) fn unzigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+/// Appends a value as a little-endian base 128 varint, like protobuf does.
+pub(crate) fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// A `Read` adapter that fails as soon as more than `limit` bytes are read from the inner reader.
+/// Unlike `Read::take` it does not silently truncate the data, so oversized input can be told
+/// apart from corrupt input.
+pub(crate) struct LimitedReader<R> {
+    inner: R,
+    limit: u64,
+    count: u64,
+    exceeded: bool,
+}
+
+impl<R: Read> LimitedReader<R> {
+    pub(crate) fn new(inner: R, limit: u64) -> LimitedReader<R> {
+        LimitedReader {
+            inner,
+            limit,
+            count: 0,
+            exceeded: false,
+        }
+    }
+
+    /// Returns the number of bytes read so far if the limit was exceeded.
+    pub(crate) fn exceeded_size(&self) -> Option<u64> {
+        if self.exceeded {
+            Some(self.count)
+        } else {
+            None
+        }
+    }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.exceeded {
+            // Read at most one byte more than allowed to detect an oversized stream early.
+            let allowed = (self.limit - self.count).saturating_add(1);
+            let len = (buf.len() as u64).min(allowed) as usize;
+            let n = self.inner.read(&mut buf[..len])?;
+            self.count += n as u64;
+            self.exceeded = self.count > self.limit;
+            if !self.exceeded {
+                return Ok(n);
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "decompressed size limit exceeded",
+        ))
+    }
+}
+
+/// Computes `offset + granularity * value` for a coordinate in nano-degrees and returns an
+/// `ErrorKind::ArithmeticOverflow` error for `name` if the result does not fit into an `i64`.
+pub(crate) fn checked_nano_coordinate(
+    offset: i64,
+    granularity: i32,
+    value: i64,
+    name: &'static str,
+) -> Result<i64, Error> {
+    i64::from(granularity)
+        .checked_mul(value)
+        .and_then(|product| product.checked_add(offset))
+        .ok_or_else(|| new_error(ErrorKind::ArithmeticOverflow { value: name }))
+}
+
+/// Computes `timestamp * date_granularity` for a time stamp in milliseconds and returns an
+/// `ErrorKind::ArithmeticOverflow` error if the result does not fit into an `i64`.
+pub(crate) fn checked_milli_timestamp(timestamp: i64, date_granularity: i32) -> Result<i64, Error> {
+    timestamp
+        .checked_mul(i64::from(date_granularity))
+        .ok_or_else(|| {
+            new_error(ErrorKind::ArithmeticOverflow {
+                value: "time stamp",
+            })
+        })
+}
+
+/// Converts nano-degrees to micro-degrees, rounding to the nearest value (halfway cases away from
+/// zero). Values that do not fit into an `i32` are saturated, which can only happen for invalid
+/// coordinates (valid values are within ±180,000,000 micro-degrees).
+pub(crate) fn nano_to_micro_degrees(nano: i64) -> i32 {
+    let micro = if nano >= 0 {
+        nano.saturating_add(500) / 1000
+    } else {
+        nano.saturating_sub(500) / 1000
+    };
+    micro.max(i64::from(i32::MIN)).min(i64::from(i32::MAX)) as i32
+}
+
+/// Checks that a coordinate in nano-degrees is within the valid range of latitudes
+/// ([-90, 90] degrees) and longitudes ([-180, 180] degrees).
+pub(crate) fn nano_coordinates_valid(nano_lat: i64, nano_lon: i64) -> bool {
+    const NANO_90: i64 = 90_000_000_000;
+    const NANO_180: i64 = 180_000_000_000;
+    (-NANO_90..=NANO_90).contains(&nano_lat) && (-NANO_180..=NANO_180).contains(&nano_lon)
+}
+
+/// A 64-bit FNV-1a hasher. Unlike `DefaultHasher`, its output is specified and does not depend on
+/// the Rust version or the platform, so hashes can be stored and compared across runs.
+pub(crate) struct StableHasher {
+    state: u64,
+}
+
+impl StableHasher {
+    pub(crate) fn new() -> StableHasher {
+        StableHasher {
+            state: 0xcbf2_9ce4_8422_2325,
+        }
+    }
+
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.state ^= u64::from(byte);
+            self.state = self.state.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    pub(crate) fn write_i64(&mut self, n: i64) {
+        self.write(&n.to_le_bytes());
+    }
+
+    /// Writes a length prefix before the bytes, so that consecutive values cannot be confused
+    /// (e.g. `"ab", "c"` and `"a", "bc"`).
+    pub(crate) fn write_prefixed(&mut self, bytes: &[u8]) {
+        self.write_i64(bytes.len() as i64);
+        self.write(bytes);
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.state
+    }
+}
+
+/// A `Read` adapter that decompresses zlib data from a byte slice with the pure Rust
+/// `miniz_oxide` decoder.
+#[cfg(not(feature = "system-libz"))]
+pub(crate) struct ZlibReader<'a> {
+    input: &'a [u8],
+    state: Box<InflateState>,
+    done: bool,
+}
+
+#[cfg(not(feature = "system-libz"))]
+impl<'a> ZlibReader<'a> {
+    pub(crate) fn new(input: &'a [u8]) -> ZlibReader<'a> {
+        ZlibReader {
+            input,
+            state: InflateState::new_boxed(DataFormat::Zlib),
+            done: false,
+        }
+    }
+}
+
+#[cfg(not(feature = "system-libz"))]
+impl<'a> Read for ZlibReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            let res = inflate(&mut self.state, self.input, buf, MZFlush::None);
+            self.input = &self.input[res.bytes_consumed..];
+
+            match res.status {
+                Ok(MZStatus::StreamEnd) => {
+                    self.done = true;
+                    return Ok(res.bytes_written);
+                }
+                Ok(_) | Err(MZError::Buf) if res.bytes_written > 0 => {
+                    return Ok(res.bytes_written);
+                }
+                Ok(_) | Err(MZError::Buf) if res.bytes_consumed > 0 => {}
+                Ok(_) | Err(MZError::Buf) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "truncated zlib stream",
+                    ));
+                }
+                Err(e) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid zlib stream: {:?}", e),
+                    ));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,330 @@
+//! Check that a PBF file is well-formed without decoding all of its elements
+
+use blob::{
+    read_up_to, Blob, BlobType, ByteOffset, Compression, MAX_BLOB_HEADER_SIZE,
+    MAX_BLOB_MESSAGE_SIZE,
+};
+use byteorder::ByteOrder;
+use error::{new_blob_error, new_protobuf_error, BlobError, Result};
+use proto::fileformat;
+use reader::SUPPORTED_FEATURES;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use util::parse_message_from_bytes;
+
+/// A problem that `validate_structure` or `validate_contents` found in a file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ValidationIssue {
+    offset: ByteOffset,
+    message: String,
+}
+
+impl ValidationIssue {
+    fn new<M: ToString>(offset: ByteOffset, message: M) -> ValidationIssue {
+        ValidationIssue {
+            offset,
+            message: message.to_string(),
+        }
+    }
+
+    /// Returns the byte offset of the blob that the issue belongs to.
+    pub fn offset(&self) -> ByteOffset {
+        self.offset
+    }
+
+    /// Returns a description of the issue.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "blob at byte {}: {}", self.offset.0, self.message)
+    }
+}
+
+/// The result of validating a file: the number of blobs that were read and all issues that were
+/// found.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ValidationReport {
+    blob_count: usize,
+    issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    /// Returns `true` if no issues were found.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Returns the number of blobs whose header and content could be read completely.
+    pub fn blob_count(&self) -> usize {
+        self.blob_count
+    }
+
+    /// Returns the issues in the order of their offsets.
+    pub fn issues(&self) -> &[ValidationIssue] {
+        &self.issues
+    }
+
+    /// Consumes the report and returns its issues.
+    pub fn into_issues(self) -> Vec<ValidationIssue> {
+        self.issues
+    }
+
+    fn add<M: ToString>(&mut self, offset: ByteOffset, message: M) {
+        self.issues.push(ValidationIssue::new(offset, message));
+    }
+}
+
+/// Checks that a PBF file is well-formed without decoding its `OSMData` blobs. It walks over
+/// every blob and checks that
+///
+/// * each blob header can be parsed and its `datasize` fits into the file,
+/// * each blob contains exactly the data it declares, with a `raw_size` for compressed data,
+/// * the first blob is an `OSMHeader` and all of its required features are supported.
+///
+/// All issues are collected into the returned report instead of stopping at the first one. Only
+/// an issue that makes it impossible to find the next blob (e.g. a truncated header) ends the
+/// walk. Use `validate_contents` to also decompress and parse every `OSMData` blob.
+///
+/// # Errors
+/// Returns an error only if the file cannot be opened or read. Problems with the content of the
+/// file are reported as issues.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let report = validate_structure("tests/test.osm.pbf")?;
+///
+/// for issue in report.issues() {
+///     println!("{}", issue);
+/// }
+/// assert!(report.is_ok());
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+pub fn validate_structure<P: AsRef<Path>>(path: P) -> Result<ValidationReport> {
+    let file = File::open(path)?;
+    validate(BufReader::new(file), false)
+}
+
+/// Checks every blob of a PBF file like `validate_structure` and additionally decompresses and
+/// parses the content of each `OSMData` blob, including the consistency checks of
+/// `PrimitiveBlock::new`. This takes about as long as reading the whole file.
+///
+/// # Errors
+/// Returns an error only if the file cannot be opened or read.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let report = validate_contents("tests/test.osm.pbf")?;
+///
+/// assert_eq!(report.blob_count(), 2);
+/// assert!(report.is_ok());
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+pub fn validate_contents<P: AsRef<Path>>(path: P) -> Result<ValidationReport> {
+    let file = File::open(path)?;
+    validate(BufReader::new(file), true)
+}
+
+fn validate<R: Read>(mut reader: R, decode: bool) -> Result<ValidationReport> {
+    let mut report = ValidationReport::default();
+    let mut offset = 0_u64;
+
+    loop {
+        let blob_offset = ByteOffset(offset);
+
+        let mut size_buf = [0_u8; 4];
+        match read_up_to(&mut reader, &mut size_buf)? {
+            0 => break,
+            4 => {}
+            _ => {
+                report.add(blob_offset, "file ends inside the size of a blob header");
+                break;
+            }
+        }
+        let header_size = u64::from(byteorder::BigEndian::read_u32(&size_buf));
+        if header_size >= MAX_BLOB_HEADER_SIZE {
+            report.add(
+                blob_offset,
+                new_blob_error(BlobError::HeaderTooBig { size: header_size }),
+            );
+            break;
+        }
+
+        let mut header_bytes = Vec::new();
+        reader
+            .by_ref()
+            .take(header_size)
+            .read_to_end(&mut header_bytes)?;
+        if (header_bytes.len() as u64) < header_size {
+            report.add(blob_offset, "file ends inside a blob header");
+            break;
+        }
+        let header: fileformat::BlobHeader = match parse_message_from_bytes(&header_bytes) {
+            Ok(header) => header,
+            Err(e) => {
+                report.add(blob_offset, new_protobuf_error(e, "blob header"));
+                break;
+            }
+        };
+        if header.get_datasize() < 0 {
+            report.add(
+                blob_offset,
+                format!(
+                    "blob header has a negative datasize of {}",
+                    header.get_datasize()
+                ),
+            );
+            break;
+        }
+
+        let datasize = header.get_datasize() as u64;
+        let mut data = Vec::new();
+        if datasize > MAX_BLOB_MESSAGE_SIZE {
+            report.add(
+                blob_offset,
+                new_blob_error(BlobError::MessageTooBig { size: datasize }),
+            );
+            let skipped = io::copy(&mut reader.by_ref().take(datasize), &mut io::sink())?;
+            if skipped < datasize {
+                report.add(blob_offset, "file ends inside the content of a blob");
+                break;
+            }
+        } else {
+            reader.by_ref().take(datasize).read_to_end(&mut data)?;
+            if (data.len() as u64) < datasize {
+                report.add(
+                    blob_offset,
+                    format!(
+                        "file ends inside the content of a blob: datasize is {} bytes, but only \
+                         {} bytes are left",
+                        datasize,
+                        data.len()
+                    ),
+                );
+                break;
+            }
+        }
+
+        offset += 4 + header_size + datasize;
+        report.blob_count += 1;
+
+        if report.blob_count == 1 && header.get_field_type() != "OSMHeader" {
+            report.add(
+                blob_offset,
+                format!(
+                    "first blob has type '{}' instead of 'OSMHeader'",
+                    header.get_field_type()
+                ),
+            );
+        }
+        if datasize > MAX_BLOB_MESSAGE_SIZE {
+            continue;
+        }
+
+        let content: fileformat::Blob = match parse_message_from_bytes(&data) {
+            Ok(content) => content,
+            Err(e) => {
+                report.add(blob_offset, new_protobuf_error(e, "blob content"));
+                continue;
+            }
+        };
+        let blob = Blob::new(header, content, Some(blob_offset));
+        check_blob(&blob, decode, &mut report);
+    }
+
+    if report.blob_count == 0 && report.is_ok() {
+        report.add(ByteOffset(0), "file contains no blobs");
+    }
+
+    Ok(report)
+}
+
+fn check_blob(blob: &Blob, decode: bool, report: &mut ValidationReport) {
+    let offset = blob.offset().unwrap_or(ByteOffset(0));
+    let proto = blob.content();
+
+    match blob.compression() {
+        None => {
+            report.add(offset, new_blob_error(BlobError::Empty));
+            return;
+        }
+        Some(Compression::Raw) => {
+            if proto.has_raw_size() && proto.get_raw_size() as usize != proto.get_raw().len() {
+                report.add(
+                    offset,
+                    format!(
+                        "raw_size is {} bytes, but the raw data has {} bytes",
+                        proto.get_raw_size(),
+                        proto.get_raw().len()
+                    ),
+                );
+            }
+        }
+        Some(_) if !proto.has_raw_size() => {
+            report.add(offset, "compressed blob does not declare its raw_size");
+        }
+        Some(_) => {
+            if blob.decompressed_size() > MAX_BLOB_MESSAGE_SIZE {
+                report.add(
+                    offset,
+                    new_blob_error(BlobError::MessageTooBig {
+                        size: blob.decompressed_size(),
+                    }),
+                );
+            }
+        }
+    }
+
+    let data_fields = [
+        proto.has_raw(),
+        proto.has_zlib_data(),
+        proto.has_lzma_data(),
+        proto.has_OBSOLETE_bzip2_data(),
+    ]
+    .iter()
+    .filter(|&&has| has)
+    .count();
+    if data_fields > 1 {
+        report.add(
+            offset,
+            format!("blob stores its content in {} data fields", data_fields),
+        );
+    }
+
+    match blob.get_type() {
+        BlobType::OsmHeader => match blob.to_headerblock() {
+            Ok(header) => {
+                for feature in header.required_features() {
+                    if !SUPPORTED_FEATURES.contains(&feature.as_str()) {
+                        report.add(
+                            offset,
+                            format!("required feature is not supported: {}", feature),
+                        );
+                    }
+                }
+            }
+            Err(e) => report.add(offset, e),
+        },
+        BlobType::OsmData if decode => {
+            if let Err(e) = blob.to_primitiveblock() {
+                report.add(offset, e);
+            }
+        }
+        BlobType::OsmData | BlobType::Unknown(_) => {}
+    }
+}
@@ -0,0 +1,736 @@
+//! Write elements to PBF files
+
+use blob::{BlobType, BlobWriter, MAX_BLOB_MESSAGE_SIZE};
+use elements::{Element, RelMemberType};
+use error::{new_blob_error, new_protobuf_error, BlobError, Result};
+use indexed::{id_range, write_block_index_magic, BlockIndexEntry};
+use owned::{owned_element, OwnedElement, OwnedInfo, OwnedNode, OwnedRelation, OwnedWay};
+use proto::osmformat;
+use protobuf::{Message, RepeatedField};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::mem;
+use util::{raw_coordinate, GRANULARITY};
+
+/// Default maximum number of elements in a single `PrimitiveBlock`.
+const MAX_ELEMENTS_PER_BLOCK: usize = 8000;
+
+/// Maximum estimated size of a single `PrimitiveBlock` in bytes (see
+/// `OwnedElement::encoded_size_hint`). This is well below `MAX_BLOB_MESSAGE_SIZE`, so blocks of
+/// few but large elements (e.g. relations with many members) are split early and all blobs have
+/// a similar size.
+const MAX_BLOCK_SIZE_HINT: usize = 4 * 1024 * 1024;
+
+/// Granularity of time stamps in milliseconds.
+const DATE_GRANULARITY: i32 = 1000;
+
+/// A writer for PBF files. Elements are buffered and written in blocks of up to 8000 elements (see
+/// `with_block_element_limit`) or an estimated 4 MiB, whichever is reached first.
+///
+/// Nodes are encoded as `DenseNodes` by default, which is the representation that is used for
+/// planet files and that most readers expect. Coordinates are stored with a granularity of 100
+/// nanodegrees and time stamps with a granularity of one second, so values are rounded to these
+/// units.
+///
+/// Call `finish` after writing the last element, otherwise the buffered elements are lost.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let block = BlobReader::from_path("tests/test.osm.pbf")?
+///     .filter_map(|blob| blob.ok()?.to_primitiveblock().ok())
+///     .next()
+///     .unwrap();
+///
+/// let mut writer = ElementWriter::new(vec![]);
+/// for element in block.into_owned_elements()? {
+///     writer.write_element(element)?;
+/// }
+/// let bytes = writer.finish()?;
+///
+/// let reader = ElementReader::new(std::io::Cursor::new(bytes));
+/// let mut dense_nodes = 0;
+/// reader.for_each(|element| {
+///     if let Element::DenseNode(_) = element {
+///         dense_nodes += 1;
+///     }
+/// })?;
+/// assert_eq!(dense_nodes, 3);
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ElementWriter<W: Write> {
+    writer: BlobWriter<W>,
+    dense_nodes: bool,
+    sort_strings: bool,
+    block_element_limit: usize,
+    required_features: Option<Vec<String>>,
+    optional_features: Vec<String>,
+    index: Option<IndexWriter>,
+    header_written: bool,
+    nodes: Vec<OwnedNode>,
+    ways: Vec<OwnedWay>,
+    relations: Vec<OwnedRelation>,
+    block_size_hint: usize,
+}
+
+impl<W: Write> ElementWriter<W> {
+    /// Creates a new `ElementWriter` that writes to the given writer.
+    pub fn new(writer: W) -> ElementWriter<W> {
+        ElementWriter {
+            writer: BlobWriter::new(writer),
+            dense_nodes: true,
+            sort_strings: false,
+            block_element_limit: MAX_ELEMENTS_PER_BLOCK,
+            required_features: None,
+            optional_features: vec![],
+            index: None,
+            header_written: false,
+            nodes: vec![],
+            ways: vec![],
+            relations: vec![],
+            block_size_hint: 0,
+        }
+    }
+
+    /// Sets whether nodes are encoded as `DenseNodes` (the default) or as individual `Node`
+    /// messages. The latter is larger and slower to read, but easier to inspect when debugging.
+    /// This should be set before writing the first element, as the `HeaderBlock` only lists
+    /// `DenseNodes` as a required feature if it is enabled by then (unless the features are set
+    /// explicitly with `required_features`).
+    pub fn dense_nodes(mut self, dense: bool) -> ElementWriter<W> {
+        self.dense_nodes = dense;
+        self
+    }
+
+    /// Sets whether the stringtable of each block is sorted by descending frequency of the strings
+    /// and then lexicographically (disabled by default). Otherwise, strings are stored in the
+    /// order of their first use.
+    ///
+    /// Both orders are deterministic, but with sorting enabled the stringtable only depends on
+    /// the set of strings in a block, not on the order of the elements, and frequent strings get
+    /// small indices, which makes blocks slightly smaller. This costs an additional pass over the
+    /// elements of each block.
+    pub fn sort_stringtable(mut self, sort: bool) -> ElementWriter<W> {
+        self.sort_strings = sort;
+        self
+    }
+
+    /// Sets the maximum number of elements in each block (8000 by default, as recommended by the
+    /// PBF specification). A limit of zero is treated as one.
+    ///
+    /// Regardless of the limit, a block is also written once its estimated size reaches 4 MiB,
+    /// and a block that would exceed `MAX_BLOB_MESSAGE_SIZE` is split before it is written, so
+    /// the output can always be read.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut writer = ElementWriter::new(vec![]).with_block_element_limit(2);
+    /// for element in ElementReader::from_path("tests/test.osm.pbf")?.read_all()? {
+    ///     writer.write_element(element)?;
+    /// }
+    /// let bytes = writer.finish()?;
+    ///
+    /// // One header blob and five elements in blocks of two
+    /// assert_eq!(BlobReader::from_bytes(&bytes).count(), 4);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn with_block_element_limit(mut self, limit: usize) -> ElementWriter<W> {
+        self.block_element_limit = limit.max(1);
+        self
+    }
+
+    /// Sets the required features that are written to the `HeaderBlock`, replacing the default of
+    /// `OsmSchema-V0.6` and, if nodes are encoded as `DenseNodes`, `DenseNodes`. Readers must
+    /// reject a file with a required feature they do not support, so only list features that the
+    /// output actually uses, e.g. `HistoricalInformation` when re-emitting a history file.
+    ///
+    /// This should be set before writing the first element, as the `HeaderBlock` is written with
+    /// the first block.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let writer = ElementWriter::new(vec![])
+    ///     .required_features(vec!["OsmSchema-V0.6", "DenseNodes", "HistoricalInformation"])
+    ///     .optional_features(vec!["Sort.Type_then_ID"]);
+    /// let bytes = writer.finish()?;
+    ///
+    /// let header = BlobReader::from_bytes(&bytes).next().unwrap()?.to_headerblock()?;
+    /// assert_eq!(header.required_features().len(), 3);
+    /// assert_eq!(header.optional_features(), ["Sort.Type_then_ID"]);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn required_features<I>(mut self, features: I) -> ElementWriter<W>
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.required_features = Some(features.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets the optional features that are written to the `HeaderBlock` (none by default). Readers
+    /// may ignore them, but can use them to pick a faster strategy, e.g. `Sort.Type_then_ID` if
+    /// the elements are sorted by type and id. Like `required_features`, this should be set
+    /// before writing the first element.
+    pub fn optional_features<I>(mut self, features: I) -> ElementWriter<W>
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.optional_features = features.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Also writes a `BlockIndex` of the written blocks to `index`, so that the output can be
+    /// read with `IndexedReader::with_index` without scanning it first. An entry with the offset
+    /// and the id ranges of each block is appended as soon as the block is written. The offsets
+    /// are counted from the start of the output, so `writer` should be empty at the start.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    /// use std::fs::File;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let output = std::env::temp_dir().join("osmpbf_with_index_example.osm.pbf");
+    /// let index = std::env::temp_dir().join("osmpbf_with_index_example.idx");
+    ///
+    /// let mut writer = ElementWriter::new(File::create(&output)?)
+    ///     .with_index(File::create(&index)?);
+    /// for element in ElementReader::from_path("tests/test.osm.pbf")?.read_all()? {
+    ///     writer.write_element(element)?;
+    /// }
+    /// writer.finish()?;
+    ///
+    /// let mut reader = IndexedReader::from_path(&output)?
+    ///     .with_index(BlockIndex::from_path(&index)?);
+    /// assert!(reader.find_element(OsmId::Relation(120))?.is_some());
+    /// # std::fs::remove_file(output)?;
+    /// # std::fs::remove_file(index)?;
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    pub fn with_index<I: Write + Send + 'static>(mut self, index: I) -> ElementWriter<W> {
+        self.index = Some(IndexWriter {
+            writer: Box::new(index),
+            started: false,
+        });
+        self
+    }
+
+    /// Adds an element to the current block. The block is written as soon as it is full.
+    ///
+    /// By convention, all nodes should be written before the ways and all ways before the
+    /// relations.
+    ///
+    /// # Errors
+    /// Returns an error if a full block could not be written.
+    pub fn write_element(&mut self, element: OwnedElement) -> Result<()> {
+        // Start a new block if the element does not fit into the current one.
+        let size_hint = element.encoded_size_hint();
+        if self.block_size_hint > 0 && self.block_size_hint + size_hint > MAX_BLOCK_SIZE_HINT {
+            self.flush()?;
+        }
+        self.block_size_hint += size_hint;
+
+        match element {
+            OwnedElement::Node(node) => self.nodes.push(node),
+            OwnedElement::Way(way) => self.ways.push(way),
+            OwnedElement::Relation(rel) => self.relations.push(rel),
+        }
+
+        if self.nodes.len() + self.ways.len() + self.relations.len() >= self.block_element_limit
+            || self.block_size_hint >= MAX_BLOCK_SIZE_HINT
+        {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Adds a copy of a borrowed element to the current block, like `write_element`. Only the
+    /// strings that the element references are looked up in the stringtable of its source block,
+    /// so this is cheaper than converting the whole block with `into_owned_elements` when only a
+    /// few elements are written.
+    ///
+    /// # Example
+    /// ```
+    /// use osmpbf::*;
+    ///
+    /// # fn foo() -> Result<()> {
+    /// let mut writer = ElementWriter::new(vec![]);
+    /// let mut result = Ok(());
+    /// ElementReader::from_path("tests/test.osm.pbf")?.for_each(|element| {
+    ///     if element.tags().len() > 0 && result.is_ok() {
+    ///         result = writer.write_borrowed(&element);
+    ///     }
+    /// })?;
+    /// result?;
+    /// let bytes = writer.finish()?;
+    ///
+    /// let mut count = 0;
+    /// ElementReader::new(&bytes[..]).for_each(|_| count += 1)?;
+    /// assert_eq!(count, 2);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    ///
+    /// # Errors
+    /// Returns an error if a string of the element is not valid UTF-8 or if a full block could not
+    /// be written.
+    pub fn write_borrowed(&mut self, element: &Element) -> Result<()> {
+        self.write_element(owned_element(element)?)
+    }
+
+    /// Writes all buffered elements as a block and flushes the underlying writer. Writes the
+    /// `HeaderBlock` first, if that has not already happened.
+    ///
+    /// # Errors
+    /// Returns an error if encoding or writing fails, or `BlobError::MessageTooBig` if a single
+    /// element does not fit into a blob.
+    pub fn flush(&mut self) -> Result<()> {
+        if !self.header_written {
+            self.write_header()?;
+        }
+
+        if !(self.nodes.is_empty() && self.ways.is_empty() && self.relations.is_empty()) {
+            let nodes = mem::take(&mut self.nodes);
+            let ways = mem::take(&mut self.ways);
+            let relations = mem::take(&mut self.relations);
+            self.block_size_hint = 0;
+            self.write_block(&nodes, &ways, &relations)?;
+        }
+
+        self.writer.get_mut().flush()?;
+        if let Some(ref mut index) = self.index {
+            index.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes all buffered elements and returns the underlying writer.
+    ///
+    /// # Errors
+    /// Returns an error if encoding or writing fails.
+    pub fn finish(mut self) -> Result<W> {
+        self.flush()?;
+        Ok(self.writer.into_inner())
+    }
+
+    /// Encodes and writes the given elements as a block. If the block turns out to be bigger than
+    /// `MAX_BLOB_MESSAGE_SIZE`, it is split in half until each part fits.
+    fn write_block(
+        &mut self,
+        nodes: &[OwnedNode],
+        ways: &[OwnedWay],
+        relations: &[OwnedRelation],
+    ) -> Result<()> {
+        let block = encode_block(nodes, ways, relations, self.dense_nodes, self.sort_strings);
+        let bytes = block
+            .write_to_bytes()
+            .map_err(|e| new_protobuf_error(e, "primitive block"))?;
+
+        if bytes.len() as u64 > MAX_BLOB_MESSAGE_SIZE {
+            let count = nodes.len() + ways.len() + relations.len();
+            if count == 1 {
+                return Err(new_blob_error(BlobError::MessageTooBig {
+                    size: bytes.len() as u64,
+                }));
+            }
+
+            // Split in element order: nodes first, then ways, then relations.
+            let mut half = count / 2;
+            let (first_nodes, last_nodes) = nodes.split_at(half.min(nodes.len()));
+            half -= first_nodes.len();
+            let (first_ways, last_ways) = ways.split_at(half.min(ways.len()));
+            half -= first_ways.len();
+            let (first_relations, last_relations) = relations.split_at(half);
+            self.write_block(first_nodes, first_ways, first_relations)?;
+            return self.write_block(last_nodes, last_ways, last_relations);
+        }
+
+        let offset = self.writer.offset();
+        self.writer.write_blob(BlobType::OsmData, &bytes)?;
+        if let Some(ref mut index) = self.index {
+            index.add(&BlockIndexEntry::new(
+                offset,
+                id_range(nodes.iter().map(|node| node.id)),
+                id_range(ways.iter().map(|way| way.id)),
+                id_range(relations.iter().map(|rel| rel.id)),
+            ))?;
+        }
+        Ok(())
+    }
+
+    fn write_header(&mut self) -> Result<()> {
+        let mut header = osmformat::HeaderBlock::new();
+        match self.required_features {
+            Some(ref features) => header.set_required_features(RepeatedField::from_slice(features)),
+            None => {
+                header
+                    .mut_required_features()
+                    .push("OsmSchema-V0.6".to_string());
+                if self.dense_nodes {
+                    header
+                        .mut_required_features()
+                        .push("DenseNodes".to_string());
+                }
+            }
+        }
+        header.set_optional_features(RepeatedField::from_slice(&self.optional_features));
+        header.set_writingprogram(concat!("osmpbf ", env!("CARGO_PKG_VERSION")).to_string());
+
+        let bytes = header
+            .write_to_bytes()
+            .map_err(|e| new_protobuf_error(e, "header block"))?;
+        self.writer.write_blob(BlobType::OsmHeader, &bytes)?;
+        self.header_written = true;
+        Ok(())
+    }
+}
+
+/// The destination of the `BlockIndex` that `ElementWriter::with_index` writes.
+struct IndexWriter {
+    writer: Box<dyn Write + Send>,
+    started: bool,
+}
+
+impl IndexWriter {
+    fn add(&mut self, entry: &BlockIndexEntry) -> Result<()> {
+        self.start()?;
+        entry.write_to(&mut self.writer)?;
+        Ok(())
+    }
+
+    /// Writes the magic bytes, if that has not happened yet, and flushes the writer, so that even
+    /// the index of an empty file is valid.
+    fn flush(&mut self) -> Result<()> {
+        self.start()?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<()> {
+        if !self.started {
+            write_block_index_magic(&mut self.writer)?;
+            self.started = true;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for IndexWriter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("IndexWriter")
+            .field("started", &self.started)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Collects the strings of a block and assigns them indices into the stringtable.
+struct StringTableBuilder {
+    indices: HashMap<String, u32>,
+    strings: Vec<Vec<u8>>,
+}
+
+impl StringTableBuilder {
+    fn new() -> StringTableBuilder {
+        StringTableBuilder {
+            indices: HashMap::new(),
+            // Index 0 is never assigned to a string, as it is the delimiter in
+            // `DenseNodes::keys_vals`.
+            strings: vec![vec![]],
+        }
+    }
+
+    /// Creates a builder that already contains all strings of the given elements, sorted by
+    /// descending frequency and then lexicographically.
+    fn sorted(
+        nodes: &[OwnedNode],
+        ways: &[OwnedWay],
+        relations: &[OwnedRelation],
+    ) -> StringTableBuilder {
+        let mut counts = HashMap::new();
+        for node in nodes {
+            count_strings(&mut counts, &node.tags, &node.info);
+        }
+        for way in ways {
+            count_strings(&mut counts, &way.tags, &way.info);
+        }
+        for rel in relations {
+            count_strings(&mut counts, &rel.tags, &rel.info);
+            for member in &rel.members {
+                *counts.entry(member.role.as_str()).or_insert(0) += 1;
+            }
+        }
+
+        let mut sorted: Vec<(&str, usize)> = counts.into_iter().collect();
+        sorted.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+        let mut builder = StringTableBuilder::new();
+        for (s, _) in sorted {
+            builder.index(s);
+        }
+        builder
+    }
+
+    fn index(&mut self, s: &str) -> u32 {
+        if let Some(&index) = self.indices.get(s) {
+            return index;
+        }
+        let index = self.strings.len() as u32;
+        self.strings.push(s.as_bytes().to_vec());
+        self.indices.insert(s.to_string(), index);
+        index
+    }
+
+    fn into_stringtable(self) -> osmformat::StringTable {
+        let mut stringtable = osmformat::StringTable::new();
+        stringtable.set_s(RepeatedField::from_vec(self.strings));
+        stringtable
+    }
+}
+
+fn count_strings<'a>(
+    counts: &mut HashMap<&'a str, usize>,
+    tags: &'a [(String, String)],
+    info: &'a OwnedInfo,
+) {
+    for (k, v) in tags {
+        *counts.entry(k.as_str()).or_insert(0) += 1;
+        *counts.entry(v.as_str()).or_insert(0) += 1;
+    }
+    if let Some(ref user) = info.user {
+        *counts.entry(user.as_str()).or_insert(0) += 1;
+    }
+}
+
+fn encode_block(
+    nodes: &[OwnedNode],
+    ways: &[OwnedWay],
+    relations: &[OwnedRelation],
+    dense_nodes: bool,
+    sort_strings: bool,
+) -> osmformat::PrimitiveBlock {
+    let mut strings = if sort_strings {
+        StringTableBuilder::sorted(nodes, ways, relations)
+    } else {
+        StringTableBuilder::new()
+    };
+    let mut groups = vec![];
+
+    if !nodes.is_empty() {
+        let mut group = osmformat::PrimitiveGroup::new();
+        if dense_nodes {
+            group.set_dense(encode_dense_nodes(nodes, &mut strings));
+        } else {
+            let nodes = nodes.iter().map(|n| encode_node(n, &mut strings)).collect();
+            group.set_nodes(RepeatedField::from_vec(nodes));
+        }
+        groups.push(group);
+    }
+    if !ways.is_empty() {
+        let mut group = osmformat::PrimitiveGroup::new();
+        let ways = ways.iter().map(|w| encode_way(w, &mut strings)).collect();
+        group.set_ways(RepeatedField::from_vec(ways));
+        groups.push(group);
+    }
+    if !relations.is_empty() {
+        let mut group = osmformat::PrimitiveGroup::new();
+        let relations = relations
+            .iter()
+            .map(|r| encode_relation(r, &mut strings))
+            .collect();
+        group.set_relations(RepeatedField::from_vec(relations));
+        groups.push(group);
+    }
+
+    let mut block = osmformat::PrimitiveBlock::new();
+    block.set_granularity(GRANULARITY);
+    block.set_date_granularity(DATE_GRANULARITY);
+    block.set_primitivegroup(RepeatedField::from_vec(groups));
+    block.set_stringtable(strings.into_stringtable());
+    block
+}
+
+/// Converts milliseconds to units of the block date granularity.
+fn raw_timestamp(milli: i64) -> i64 {
+    milli.div_euclid(i64::from(DATE_GRANULARITY))
+}
+
+fn encode_tags(
+    tags: &[(String, String)],
+    strings: &mut StringTableBuilder,
+) -> (Vec<u32>, Vec<u32>) {
+    tags.iter()
+        .map(|(k, v)| (strings.index(k), strings.index(v)))
+        .unzip()
+}
+
+fn encode_info(info: &OwnedInfo, strings: &mut StringTableBuilder) -> Option<osmformat::Info> {
+    if *info == OwnedInfo::default() {
+        return None;
+    }
+
+    let mut encoded = osmformat::Info::new();
+    if let Some(version) = info.version {
+        encoded.set_version(version);
+    }
+    if let Some(milli_timestamp) = info.milli_timestamp {
+        encoded.set_timestamp(raw_timestamp(milli_timestamp));
+    }
+    if let Some(changeset) = info.changeset {
+        encoded.set_changeset(changeset);
+    }
+    if let Some(uid) = info.uid {
+        encoded.set_uid(uid);
+    }
+    if let Some(ref user) = info.user {
+        encoded.set_user_sid(strings.index(user));
+    }
+    if !info.visible {
+        encoded.set_visible(false);
+    }
+    Some(encoded)
+}
+
+fn encode_node(node: &OwnedNode, strings: &mut StringTableBuilder) -> osmformat::Node {
+    let mut encoded = osmformat::Node::new();
+    encoded.set_id(node.id);
+    encoded.set_lat(raw_coordinate(node.nano_lat));
+    encoded.set_lon(raw_coordinate(node.nano_lon));
+    let (keys, vals) = encode_tags(&node.tags, strings);
+    encoded.set_keys(keys);
+    encoded.set_vals(vals);
+    if let Some(info) = encode_info(&node.info, strings) {
+        encoded.set_info(info);
+    }
+    encoded
+}
+
+fn encode_dense_nodes(
+    nodes: &[OwnedNode],
+    strings: &mut StringTableBuilder,
+) -> osmformat::DenseNodes {
+    // The tags of each node are terminated by a zero, but the array stays empty if there are no
+    // tags at all.
+    let has_tags = nodes.iter().any(|n| !n.tags.is_empty());
+    let has_invisible = nodes.iter().any(|n| !n.info.visible);
+    // Like `Info`, `DenseInfo` is omitted if no node has metadata.
+    let has_info = nodes.iter().any(|n| n.info != OwnedInfo::default());
+
+    let mut dense = osmformat::DenseNodes::new();
+    let mut info = osmformat::DenseInfo::new();
+    let (mut last_id, mut last_lat, mut last_lon) = (0, 0, 0);
+    let (mut last_timestamp, mut last_changeset) = (0, 0);
+    let (mut last_uid, mut last_user_sid) = (0_i32, 0_i32);
+
+    for node in nodes {
+        let lat = raw_coordinate(node.nano_lat);
+        let lon = raw_coordinate(node.nano_lon);
+        dense.mut_id().push(node.id - last_id);
+        dense.mut_lat().push(lat - last_lat);
+        dense.mut_lon().push(lon - last_lon);
+        last_id = node.id;
+        last_lat = lat;
+        last_lon = lon;
+
+        if has_tags {
+            for (k, v) in &node.tags {
+                dense.mut_keys_vals().push(strings.index(k) as i32);
+                dense.mut_keys_vals().push(strings.index(v) as i32);
+            }
+            dense.mut_keys_vals().push(0);
+        }
+
+        if !has_info {
+            continue;
+        }
+
+        // Unlike `Info`, `DenseInfo` has no optional fields, so missing values are written as
+        // defaults. Index 0 of the stringtable is the empty string.
+        let timestamp = node.info.milli_timestamp.map_or(0, raw_timestamp);
+        let changeset = node.info.changeset.unwrap_or(0);
+        let uid = node.info.uid.unwrap_or(0);
+        let user_sid = node.info.user.as_ref().map_or(0, |u| strings.index(u)) as i32;
+        info.mut_version().push(node.info.version.unwrap_or(-1));
+        info.mut_timestamp().push(timestamp - last_timestamp);
+        info.mut_changeset().push(changeset - last_changeset);
+        info.mut_uid().push(uid.wrapping_sub(last_uid));
+        info.mut_user_sid().push(user_sid - last_user_sid);
+        last_timestamp = timestamp;
+        last_changeset = changeset;
+        last_uid = uid;
+        last_user_sid = user_sid;
+
+        if has_invisible {
+            info.mut_visible().push(node.info.visible);
+        }
+    }
+
+    if has_info {
+        dense.set_denseinfo(info);
+    }
+    dense
+}
+
+fn encode_way(way: &OwnedWay, strings: &mut StringTableBuilder) -> osmformat::Way {
+    let mut encoded = osmformat::Way::new();
+    encoded.set_id(way.id);
+    let (keys, vals) = encode_tags(&way.tags, strings);
+    encoded.set_keys(keys);
+    encoded.set_vals(vals);
+    if let Some(info) = encode_info(&way.info, strings) {
+        encoded.set_info(info);
+    }
+
+    let mut last_ref = 0;
+    for &node_ref in &way.refs {
+        encoded.mut_refs().push(node_ref - last_ref);
+        last_ref = node_ref;
+    }
+    encoded
+}
+
+fn encode_relation(rel: &OwnedRelation, strings: &mut StringTableBuilder) -> osmformat::Relation {
+    let mut encoded = osmformat::Relation::new();
+    encoded.set_id(rel.id);
+    let (keys, vals) = encode_tags(&rel.tags, strings);
+    encoded.set_keys(keys);
+    encoded.set_vals(vals);
+    if let Some(info) = encode_info(&rel.info, strings) {
+        encoded.set_info(info);
+    }
+
+    let mut last_id = 0;
+    for member in &rel.members {
+        encoded
+            .mut_roles_sid()
+            .push(strings.index(&member.role) as i32);
+        encoded.mut_memids().push(member.member_id - last_id);
+        encoded.mut_types().push(match member.member_type {
+            RelMemberType::Node => osmformat::Relation_MemberType::NODE,
+            RelMemberType::Way => osmformat::Relation_MemberType::WAY,
+            RelMemberType::Relation => osmformat::Relation_MemberType::RELATION,
+        });
+        last_id = member.member_id;
+    }
+    encoded
+}
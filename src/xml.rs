@@ -0,0 +1,739 @@
+//! Read and write elements in the OSM XML format
+//!
+//! OSM XML (`.osm`) is the original exchange format of OpenStreetMap
+//! (see [OSM wiki](https://wiki.openstreetmap.org/wiki/OSM_XML)). It is much larger and slower
+//! to process than PBF, but many legacy tools and editors only read and write XML.
+
+use elements::{Element, RelMemberType};
+use error::{new_error, ErrorKind, Result};
+use owned::{
+    owned_element, OwnedElement, OwnedInfo, OwnedNode, OwnedRelMember, OwnedRelation, OwnedWay,
+};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use util::raw_coordinate;
+
+/// A writer for OSM XML files.
+///
+/// Coordinates are written with seven decimal places (100 nanodegrees), the precision of the
+/// OpenStreetMap database, and time stamps are written in seconds. Metadata attributes are only
+/// written if they are present; `visible="false"` is only written for deleted elements.
+///
+/// Call `finish` after writing the last element to close the `<osm>` root element.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let mut writer = OsmXmlWriter::new(vec![]);
+/// let mut result = Ok(());
+/// ElementReader::from_path("tests/test.osm.pbf")?.for_each(|element| {
+///     if result.is_ok() {
+///         result = writer.write_borrowed(&element);
+///     }
+/// })?;
+/// result?;
+///
+/// let xml = String::from_utf8(writer.finish()?).unwrap();
+/// assert!(xml.contains(r#"<tag k="name" v="triangle"/>"#));
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct OsmXmlWriter<W: Write> {
+    writer: W,
+    header_written: bool,
+}
+
+impl<W: Write> OsmXmlWriter<W> {
+    /// Creates a new `OsmXmlWriter` that writes to the given writer. Wrap the writer in a
+    /// `std::io::BufWriter` if necessary, as elements are written in many small pieces.
+    pub fn new(writer: W) -> OsmXmlWriter<W> {
+        OsmXmlWriter {
+            writer,
+            header_written: false,
+        }
+    }
+
+    /// Writes an element.
+    ///
+    /// # Errors
+    /// Returns an error if writing to the underlying writer fails.
+    pub fn write_element(&mut self, element: OwnedElement) -> Result<()> {
+        self.write_header()?;
+        match element {
+            OwnedElement::Node(ref node) => self.write_node(node)?,
+            OwnedElement::Way(ref way) => self.write_way(way)?,
+            OwnedElement::Relation(ref rel) => self.write_relation(rel)?,
+        }
+        Ok(())
+    }
+
+    /// Writes a copy of a borrowed element, like `write_element`.
+    ///
+    /// # Errors
+    /// Returns an error if a string of the element is not valid UTF-8 or if writing to the
+    /// underlying writer fails.
+    pub fn write_borrowed(&mut self, element: &Element) -> Result<()> {
+        self.write_element(owned_element(element)?)
+    }
+
+    /// Closes the root element and returns the underlying writer.
+    ///
+    /// # Errors
+    /// Returns an error if writing to the underlying writer fails.
+    pub fn finish(mut self) -> Result<W> {
+        self.write_header()?;
+        self.writer.write_all(b"</osm>\n")?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_header(&mut self) -> Result<()> {
+        if !self.header_written {
+            writeln!(self.writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+            writeln!(
+                self.writer,
+                r#"<osm version="0.6" generator="osmpbf {}">"#,
+                env!("CARGO_PKG_VERSION")
+            )?;
+            self.header_written = true;
+        }
+        Ok(())
+    }
+
+    fn write_node(&mut self, node: &OwnedNode) -> Result<()> {
+        write!(self.writer, r#"  <node id="{}""#, node.id)?;
+        self.write_info(&node.info)?;
+        if node.info.visible {
+            write!(
+                self.writer,
+                r#" lat="{}" lon="{}""#,
+                format_coordinate(node.nano_lat),
+                format_coordinate(node.nano_lon)
+            )?;
+        }
+        if node.tags.is_empty() {
+            self.writer.write_all(b"/>\n")?;
+        } else {
+            self.writer.write_all(b">\n")?;
+            self.write_tags(&node.tags)?;
+            self.writer.write_all(b"  </node>\n")?;
+        }
+        Ok(())
+    }
+
+    fn write_way(&mut self, way: &OwnedWay) -> Result<()> {
+        write!(self.writer, r#"  <way id="{}""#, way.id)?;
+        self.write_info(&way.info)?;
+        if way.refs.is_empty() && way.tags.is_empty() {
+            self.writer.write_all(b"/>\n")?;
+            return Ok(());
+        }
+        self.writer.write_all(b">\n")?;
+        for node_id in &way.refs {
+            writeln!(self.writer, r#"    <nd ref="{}"/>"#, node_id)?;
+        }
+        self.write_tags(&way.tags)?;
+        self.writer.write_all(b"  </way>\n")?;
+        Ok(())
+    }
+
+    fn write_relation(&mut self, rel: &OwnedRelation) -> Result<()> {
+        write!(self.writer, r#"  <relation id="{}""#, rel.id)?;
+        self.write_info(&rel.info)?;
+        if rel.members.is_empty() && rel.tags.is_empty() {
+            self.writer.write_all(b"/>\n")?;
+            return Ok(());
+        }
+        self.writer.write_all(b">\n")?;
+        for member in &rel.members {
+            let member_type = match member.member_type {
+                RelMemberType::Node => "node",
+                RelMemberType::Way => "way",
+                RelMemberType::Relation => "relation",
+            };
+            writeln!(
+                self.writer,
+                r#"    <member type="{}" ref="{}" role="{}"/>"#,
+                member_type,
+                member.member_id,
+                escape(&member.role)
+            )?;
+        }
+        self.write_tags(&rel.tags)?;
+        self.writer.write_all(b"  </relation>\n")?;
+        Ok(())
+    }
+
+    fn write_info(&mut self, info: &OwnedInfo) -> Result<()> {
+        if let Some(version) = info.version {
+            write!(self.writer, r#" version="{}""#, version)?;
+        }
+        if let Some(milli_timestamp) = info.milli_timestamp {
+            write!(
+                self.writer,
+                r#" timestamp="{}""#,
+                format_timestamp(milli_timestamp.div_euclid(1000))
+            )?;
+        }
+        if let Some(changeset) = info.changeset {
+            write!(self.writer, r#" changeset="{}""#, changeset)?;
+        }
+        if let Some(uid) = info.uid {
+            write!(self.writer, r#" uid="{}""#, uid)?;
+        }
+        if let Some(ref user) = info.user {
+            write!(self.writer, r#" user="{}""#, escape(user))?;
+        }
+        if !info.visible {
+            self.writer.write_all(br#" visible="false""#)?;
+        }
+        Ok(())
+    }
+
+    fn write_tags(&mut self, tags: &[(String, String)]) -> Result<()> {
+        for (key, value) in tags {
+            writeln!(
+                self.writer,
+                r#"    <tag k="{}" v="{}"/>"#,
+                escape(key),
+                escape(value)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A reader for OSM XML files that yields owned elements.
+///
+/// XML files have no string tables or blocks, so the reader returns an `OwnedElement` for each
+/// `<node>`, `<way>` and `<relation>` element and ignores all other markup such as `<bounds>`.
+/// Coordinates are converted to nanodegrees and time stamps to milliseconds, like the values of
+/// `OwnedNode` and `OwnedInfo` that are read from PBF files.
+///
+/// The reader is an iterator of `Result<OwnedElement>` that stops after the first error.
+///
+/// # Example
+/// ```
+/// use osmpbf::*;
+///
+/// # fn foo() -> Result<()> {
+/// let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+/// <osm version="0.6">
+///   <node id="1" lat="52.5" lon="13.4"/>
+///   <way id="2">
+///     <nd ref="1"/>
+///     <tag k="highway" v="residential"/>
+///   </way>
+/// </osm>"#;
+///
+/// let elements = OsmXmlReader::new(xml.as_bytes()).collect::<Result<Vec<_>>>()?;
+/// assert_eq!(elements.len(), 2);
+/// assert_eq!(elements[1].tags(), &[("highway".to_string(), "residential".to_string())]);
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct OsmXmlReader<R: BufRead> {
+    reader: R,
+    buf: Vec<u8>,
+    /// Number of bytes consumed from the reader.
+    offset: u64,
+    /// Position of the markup that is currently processed.
+    markup_offset: u64,
+    /// The element whose child elements are currently read.
+    current: Option<OwnedElement>,
+    failed: bool,
+}
+
+/// A start tag, an end tag or other markup such as comments.
+enum Markup {
+    Start {
+        name: String,
+        attributes: Vec<(String, String)>,
+        empty: bool,
+    },
+    End {
+        name: String,
+    },
+    Other,
+}
+
+impl OsmXmlReader<BufReader<File>> {
+    /// Tries to open the file at the given path to create an `OsmXmlReader` from it.
+    ///
+    /// # Errors
+    /// Returns the same errors that `std::fs::File::open` returns.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(OsmXmlReader::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> OsmXmlReader<R> {
+    /// Creates a new `OsmXmlReader` that reads from the given reader.
+    pub fn new(reader: R) -> OsmXmlReader<R> {
+        OsmXmlReader {
+            reader,
+            buf: vec![],
+            offset: 0,
+            markup_offset: 0,
+            current: None,
+            failed: false,
+        }
+    }
+
+    /// Decodes the file and calls the closure for each element, like `ElementReader::for_each`.
+    ///
+    /// # Errors
+    /// Returns an error if reading fails or if the file is not valid OSM XML.
+    pub fn for_each<F>(self, mut f: F) -> Result<()>
+    where
+        F: FnMut(OwnedElement),
+    {
+        for element in self {
+            f(element?);
+        }
+        Ok(())
+    }
+
+    fn error(&self, message: String) -> ::error::Error {
+        new_error(ErrorKind::InvalidXml {
+            offset: self.markup_offset,
+            message,
+        })
+    }
+
+    /// Reads the next markup between `<` and `>` and skips the text in front of it. Returns
+    /// `None` at the end of the input.
+    fn next_markup(&mut self) -> Result<Option<Markup>> {
+        self.buf.clear();
+        let skipped = self.reader.read_until(b'<', &mut self.buf)?;
+        self.offset += skipped as u64;
+        if self.buf.last() != Some(&b'<') {
+            return Ok(None);
+        }
+        self.markup_offset = self.offset - 1;
+
+        self.buf.clear();
+        loop {
+            let len = self.reader.read_until(b'>', &mut self.buf)?;
+            self.offset += len as u64;
+            if self.buf.last() != Some(&b'>') {
+                return Err(self.error("unterminated markup".to_string()));
+            }
+            if is_complete_markup(&self.buf) {
+                break;
+            }
+        }
+        self.buf.pop();
+
+        if self.buf.starts_with(b"?") || self.buf.starts_with(b"!") {
+            return Ok(Some(Markup::Other));
+        }
+        let markup = match ::std::str::from_utf8(&self.buf) {
+            Ok(markup) => markup.to_string(),
+            Err(err) => return Err(self.error(format!("invalid UTF-8: {}", err))),
+        };
+        if let Some(name) = markup.strip_prefix('/') {
+            return Ok(Some(Markup::End {
+                name: name.trim().to_string(),
+            }));
+        }
+        let (markup, empty) = match markup.trim_end().strip_suffix('/') {
+            Some(markup) => (markup, true),
+            None => (markup.trim_end(), false),
+        };
+        let name_end = markup
+            .find(|c: char| c.is_ascii_whitespace())
+            .unwrap_or(markup.len());
+        let attributes = parse_attributes(&markup[name_end..]).map_err(|m| self.error(m))?;
+        Ok(Some(Markup::Start {
+            name: markup[..name_end].to_string(),
+            attributes,
+            empty,
+        }))
+    }
+
+    /// Processes a markup and returns an element if it is complete.
+    fn process(&mut self, markup: Markup) -> Result<Option<OwnedElement>> {
+        match markup {
+            Markup::Start {
+                name,
+                attributes,
+                empty,
+            } => {
+                let attributes = Attributes {
+                    attributes: &attributes,
+                    offset: self.markup_offset,
+                };
+                match self.current {
+                    None => {
+                        let element = match name.as_str() {
+                            "node" => OwnedElement::Node(OwnedNode {
+                                id: attributes.required("id")?,
+                                nano_lat: attributes.coordinate("lat")?,
+                                nano_lon: attributes.coordinate("lon")?,
+                                tags: vec![],
+                                info: attributes.info()?,
+                            }),
+                            "way" => OwnedElement::Way(OwnedWay {
+                                id: attributes.required("id")?,
+                                refs: vec![],
+                                tags: vec![],
+                                info: attributes.info()?,
+                            }),
+                            "relation" => OwnedElement::Relation(OwnedRelation {
+                                id: attributes.required("id")?,
+                                members: vec![],
+                                tags: vec![],
+                                info: attributes.info()?,
+                            }),
+                            // Other elements like `<osm>` or `<bounds>` are ignored. Their tags
+                            // (e.g. of `<changeset>`) are skipped below as there is no current
+                            // element.
+                            _ => return Ok(None),
+                        };
+                        if empty {
+                            return Ok(Some(element));
+                        }
+                        self.current = Some(element);
+                    }
+                    Some(ref mut element) => match (name.as_str(), element) {
+                        ("tag", element) => {
+                            let key = attributes.string("k")?;
+                            let value = attributes.string("v")?;
+                            tags_mut(element).push((key, value));
+                        }
+                        ("nd", OwnedElement::Way(way)) => {
+                            way.refs.push(attributes.required("ref")?);
+                        }
+                        ("member", OwnedElement::Relation(rel)) => {
+                            let member_type = match attributes.get("type") {
+                                Some("node") => RelMemberType::Node,
+                                Some("way") => RelMemberType::Way,
+                                Some("relation") => RelMemberType::Relation,
+                                _ => return Err(attributes.invalid("type")),
+                            };
+                            rel.members.push(OwnedRelMember {
+                                member_type,
+                                member_id: attributes.required("ref")?,
+                                role: attributes.get("role").unwrap_or("").to_string(),
+                            });
+                        }
+                        _ => {}
+                    },
+                }
+                Ok(None)
+            }
+            Markup::End { name } => {
+                let matches = match self.current {
+                    Some(OwnedElement::Node(_)) => name == "node",
+                    Some(OwnedElement::Way(_)) => name == "way",
+                    Some(OwnedElement::Relation(_)) => name == "relation",
+                    None => false,
+                };
+                Ok(if matches { self.current.take() } else { None })
+            }
+            Markup::Other => Ok(None),
+        }
+    }
+
+    fn next_element(&mut self) -> Result<Option<OwnedElement>> {
+        loop {
+            let markup = match self.next_markup()? {
+                Some(markup) => markup,
+                None if self.current.is_some() => {
+                    return Err(self.error("unexpected end of file inside an element".to_string()))
+                }
+                None => return Ok(None),
+            };
+            if let Some(element) = self.process(markup)? {
+                return Ok(Some(element));
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for OsmXmlReader<R> {
+    type Item = Result<OwnedElement>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.next_element() {
+            Ok(element) => element.map(Ok),
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// The attributes of a start tag and the position of the tag for error messages.
+struct Attributes<'a> {
+    attributes: &'a [(String, String)],
+    offset: u64,
+}
+
+impl<'a> Attributes<'a> {
+    fn get(&self, name: &str) -> Option<&'a str> {
+        self.attributes
+            .iter()
+            .find(|&(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn invalid(&self, name: &str) -> ::error::Error {
+        let message = match self.get(name) {
+            Some(value) => format!("invalid value of attribute '{}': '{}'", name, value),
+            None => format!("missing attribute '{}'", name),
+        };
+        new_error(ErrorKind::InvalidXml {
+            offset: self.offset,
+            message,
+        })
+    }
+
+    fn string(&self, name: &str) -> Result<String> {
+        self.get(name)
+            .map(str::to_string)
+            .ok_or_else(|| self.invalid(name))
+    }
+
+    fn required<T: ::std::str::FromStr>(&self, name: &str) -> Result<T> {
+        self.optional(name)?.ok_or_else(|| self.invalid(name))
+    }
+
+    fn optional<T: ::std::str::FromStr>(&self, name: &str) -> Result<Option<T>> {
+        match self.get(name) {
+            Some(value) => value.parse().map(Some).map_err(|_| self.invalid(name)),
+            None => Ok(None),
+        }
+    }
+
+    /// Parses a coordinate in degrees to nanodegrees. Deleted nodes have no coordinates, which
+    /// is read as zero.
+    fn coordinate(&self, name: &str) -> Result<i64> {
+        match self.optional::<f64>(name)? {
+            Some(degrees) if degrees.is_finite() => Ok((degrees * 1e9).round() as i64),
+            Some(_) => Err(self.invalid(name)),
+            None => Ok(0),
+        }
+    }
+
+    fn info(&self) -> Result<OwnedInfo> {
+        let milli_timestamp = match self.get("timestamp") {
+            Some(timestamp) => {
+                Some(parse_timestamp(timestamp).ok_or_else(|| self.invalid("timestamp"))? * 1000)
+            }
+            None => None,
+        };
+        let visible = match self.get("visible") {
+            Some("true") | None => true,
+            Some("false") => false,
+            Some(_) => return Err(self.invalid("visible")),
+        };
+        Ok(OwnedInfo {
+            version: self.optional("version")?,
+            milli_timestamp,
+            changeset: self.optional("changeset")?,
+            uid: self.optional("uid")?,
+            user: self.get("user").map(str::to_string),
+            visible,
+        })
+    }
+}
+
+fn tags_mut(element: &mut OwnedElement) -> &mut Vec<(String, String)> {
+    match *element {
+        OwnedElement::Node(ref mut node) => &mut node.tags,
+        OwnedElement::Way(ref mut way) => &mut way.tags,
+        OwnedElement::Relation(ref mut rel) => &mut rel.tags,
+    }
+}
+
+/// Returns `true` if the markup (without the leading `<`) ends with a `>` that is not part of a
+/// comment, a CDATA section or an attribute value.
+fn is_complete_markup(markup: &[u8]) -> bool {
+    if markup.starts_with(b"!--") {
+        return markup.len() >= 5 && markup.ends_with(b"-->");
+    }
+    if markup.starts_with(b"![CDATA[") {
+        return markup.ends_with(b"]]>");
+    }
+    let mut quote = None;
+    for &b in markup {
+        match quote {
+            Some(q) if b == q => quote = None,
+            None if b == b'"' || b == b'\'' => quote = Some(b),
+            _ => {}
+        }
+    }
+    quote.is_none()
+}
+
+/// Parses the attributes of a start tag, e.g. ` id="1" lat='52.5'`.
+fn parse_attributes(mut s: &str) -> ::std::result::Result<Vec<(String, String)>, String> {
+    let mut attributes = vec![];
+    loop {
+        s = s.trim_start();
+        if s.is_empty() {
+            return Ok(attributes);
+        }
+        let eq = s
+            .find('=')
+            .ok_or_else(|| format!("invalid attribute: {}", s))?;
+        let name = s[..eq].trim_end();
+        s = s[eq + 1..].trim_start();
+        let quote = match s.chars().next() {
+            Some(c @ '"') | Some(c @ '\'') => c,
+            _ => return Err(format!("unquoted value of attribute '{}'", name)),
+        };
+        let end = s[1..]
+            .find(quote)
+            .ok_or_else(|| format!("unterminated value of attribute '{}'", name))?;
+        attributes.push((name.to_string(), unescape(&s[1..end + 1])?));
+        s = &s[end + 2..];
+    }
+}
+
+/// Replaces the entity and character references of an attribute value.
+fn unescape(s: &str) -> ::std::result::Result<String, String> {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest
+            .find(';')
+            .ok_or_else(|| format!("unterminated reference in '{}'", s))?;
+        let c = match &rest[1..end] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            reference => {
+                let code = match reference.strip_prefix("#x") {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => reference.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+                };
+                code.and_then(::std::char::from_u32)
+            }
+        };
+        unescaped.push(c.ok_or_else(|| format!("invalid reference '{}'", &rest[..=end]))?);
+        rest = &rest[end + 1..];
+    }
+    unescaped.push_str(rest);
+    Ok(unescaped)
+}
+
+/// Formats nanodegrees with seven decimal places, rounding to the nearest 100 nanodegrees.
+fn format_coordinate(nano: i64) -> String {
+    let units = raw_coordinate(nano);
+    let sign = if units < 0 { "-" } else { "" };
+    let abs = units.unsigned_abs();
+    format!("{}{}.{:07}", sign, abs / 10_000_000, abs % 10_000_000)
+}
+
+/// Formats seconds since the Unix epoch as an ISO 8601 date and time in UTC, e.g.
+/// `2014-01-01T12:30:00Z`.
+fn format_timestamp(seconds: i64) -> String {
+    let days = seconds.div_euclid(86_400);
+    let secs_of_day = seconds.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+/// Converts days since 1970-01-01 to a date of the proleptic Gregorian calendar (algorithm by
+/// Howard Hinnant).
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Parses an ISO 8601 date and time in UTC like `2014-01-01T12:30:00Z` to seconds since the Unix
+/// epoch.
+fn parse_timestamp(s: &str) -> Option<i64> {
+    let b = s.as_bytes();
+    if b.len() != 20 || b[4] != b'-' || b[7] != b'-' || b[10] != b'T' || b[13] != b':' {
+        return None;
+    }
+    if b[16] != b':' || b[19] != b'Z' {
+        return None;
+    }
+    let number = |range: ::std::ops::Range<usize>| -> Option<i64> {
+        let digits = &s[range];
+        if digits.bytes().all(|b| b.is_ascii_digit()) {
+            digits.parse().ok()
+        } else {
+            None
+        }
+    };
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+    if second > 60 {
+        return None;
+    }
+    Some(days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second)
+}
+
+/// Converts a date of the proleptic Gregorian calendar to days since 1970-01-01, the inverse of
+/// `civil_from_days`.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Escapes a string for use in an attribute value. Tabs and line breaks are escaped as well, as
+/// XML parsers would normalize them to spaces otherwise. Characters that XML 1.0 does not allow at
+/// all, such as the other C0 control characters, are replaced with U+FFFD.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' => escaped.push_str("&#9;"),
+            '\n' => escaped.push_str("&#10;"),
+            '\r' => escaped.push_str("&#13;"),
+            '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => escaped.push('\u{fffd}'),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
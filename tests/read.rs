@@ -1,6 +1,11 @@
+#[cfg(feature = "system-libz")]
+extern crate flate2;
 extern crate osmpbf;
+extern crate protobuf;
+extern crate rayon;
 
 use osmpbf::*;
+use std::io::{Read, Seek, SeekFrom};
 
 static TEST_FILE_PATHS: [&str; 3] = [
     "tests/test.osm.pbf",
@@ -20,6 +25,7 @@ fn check_header_block_content(block: &HeaderBlock) {
         }
     }
     assert_eq!(block.optional_features().len(), 0);
+    assert!(!block.has_historical_information());
 }
 
 // Compare the content of a PrimitiveBlock with known values from the test file.
@@ -41,6 +47,9 @@ fn check_primitive_block_content(block: &PrimitiveBlock) {
         assert_eq!(nodes[0].info().uid(), Some(17));
         assert_eq!(nodes[1].info().uid(), Some(17));
         assert_eq!(nodes[2].info().uid(), Some(17));
+
+        assert_eq!(nodes[0].info().visible_explicit(), None);
+        assert!(nodes[0].info().visible());
     }
 
     let dense_nodes: Vec<_> = block.groups().flat_map(|g| g.dense_nodes()).collect();
@@ -71,6 +80,11 @@ fn check_primitive_block_content(block: &PrimitiveBlock) {
 
         assert!(way_tags.contains(&("building", "yes")));
         assert!(way_tags.contains(&("name", "triangle")));
+
+        let mut refs = vec![1];
+        ways[0].decoded_refs_into(&mut refs);
+        assert_eq!(refs, [1, 105, 106, 108, 105]);
+        assert_eq!(refs[1..], ways[0].refs().collect::<Vec<_>>()[..]);
     }
 
     {
@@ -84,6 +98,15 @@ fn check_primitive_block_content(block: &PrimitiveBlock) {
         let members = relations[0].members().collect::<Vec<_>>();
         assert_eq!(members.len(), 1);
         assert_eq!(members[0].role().unwrap(), "test_role");
+
+        let roles = relations[0].roles().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(roles, ["test_role"]);
+
+        let owned_members = relations[0].owned_members();
+        assert_eq!(
+            owned_members,
+            [(RelMemberType::Way, 107, "test_role".to_string())]
+        );
     }
 }
 
@@ -96,6 +119,9 @@ fn read_blobs() {
         assert_eq!(blobs.len(), 2);
         assert_eq!(blobs[0].get_type(), BlobType::OsmHeader);
         assert_eq!(blobs[1].get_type(), BlobType::OsmData);
+        assert!(blobs[0].is_header() && !blobs[0].is_data());
+        assert!(blobs[1].is_data() && !blobs[1].is_header());
+        assert_eq!(blobs[1].index_data(), None);
 
         let header = blobs[0].to_headerblock().unwrap();
         check_header_block_content(&header);
@@ -162,6 +188,23 @@ fn read_elements() {
     }
 }
 
+#[test]
+fn for_each_stops_at_framing_error() {
+    for path in &TEST_FILE_PATHS {
+        // Append a blob header size that exceeds MAX_BLOB_HEADER_SIZE.
+        let mut data = std::fs::read(path).unwrap();
+        data.extend_from_slice(&[0xff; 4]);
+
+        // The blobs are decoded one at a time, so the elements before the error are visited.
+        let reader = ElementReader::new(data.as_slice());
+        let mut elements = 0_usize;
+        let result = reader.for_each(|_element| elements += 1);
+
+        assert!(result.is_err());
+        assert_eq!(elements, 5);
+    }
+}
+
 #[test]
 fn par_read_elements() {
     for path in &TEST_FILE_PATHS {
@@ -174,3 +217,1910 @@ fn par_read_elements() {
         assert_eq!(elements, 5);
     }
 }
+
+#[test]
+fn read_owned_elements() {
+    for path in &TEST_FILE_PATHS {
+        let reader = BlobReader::from_path(path).unwrap();
+        let blobs = reader.collect::<Result<Vec<_>>>().unwrap();
+
+        let elements = blobs[1]
+            .to_primitiveblock()
+            .unwrap()
+            .into_owned_elements()
+            .unwrap();
+        assert_eq!(elements.len(), 5);
+
+        let ids: Vec<_> = elements.iter().map(|e| e.id()).collect();
+        assert_eq!(ids, [105, 106, 108, 107, 120]);
+
+        if let OwnedElement::Node(ref node) = elements[1] {
+            assert!(approx_eq(node.lat(), 52.11992359584));
+            assert!(approx_eq(node.lon(), 11.62564468943));
+            assert_eq!(node.info.uid, Some(17));
+            assert_eq!(node.info.user, Some("testuser".to_string()));
+        } else {
+            panic!("Unexpected element type");
+        }
+
+        if let OwnedElement::Way(ref way) = elements[3] {
+            assert_eq!(way.refs, [105, 106, 108, 105]);
+            assert!(way
+                .tags
+                .contains(&("building".to_string(), "yes".to_string())));
+            assert!(way
+                .tags
+                .contains(&("name".to_string(), "triangle".to_string())));
+        } else {
+            panic!("Unexpected element type");
+        }
+
+        if let OwnedElement::Relation(ref rel) = elements[4] {
+            assert_eq!(rel.members.len(), 1);
+            assert_eq!(rel.members[0].member_id, 107);
+            assert_eq!(rel.members[0].member_type, RelMemberType::Way);
+            assert_eq!(rel.members[0].role, "test_role");
+        } else {
+            panic!("Unexpected element type");
+        }
+
+        let all = ElementReader::from_path(path).unwrap().read_all().unwrap();
+        assert_eq!(all, elements);
+    }
+}
+
+#[test]
+fn read_elements_in_block() {
+    for path in &TEST_FILE_PATHS {
+        let reader = ElementReader::from_path(path).unwrap();
+        let mut elements = 0_usize;
+
+        reader
+            .for_each_in_block(|header, block, _element| {
+                check_header_block_content(header.unwrap());
+                assert_eq!(block.date_granularity(), 1000);
+                elements += 1;
+            })
+            .unwrap();
+
+        assert_eq!(elements, 5);
+
+        // A concatenation of two files yields the header of each part, and blocks without a
+        // preceding header have none.
+        let file = std::fs::read(path).unwrap();
+        let data_offset = BlobReader::from_bytes(&file).index().unwrap()[1].0 as usize;
+        let mut data = file[data_offset..].to_vec();
+        data.extend_from_slice(&file);
+        let mut headers = vec![];
+        ElementReader::new(data.as_slice())
+            .for_each_in_block(|header, _block, _element| headers.push(header.is_some()))
+            .unwrap();
+        assert_eq!(headers, [[false; 5], [true; 5]].concat());
+    }
+}
+
+#[test]
+fn skip_corrupt_blobs() {
+    let data = std::fs::read("tests/test.osm.pbf").unwrap();
+    let blobs = BlobReader::from_path("tests/test.osm.pbf")
+        .unwrap()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    let split = blobs[1].offset().unwrap().0 as usize;
+
+    // Insert garbage between the first and the second blob.
+    let mut corrupt = data[..split].to_vec();
+    corrupt.extend_from_slice(&[0xff; 7]);
+    corrupt.extend_from_slice(&data[split..]);
+
+    let reader = BlobReader::new_seekable(std::io::Cursor::new(corrupt)).unwrap();
+    let results: Vec<_> = reader.skip_errors().collect();
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].as_ref().unwrap().get_type(), BlobType::OsmHeader);
+    assert!(results[1].is_err());
+    let last = results[2].as_ref().unwrap();
+    assert_eq!(last.get_type(), BlobType::OsmData);
+    assert_eq!(last.offset(), Some(ByteOffset(split as u64 + 7)));
+    check_primitive_block_content(&last.to_primitiveblock().unwrap());
+}
+
+#[test]
+fn display_types() {
+    let types: Vec<_> = BlobReader::from_path("tests/test.osm.pbf")
+        .unwrap()
+        .map(|blob| blob.unwrap().get_type().to_string())
+        .collect();
+    assert_eq!(types, ["OSMHeader", "OSMData"]);
+
+    let mut element_types = vec![];
+    ElementReader::from_path("tests/test.osm.pbf")
+        .unwrap()
+        .for_each(|element| element_types.push(element.element_type().to_string()))
+        .unwrap();
+    assert_eq!(element_types, ["node", "node", "node", "way", "relation"]);
+}
+
+#[test]
+fn micro_degree_coordinates() {
+    for path in &TEST_FILE_PATHS {
+        let reader = ElementReader::from_path(path).unwrap();
+        let mut coords = vec![];
+
+        reader
+            .for_each(|element| match element {
+                Element::Node(node) => coords.push(node.lat_lon_micro()),
+                Element::DenseNode(node) => coords.push(node.lat_lon_micro()),
+                _ => {}
+            })
+            .unwrap();
+
+        assert_eq!(coords[1], (52_119_924, 11_625_645));
+        assert_eq!(coords[2], (52_119_899, 11_631_019));
+    }
+}
+
+#[test]
+fn block_tags() {
+    for path in &TEST_FILE_PATHS {
+        let reader = BlobReader::from_path(path).unwrap();
+        let blobs = reader.collect::<Result<Vec<_>>>().unwrap();
+        let block = blobs[1].to_primitiveblock().unwrap();
+
+        let mut tags = vec![];
+        block.for_each_tag(|id, k, v| tags.push((id, k.to_string(), v.to_string())));
+        tags.sort();
+
+        assert_eq!(
+            tags,
+            [
+                (107, "building".to_string(), "yes".to_string()),
+                (107, "name".to_string(), "triangle".to_string()),
+                (120, "rel_key".to_string(), "rel_value".to_string()),
+            ]
+        );
+    }
+}
+
+#[test]
+fn untagged_nodes() {
+    for path in &TEST_FILE_PATHS {
+        let reader = ElementReader::from_path(path).unwrap();
+        let mut tagged = 0;
+
+        reader
+            .for_each(|element| match element {
+                Element::Node(node) => tagged += node.is_tagged() as u32,
+                Element::DenseNode(node) => tagged += node.is_tagged() as u32,
+                _ => {}
+            })
+            .unwrap();
+
+        assert_eq!(tagged, 0);
+    }
+}
+
+#[test]
+fn compare_tags() {
+    for path in &TEST_FILE_PATHS {
+        let reader = ElementReader::from_path(path).unwrap();
+        let mut matches = vec![];
+
+        reader
+            .for_each(|element| match element {
+                Element::Way(way) => {
+                    matches.push(way.tag_equals("building", "yes"));
+                    matches.push(way.tag_equals("name", "yes"));
+                    matches.push(way.tag_equals("unknown", "yes"));
+                }
+                Element::Relation(rel) => matches.push(rel.tag_equals("rel_key", "rel_value")),
+                _ => {}
+            })
+            .unwrap();
+
+        assert_eq!(matches, [true, false, false, true]);
+    }
+}
+
+#[test]
+fn seek_to_blob_index() {
+    for path in &TEST_FILE_PATHS {
+        let mut reader = BlobReader::from_path(path).unwrap();
+        let index = reader.index().unwrap();
+        assert_eq!(index.len(), 2);
+        assert_eq!(index[0], ByteOffset(0));
+
+        reader.seek_to_blob(1).unwrap();
+        let blob = reader.next().unwrap().unwrap();
+        assert_eq!(blob.get_type(), BlobType::OsmData);
+        assert_eq!(blob.offset(), Some(index[1]));
+
+        reader.seek_to_blob(0).unwrap();
+        let blob = reader.next().unwrap().unwrap();
+        assert_eq!(blob.get_type(), BlobType::OsmHeader);
+
+        assert!(reader.seek_to_blob(2).is_err());
+    }
+}
+
+#[test]
+fn reject_negative_datasize_when_seeking() {
+    use osmpbf::proto::fileformat;
+    use protobuf::Message;
+
+    let mut header = fileformat::BlobHeader::new();
+    header.set_field_type("OSMData".to_string());
+    header.set_datasize(-20);
+    let header = header.write_to_bytes().unwrap();
+
+    for path in &TEST_FILE_PATHS {
+        let mut data = std::fs::read(path).unwrap();
+        data.extend_from_slice(&(header.len() as u32).to_be_bytes());
+        data.extend_from_slice(&header);
+
+        let mut reader = BlobReader::from_bytes(&data);
+        assert!(reader.index().is_err());
+        assert!(reader.seek_to_blob(5).is_err());
+
+        // The reader is back at the start after the failed index.
+        let mut reader = BlobReader::from_bytes(&data);
+        assert!(reader.index().is_err());
+        let blob = reader.next().unwrap().unwrap();
+        assert_eq!(blob.get_type(), BlobType::OsmHeader);
+        assert_eq!(blob.offset(), Some(ByteOffset(0)));
+    }
+}
+
+#[test]
+fn read_ordered_in_parallel() {
+    for path in &TEST_FILE_PATHS {
+        for &window in &[0, 1, 4] {
+            let reader = ElementReader::from_path(path).unwrap();
+            let mut ids = vec![];
+            reader
+                .par_for_each_ordered(window, |element| {
+                    ids.push(match element {
+                        Element::Node(node) => node.id(),
+                        Element::DenseNode(dnode) => dnode.id,
+                        Element::Way(way) => way.id(),
+                        Element::Relation(rel) => rel.id(),
+                    })
+                })
+                .unwrap();
+            assert_eq!(ids, [105, 106, 108, 107, 120]);
+        }
+    }
+}
+
+#[test]
+fn parse_object_notation() {
+    assert_eq!(parse_osm_id("node/-1"), Some((ElementType::Node, -1)));
+    assert_eq!(parse_osm_id("n42"), Some((ElementType::Node, 42)));
+    assert_eq!(parse_osm_id("way/12345"), Some((ElementType::Way, 12345)));
+    assert_eq!(parse_osm_id("r7"), Some((ElementType::Relation, 7)));
+
+    assert_eq!(parse_osm_id(""), None);
+    assert_eq!(parse_osm_id("node/"), None);
+    assert_eq!(parse_osm_id("node42"), None);
+    assert_eq!(parse_osm_id("n/42"), None);
+    assert_eq!(parse_osm_id("way/ 1"), None);
+    assert_eq!(parse_osm_id("42"), None);
+}
+
+#[test]
+fn estimate_uncompressed_size() {
+    for (path, expected) in TEST_FILE_PATHS.iter().zip(&[260, 260, 281]) {
+        let mut reader = BlobReader::from_path(path).unwrap();
+        let _header = reader.next().unwrap().unwrap();
+
+        let size = reader.estimated_uncompressed_size().unwrap();
+        assert_eq!(size, Some(*expected));
+
+        // The reader is back at the data blob, and the estimate is not counted in the metrics.
+        let blob = reader.next().unwrap().unwrap();
+        assert_eq!(blob.get_type(), BlobType::OsmData);
+        assert_eq!(reader.metrics().blobs_read(), 2);
+
+        // Readers without known offsets are restored as well.
+        let bytes = std::fs::read(path).unwrap();
+        let mut reader = BlobReader::new(std::io::Cursor::new(bytes));
+        assert_eq!(
+            reader.estimated_uncompressed_size().unwrap(),
+            Some(*expected)
+        );
+        let _header = reader.next().unwrap().unwrap();
+        assert_eq!(
+            reader.estimated_uncompressed_size().unwrap(),
+            Some(*expected)
+        );
+        let blob = reader.next().unwrap().unwrap();
+        assert_eq!(blob.get_type(), BlobType::OsmData);
+        assert_eq!(blob.offset(), None);
+        assert!(reader.next().is_none());
+        assert_eq!(reader.metrics().blobs_read(), 2);
+    }
+}
+
+#[test]
+// Elements borrow protobuf messages with cached sizes, but they are hashed by id only.
+#[allow(clippy::mutable_key_type)]
+fn deduplicate_elements() {
+    use std::collections::HashSet;
+
+    let blobs = BlobReader::from_path(TEST_FILE_PATHS[0])
+        .unwrap()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    let first = blobs[1].to_primitiveblock().unwrap();
+    let second = blobs[1].to_primitiveblock().unwrap();
+
+    let mut set = HashSet::new();
+    for element in first.elements().chain(second.elements()) {
+        set.insert(element);
+    }
+    assert_eq!(set.len(), 5);
+
+    let ways: Vec<_> = first.groups().flat_map(|g| g.ways()).collect();
+    let other_ways: Vec<_> = second.groups().flat_map(|g| g.ways()).collect();
+    assert_eq!(ways[0], other_ways[0]);
+}
+
+#[test]
+fn valid_coordinates() {
+    for path in &TEST_FILE_PATHS {
+        let reader = ElementReader::from_path(path).unwrap();
+        let mut valid = vec![];
+        reader
+            .for_each(|element| match element {
+                Element::Node(node) => valid.push(node.coordinates_valid()),
+                Element::DenseNode(dnode) => valid.push(dnode.coordinates_valid()),
+                _ => {}
+            })
+            .unwrap();
+        assert_eq!(valid, [true, true, true]);
+
+        let reader = ElementReader::from_path(path).unwrap();
+        assert!(reader.blobs_with_invalid_coordinates().unwrap().is_empty());
+    }
+}
+
+#[test]
+fn read_from_bytes() {
+    for path in &TEST_FILE_PATHS {
+        let data = std::fs::read(path).unwrap();
+        let mut reader = BlobReader::from_bytes(&data);
+        let index = reader.index().unwrap();
+        assert_eq!(index.len(), 2);
+
+        let blobs = reader.collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(blobs.len(), 2);
+        assert_eq!(blobs[1].offset(), Some(index[1]));
+        check_primitive_block_content(&blobs[1].to_primitiveblock().unwrap());
+    }
+}
+
+#[test]
+fn limit_decoded_size() {
+    for path in &TEST_FILE_PATHS {
+        let blobs = BlobReader::from_path(path)
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+
+        match blobs[1].decode_with_limit(16).unwrap_err().into_kind() {
+            ErrorKind::Blob(BlobError::MessageTooBig { size }) => assert!(size > 16),
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        if let BlobDecode::OsmData(block) = blobs[1].decode_with_limit(1024).unwrap() {
+            check_primitive_block_content(&block);
+        } else {
+            panic!("Unexpected blob type");
+        }
+
+        // The largest possible limit must not overflow.
+        if let BlobDecode::OsmData(block) = blobs[1].decode_with_limit(u64::MAX).unwrap() {
+            check_primitive_block_content(&block);
+        } else {
+            panic!("Unexpected blob type");
+        }
+    }
+}
+
+#[test]
+fn detect_dense_nodes() {
+    for (path, dense) in TEST_FILE_PATHS.iter().zip(&[true, true, false]) {
+        let blobs = BlobReader::from_path(path)
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        let block = blobs[1].to_primitiveblock().unwrap();
+        assert_eq!(block.uses_dense_nodes(), *dense);
+    }
+}
+
+#[test]
+fn copy_raw_blobs() {
+    for path in &TEST_FILE_PATHS {
+        let mut writer = BlobWriter::new(vec![]);
+        for blob in BlobReader::from_path(path).unwrap() {
+            writer.write_raw_blob(&blob.unwrap()).unwrap();
+        }
+        let copy = writer.into_inner();
+        assert_eq!(copy, std::fs::read(path).unwrap());
+
+        let blobs = BlobReader::from_bytes(&copy)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        check_primitive_block_content(&blobs[1].to_primitiveblock().unwrap());
+    }
+}
+
+#[test]
+fn filter_elements_by_tag() {
+    for path in &TEST_FILE_PATHS {
+        let blobs = BlobReader::from_path(path)
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        let block = blobs[1].to_primitiveblock().unwrap();
+
+        let ids = |key, value| -> Vec<OsmId> {
+            block
+                .elements_with_tag(key, value)
+                .map(|e| e.osm_id())
+                .collect()
+        };
+        assert_eq!(ids("name", None), [OsmId::Way(107)]);
+        assert_eq!(ids("name", Some("triangle")), [OsmId::Way(107)]);
+        assert_eq!(ids("name", Some("square")), []);
+        assert_eq!(ids("rel_key", Some("rel_value")), [OsmId::Relation(120)]);
+        assert_eq!(ids("unknown", None), []);
+    }
+}
+
+#[test]
+fn match_tags_with_resolved_indices() {
+    let blocks: Vec<PrimitiveBlock> = TEST_FILE_PATHS
+        .iter()
+        .map(|path| {
+            let blobs = BlobReader::from_path(path)
+                .unwrap()
+                .collect::<Result<Vec<_>>>()
+                .unwrap();
+            blobs[1].to_primitiveblock().unwrap()
+        })
+        .collect();
+
+    for block in &blocks {
+        let matcher = block.tag_matcher("name", Some("triangle"));
+        assert!(matcher.can_match());
+        for other in &blocks {
+            // Elements of other blocks are compared by their strings.
+            let ids: Vec<OsmId> = other
+                .elements()
+                .filter(|e| matcher.matches(e))
+                .map(|e| e.osm_id())
+                .collect();
+            assert_eq!(ids, [OsmId::Way(107)]);
+        }
+
+        for element in block.elements() {
+            let any_name = block.tag_matcher("name", None);
+            assert_eq!(
+                any_name.matches(&element),
+                element.tags().any(|(k, _)| k == "name")
+            );
+        }
+        assert!(!block.tag_matcher("name", Some("square")).can_match());
+        assert!(!block.tag_matcher("unknown", None).can_match());
+    }
+    // Elements with duplicate keys match if any of their pairs does, in their own block and in
+    // other blocks.
+    let build = |dense| {
+        testing::TestPbfBuilder::new()
+            .node(1, 1.0, 2.0, &[("k", "a"), ("k", "b")])
+            .dense_nodes(dense)
+            .build()
+            .unwrap()
+    };
+    let (plain, dense) = (build(false), build(true));
+    let block = |data| {
+        BlobReader::from_bytes(data)
+            .nth(1)
+            .unwrap()
+            .unwrap()
+            .to_primitiveblock()
+            .unwrap()
+    };
+    let blocks = [block(&plain), block(&dense)];
+    for block in &blocks {
+        let element = block.elements().next().unwrap();
+        assert_eq!(element.raw_tags().count(), 2);
+        match element {
+            Element::Node(ref node) => assert!(node.tag_equals("k", "b")),
+            Element::DenseNode(ref node) => assert!(node.tag_equals("k", "b")),
+            _ => panic!("expected a node"),
+        }
+        for other in &blocks {
+            let other_element = other.elements().next().unwrap();
+            assert!(block.tag_matcher("k", Some("a")).matches(&other_element));
+            assert!(block.tag_matcher("k", Some("b")).matches(&other_element));
+        }
+    }
+}
+
+#[test]
+fn iterate_block_by_reference() {
+    for path in &TEST_FILE_PATHS {
+        let blobs = BlobReader::from_path(path)
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        let block = blobs[1].to_primitiveblock().unwrap();
+
+        let mut block_ids = vec![];
+        for element in &block {
+            block_ids.push(element.osm_id());
+        }
+
+        let mut group_ids = vec![];
+        for group in block.groups() {
+            for element in &group {
+                group_ids.push(element.osm_id());
+            }
+        }
+
+        assert_eq!(block_ids.len(), 5);
+        assert_eq!(block_ids, group_ids);
+    }
+}
+
+#[test]
+fn configure_element_reader() {
+    for path in &TEST_FILE_PATHS {
+        let reader = ElementReaderBuilder::new()
+            .buffer_capacity(16)
+            .check_required_features(true)
+            .skip_unknown_blobs(false)
+            .num_threads(1)
+            .from_path(path)
+            .unwrap();
+        let mut ids = vec![];
+        reader
+            .par_for_each_ordered(2, |element| ids.push(element.osm_id()))
+            .unwrap();
+        assert_eq!(ids.len(), 5);
+
+        let reader = ElementReaderBuilder::new()
+            .max_blob_size(16)
+            .from_path(path)
+            .unwrap();
+        match reader.for_each(|_| {}).unwrap_err().into_kind() {
+            ErrorKind::Blob(BlobError::MessageTooBig { .. }) => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+}
+
+#[test]
+fn node_coordinates() {
+    for path in &TEST_FILE_PATHS {
+        let reader = ElementReader::from_path(path).unwrap();
+        let mut coords = vec![];
+        reader
+            .for_each(|element| match element {
+                Element::Node(node) => coords.push(node.coordinate()),
+                Element::DenseNode(dnode) => coords.push(dnode.coordinate()),
+                _ => {}
+            })
+            .unwrap();
+
+        assert_eq!(coords.len(), 3);
+        assert!(approx_eq(coords[1].lat, 52.11992359584));
+        assert!(approx_eq(coords[1].lon, 11.62564468943));
+        assert_eq!(coords[0].haversine_distance(&coords[0]), 0.0);
+        let d = coords[0].haversine_distance(&coords[1]);
+        assert!(approx_eq(d, coords[1].haversine_distance(&coords[0])));
+        assert!(d > 0.0);
+    }
+}
+
+#[test]
+fn way_geometry() {
+    for path in &TEST_FILE_PATHS {
+        let mut reader = IndexedReader::from_path(path).unwrap();
+        let mut ways = vec![];
+        reader
+            .for_each_way_geometry(|way, coords, length| {
+                ways.push((way.id(), coords.to_vec(), length));
+            })
+            .unwrap();
+
+        assert_eq!(ways.len(), 1);
+        let (id, ref coords, length) = ways[0];
+        assert_eq!(id, 107);
+        assert_eq!(coords.len(), 4);
+        assert_eq!(coords[0], coords[3]);
+
+        // The closed triangle of the test file is about 333.85 m + 366.96 m + 330.85 m long.
+        assert!((length - 1_031.655).abs() < 0.01, "length: {}", length);
+        assert_eq!(way_length_meters(coords), length);
+        assert_eq!(way_length_meters(&coords[..1]), 0.0);
+    }
+}
+
+#[test]
+fn filter_by_raw_tags() {
+    for path in &TEST_FILE_PATHS {
+        let reader = ElementReader::from_path(path).unwrap();
+        let ids = reader
+            .filter_map_reduce(
+                |block| {
+                    let key = block
+                        .raw_stringtable()
+                        .iter()
+                        .position(|entry| entry.as_slice() == b"rel_key")
+                        .map(|i| i as u32);
+                    move |mut tags: ElementRawTagIter| {
+                        key.is_some_and(|key| tags.any(|(k, _)| k == key))
+                    }
+                },
+                |element| vec![element.osm_id()],
+                Vec::new,
+                |mut a, b| {
+                    a.extend(b);
+                    a
+                },
+            )
+            .unwrap();
+        assert_eq!(ids, vec![OsmId::Relation(120)]);
+
+        let reader = ElementReader::from_path(path).unwrap();
+        let mut tag_counts = vec![];
+        reader
+            .for_each(|element| tag_counts.push(element.raw_tags().len()))
+            .unwrap();
+        assert_eq!(tag_counts, vec![0, 0, 0, 2, 1]);
+    }
+}
+
+#[test]
+fn group_versions() {
+    for path in &TEST_FILE_PATHS {
+        let reader = ElementReader::from_path(path).unwrap();
+        let ids: Vec<_> = reader
+            .version_histories()
+            .map(|history| {
+                let history = history.unwrap();
+                assert_eq!(history.versions().len(), 1);
+                history.osm_id()
+            })
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                OsmId::Node(105),
+                OsmId::Node(106),
+                OsmId::Node(108),
+                OsmId::Way(107),
+                OsmId::Relation(120)
+            ]
+        );
+    }
+
+    let version = |element: OwnedElement, version| match element {
+        OwnedElement::Node(mut node) => {
+            node.info.version = Some(version);
+            OwnedElement::Node(node)
+        }
+        OwnedElement::Way(mut way) => {
+            way.info.version = Some(version);
+            OwnedElement::Way(way)
+        }
+        other => other,
+    };
+    let node = |id| {
+        OwnedElement::Node(OwnedNode {
+            id,
+            ..OwnedNode::default()
+        })
+    };
+    let way = |id| {
+        OwnedElement::Way(OwnedWay {
+            id,
+            ..OwnedWay::default()
+        })
+    };
+
+    // The versions of node 1 are split over two blocks.
+    let mut writer = ElementWriter::new(vec![]);
+    writer.write_element(version(node(1), 1)).unwrap();
+    writer.flush().unwrap();
+    writer.write_element(version(node(1), 2)).unwrap();
+    writer.write_element(version(node(2), 1)).unwrap();
+    writer.write_element(version(way(1), 1)).unwrap();
+    writer.write_element(version(way(1), 2)).unwrap();
+    writer.write_element(version(way(1), 3)).unwrap();
+    let bytes = writer.finish().unwrap();
+
+    let histories: Vec<_> = ElementReader::new(std::io::Cursor::new(bytes))
+        .version_histories()
+        .map(|history| history.unwrap())
+        .collect();
+    let summary: Vec<_> = histories
+        .iter()
+        .map(|h| (h.osm_id(), h.versions().len(), h.latest().info().version))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (OsmId::Node(1), 2, Some(2)),
+            (OsmId::Node(2), 1, Some(1)),
+            (OsmId::Way(1), 3, Some(3)),
+        ]
+    );
+}
+
+#[test]
+fn read_latest_versions() {
+    let node = |id, version, visible| {
+        OwnedElement::Node(OwnedNode {
+            id,
+            info: OwnedInfo {
+                version: Some(version),
+                visible,
+                ..OwnedInfo::default()
+            },
+            ..OwnedNode::default()
+        })
+    };
+    let write = |elements: Vec<OwnedElement>| {
+        let mut writer = ElementWriter::new(vec![]);
+        for element in elements {
+            writer.write_element(element).unwrap();
+        }
+        writer.finish().unwrap()
+    };
+    let latest = |bytes: Vec<u8>| {
+        ElementReader::new(std::io::Cursor::new(bytes))
+            .latest_versions()
+            .map(|e| e.map(|e| (e.osm_id(), e.info().version)))
+            .collect::<Result<Vec<_>>>()
+    };
+
+    let bytes = write(vec![
+        node(1, 1, true),
+        node(1, 2, true),
+        node(2, 1, true),
+        node(2, 2, false),
+        node(3, 1, true),
+        OwnedElement::Way(OwnedWay {
+            id: 1,
+            ..OwnedWay::default()
+        }),
+    ]);
+    assert_eq!(
+        latest(bytes).unwrap(),
+        [
+            (OsmId::Node(1), Some(2)),
+            (OsmId::Node(3), Some(1)),
+            (OsmId::Way(1), None),
+        ]
+    );
+
+    let unsorted_ids = write(vec![node(2, 1, true), node(1, 1, true)]);
+    match latest(unsorted_ids).unwrap_err().into_kind() {
+        ErrorKind::UnsortedElements { id } => assert_eq!(id, OsmId::Node(1)),
+        kind => panic!("unexpected error: {:?}", kind),
+    }
+
+    let unsorted_versions = write(vec![node(1, 2, true), node(1, 1, true)]);
+    match latest(unsorted_versions).unwrap_err().into_kind() {
+        ErrorKind::UnsortedElements { id } => assert_eq!(id, OsmId::Node(1)),
+        kind => panic!("unexpected error: {:?}", kind),
+    }
+}
+
+#[test]
+fn detect_compression() {
+    let expected = [Compression::Zlib, Compression::Raw, Compression::Raw];
+    for (path, &compression) in TEST_FILE_PATHS.iter().zip(&expected) {
+        let reader = BlobReader::from_path(path).unwrap();
+        for blob in reader {
+            assert_eq!(blob.unwrap().compression(), Some(compression));
+        }
+    }
+}
+
+#[test]
+fn send_errors_between_threads() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Error>();
+    assert_send_sync::<ErrorKind>();
+    assert_send_sync::<Result<Blob>>();
+
+    let handle = std::thread::spawn(|| BlobReader::from_path("tests/does_not_exist.osm.pbf"));
+    match handle.join().unwrap().unwrap_err().into_kind() {
+        ErrorKind::Io(_) => {}
+        kind => panic!("unexpected error: {:?}", kind),
+    }
+}
+
+#[test]
+fn decode_stringtable_once() {
+    for path in &TEST_FILE_PATHS {
+        let reader = BlobReader::from_path(path).unwrap();
+        for blob in reader {
+            if let BlobDecode::OsmData(block) = blob.unwrap().decode().unwrap() {
+                let strings = block.decoded_stringtable().unwrap();
+                assert_eq!(strings.len(), block.raw_stringtable().len());
+                assert!(std::ptr::eq(strings, block.decoded_stringtable().unwrap()));
+
+                // A clone of the block keeps the decoded strings.
+                let copy = block.clone();
+                assert_eq!(copy.decoded_stringtable().unwrap(), strings);
+
+                let mut tags = vec![];
+                for element in block.elements() {
+                    if let Element::Way(_) = element {
+                        for (k, v) in element.raw_tags() {
+                            tags.push((&*strings[k as usize], &*strings[v as usize]));
+                        }
+                    }
+                }
+                tags.sort();
+                assert_eq!(tags, [("building", "yes"), ("name", "triangle")]);
+            }
+        }
+    }
+}
+
+#[test]
+fn count_element_histogram() {
+    for path in &TEST_FILE_PATHS {
+        let reader = ElementReader::from_path(path).unwrap();
+        let histogram = reader.element_histogram().unwrap();
+
+        assert_eq!(histogram.total(), 5);
+        assert_eq!(histogram.nodes.total, 3);
+        assert_eq!(histogram.nodes.untagged(), 3);
+        assert_eq!(histogram.ways.total, 1);
+        assert_eq!(histogram.ways.tagged, 1);
+        assert_eq!(histogram.relations.total, 1);
+        assert_eq!(histogram.relations.tagged, 1);
+        assert_eq!(histogram.nodes.with_info, 3);
+        assert_eq!(histogram.ways.with_info, 1);
+        assert_eq!(histogram.relations.with_info, 1);
+    }
+
+    let node = |id, tags: &[(&str, &str)]| {
+        OwnedElement::Node(OwnedNode {
+            id,
+            tags: tags
+                .iter()
+                .map(|&(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..OwnedNode::default()
+        })
+    };
+    for &dense in &[true, false] {
+        let mut writer = ElementWriter::new(vec![]).dense_nodes(dense);
+        writer
+            .write_element(node(1, &[("a", "b"), ("c", "d")]))
+            .unwrap();
+        writer.write_element(node(2, &[])).unwrap();
+        writer.write_element(node(3, &[("a", "b")])).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let reader = ElementReader::new(std::io::Cursor::new(bytes));
+        let histogram = reader.element_histogram().unwrap();
+        assert_eq!(histogram.nodes.total, 3);
+        assert_eq!(histogram.nodes.tagged, 2);
+    }
+}
+
+#[test]
+fn find_nearest_node() {
+    for path in &TEST_FILE_PATHS {
+        let mut reader = IndexedReader::from_path(path).unwrap();
+
+        let near_106 = Coordinate::new(52.11992, 11.62564);
+        let node = reader.nearest_node(near_106, 50.0).unwrap().unwrap();
+        assert_eq!(node.id, 106);
+        assert!(approx_eq(node.lat(), 52.11992359584));
+
+        let near_108 = Coordinate::new(52.1199, 11.6310);
+        let node = reader.nearest_node(near_108, 50.0).unwrap().unwrap();
+        assert_eq!(node.id, 108);
+
+        let far_away = Coordinate::new(52.2, 11.7);
+        assert_eq!(reader.nearest_node(far_away, 1000.0).unwrap(), None);
+        let node = reader
+            .nearest_node(far_away, 20_000_000.0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(node.id, 105);
+
+        for &radius in &[1e300, f64::INFINITY] {
+            let node = reader.nearest_node(far_away, radius).unwrap().unwrap();
+            assert_eq!(node.id, 105);
+        }
+    }
+}
+
+/// A seekable reader that counts the bytes that are actually read.
+struct CountingCursor {
+    inner: std::io::Cursor<Vec<u8>>,
+    bytes_read: u64,
+}
+
+impl CountingCursor {
+    fn new(data: Vec<u8>) -> CountingCursor {
+        CountingCursor {
+            inner: std::io::Cursor::new(data),
+            bytes_read: 0,
+        }
+    }
+}
+
+impl Read for CountingCursor {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes_read += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for CountingCursor {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[test]
+fn skip_with_nth() {
+    for path in &TEST_FILE_PATHS {
+        let mut reader = BlobReader::from_path(path).unwrap();
+        let index = reader.index().unwrap();
+        reader.seek_to_blob(0).unwrap();
+
+        let blob = reader.nth(1).unwrap().unwrap();
+        assert_eq!(blob.get_type(), BlobType::OsmData);
+        assert_eq!(blob.offset(), Some(index[1]));
+        assert!(reader.next().is_none());
+
+        let mut reader = BlobReader::from_path(path).unwrap();
+        assert!(reader.nth(2).is_none());
+
+        let mut reader = BlobReader::from_path(path).unwrap();
+        let block = match reader.nth(1).unwrap().unwrap().decode().unwrap() {
+            BlobDecode::OsmData(block) => block,
+            _ => panic!("expected an OSMData blob"),
+        };
+        let group = block.groups().find(|g| g.ways().len() > 0).unwrap();
+        let last = group.ways().len() - 1;
+        assert_eq!(group.ways().nth(last).map(|way| way.id()), Some(107));
+        assert!(group.ways().nth(last + 1).is_none());
+        assert!(block.groups().nth(block.groups().len()).is_none());
+
+        // Seekable readers do not read the content of the skipped header blob.
+        let file = std::fs::read(path).unwrap();
+        let mut reader = BlobReader::new_seekable(CountingCursor::new(file.clone())).unwrap();
+        assert_eq!(reader.nth(1).unwrap().unwrap().offset(), Some(index[1]));
+        let header_size = u64::from(u32::from_be_bytes([file[0], file[1], file[2], file[3]]));
+        let skipped = index[1].0 - 4 - header_size;
+        assert_eq!(reader.into_inner().bytes_read, file.len() as u64 - skipped);
+
+        // Other readers read and discard it.
+        let mut reader = BlobReader::new(file.as_slice());
+        let blob = reader.nth(1).unwrap().unwrap();
+        assert_eq!(blob.get_type(), BlobType::OsmData);
+        assert!(reader.next().is_none());
+    }
+}
+
+#[test]
+fn read_only_header_blocks() {
+    for path in &TEST_FILE_PATHS {
+        let file = std::fs::read(path).unwrap();
+        let mut concatenated = file.clone();
+        concatenated.extend_from_slice(&file);
+
+        let headers = BlobReader::from_bytes(&concatenated).header_blocks();
+        let metrics = headers.get_ref().shared_metrics();
+        let headers = headers.collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(headers.len(), 2);
+        for header in &headers {
+            assert!(header
+                .required_features()
+                .contains(&"OsmSchema-V0.6".to_string()));
+        }
+
+        // The data blobs are skipped without parsing.
+        assert_eq!(metrics.blobs_read(), 2);
+        assert_eq!(metrics.bytes_read(), concatenated.len() as u64);
+
+        // Seekable readers only read the blob headers of the data blobs.
+        let data_offset = BlobReader::from_bytes(&file).index().unwrap()[1].0;
+        let at = data_offset as usize;
+        let data_header_size =
+            u32::from_be_bytes([file[at], file[at + 1], file[at + 2], file[at + 3]]);
+        let reader = BlobReader::new_seekable(CountingCursor::new(concatenated.clone())).unwrap();
+        let mut headers = reader.header_blocks();
+        assert_eq!(headers.by_ref().count(), 2);
+        let cursor = headers.into_inner().into_inner();
+        assert_eq!(
+            cursor.bytes_read,
+            2 * (data_offset + 4 + u64::from(data_header_size))
+        );
+    }
+}
+
+#[test]
+fn par_decode_with_offsets() {
+    use rayon::iter::ParallelIterator;
+
+    for path in &TEST_FILE_PATHS {
+        let index = BlobReader::seekable_from_path(path)
+            .unwrap()
+            .index()
+            .unwrap();
+        let mut decoded = BlobReader::from_path(path)
+            .unwrap()
+            .par_decode()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        decoded.sort_by_key(|&(offset, _)| offset);
+
+        let offsets: Vec<_> = decoded.iter().map(|&(offset, _)| offset).collect();
+        assert_eq!(offsets, index);
+        assert!(matches!(decoded[0].1, BlobDecode::OsmHeader(_)));
+        assert!(matches!(decoded[1].1, BlobDecode::OsmData(_)));
+    }
+}
+
+#[test]
+fn par_decode_without_known_offset() {
+    use rayon::iter::ParallelIterator;
+    use std::io::Cursor;
+
+    for path in &TEST_FILE_PATHS {
+        let index = BlobReader::seekable_from_path(path)
+            .unwrap()
+            .index()
+            .unwrap();
+        let bytes = std::fs::read(path).unwrap();
+
+        // `BlobReader::new` does not track offsets, so they are taken from the stream position.
+        let mut offsets = BlobReader::new(Cursor::new(bytes))
+            .par_decode()
+            .map(|decoded| decoded.map(|(offset, _)| offset))
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        offsets.sort();
+        assert_eq!(offsets, index);
+    }
+}
+
+#[test]
+fn take_first_blobs() {
+    for path in &TEST_FILE_PATHS {
+        let index = BlobReader::seekable_from_path(path)
+            .unwrap()
+            .index()
+            .unwrap();
+
+        let mut preview = BlobReader::from_path(path).unwrap().take_blobs(1);
+        assert_eq!(preview.size_hint(), (0, Some(1)));
+        let blob = preview.next().unwrap().unwrap();
+        assert_eq!(blob.get_type(), BlobType::OsmHeader);
+        assert!(preview.next().is_none());
+        assert!(preview.next().is_none());
+
+        let mut reader = preview.into_inner();
+        assert_eq!(reader.next().unwrap().unwrap().offset(), Some(index[1]));
+
+        // Taking more blobs than there are ends with the file.
+        let all = BlobReader::from_path(path).unwrap().take_blobs(10);
+        assert_eq!(all.count(), 2);
+    }
+}
+
+#[test]
+fn collect_uid_users() {
+    for path in &TEST_FILE_PATHS {
+        let mut users = vec![];
+        for blob in BlobReader::from_path(path).unwrap() {
+            if let BlobDecode::OsmData(block) = blob.unwrap().decode().unwrap() {
+                block.for_each_uid_user(|uid, user| users.push((uid, user.to_string())));
+            }
+        }
+        // All elements are from the same user, but it is reported only once.
+        assert_eq!(users, [(17, "testuser".to_string())]);
+    }
+}
+
+#[test]
+fn collect_reader_metrics() {
+    for path in &TEST_FILE_PATHS {
+        let file_size = std::fs::metadata(path).unwrap().len();
+
+        let mut reader = BlobReader::from_path(path).unwrap();
+        assert!(reader.by_ref().all(|blob| blob.is_ok()));
+        assert_eq!(reader.metrics().blobs_read(), 2);
+        assert_eq!(reader.metrics().bytes_read(), file_size);
+        assert_eq!(reader.metrics().decode_errors(), 0);
+
+        let reader = ElementReader::from_path(path).unwrap();
+        let metrics = reader.shared_metrics();
+        let count = reader
+            .par_map_reduce(|_| 1, || 0_u64, |a, b| a + b)
+            .unwrap();
+        assert_eq!(count, 5);
+        assert_eq!(metrics.blobs_read(), 2);
+        assert_eq!(metrics.bytes_read(), file_size);
+        assert_eq!(metrics.decode_errors(), 0);
+
+        let reader = ElementReaderBuilder::new()
+            .max_blob_size(16)
+            .from_path(path)
+            .unwrap();
+        let metrics = reader.shared_metrics();
+        assert!(reader.for_each(|_| {}).is_err());
+        assert_eq!(metrics.decode_errors(), 1);
+    }
+
+    let compressed = ElementReader::from_path(TEST_FILE_PATHS[0]).unwrap();
+    let metrics = compressed.shared_metrics();
+    compressed.for_each(|_| {}).unwrap();
+    assert!(metrics.bytes_decompressed() > 0);
+}
+
+#[test]
+fn deduplicate_by_osm_id() {
+    let blobs = BlobReader::from_path(TEST_FILE_PATHS[0])
+        .unwrap()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    let first = blobs[1].to_primitiveblock().unwrap();
+    let second = blobs[1].to_primitiveblock().unwrap();
+
+    let ids: Vec<_> = DedupByOsmId::new(first.elements().chain(second.elements()))
+        .map(|e| e.osm_id())
+        .collect();
+    assert_eq!(ids.len(), 5);
+
+    let mut dedup = DedupByOsmId::with_set(first.elements(), OsmIdBitmap::new());
+    assert_eq!(dedup.by_ref().count(), 5);
+    let seen = dedup.into_set();
+    assert!(seen.contains(OsmId::Way(107)));
+    assert!(!seen.contains(OsmId::Node(107)));
+
+    let mut dedup = DedupByOsmId::with_set(second.elements(), seen);
+    assert!(dedup.next().is_none());
+
+    let mut bitmap = OsmIdBitmap::new();
+    assert!(bitmap.insert(OsmId::Node(-1)));
+    assert!(!bitmap.insert(OsmId::Node(-1)));
+    assert!(bitmap.insert(OsmId::Relation(1 << 20)));
+    assert!(bitmap.contains(OsmId::Relation(1 << 20)));
+    assert!(!bitmap.contains(OsmId::Relation((1 << 20) + 1)));
+
+    // Extreme ids only allocate the pages that they occupy.
+    for &id in &[i64::MAX, i64::MIN, i64::MAX - 64] {
+        assert!(bitmap.insert(OsmId::Way(id)));
+        assert!(bitmap.contains(OsmId::Way(id)));
+        assert!(!bitmap.contains(OsmId::Node(id)));
+    }
+    assert!(!bitmap.contains(OsmId::Way(i64::MAX - 1)));
+    assert!(!bitmap.contains(OsmId::Node(1)));
+}
+
+#[test]
+fn report_invalid_tag_strings() {
+    use osmpbf::proto::osmformat;
+    use protobuf::{Message, RepeatedField};
+
+    let mut way = osmformat::Way::new();
+    way.set_id(42);
+    way.set_keys(vec![1, 1, 3]);
+    way.set_vals(vec![1, 2, 1]);
+    let mut group = osmformat::PrimitiveGroup::new();
+    group.set_ways(RepeatedField::from_vec(vec![way]));
+    let mut stringtable = osmformat::StringTable::new();
+    stringtable.set_s(RepeatedField::from_vec(vec![
+        vec![],
+        b"name".to_vec(),
+        vec![0xff],
+        vec![0xfe],
+    ]));
+    let mut block = osmformat::PrimitiveBlock::new();
+    block.set_stringtable(stringtable);
+    block.set_primitivegroup(RepeatedField::from_vec(vec![group]));
+
+    let mut writer = BlobWriter::new(vec![]);
+    writer
+        .write_blob(BlobType::OsmData, &block.write_to_bytes().unwrap())
+        .unwrap();
+    let bytes = writer.into_inner();
+    let block = BlobReader::from_bytes(&bytes)
+        .next()
+        .unwrap()
+        .unwrap()
+        .to_primitiveblock()
+        .unwrap();
+
+    let element = block.elements().next().unwrap();
+    let tags: Vec<_> = element.try_tags().collect();
+    assert_eq!(tags.len(), 3);
+    assert_eq!(tags[0].as_ref().unwrap(), &("name", "name"));
+
+    match *tags[1].as_ref().unwrap_err().kind() {
+        ErrorKind::InvalidTag { id, part, ref err } => {
+            assert_eq!(id, OsmId::Way(42));
+            assert_eq!(part, TagPart::Value);
+            assert!(matches!(
+                *err.kind(),
+                ErrorKind::StringtableUtf8 { index: 2, .. }
+            ));
+        }
+        _ => panic!("expected an invalid tag error"),
+    }
+    let err = tags[2].as_ref().unwrap_err();
+    assert!(err.to_string().starts_with("tag key of way/42 is invalid"));
+
+    // `tags` skips the invalid tags.
+    assert_eq!(element.tags().count(), 1);
+}
+
+#[cfg(feature = "xml")]
+#[test]
+fn read_xml() {
+    let xml = r#"<?xml version='1.0' encoding='UTF-8'?>
+<!-- a comment with <markup> -->
+<osm version="0.6" generator="test">
+  <bounds minlat="0" minlon="0" maxlat="1" maxlon="1"/>
+  <node id="-3" version="1" timestamp="2014-01-01T12:30:00Z" uid="17" user="a&amp;b" lat="-0.5" lon='1e-7'>
+    <tag k="note" v="x > y &#x263A;"/>
+  </node>
+  <node id="4" version="2" visible="false"/>
+  <relation id="7">
+    <member type="node" ref="-3" role=""/>
+  </relation>
+</osm>
+"#;
+    let elements = OsmXmlReader::new(xml.as_bytes())
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(
+        elements,
+        [
+            OwnedElement::Node(OwnedNode {
+                id: -3,
+                nano_lat: -500_000_000,
+                nano_lon: 100,
+                tags: vec![("note".to_string(), "x > y \u{263A}".to_string())],
+                info: OwnedInfo {
+                    version: Some(1),
+                    milli_timestamp: Some(1_388_579_400_000),
+                    uid: Some(17),
+                    user: Some("a&b".to_string()),
+                    ..OwnedInfo::default()
+                },
+            }),
+            OwnedElement::Node(OwnedNode {
+                id: 4,
+                info: OwnedInfo {
+                    version: Some(2),
+                    visible: false,
+                    ..OwnedInfo::default()
+                },
+                ..OwnedNode::default()
+            }),
+            OwnedElement::Relation(OwnedRelation {
+                id: 7,
+                members: vec![OwnedRelMember {
+                    member_type: RelMemberType::Node,
+                    member_id: -3,
+                    role: String::new(),
+                }],
+                ..OwnedRelation::default()
+            }),
+        ]
+    );
+
+    let invalid = "<osm>\n  <way id=\"1\"><nd/></way>\n</osm>";
+    let mut reader = OsmXmlReader::new(invalid.as_bytes());
+    match reader.next().unwrap().unwrap_err().into_kind() {
+        ErrorKind::InvalidXml { offset, message } => {
+            assert_eq!(offset, 20);
+            assert_eq!(message, "missing attribute 'ref'");
+        }
+        kind => panic!("unexpected error: {:?}", kind),
+    }
+    assert!(reader.next().is_none());
+}
+
+#[test]
+fn decode_malformed_bytes() {
+    for path in &TEST_FILE_PATHS {
+        let data = std::fs::read(path).unwrap();
+        assert_eq!(osmpbf::testing::decode_blob_bytes(&data).unwrap(), 5);
+
+        // Truncated files and flipped bytes must lead to errors, not to panics.
+        for len in 0..data.len() {
+            let _ = osmpbf::testing::decode_blob_bytes(&data[..len]);
+        }
+        for i in 0..data.len() {
+            let mut corrupt = data.clone();
+            corrupt[i] ^= 0xff;
+            let _ = osmpbf::testing::decode_blob_bytes(&corrupt);
+        }
+    }
+
+    // The user names of dense nodes with metadata are decoded as well.
+    use osmpbf::proto::osmformat;
+    use protobuf::{Message, RepeatedField};
+
+    let mut info = osmformat::DenseInfo::new();
+    info.set_version(vec![1]);
+    info.set_timestamp(vec![0]);
+    info.set_changeset(vec![0]);
+    info.set_uid(vec![0]);
+    info.set_user_sid(vec![1]);
+    let mut dense = osmformat::DenseNodes::new();
+    dense.set_id(vec![1]);
+    dense.set_lat(vec![0]);
+    dense.set_lon(vec![0]);
+    dense.set_denseinfo(info);
+    let mut group = osmformat::PrimitiveGroup::new();
+    group.set_dense(dense);
+    let mut stringtable = osmformat::StringTable::new();
+    stringtable.set_s(RepeatedField::from_vec(vec![vec![], vec![0xff]]));
+    let mut block = osmformat::PrimitiveBlock::new();
+    block.set_stringtable(stringtable);
+    block.set_primitivegroup(RepeatedField::from_vec(vec![group]));
+    let mut writer = BlobWriter::new(vec![]);
+    writer
+        .write_blob(BlobType::OsmData, &block.write_to_bytes().unwrap())
+        .unwrap();
+    let bytes = writer.into_inner();
+    match osmpbf::testing::decode_blob_bytes(&bytes)
+        .unwrap_err()
+        .into_kind()
+    {
+        ErrorKind::StringtableUtf8 { index, .. } => assert_eq!(index, 1),
+        kind => panic!("unexpected error: {:?}", kind),
+    }
+}
+
+#[test]
+fn reject_inconsistent_blocks() {
+    use osmpbf::proto::osmformat;
+    use protobuf::{Message, RepeatedField};
+
+    let decode = |group: osmformat::PrimitiveGroup| {
+        let mut stringtable = osmformat::StringTable::new();
+        stringtable.set_s(RepeatedField::from_vec(vec![vec![], b"name".to_vec()]));
+        let mut block = osmformat::PrimitiveBlock::new();
+        block.set_stringtable(stringtable);
+        block.set_primitivegroup(RepeatedField::from_vec(vec![group]));
+
+        let mut writer = BlobWriter::new(vec![]);
+        writer
+            .write_blob(BlobType::OsmData, &block.write_to_bytes().unwrap())
+            .unwrap();
+        let bytes = writer.into_inner();
+        let blob = BlobReader::from_bytes(&bytes).next().unwrap().unwrap();
+        blob.to_primitiveblock().map(|_| ())
+    };
+
+    let mut way = osmformat::Way::new();
+    way.set_id(42);
+    way.set_keys(vec![1]);
+    way.set_vals(vec![1]);
+    let mut group = osmformat::PrimitiveGroup::new();
+    group.set_ways(RepeatedField::from_vec(vec![way.clone()]));
+    assert!(decode(group).is_ok());
+
+    way.set_vals(vec![2]);
+    let mut group = osmformat::PrimitiveGroup::new();
+    group.set_ways(RepeatedField::from_vec(vec![way]));
+    let err = decode(group).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "primitive block is inconsistent: way 42 references string 2, but the stringtable has 2 \
+         entries"
+    );
+
+    let mut dense = osmformat::DenseNodes::new();
+    dense.set_id(vec![1, 1]);
+    dense.set_lat(vec![0, 0]);
+    dense.set_lon(vec![0]);
+    let mut group = osmformat::PrimitiveGroup::new();
+    group.set_dense(dense);
+    match decode(group).unwrap_err().into_kind() {
+        ErrorKind::InvalidPrimitiveBlock { reason } => {
+            assert_eq!(
+                reason,
+                "longitudes of dense nodes has 1 entries instead of 2"
+            );
+        }
+        kind => panic!("unexpected error: {:?}", kind),
+    }
+
+    // User name index 0 is the empty string, even if the stringtable is empty.
+    let mut info = osmformat::DenseInfo::new();
+    info.set_version(vec![1]);
+    info.set_timestamp(vec![0]);
+    info.set_changeset(vec![0]);
+    info.set_uid(vec![0]);
+    info.set_user_sid(vec![0]);
+    let mut dense = osmformat::DenseNodes::new();
+    dense.set_id(vec![1]);
+    dense.set_lat(vec![0]);
+    dense.set_lon(vec![0]);
+    dense.set_denseinfo(info);
+    let mut group = osmformat::PrimitiveGroup::new();
+    group.set_dense(dense);
+    let mut block = osmformat::PrimitiveBlock::new();
+    block.set_stringtable(osmformat::StringTable::new());
+    block.set_primitivegroup(RepeatedField::from_vec(vec![group]));
+    let mut writer = BlobWriter::new(vec![]);
+    writer
+        .write_blob(BlobType::OsmData, &block.write_to_bytes().unwrap())
+        .unwrap();
+    let bytes = writer.into_inner();
+    let blob = BlobReader::from_bytes(&bytes).next().unwrap().unwrap();
+    let block = blob.to_primitiveblock().unwrap();
+    assert_eq!(block.elements().count(), 1);
+}
+
+#[cfg(feature = "system-libz")]
+#[test]
+fn read_gzip_file() {
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    let data = std::fs::read(TEST_FILE_PATHS[0]).unwrap();
+    // Two gzip members, like the output of parallel compressors.
+    let mut gzipped = vec![];
+    for part in [&data[..100], &data[100..]] {
+        let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(part).unwrap();
+        gzipped.extend(encoder.finish().unwrap());
+    }
+    let path = std::env::temp_dir().join("osmpbf_test_read_gzip.osm.pbf.gz");
+    std::fs::write(&path, &gzipped).unwrap();
+
+    let elements = ElementReader::from_gzip_path(&path)
+        .unwrap()
+        .read_all()
+        .unwrap();
+    let expected = ElementReader::from_path(TEST_FILE_PATHS[0])
+        .unwrap()
+        .read_all()
+        .unwrap();
+    assert_eq!(elements, expected);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn relation_types() {
+    for path in &TEST_FILE_PATHS {
+        let mut types = vec![];
+        ElementReader::from_path(path)
+            .unwrap()
+            .for_each(|element| {
+                if let Element::Relation(rel) = element {
+                    types.push(rel.relation_type().map(str::to_string));
+                }
+            })
+            .unwrap();
+        // The relation of the test file has no type tag.
+        assert_eq!(types, [None]);
+    }
+
+    assert_eq!(
+        RelationType::from("multipolygon"),
+        RelationType::Multipolygon
+    );
+    assert_eq!(RelationType::from("route"), RelationType::Route);
+    let other = RelationType::from("multipolgon");
+    assert_eq!(other, RelationType::Other("multipolgon"));
+    assert_eq!(other.to_string(), "multipolgon");
+    assert_eq!(RelationType::Boundary.as_str(), "boundary");
+}
+
+#[test]
+fn estimate_element_counts() {
+    for path in &TEST_FILE_PATHS {
+        let blobs = BlobReader::from_path(path)
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(blobs[0].estimate_element_count(), None);
+        let estimate = blobs[1].estimate_element_count().unwrap();
+        assert!(estimate > 0);
+    }
+}
+
+#[test]
+fn ignore_negative_raw_sizes() {
+    use osmpbf::proto::fileformat;
+    use protobuf::Message;
+
+    let mut blob = fileformat::Blob::new();
+    blob.set_zlib_data(vec![0; 16]);
+    blob.set_raw_size(-1);
+    let blob = blob.write_to_bytes().unwrap();
+    let mut header = fileformat::BlobHeader::new();
+    header.set_field_type("OSMData".to_string());
+    header.set_datasize(blob.len() as i32);
+    let header = header.write_to_bytes().unwrap();
+
+    let mut data = (header.len() as u32).to_be_bytes().to_vec();
+    data.extend_from_slice(&header);
+    data.extend_from_slice(&blob);
+
+    let mut reader = BlobReader::from_bytes(&data);
+    assert_eq!(reader.estimated_uncompressed_size().unwrap(), None);
+    let blob = reader.next().unwrap().unwrap();
+    assert_eq!(blob.estimate_element_count(), None);
+}
+
+#[test]
+fn validate_files() {
+    for path in &TEST_FILE_PATHS {
+        let report = validate_contents(path).unwrap();
+        assert!(report.is_ok(), "{:?}", report.issues());
+        assert_eq!(report.blob_count(), 2);
+        assert_eq!(validate_structure(path).unwrap(), report);
+    }
+
+    let data = std::fs::read(TEST_FILE_PATHS[0]).unwrap();
+    let offsets = BlobReader::from_path(TEST_FILE_PATHS[0])
+        .unwrap()
+        .index()
+        .unwrap();
+    let data_start = offsets[1].0 as usize;
+
+    // Drop the header blob and append a truncated copy of the data blob.
+    let mut broken = data[data_start..].to_vec();
+    broken.extend_from_slice(&data[data_start..data_start + 20]);
+    let path = std::env::temp_dir().join("osmpbf_test_validate_files.osm.pbf");
+    std::fs::write(&path, &broken).unwrap();
+    let report = validate_structure(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let issues = report.issues();
+    assert_eq!(report.blob_count(), 1);
+    assert_eq!(issues.len(), 2);
+    assert_eq!(issues[0].offset(), ByteOffset(0));
+    assert!(issues[0].message().contains("instead of 'OSMHeader'"));
+    assert_eq!(
+        issues[1].offset(),
+        ByteOffset((data.len() - data_start) as u64)
+    );
+    assert!(issues[1].message().starts_with("file ends inside"));
+}
+
+#[test]
+fn block_changesets() {
+    for path in &TEST_FILE_PATHS {
+        let mut expected = std::collections::BTreeSet::new();
+        let mut changesets = vec![];
+        for blob in BlobReader::from_path(path).unwrap() {
+            if let BlobDecode::OsmData(block) = blob.unwrap().decode().unwrap() {
+                for element in block.clone().into_owned_elements().unwrap() {
+                    expected.extend(element.info().changeset);
+                }
+                changesets.extend(block.changesets());
+            }
+        }
+        assert!(!changesets.is_empty());
+        assert_eq!(changesets, expected.into_iter().collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn decompress_blobs() {
+    use osmpbf::proto::osmformat;
+    use protobuf::Message;
+
+    let read_bytes = |path| {
+        BlobReader::from_path(path)
+            .unwrap()
+            .map(|blob| blob.unwrap().decompressed_bytes().unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    // The zlib compressed and the uncompressed test files contain the same blocks.
+    let bytes = read_bytes(TEST_FILE_PATHS[0]);
+    assert_eq!(bytes.len(), 2);
+    assert_eq!(bytes, read_bytes(TEST_FILE_PATHS[1]));
+
+    let block = osmformat::PrimitiveBlock::parse_from_bytes(&bytes[1]).unwrap();
+    assert_eq!(block.get_primitivegroup().len(), 3);
+}
+
+#[test]
+fn wrap_overflowing_deltas() {
+    use osmpbf::proto::osmformat;
+    use protobuf::{Message, RepeatedField};
+
+    let mut info = osmformat::DenseInfo::new();
+    info.set_version(vec![1, 0]);
+    info.set_timestamp(vec![0, 0]);
+    info.set_changeset(vec![0, 0]);
+    info.set_uid(vec![i32::MAX, 1]);
+    info.set_user_sid(vec![1, 0]);
+    let mut dense = osmformat::DenseNodes::new();
+    dense.set_id(vec![i64::MAX, 1]);
+    dense.set_lat(vec![0, 0]);
+    dense.set_lon(vec![0, 0]);
+    dense.set_keys_vals(vec![1, 1, 0, 0]);
+    dense.set_denseinfo(info);
+    let mut way = osmformat::Way::new();
+    way.set_id(1);
+    way.set_refs(vec![i64::MAX, 1, 0]);
+    let mut member = osmformat::Relation::new();
+    member.set_id(2);
+    member.set_roles_sid(vec![0, 0]);
+    member.set_memids(vec![i64::MAX, 1]);
+    member.set_types(vec![osmformat::Relation_MemberType::NODE; 2]);
+    let mut group = osmformat::PrimitiveGroup::new();
+    group.set_dense(dense);
+    group.set_ways(RepeatedField::from_vec(vec![way]));
+    group.set_relations(RepeatedField::from_vec(vec![member]));
+    let mut stringtable = osmformat::StringTable::new();
+    stringtable.set_s(RepeatedField::from_vec(vec![vec![], b"k".to_vec()]));
+    let mut block = osmformat::PrimitiveBlock::new();
+    block.set_stringtable(stringtable);
+    block.set_primitivegroup(RepeatedField::from_vec(vec![group]));
+
+    let mut writer = BlobWriter::new(vec![]);
+    writer
+        .write_blob(BlobType::OsmData, &block.write_to_bytes().unwrap())
+        .unwrap();
+    let bytes = writer.into_inner();
+    let block = BlobReader::from_bytes(&bytes)
+        .next()
+        .unwrap()
+        .unwrap()
+        .to_primitiveblock()
+        .unwrap();
+
+    // Corrupt deltas wrap around instead of panicking in debug builds.
+    let mut tagged = vec![];
+    block.for_each_tag(|id, _, _| tagged.push(id));
+    assert_eq!(tagged, [i64::MAX]);
+    let mut uids = vec![];
+    block.for_each_uid_user(|uid, _| uids.push(uid));
+    assert_eq!(uids, [i32::MAX, i32::MIN]);
+    assert_eq!(block.id_range(), Some((i64::MIN, i64::MAX)));
+
+    let way = block.groups().flat_map(|g| g.ways()).next().unwrap();
+    let mut refs = vec![];
+    way.decoded_refs_into(&mut refs);
+    assert_eq!(refs, [i64::MAX, i64::MIN, i64::MIN]);
+    assert_eq!(way.refs().collect::<Vec<_>>(), refs);
+    assert_eq!(way.dedup_refs().collect::<Vec<_>>(), [i64::MAX, i64::MIN]);
+
+    let rel = block.groups().flat_map(|g| g.relations()).next().unwrap();
+    let member_ids: Vec<i64> = rel.members().map(|m| m.member_id).collect();
+    assert_eq!(member_ids, [i64::MAX, i64::MIN]);
+}
+
+#[test]
+fn detect_arithmetic_overflow() {
+    use osmpbf::proto::osmformat;
+    use protobuf::{Message, RepeatedField};
+
+    let mut info = osmformat::Info::new();
+    info.set_timestamp(i64::MAX / 10);
+    let mut node = osmformat::Node::new();
+    node.set_id(1);
+    node.set_lat(i64::MAX / 10);
+    node.set_lon(5);
+    node.set_info(info);
+    let mut group = osmformat::PrimitiveGroup::new();
+    group.set_nodes(RepeatedField::from_vec(vec![node]));
+    let mut block = osmformat::PrimitiveBlock::new();
+    block.set_stringtable(osmformat::StringTable::new());
+    block.set_primitivegroup(RepeatedField::from_vec(vec![group]));
+
+    let mut writer = BlobWriter::new(vec![]);
+    writer
+        .write_blob(BlobType::OsmData, &block.write_to_bytes().unwrap())
+        .unwrap();
+    let bytes = writer.into_inner();
+    let block = BlobReader::from_bytes(&bytes)
+        .next()
+        .unwrap()
+        .unwrap()
+        .to_primitiveblock()
+        .unwrap();
+
+    let node = block.groups().flat_map(|g| g.nodes()).next().unwrap();
+    assert_eq!(node.lon_in_nano_degrees_checked().unwrap(), 500);
+    assert!(matches!(
+        *node.lat_in_nano_degrees_checked().unwrap_err().kind(),
+        ErrorKind::ArithmeticOverflow { value: "latitude" }
+    ));
+    assert!(matches!(
+        *node.info().milli_timestamp_checked().unwrap_err().kind(),
+        ErrorKind::ArithmeticOverflow {
+            value: "time stamp"
+        }
+    ));
+
+    // The unchecked accessors wrap around instead of panicking.
+    let _ = node.lat_in_nano_degrees();
+    let _ = node.info().milli_timestamp();
+}
+
+#[test]
+fn par_iter_groups() {
+    use rayon::prelude::*;
+
+    for path in &TEST_FILE_PATHS {
+        for blob in BlobReader::from_path(path).unwrap() {
+            if let BlobDecode::OsmData(block) = blob.unwrap().decode().unwrap() {
+                let sequential: Vec<usize> = block.groups().map(|g| g.elements().count()).collect();
+                let parallel: Vec<usize> = (&block)
+                    .into_par_iter()
+                    .map(|g| g.elements().count())
+                    .collect();
+                assert_eq!(block.into_par_iter().len(), block.num_groups());
+                assert_eq!(parallel, sequential);
+            }
+        }
+    }
+}
+
+#[test]
+fn collect_node_coordinates() {
+    for path in &TEST_FILE_PATHS {
+        let store = ElementReader::from_path(path)
+            .unwrap()
+            .collect_node_coords()
+            .unwrap();
+        assert_eq!(store.len(), 3);
+
+        ElementReader::from_path(path)
+            .unwrap()
+            .for_each(|element| {
+                let (id, lat, lon) = match element {
+                    Element::Node(ref node) => (node.id(), node.lat(), node.lon()),
+                    Element::DenseNode(ref dnode) => (dnode.id, dnode.lat(), dnode.lon()),
+                    _ => {
+                        assert_eq!(store.get(element.osm_id().id()), None);
+                        return;
+                    }
+                };
+                let (store_lat, store_lon) = store.get(id).unwrap();
+                assert!((store_lat - lat).abs() < 1e-6);
+                assert!((store_lon - lon).abs() < 1e-6);
+            })
+            .unwrap();
+    }
+
+    // Coordinates of corrupt nodes that are out of the `i32` range are saturated.
+    for &dense in &[true, false] {
+        let bytes = testing::TestPbfBuilder::new()
+            .dense_nodes(dense)
+            .node(1, 5000.0, -5000.0, &[])
+            .node(2, 52.5, 13.4, &[])
+            .build()
+            .unwrap();
+        let store = ElementReader::new(&bytes[..])
+            .collect_node_coords()
+            .unwrap();
+        assert_eq!(store.get_micro(1), Some((i32::MAX, i32::MIN)));
+        assert_eq!(store.get_micro(2), Some((52_500_000, 13_400_000)));
+    }
+
+    // Unsorted input is sorted, and the last of several nodes with the same id is kept.
+    let store: NodeCoordStore = vec![(5, 1, 1), (2, 2, 2), (5, 3, 3), (-1, 4, 4)]
+        .into_iter()
+        .collect();
+    assert_eq!(store.len(), 3);
+    assert_eq!(store.get_micro(5), Some((3, 3)));
+    assert_eq!(store.get_micro(-1), Some((4, 4)));
+    assert!(NodeCoordStore::new().is_empty());
+}
+
+#[test]
+fn compact_node_store() {
+    // A simple linear congruential generator for reproducible gaps and coordinates
+    let mut state = 12345_u64;
+    let mut next = move || {
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (state >> 33) as i64
+    };
+
+    let mut nodes = vec![(i64::MIN, -90_000_000, -180_000_000)];
+    let mut id = -5;
+    for _ in 0..1000 {
+        id += 1 + next() % 3;
+        nodes.push((
+            id,
+            (next() % 180_000_000 - 90_000_000) as i32,
+            (next() % 360_000_000 - 180_000_000) as i32,
+        ));
+    }
+    nodes.push((i64::MAX, 90_000_000, 180_000_000));
+
+    let mut store = CompactNodeStore::new();
+    for &(id, lat, lon) in &nodes {
+        store.insert(id, lat, lon).unwrap();
+    }
+    assert_eq!(store.len(), nodes.len());
+    for &(id, lat, lon) in &nodes {
+        assert_eq!(store.get_micro(id), Some((lat, lon)));
+    }
+    let ids: std::collections::HashSet<i64> = nodes.iter().map(|n| n.0).collect();
+    for id in -10..nodes[nodes.len() - 2].0 + 10 {
+        if !ids.contains(&id) {
+            assert_eq!(store.get_micro(id), None);
+        }
+    }
+    assert_eq!(store.get(i64::MAX), Some((90.0, 180.0)));
+
+    // Nodes are rejected unless their ids are ascending.
+    let err = store.insert(7, 0, 0).unwrap_err();
+    assert!(matches!(
+        *err.kind(),
+        ErrorKind::UnsortedElements { id: OsmId::Node(7) }
+    ));
+    assert_eq!(store.len(), nodes.len());
+
+    let compact = nodes
+        .iter()
+        .cloned()
+        .collect::<NodeCoordStore>()
+        .to_compact();
+    assert_eq!(compact.len(), store.len());
+    assert_eq!(
+        compact.get_micro(nodes[500].0),
+        Some((nodes[500].1, nodes[500].2))
+    );
+    assert!(compact.memory_usage() < nodes.len() * 16);
+    assert!(CompactNodeStore::new().get(1).is_none());
+}
+
+#[test]
+fn effective_coordinate_precision() {
+    use osmpbf::proto::osmformat;
+    use protobuf::Message;
+
+    for path in &TEST_FILE_PATHS {
+        for blob in BlobReader::from_path(path).unwrap() {
+            if let BlobDecode::OsmData(block) = blob.unwrap().decode().unwrap() {
+                assert_eq!(block.granularity(), 100);
+                assert!(approx_eq(block.effective_precision_meters(), 0.011_119_5));
+            }
+        }
+    }
+
+    // A coarse granularity of 0.01 degrees
+    let mut block = osmformat::PrimitiveBlock::new();
+    block.set_stringtable(osmformat::StringTable::new());
+    block.set_granularity(10_000_000);
+    let mut writer = BlobWriter::new(vec![]);
+    writer
+        .write_blob(BlobType::OsmData, &block.write_to_bytes().unwrap())
+        .unwrap();
+    let bytes = writer.into_inner();
+    let block = BlobReader::from_bytes(&bytes)
+        .next()
+        .unwrap()
+        .unwrap()
+        .to_primitiveblock()
+        .unwrap();
+    assert!((block.effective_precision_meters() - 1_111.95).abs() < 0.01);
+}
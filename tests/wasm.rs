@@ -0,0 +1,52 @@
+// Run with `wasm-pack test --node -- --no-default-features` or, with `wasm-bindgen-cli` installed,
+// `CARGO_TARGET_WASM32_UNKNOWN_UNKNOWN_RUNNER=wasm-bindgen-test-runner cargo test --target
+// wasm32-unknown-unknown --no-default-features --test wasm`.
+#![cfg(target_arch = "wasm32")]
+
+extern crate osmpbf;
+extern crate wasm_bindgen_test;
+
+use osmpbf::*;
+use std::io::Cursor;
+use wasm_bindgen_test::*;
+
+static TEST_FILES: [&[u8]; 3] = [
+    include_bytes!("test.osm.pbf"),
+    include_bytes!("test_nozlib.osm.pbf"),
+    include_bytes!("test_nozlib_nodense.osm.pbf"),
+];
+
+#[wasm_bindgen_test]
+fn read_blobs_from_bytes() {
+    for data in &TEST_FILES {
+        let blobs = BlobReader::from_bytes(data)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(blobs.len(), 2);
+        assert_eq!(blobs[0].get_type(), BlobType::OsmHeader);
+        assert_eq!(blobs[1].get_type(), BlobType::OsmData);
+    }
+}
+
+#[wasm_bindgen_test]
+fn read_elements_from_bytes() {
+    for data in &TEST_FILES {
+        let reader = ElementReader::new(Cursor::new(*data));
+        let mut ids = vec![];
+        reader
+            .for_each(|element| {
+                ids.push(element.osm_id());
+            })
+            .unwrap();
+        assert_eq!(
+            ids,
+            [
+                OsmId::Node(105),
+                OsmId::Node(106),
+                OsmId::Node(108),
+                OsmId::Way(107),
+                OsmId::Relation(120),
+            ]
+        );
+    }
+}
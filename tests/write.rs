@@ -0,0 +1,1027 @@
+extern crate osmpbf;
+
+use osmpbf::testing::{assert_pbf_equivalent, TestPbfBuilder};
+use osmpbf::*;
+
+static TEST_FILE_PATHS: [&str; 3] = [
+    "tests/test.osm.pbf",
+    "tests/test_nozlib.osm.pbf",
+    "tests/test_nozlib_nodense.osm.pbf",
+];
+
+fn node(id: i64, tags: &[(&str, &str)]) -> OwnedElement {
+    OwnedElement::Node(OwnedNode {
+        id,
+        nano_lat: 52_119_923_600 + id * 1_000,
+        nano_lon: 11_625_644_700 - id * 1_000,
+        tags: tags
+            .iter()
+            .map(|&(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        info: OwnedInfo::default(),
+    })
+}
+
+fn write_elements(elements: Vec<OwnedElement>, dense: bool) -> Vec<u8> {
+    let mut writer = ElementWriter::new(vec![]).dense_nodes(dense);
+    for element in elements {
+        writer.write_element(element).unwrap();
+    }
+    writer.finish().unwrap()
+}
+
+fn read_blocks(bytes: &[u8]) -> (HeaderBlock, Vec<PrimitiveBlock>) {
+    let mut header = None;
+    let mut blocks = vec![];
+    for blob in BlobReader::from_bytes(bytes) {
+        match blob.unwrap().decode().unwrap() {
+            BlobDecode::OsmHeader(h) => header = Some(*h),
+            BlobDecode::OsmData(block) => blocks.push(block),
+            BlobDecode::Unknown(_) => panic!("unexpected blob type"),
+        }
+    }
+    (header.unwrap(), blocks)
+}
+
+fn read_owned_elements(bytes: &[u8]) -> Vec<OwnedElement> {
+    let (_, blocks) = read_blocks(bytes);
+    blocks
+        .into_iter()
+        .flat_map(|block| block.into_owned_elements().unwrap())
+        .collect()
+}
+
+#[test]
+fn write_dense_nodes() {
+    let elements = vec![
+        node(1, &[("amenity", "cafe"), ("name", "Cafe")]),
+        node(3, &[]),
+        node(2, &[("name", "Bar")]),
+        node(5, &[]),
+    ];
+    let bytes = write_elements(elements.clone(), true);
+    let (header, blocks) = read_blocks(&bytes);
+
+    assert!(header
+        .required_features()
+        .contains(&"DenseNodes".to_string()));
+    assert_eq!(blocks.len(), 1);
+
+    let group = blocks[0].groups().next().unwrap();
+    let raw = group.dense_raw().unwrap();
+    assert_eq!(raw.ids(), [1, 2, -1, 3]);
+
+    // Each node's tags are terminated by a zero, untagged nodes only by the zero.
+    let keys_vals = raw.keys_vals();
+    assert_eq!(keys_vals.len(), 4 + 1 + 1 + 2 + 1 + 1);
+    assert_eq!(keys_vals[4], 0);
+    assert_eq!(keys_vals[5], 0);
+    assert_eq!(keys_vals[8], 0);
+    assert_eq!(keys_vals[9], 0);
+    assert!(keys_vals[..4].iter().all(|&i| i != 0));
+
+    let read_back = blocks
+        .into_iter()
+        .next()
+        .unwrap()
+        .into_owned_elements()
+        .unwrap();
+    assert_eq!(read_back.len(), elements.len());
+    for (a, b) in elements.iter().zip(&read_back) {
+        assert_eq!(a.id(), b.id());
+        assert_eq!(a.tags(), b.tags());
+    }
+}
+
+#[test]
+fn write_untagged_dense_nodes() {
+    let bytes = write_elements(vec![node(1, &[]), node(2, &[])], true);
+    let (_, blocks) = read_blocks(&bytes);
+
+    let group = blocks[0].groups().next().unwrap();
+    assert_eq!(group.dense_raw().unwrap().keys_vals().len(), 0);
+    assert_eq!(group.dense_nodes().count(), 2);
+}
+
+#[test]
+fn write_sparse_nodes() {
+    let elements = vec![node(1, &[("name", "Cafe")]), node(2, &[])];
+    let bytes = write_elements(elements, false);
+    let (header, blocks) = read_blocks(&bytes);
+
+    assert!(!header
+        .required_features()
+        .contains(&"DenseNodes".to_string()));
+    assert!(!blocks[0].uses_dense_nodes());
+
+    let nodes: Vec<_> = blocks[0].groups().flat_map(|g| g.nodes()).collect();
+    assert_eq!(nodes.len(), 2);
+    assert_eq!(nodes[0].tags().collect::<Vec<_>>(), [("name", "Cafe")]);
+    assert_eq!(nodes[1].lat_in_nano_degrees(), 52_119_925_600);
+    assert_eq!(nodes[1].lon_in_nano_degrees(), 11_625_642_700);
+}
+
+#[test]
+fn round_trip() {
+    for path in &TEST_FILE_PATHS {
+        let original = std::fs::read(path).unwrap();
+        let elements = read_owned_elements(&original);
+
+        for &dense in &[true, false] {
+            let copy = write_elements(elements.clone(), dense);
+            assert_pbf_equivalent(&original[..], &copy[..]);
+
+            let copied_elements = read_owned_elements(&copy);
+            assert_eq!(copied_elements.len(), elements.len());
+            for (a, b) in elements.iter().zip(&copied_elements) {
+                assert_eq!(a.osm_id(), b.osm_id());
+                assert_eq!(a.tags(), b.tags());
+                match (a, b) {
+                    (OwnedElement::Node(x), OwnedElement::Node(y)) => {
+                        assert!((x.lat() - y.lat()).abs() < 1.0e-6);
+                        assert!((x.lon() - y.lon()).abs() < 1.0e-6);
+                    }
+                    (OwnedElement::Way(x), OwnedElement::Way(y)) => {
+                        assert_eq!(x.refs, y.refs);
+                    }
+                    (OwnedElement::Relation(x), OwnedElement::Relation(y)) => {
+                        assert_eq!(x.members, y.members);
+                    }
+                    _ => panic!("element types differ"),
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn round_trip_dense_nodes_without_info() {
+    let elements = vec![node(1, &[("name", "Cafe")]), node(2, &[])];
+    let bytes = write_elements(elements.clone(), true);
+
+    let (_, blocks) = read_blocks(&bytes);
+    let group = blocks[0].groups().next().unwrap();
+    assert!(group.dense_raw().unwrap().versions().is_empty());
+    assert!(group.dense_nodes().all(|dnode| !dnode.has_info()));
+    assert_eq!(read_owned_elements(&bytes), elements);
+
+    // Metadata of a single node is written for all nodes of the group.
+    let mut with_info = elements;
+    if let OwnedElement::Node(ref mut node) = with_info[1] {
+        node.info.version = Some(3);
+    }
+    let copied_elements = read_owned_elements(&write_elements(with_info, true));
+    assert_eq!(copied_elements[0].info().version, Some(-1));
+    assert_eq!(copied_elements[1].info().version, Some(3));
+}
+
+#[test]
+#[should_panic(expected = "way/107: refs differ")]
+fn detect_different_refs() {
+    let original = std::fs::read(TEST_FILE_PATHS[0]).unwrap();
+    let mut elements = read_owned_elements(&original);
+    for element in &mut elements {
+        if let OwnedElement::Way(ref mut way) = *element {
+            way.refs.pop();
+        }
+    }
+
+    let copy = write_elements(elements, true);
+    assert_pbf_equivalent(&original[..], &copy[..]);
+}
+
+#[test]
+fn extract_by_bbox() {
+    let output = std::env::temp_dir().join("osmpbf_test_extract_bbox.osm.pbf");
+    for path in &TEST_FILE_PATHS {
+        // Only node 106 is inside, but the way completes the other nodes.
+        let bbox = BBox::new(52.1199, 11.6256, 52.1200, 11.6257);
+        let stats = extract_bbox(path, &output, bbox).unwrap();
+        assert_eq!(
+            stats,
+            ExtractStats {
+                nodes: 3,
+                ways: 1,
+                relations: 1,
+            }
+        );
+        let original = std::fs::read(path).unwrap();
+        let extract = std::fs::read(&output).unwrap();
+        assert_pbf_equivalent(&original[..], &extract[..]);
+
+        let bbox = BBox::new(10.0, 10.0, 11.0, 11.0);
+        let stats = extract_bbox(path, &output, bbox).unwrap();
+        assert_eq!(stats, ExtractStats::default());
+        let extract = std::fs::read(&output).unwrap();
+        assert!(read_owned_elements(&extract).is_empty());
+    }
+    std::fs::remove_file(output).unwrap();
+}
+
+#[test]
+fn extract_by_tag_predicate() {
+    let output = std::env::temp_dir().join("osmpbf_test_extract_by_tags.osm.pbf");
+    for path in &TEST_FILE_PATHS {
+        let stats = extract_by_tags(path, &output, |element_type, mut tags| {
+            element_type == ElementType::Relation && tags.any(|tag| tag == ("rel_key", "rel_value"))
+        })
+        .unwrap();
+        assert_eq!(
+            stats,
+            ExtractStats {
+                nodes: 0,
+                ways: 0,
+                relations: 1,
+            }
+        );
+
+        let stats = extract_by_tags(path, &output, |_, mut tags| {
+            tags.any(|(_, value)| value == "triangle")
+        })
+        .unwrap();
+        assert_eq!(stats.nodes, 3);
+        assert_eq!(stats.ways, 1);
+        assert_eq!(stats.relations, 0);
+
+        let extract = std::fs::read(&output).unwrap();
+        let ids: Vec<_> = read_owned_elements(&extract)
+            .iter()
+            .map(|e| e.osm_id())
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                OsmId::Node(105),
+                OsmId::Node(106),
+                OsmId::Node(108),
+                OsmId::Way(107)
+            ]
+        );
+    }
+    std::fs::remove_file(output).unwrap();
+}
+
+#[test]
+fn node_id_range() {
+    for &dense in &[true, false] {
+        let elements = vec![node(7, &[]), node(3, &[]), node(12, &[]), node(5, &[])];
+        let bytes = write_elements(elements, dense);
+        let (_, blocks) = read_blocks(&bytes);
+        assert_eq!(blocks[0].id_range(), Some((3, 12)));
+
+        let way = OwnedElement::Way(OwnedWay {
+            id: 1,
+            ..OwnedWay::default()
+        });
+        let bytes = write_elements(vec![way], dense);
+        let (_, blocks) = read_blocks(&bytes);
+        assert_eq!(blocks[0].id_range(), None);
+    }
+}
+
+#[test]
+fn content_hash_ignores_metadata() {
+    let hashes = |elements: Vec<OwnedElement>, dense: bool| {
+        let bytes = write_elements(elements, dense);
+        let mut hashes = vec![];
+        ElementReader::new(&bytes[..])
+            .for_each(|element| hashes.push(element.content_hash()))
+            .unwrap();
+        hashes
+    };
+
+    let mut bumped = node(1, &[("amenity", "cafe"), ("name", "Cafe")]);
+    if let OwnedElement::Node(ref mut n) = bumped {
+        n.info.version = Some(2);
+        n.info.changeset = Some(42);
+        n.tags.reverse();
+    }
+    let original = hashes(
+        vec![node(1, &[("name", "Cafe"), ("amenity", "cafe")])],
+        true,
+    );
+    assert_eq!(hashes(vec![bumped], false), original);
+    assert_ne!(hashes(vec![node(1, &[("name", "Bar")])], true), original);
+    assert_ne!(
+        hashes(vec![node(2, &[])], true),
+        hashes(vec![node(3, &[])], true)
+    );
+}
+
+#[test]
+fn semantic_equality_ignores_metadata() {
+    // Writes both elements and compares them after reading them back.
+    let equal = |a: OwnedElement, b: OwnedElement, dense: bool| {
+        let (_, blocks_a) = read_blocks(&write_elements(vec![a], true));
+        let (_, blocks_b) = read_blocks(&write_elements(vec![b], dense));
+        let a = blocks_a[0].elements().next().unwrap();
+        let b = blocks_b[0].elements().next().unwrap();
+        assert_eq!(a.semantically_equals(&b), b.semantically_equals(&a));
+        a.semantically_equals(&b)
+    };
+
+    let mut bumped = node(1, &[("amenity", "cafe"), ("name", "Cafe")]);
+    if let OwnedElement::Node(ref mut n) = bumped {
+        n.info.version = Some(2);
+        n.info.changeset = Some(42);
+        n.info.uid = Some(7);
+        n.tags.reverse();
+    }
+    let cafe = || node(1, &[("name", "Cafe"), ("amenity", "cafe")]);
+    assert!(equal(cafe(), bumped.clone(), false));
+    assert!(equal(cafe(), bumped, true));
+    assert!(!equal(
+        cafe(),
+        node(2, &[("name", "Cafe"), ("amenity", "cafe")]),
+        true
+    ));
+    assert!(!equal(cafe(), node(1, &[("name", "Cafe")]), true));
+
+    // Duplicate keys are compared as a multiset.
+    let duplicates = |tags: &[(&str, &str)]| node(1, tags);
+    assert!(equal(
+        duplicates(&[("a", "1"), ("a", "1"), ("b", "2")]),
+        duplicates(&[("b", "2"), ("a", "1"), ("a", "1")]),
+        true
+    ));
+    assert!(!equal(
+        duplicates(&[("a", "1"), ("a", "1"), ("b", "2")]),
+        duplicates(&[("a", "1"), ("b", "2"), ("b", "2")]),
+        true
+    ));
+
+    let mut moved = node(1, &[]);
+    if let OwnedElement::Node(ref mut n) = moved {
+        n.nano_lat += 1_000;
+    }
+    assert!(!equal(node(1, &[]), moved, false));
+
+    let way = |refs: Vec<i64>| {
+        OwnedElement::Way(OwnedWay {
+            id: 5,
+            refs,
+            ..OwnedWay::default()
+        })
+    };
+    assert!(equal(way(vec![1, 2, 3]), way(vec![1, 2, 3]), true));
+    assert!(!equal(way(vec![1, 2, 3]), way(vec![3, 2, 1]), true));
+
+    let relation = |role: &str| {
+        OwnedElement::Relation(OwnedRelation {
+            id: 9,
+            members: vec![OwnedRelMember {
+                member_type: RelMemberType::Way,
+                member_id: 5,
+                role: role.to_string(),
+            }],
+            ..OwnedRelation::default()
+        })
+    };
+    assert!(equal(relation("outer"), relation("outer"), true));
+    assert!(!equal(relation("outer"), relation("inner"), true));
+}
+
+#[test]
+fn dedup_way_refs() {
+    let way = OwnedElement::Way(OwnedWay {
+        id: 1,
+        refs: vec![4, 4, 5, 6, 6, 6, 4],
+        ..OwnedWay::default()
+    });
+    let bytes = write_elements(vec![way], true);
+    let (_, blocks) = read_blocks(&bytes);
+
+    let group = blocks[0].groups().next().unwrap();
+    let way = group.ways().next().unwrap();
+    assert!(way.has_duplicate_consecutive_refs());
+    assert_eq!(way.dedup_refs().collect::<Vec<_>>(), [4, 5, 6, 4]);
+    assert_eq!(way.refs().count(), 7);
+}
+
+#[test]
+fn write_borrowed_elements() {
+    for path in &TEST_FILE_PATHS {
+        let mut writer = ElementWriter::new(vec![]);
+        ElementReader::from_path(path)
+            .unwrap()
+            .for_each(|element| writer.write_borrowed(&element).unwrap())
+            .unwrap();
+        let copy = writer.finish().unwrap();
+
+        let original = std::fs::read(path).unwrap();
+        assert_pbf_equivalent(&original[..], &copy[..]);
+        assert_eq!(read_owned_elements(&copy), read_owned_elements(&original));
+    }
+}
+
+#[test]
+fn sort_block_elements_by_id() {
+    let way = |id| {
+        OwnedElement::Way(OwnedWay {
+            id,
+            ..OwnedWay::default()
+        })
+    };
+    let elements = vec![node(7, &[]), way(2), node(-3, &[]), node(5, &[]), way(1)];
+    let bytes = write_elements(elements, true);
+    let (_, blocks) = read_blocks(&bytes);
+
+    let ids: Vec<_> = blocks[0]
+        .elements_sorted_by_id()
+        .iter()
+        .map(|e| e.osm_id())
+        .collect();
+    assert_eq!(
+        ids,
+        [
+            OsmId::Node(-3),
+            OsmId::Node(5),
+            OsmId::Node(7),
+            OsmId::Way(1),
+            OsmId::Way(2)
+        ]
+    );
+}
+
+#[cfg(feature = "o5m")]
+#[test]
+fn write_o5m() {
+    let small_node = |id| {
+        OwnedElement::Node(OwnedNode {
+            id,
+            nano_lat: 1_000,
+            nano_lon: -200,
+            tags: vec![("name".to_string(), "Cafe".to_string())],
+            info: OwnedInfo::default(),
+        })
+    };
+    let way = OwnedElement::Way(OwnedWay {
+        id: 5,
+        refs: vec![1, 3],
+        ..OwnedWay::default()
+    });
+
+    let mut writer = O5mWriter::new(vec![]);
+    for element in [small_node(1), small_node(3), way] {
+        writer.write_element(element).unwrap();
+    }
+    let bytes = writer.finish().unwrap();
+
+    let mut expected = b"\xff\xe0\x04o5m2".to_vec();
+    // Node 1 with an inline tag.
+    expected.extend_from_slice(b"\x10\x0f\x02\x00\x03\x14\x00name\x00Cafe\x00");
+    // Node 3 refers back to the tag of node 1.
+    expected.extend_from_slice(b"\x10\x05\x04\x00\x00\x00\x01");
+    // The delta coding is reset before the ways.
+    expected.extend_from_slice(b"\xff\x11\x05\x0a\x00\x02\x02\x04");
+    expected.push(0xfe);
+    assert_eq!(bytes, expected);
+
+    // Strings that readers have forgotten are written inline again.
+    let tagged_node = |id: i64, value: &str| {
+        OwnedElement::Node(OwnedNode {
+            id,
+            tags: vec![("k".to_string(), value.to_string())],
+            ..OwnedNode::default()
+        })
+    };
+    let mut writer = O5mWriter::new(vec![]);
+    writer.write_element(tagged_node(0, "first")).unwrap();
+    for id in 1..=15_000 {
+        writer
+            .write_element(tagged_node(id, &id.to_string()))
+            .unwrap();
+    }
+    writer.write_element(tagged_node(15_001, "first")).unwrap();
+    writer.write_element(tagged_node(15_002, "first")).unwrap();
+    let bytes = writer.finish().unwrap();
+    let inline = b"\x00k\x00first\x00";
+    let count = bytes.windows(inline.len()).filter(|w| w == inline).count();
+    assert_eq!(count, 2);
+}
+
+#[cfg(feature = "xml")]
+#[test]
+fn write_xml() {
+    let node = OwnedElement::Node(OwnedNode {
+        id: 1,
+        nano_lat: -1_234_567_890,
+        nano_lon: 10_000_000_049,
+        tags: vec![("name".to_string(), "Tom & \"Jerry\"".to_string())],
+        info: OwnedInfo {
+            version: Some(2),
+            milli_timestamp: Some(1_388_579_400_000),
+            uid: Some(17),
+            user: Some("<user>".to_string()),
+            ..OwnedInfo::default()
+        },
+    });
+    let way = OwnedElement::Way(OwnedWay {
+        id: 5,
+        refs: vec![1, 3],
+        ..OwnedWay::default()
+    });
+    let rel = OwnedElement::Relation(OwnedRelation {
+        id: 7,
+        members: vec![OwnedRelMember {
+            member_type: RelMemberType::Way,
+            member_id: 5,
+            role: "outer".to_string(),
+        }],
+        ..OwnedRelation::default()
+    });
+
+    let mut writer = OsmXmlWriter::new(vec![]);
+    for element in [node, way, rel] {
+        writer.write_element(element).unwrap();
+    }
+    let xml = String::from_utf8(writer.finish().unwrap()).unwrap();
+    let lines: Vec<_> = xml.lines().skip(2).collect();
+    assert_eq!(
+        lines,
+        [
+            r#"  <node id="1" version="2" timestamp="2014-01-01T12:30:00Z" uid="17" user="&lt;user&gt;" lat="-1.2345679" lon="10.0000000">"#,
+            r#"    <tag k="name" v="Tom &amp; &quot;Jerry&quot;"/>"#,
+            r#"  </node>"#,
+            r#"  <way id="5">"#,
+            r#"    <nd ref="1"/>"#,
+            r#"    <nd ref="3"/>"#,
+            r#"  </way>"#,
+            r#"  <relation id="7">"#,
+            r#"    <member type="way" ref="5" role="outer"/>"#,
+            r#"  </relation>"#,
+            r#"</osm>"#,
+        ]
+    );
+}
+
+#[cfg(feature = "xml")]
+#[test]
+fn write_xml_replaces_control_characters() {
+    let node = OwnedElement::Node(OwnedNode {
+        id: 1,
+        tags: vec![("note".to_string(), "a\u{1}b\tc\u{ffff}".to_string())],
+        ..OwnedNode::default()
+    });
+
+    let mut writer = OsmXmlWriter::new(vec![]);
+    writer.write_element(node).unwrap();
+    let xml = String::from_utf8(writer.finish().unwrap()).unwrap();
+    assert!(xml.contains("<tag k=\"note\" v=\"a\u{fffd}b&#9;c\u{fffd}\"/>"));
+    assert!(!xml.contains('\u{1}'));
+}
+
+#[cfg(feature = "xml")]
+#[test]
+fn xml_round_trip() {
+    let elements = read_owned_elements(&std::fs::read("tests/test.osm.pbf").unwrap());
+    let mut writer = OsmXmlWriter::new(vec![]);
+    for element in elements.iter().cloned() {
+        writer.write_element(element).unwrap();
+    }
+    let xml = writer.finish().unwrap();
+
+    let read = OsmXmlReader::new(&xml[..])
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(read, elements);
+}
+
+#[test]
+fn index_parents() {
+    let way = |id, refs: &[i64]| {
+        OwnedElement::Way(OwnedWay {
+            id,
+            refs: refs.to_vec(),
+            ..OwnedWay::default()
+        })
+    };
+    let rel = |id, member_id| {
+        OwnedElement::Relation(OwnedRelation {
+            id,
+            members: vec![OwnedRelMember {
+                member_type: RelMemberType::Relation,
+                member_id,
+                role: String::new(),
+            }],
+            ..OwnedRelation::default()
+        })
+    };
+
+    let mut index = ParentIndex::with_maps(
+        std::collections::BTreeMap::new(),
+        Some(std::collections::BTreeMap::new()),
+    );
+    for element in &[
+        way(1, &[10, 11, 12, 10]),
+        way(2, &[12, 13]),
+        rel(5, 6),
+        rel(7, 6),
+    ] {
+        index.add_owned_element(element);
+    }
+
+    let (node_ways, relation_parents) = index.into_maps();
+    let intersections: Vec<_> = node_ways
+        .iter()
+        .filter(|(_, ways)| ways.len() > 1)
+        .map(|(&node, _)| node)
+        .collect();
+    assert_eq!(intersections, [12]);
+    assert_eq!(node_ways[&10], [OsmId::Way(1)]);
+    assert_eq!(
+        relation_parents.unwrap()[&6],
+        [OsmId::Relation(5), OsmId::Relation(7)]
+    );
+
+    let reader = ElementReader::from_path("tests/test.osm.pbf").unwrap();
+    let index = ParentIndex::from_reader(reader).unwrap();
+    assert_eq!(index.node_ways().len(), 3);
+    assert!(index.relation_parents().is_none());
+}
+
+#[test]
+fn split_blocks_by_size() {
+    let relations: Vec<_> = (0..6)
+        .map(|id| {
+            let members = (0..150_000)
+                .map(|i| OwnedRelMember {
+                    member_type: RelMemberType::Way,
+                    member_id: i * 1_000_003 % 90_000_000,
+                    role: "outer".to_string(),
+                })
+                .collect();
+            OwnedElement::Relation(OwnedRelation {
+                id,
+                members,
+                ..OwnedRelation::default()
+            })
+        })
+        .collect();
+
+    for relation in &relations {
+        assert!(relation.encoded_size_hint() > 2 * 1024 * 1024);
+    }
+
+    let bytes = write_elements(relations.clone(), true);
+    let (_, blocks) = read_blocks(&bytes);
+
+    assert_eq!(blocks.len(), relations.len());
+    assert_eq!(read_owned_elements(&bytes), relations);
+}
+
+#[test]
+fn reject_oversized_blobs() {
+    let mut writer = BlobWriter::new(vec![]);
+    let data = vec![0; MAX_BLOB_MESSAGE_SIZE as usize + 1];
+    match writer.write_blob(BlobType::OsmData, &data) {
+        Err(ref e) => match *e.kind() {
+            ErrorKind::Blob(BlobError::MessageTooBig { size }) => {
+                assert_eq!(size, data.len() as u64)
+            }
+            ref kind => panic!("unexpected error: {:?}", kind),
+        },
+        Ok(()) => panic!("expected an error"),
+    }
+    assert_eq!(writer.offset(), ByteOffset(0));
+
+    writer
+        .write_blob(BlobType::OsmData, &data[..MAX_BLOB_MESSAGE_SIZE as usize])
+        .unwrap();
+    let bytes = writer.into_inner();
+    let blob = BlobReader::from_bytes(&bytes).next().unwrap().unwrap();
+    assert_eq!(blob.decompressed_bytes().unwrap().len(), data.len() - 1);
+}
+
+#[test]
+fn limit_block_elements() {
+    let mut writer = ElementWriter::new(vec![]).with_block_element_limit(3);
+    for element in (1..=10).map(|id| node(id, &[])) {
+        writer.write_element(element).unwrap();
+    }
+    let bytes = writer.finish().unwrap();
+    let (_, blocks) = read_blocks(&bytes);
+
+    let sizes: Vec<_> = blocks.iter().map(|b| b.elements().count()).collect();
+    assert_eq!(sizes, [3, 3, 3, 1]);
+    let ids: Vec<_> = read_owned_elements(&bytes).iter().map(|e| e.id()).collect();
+    assert_eq!(ids, (1..=10).collect::<Vec<_>>());
+}
+
+#[test]
+fn sort_stringtable() {
+    let elements = vec![
+        node(1, &[("name", "Cafe"), ("amenity", "cafe")]),
+        node(2, &[("amenity", "bar")]),
+        node(3, &[("amenity", "cafe")]),
+    ];
+    let mut reversed = elements.clone();
+    reversed.reverse();
+
+    let write_sorted = |elements: Vec<OwnedElement>| {
+        let mut writer = ElementWriter::new(vec![]).sort_stringtable(true);
+        for element in elements {
+            writer.write_element(element).unwrap();
+        }
+        writer.finish().unwrap()
+    };
+    let bytes = write_sorted(elements.clone());
+
+    let (_, blocks) = read_blocks(&bytes);
+    let strings: Vec<_> = blocks[0]
+        .raw_stringtable()
+        .iter()
+        .map(|s| String::from_utf8(s.clone()).unwrap())
+        .collect();
+    assert_eq!(strings, ["", "amenity", "cafe", "Cafe", "bar", "name"]);
+
+    // The stringtable does not depend on the order of the elements.
+    let (_, reversed_blocks) = read_blocks(&write_sorted(reversed));
+    assert_eq!(
+        reversed_blocks[0].raw_stringtable(),
+        blocks[0].raw_stringtable()
+    );
+    assert_eq!(
+        read_owned_elements(&bytes),
+        read_owned_elements(&write_elements(elements, true))
+    );
+}
+
+#[test]
+fn explicit_visibility() {
+    let way = |id, visible| {
+        OwnedElement::Way(OwnedWay {
+            id,
+            refs: vec![1, 2],
+            info: OwnedInfo {
+                version: Some(2),
+                visible,
+                ..OwnedInfo::default()
+            },
+            ..OwnedWay::default()
+        })
+    };
+    let bytes = write_elements(vec![way(1, true), way(2, false)], true);
+
+    let mut flags = vec![];
+    ElementReader::new(&bytes[..])
+        .for_each(|element| {
+            if let Element::Way(way) = element {
+                flags.push((way.info().visible_explicit(), way.info().visible()));
+            }
+        })
+        .unwrap();
+    // Visible elements are written without the flag.
+    assert_eq!(flags, [(None, true), (Some(false), false)]);
+}
+
+#[test]
+fn merge_sorted_elements() {
+    let base = vec![
+        node(1, &[("name", "old")]),
+        node(3, &[]),
+        node(5, &[]),
+        OwnedElement::Way(OwnedWay {
+            id: 1,
+            refs: vec![1, 3],
+            ..OwnedWay::default()
+        }),
+    ];
+    let update = vec![
+        node(1, &[("name", "new")]),
+        node(2, &[]),
+        node(5, &[]),
+        node(6, &[]),
+    ];
+    let bytes = write_elements(base, true);
+
+    // Keep the element of the update, but treat an untagged node 5 as a deletion.
+    let merged = merge_sorted(
+        ElementReader::new(&bytes[..]).owned_elements(),
+        update.into_iter().map(Ok),
+        |_, new| if new.id() == 5 { None } else { Some(new) },
+    )
+    .collect::<Result<Vec<_>>>()
+    .unwrap();
+
+    let ids: Vec<_> = merged.iter().map(|e| e.osm_id()).collect();
+    assert_eq!(
+        ids,
+        [
+            OsmId::Node(1),
+            OsmId::Node(2),
+            OsmId::Node(3),
+            OsmId::Node(6),
+            OsmId::Way(1)
+        ]
+    );
+    assert_eq!(merged[0].tags()[0].1, "new");
+
+    let unsorted = vec![node(2, &[]), node(1, &[])];
+    let mut merged = merge_sorted(unsorted.into_iter().map(Ok), vec![], |a, _| Some(a));
+    assert!(merged.next().unwrap().is_ok());
+    assert!(merged.next().unwrap().is_err());
+    assert!(merged.next().is_none());
+}
+
+#[test]
+fn custom_header_features() {
+    let (header, _) = read_blocks(&write_elements(vec![node(1, &[])], true));
+    assert_eq!(header.required_features(), ["OsmSchema-V0.6", "DenseNodes"]);
+    assert!(header.optional_features().is_empty());
+
+    let mut writer = ElementWriter::new(vec![])
+        .required_features(vec![
+            "OsmSchema-V0.6".to_string(),
+            "DenseNodes".to_string(),
+            "HistoricalInformation".to_string(),
+        ])
+        .optional_features(vec!["Sort.Type_then_ID"]);
+    writer.write_element(node(1, &[])).unwrap();
+    let bytes = writer.finish().unwrap();
+
+    // The explicit list replaces the default.
+    let (header, blocks) = read_blocks(&bytes);
+    assert_eq!(
+        header.required_features(),
+        ["OsmSchema-V0.6", "DenseNodes", "HistoricalInformation"]
+    );
+    assert_eq!(header.optional_features(), ["Sort.Type_then_ID"]);
+    assert_eq!(blocks.len(), 1);
+}
+
+#[test]
+fn write_block_index() {
+    let output = std::env::temp_dir().join("osmpbf_test_write_block_index.osm.pbf");
+    let index_path = std::env::temp_dir().join("osmpbf_test_write_block_index.idx");
+
+    let elements = ElementReader::from_path(TEST_FILE_PATHS[0])
+        .unwrap()
+        .read_all()
+        .unwrap();
+    let mut writer = ElementWriter::new(std::fs::File::create(&output).unwrap())
+        .with_block_element_limit(2)
+        .with_index(std::fs::File::create(&index_path).unwrap());
+    for element in elements.clone() {
+        writer.write_element(element).unwrap();
+    }
+    writer.finish().unwrap();
+
+    // The index that is written in one pass matches the one from scanning the file.
+    let index = BlockIndex::from_path(&index_path).unwrap();
+    let mut scanned = IndexedReader::from_path(&output).unwrap();
+    assert_eq!(&index, scanned.block_index().unwrap());
+    assert_eq!(index.entries().len(), 3);
+    assert_eq!(index.entries()[0].node_ids(), Some((105, 106)));
+    assert_eq!(index.entries()[1].node_ids(), Some((108, 108)));
+    assert_eq!(index.entries()[1].way_ids(), Some((107, 107)));
+    assert_eq!(index.entries()[2].relation_ids(), Some((120, 120)));
+
+    let mut bytes = vec![];
+    index.write_to(&mut bytes).unwrap();
+    assert_eq!(bytes, std::fs::read(&index_path).unwrap());
+    assert!(BlockIndex::read_from(&bytes[..bytes.len() - 1]).is_err());
+    assert!(BlockIndex::read_from(&b"not an index"[..]).is_err());
+
+    let mut reader = IndexedReader::from_path(&output).unwrap().with_index(index);
+    for element in elements {
+        let found = reader.find_element(element.osm_id()).unwrap();
+        assert_eq!(found.map(|e| e.osm_id()), Some(element.osm_id()));
+    }
+    assert!(reader.find_element(OsmId::Node(107)).unwrap().is_none());
+    assert!(reader.find_element(OsmId::Way(105)).unwrap().is_none());
+
+    std::fs::remove_file(&output).unwrap();
+    std::fs::remove_file(&index_path).unwrap();
+}
+
+#[test]
+fn resolve_relation_members() {
+    let member = |member_type, member_id, role: &str| OwnedRelMember {
+        member_type,
+        member_id,
+        role: role.to_string(),
+    };
+    let elements = vec![
+        node(1, &[]),
+        node(2, &[]),
+        node(3, &[]),
+        OwnedElement::Way(OwnedWay {
+            id: 10,
+            refs: vec![2, 3],
+            ..OwnedWay::default()
+        }),
+        OwnedElement::Relation(OwnedRelation {
+            id: 20,
+            members: vec![
+                member(RelMemberType::Node, 1, "label"),
+                member(RelMemberType::Node, 99, "label"),
+                member(RelMemberType::Way, 10, "outer"),
+                member(RelMemberType::Way, 11, "inner"),
+                member(RelMemberType::Relation, 21, ""),
+                member(RelMemberType::Relation, 22, ""),
+            ],
+            ..OwnedRelation::default()
+        }),
+        OwnedElement::Relation(OwnedRelation {
+            id: 21,
+            ..OwnedRelation::default()
+        }),
+    ];
+
+    let output = std::env::temp_dir().join("osmpbf_test_resolve_relation_members.osm.pbf");
+    let mut writer =
+        ElementWriter::new(std::fs::File::create(&output).unwrap()).with_block_element_limit(2);
+    for element in elements {
+        writer.write_element(element).unwrap();
+    }
+    writer.finish().unwrap();
+
+    let mut resolved = vec![];
+    IndexedReader::from_path(&output)
+        .unwrap()
+        .for_each_relation_resolved(|rel, members| resolved.push((rel.id(), members.to_vec())))
+        .unwrap();
+    std::fs::remove_file(&output).unwrap();
+
+    assert_eq!(resolved.len(), 2);
+    let (id, ref members) = resolved[0];
+    assert_eq!(id, 20);
+    let present: Vec<bool> = members.iter().map(|m| m.present).collect();
+    assert_eq!(present, [true, false, true, false, true, false]);
+    assert_eq!(members[0].role, "label");
+    assert_eq!(members[2].role, "outer");
+
+    let coord = members[0].coordinate.unwrap();
+    assert!((coord.lat - 52.1199246).abs() < 1e-6);
+    assert!((coord.lon - 11.6256437).abs() < 1e-6);
+    assert!(members[1..].iter().all(|m| m.coordinate.is_none()));
+
+    assert_eq!(resolved[1], (21, vec![]));
+}
+
+#[test]
+fn build_test_pbf() {
+    let builder = TestPbfBuilder::new()
+        .node(1, 52.5, 13.4, &[("amenity", "cafe")])
+        .node(2, -33.9, 151.2, &[])
+        .way(10, &[1, 2], &[("highway", "path")])
+        .relation(20, &[(RelMemberType::Way, 10, "outer")], &[]);
+
+    for &dense in &[true, false] {
+        let bytes = builder.clone().dense_nodes(dense).build().unwrap();
+        let (_, blocks) = read_blocks(&bytes);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].uses_dense_nodes(), dense);
+
+        let elements = read_owned_elements(&bytes);
+        assert_eq!(elements.len(), 4);
+        match elements[1] {
+            OwnedElement::Node(ref n) => {
+                assert_eq!((n.nano_lat, n.nano_lon), (-33_900_000_000, 151_200_000_000))
+            }
+            _ => panic!("expected a node"),
+        }
+        match elements[3] {
+            OwnedElement::Relation(ref rel) => assert_eq!(rel.members[0].role, "outer"),
+            _ => panic!("expected a relation"),
+        }
+    }
+
+    // Unknown blobs follow the header blob and are skipped by the element reader.
+    let bytes = builder
+        .clone()
+        .block_element_limit(1)
+        .unknown_blob("Example", b"data")
+        .build()
+        .unwrap();
+    let types: Vec<String> = BlobReader::from_bytes(&bytes)
+        .map(|blob| blob.unwrap().get_type().to_string())
+        .collect();
+    assert_eq!(
+        types,
+        [
+            "OSMHeader",
+            "Example",
+            "OSMData",
+            "OSMData",
+            "OSMData",
+            "OSMData"
+        ]
+    );
+    assert_eq!(ElementReader::new(&bytes[..]).read_all().unwrap().len(), 4);
+
+    // Only the first data block is corrupted.
+    let bytes = builder
+        .block_element_limit(2)
+        .truncate_stringtable()
+        .build()
+        .unwrap();
+    let mut blobs = BlobReader::from_bytes(&bytes).skip(1);
+    let err = blobs.next().unwrap().unwrap().decode().unwrap_err();
+    assert!(matches!(
+        *err.kind(),
+        ErrorKind::InvalidPrimitiveBlock { .. }
+    ));
+    assert!(blobs.next().unwrap().unwrap().decode().is_ok());
+}